@@ -11,7 +11,7 @@ fn main() {
     // client_config.password = Some("ignite".into());
 
     // Create an actual client. The protocol handshake is done here
-    let mut ignite = ignite_rs::new_client(client_config).unwrap();
+    let ignite = ignite_rs::new_client(client_config).unwrap();
 
     // Get a list of present caches
     if let Ok(names) = ignite.get_cache_names() {
@@ -30,6 +30,7 @@ fn main() {
     let val = MyOtherType {
         list: vec![Some(FooBar {})],
         arr: vec![-23423423i64, -2342343242315i64],
+        statuses: vec![Status::Active, Status::Inactive],
     };
 
     // Put value
@@ -50,7 +51,14 @@ struct MyType {
 struct MyOtherType {
     list: Vec<Option<FooBar>>,
     arr: Vec<i64>,
+    statuses: Vec<Status>,
 }
 
 #[derive(IgniteObj, Clone, Debug)]
 struct FooBar {}
+
+#[derive(IgniteObj, Clone, Debug)]
+enum Status {
+    Active,
+    Inactive,
+}
@@ -0,0 +1,83 @@
+//! Translation of the node addresses for the clients outside of the cluster network, like behind NAT
+use std::collections::HashMap;
+
+use crate::ClientConfig;
+
+/// Rewrites the addresses of the nodes, configured and discovered, before connecting to them.
+/// The client keeps the original addresses otherwise, like in the connection events
+pub trait AddressTranslator: Send + Sync {
+    /// Returns the address to connect to instead of `addr`, both as `host:port`.
+    /// None if the node could not be reached at the address, so the discovered one is skipped
+    fn translate(&self, addr: &str) -> Option<String>;
+}
+
+impl<F: Fn(&str) -> Option<String> + Send + Sync> AddressTranslator for F {
+    fn translate(&self, addr: &str) -> Option<String> {
+        self(addr)
+    }
+}
+
+/// Translates the addresses by the map, the other ones are used as is.
+/// The keys are either `host:port`, or hosts which are mapped keeping the port
+#[derive(Debug, Clone, Default)]
+pub struct AddressMap {
+    map: HashMap<String, String>,
+}
+
+impl AddressMap {
+    pub fn new(map: HashMap<String, String>) -> AddressMap {
+        AddressMap { map }
+    }
+}
+
+impl AddressTranslator for AddressMap {
+    fn translate(&self, addr: &str) -> Option<String> {
+        if let Some(translated) = self.map.get(addr) {
+            return Some(translated.clone());
+        }
+        let translated = addr
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some(format!("{}:{}", self.map.get(host)?, port)));
+        Some(translated.unwrap_or_else(|| addr.to_owned()))
+    }
+}
+
+/// Returns the address to connect to, translated if the client has the translator
+pub(crate) fn translated(conf: &ClientConfig, addr: &str) -> Option<String> {
+    match &conf.address_translator {
+        Some(translator) => translator.translate(addr),
+        None => Some(addr.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_map() {
+        let map = AddressMap::new(
+            vec![
+                (
+                    "10.0.0.1:10800".to_owned(),
+                    "ignite.example.com:20801".to_owned(),
+                ),
+                ("10.0.0.2".to_owned(), "ignite.example.com".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            map.translate("10.0.0.1:10800").as_deref(),
+            Some("ignite.example.com:20801")
+        );
+        assert_eq!(
+            map.translate("10.0.0.2:10801").as_deref(),
+            Some("ignite.example.com:10801")
+        );
+        assert_eq!(
+            map.translate("10.0.0.3:10800").as_deref(),
+            Some("10.0.0.3:10800")
+        );
+    }
+}
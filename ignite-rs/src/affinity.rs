@@ -0,0 +1,140 @@
+//! Partition awareness. Single-key requests are sent to the primary node of the key,
+//! which is found the same way as RendezvousAffinityFunction does it
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::protocol::TypeCode;
+use crate::WritableType;
+
+/// Affinity topology version of the cluster, major and minor
+pub(crate) type TopologyVersion = (i64, i32);
+
+/// Primary nodes of the partitions of a cache
+pub(crate) struct PartitionMap {
+    /// Primary node of each partition
+    pub(crate) nodes: Arc<Vec<Option<u128>>>,
+    /// Key types which have affinity key fields. Such keys are not routed
+    pub(crate) key_type_ids: Vec<i32>,
+}
+
+impl PartitionMap {
+    /// Returns the primary node of the key, if it is known
+    pub(crate) fn primary_node(&self, key: &dyn WritableType) -> Option<u128> {
+        *self.nodes.get(self.partition(key)?)?
+    }
+
+    /// Returns the partition of the key, if its hash code could be computed
+    pub(crate) fn partition(&self, key: &dyn WritableType) -> Option<usize> {
+        let mut bytes = Vec::with_capacity(key.size());
+        key.write(&mut bytes).ok()?;
+        if bytes.first() == Some(&(TypeCode::ComplexObj as u8)) {
+            let type_id = i32::from_le_bytes(<[u8; 4]>::try_from(bytes.get(4..8)?).ok()?);
+            if self.key_type_ids.contains(&type_id) {
+                return None;
+            }
+        }
+        let hash = java_hash_code(&bytes)?;
+        Some(partition(hash, self.nodes.len()))
+    }
+}
+
+/// Partition of the key with the given hash code, as RendezvousAffinityFunction computes it
+pub(crate) fn partition(hash: i32, parts: usize) -> usize {
+    if parts.is_power_of_two() {
+        ((hash ^ (hash as u32 >> 16) as i32) & (parts as i32 - 1)) as usize
+    } else {
+        (hash % parts as i32).unsigned_abs() as usize
+    }
+}
+
+/// Returns the hash code Java computes for the serialized value, if its type is supported
+pub(crate) fn java_hash_code(bytes: &[u8]) -> Option<i32> {
+    let (type_code, value) = bytes.split_first()?;
+    let read_4 = |from: usize| <[u8; 4]>::try_from(value.get(from..from + 4)?).ok();
+    let read_8 = |from: usize| <[u8; 8]>::try_from(value.get(from..from + 8)?).ok();
+    let long_hash = |v: i64| (v ^ (v as u64 >> 32) as i64) as i32;
+    match TypeCode::try_from(*type_code).ok()? {
+        TypeCode::Byte => Some(*value.first()? as i8 as i32),
+        TypeCode::Short => Some(i16::from_le_bytes([*value.first()?, *value.get(1)?]) as i32),
+        TypeCode::Char => Some(u16::from_le_bytes([*value.first()?, *value.get(1)?]) as i32),
+        TypeCode::Int => Some(i32::from_le_bytes(read_4(0)?)),
+        TypeCode::Long => Some(long_hash(i64::from_le_bytes(read_8(0)?))),
+        TypeCode::Float => {
+            let value = f32::from_le_bytes(read_4(0)?);
+            // floatToIntBits collapses all NaNs into the canonical one
+            Some(if value.is_nan() {
+                0x7fc0_0000
+            } else {
+                value.to_bits() as i32
+            })
+        }
+        TypeCode::Double => {
+            let value = f64::from_le_bytes(read_8(0)?);
+            let bits = if value.is_nan() {
+                0x7ff8_0000_0000_0000
+            } else {
+                value.to_bits() as i64
+            };
+            Some(long_hash(bits))
+        }
+        TypeCode::Bool => Some(if *value.first()? != 0 { 1231 } else { 1237 }),
+        TypeCode::String => {
+            let len = i32::from_le_bytes(read_4(0)?) as usize;
+            let value = std::str::from_utf8(value.get(4..4 + len)?).ok()?;
+            Some(value.encode_utf16().fold(0i32, |hash, unit| {
+                hash.wrapping_mul(31).wrapping_add(unit as i32)
+            }))
+        }
+        TypeCode::Uuid => {
+            let hilo = i64::from_le_bytes(read_8(0)?) ^ i64::from_le_bytes(read_8(8)?);
+            Some((hilo >> 32) as i32 ^ hilo as i32)
+        }
+        TypeCode::ComplexObj => Some(i32::from_le_bytes(read_4(7)?)), // hash code at offset 8
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_of(value: &dyn WritableType) -> Option<i32> {
+        let mut bytes = vec![];
+        value.write(&mut bytes).unwrap();
+        java_hash_code(&bytes)
+    }
+
+    #[test]
+    fn test_java_hash_code() {
+        assert_eq!(hash_of(&-5i32), Some(-5));
+        assert_eq!(hash_of(&0x1_0000_0002i64), Some(3));
+        assert_eq!(hash_of(&-1i64), Some(0));
+        assert_eq!(hash_of(&true), Some(1231));
+        assert_eq!(hash_of(&1.5f64), Some(1073217536));
+        assert_eq!(hash_of(&"hello".to_owned()), Some(99162322));
+        assert_eq!(hash_of(&"\u{1F600}".to_owned()), Some(1772899)); // surrogate pair
+        assert_eq!(hash_of(&vec![1i32]), None);
+    }
+
+    #[test]
+    fn test_partition() {
+        assert_eq!(partition(99162322, 1024), 315);
+        assert_eq!(partition(-5, 1024), 4);
+        assert_eq!(partition(-5, 1000), 5);
+    }
+
+    #[test]
+    fn test_partition_map() {
+        let mut nodes = vec![Some(1); 1024];
+        nodes[315] = None;
+        let map = PartitionMap {
+            nodes: Arc::new(nodes),
+            key_type_ids: vec![],
+        };
+        assert_eq!(map.partition(&"hello".to_owned()), Some(315));
+        assert_eq!(map.primary_node(&"hello".to_owned()), None);
+        assert_eq!(map.primary_node(&-5i32), Some(1));
+        assert_eq!(map.partition(&vec![1i32]), None);
+    }
+}
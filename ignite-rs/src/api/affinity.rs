@@ -0,0 +1,84 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use crate::affinity::{PartitionMap, TopologyVersion};
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{read_bool, read_i32, read_i64, read_u8, read_uuid, write_i32, TypeCode};
+use crate::{ReadableReq, WriteableReq};
+
+/// Cache Partitions 1101
+pub(crate) struct CachePartitionsReq<'a> {
+    pub(crate) cache_ids: &'a [i32],
+}
+
+impl WriteableReq for CachePartitionsReq<'_> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i32(writer, self.cache_ids.len() as i32)?;
+        for id in self.cache_ids {
+            write_i32(writer, *id)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        4 + 4 * self.cache_ids.len() // len, cache ids
+    }
+}
+
+/// Partition maps of the requested caches and of the other caches of their groups.
+/// Caches which have no map could not be routed by keys
+pub(crate) struct CachePartitionsResp {
+    pub(crate) topology: TopologyVersion,
+    pub(crate) caches: Vec<(i32, Option<Arc<PartitionMap>>)>,
+}
+
+impl ReadableReq for CachePartitionsResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let topology = (read_i64(reader)?, read_i32(reader)?);
+        let mut caches = Vec::new();
+        for _ in 0..read_i32(reader)? {
+            let applicable = read_bool(reader)?;
+            let mut group = Vec::new();
+            for _ in 0..read_i32(reader)? {
+                let cache_id = read_i32(reader)?;
+                let mut key_type_ids = Vec::new();
+                if applicable {
+                    for _ in 0..read_i32(reader)? {
+                        key_type_ids.push(read_i32(reader)?);
+                        let _affinity_key_field_id = read_i32(reader)?;
+                    }
+                }
+                group.push((cache_id, key_type_ids));
+            }
+            if !applicable {
+                caches.extend(group.into_iter().map(|(cache_id, _)| (cache_id, None)));
+                continue;
+            }
+            let mut nodes = Vec::new();
+            for _ in 0..read_i32(reader)? {
+                let node_id = match TypeCode::try_from(read_u8(reader)?)? {
+                    TypeCode::Uuid => read_uuid(reader)?,
+                    _ => return Err(IgniteError::from("Expected node UUID!")),
+                };
+                for _ in 0..read_i32(reader)? {
+                    let partition = read_i32(reader)? as usize;
+                    if nodes.len() <= partition {
+                        nodes.resize(partition + 1, None);
+                    }
+                    nodes[partition] = Some(node_id);
+                }
+            }
+            let nodes = Arc::new(nodes);
+            caches.extend(group.into_iter().map(|(cache_id, key_type_ids)| {
+                let map = PartitionMap {
+                    nodes: nodes.clone(),
+                    key_type_ids,
+                };
+                (cache_id, Some(Arc::new(map)))
+            }));
+        }
+        Ok(CachePartitionsResp { topology, caches })
+    }
+}
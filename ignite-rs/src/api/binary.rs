@@ -0,0 +1,110 @@
+use std::io;
+use std::io::{Read, Write};
+
+use crate::binary::{BinaryType, PlatformId};
+use crate::error::IgniteResult;
+use crate::protocol::binary_type::{get_binary_type_bytes, read_binary_type};
+use crate::protocol::{read_bool, write_i32, write_string_type_code, write_u8};
+use crate::{ReadableReq, ReadableType, WriteableReq};
+
+/// Get Binary Type 3002
+pub(crate) struct BinaryTypeGetReq {
+    pub(crate) type_id: i32,
+}
+
+impl WriteableReq for BinaryTypeGetReq {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i32(writer, self.type_id)
+    }
+
+    fn size(&self) -> usize {
+        4 // type id
+    }
+}
+
+pub(crate) struct BinaryTypeGetResp {
+    pub(crate) binary_type: Option<BinaryType>,
+}
+
+impl ReadableReq for BinaryTypeGetResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let binary_type = match read_bool(reader)? {
+            true => Some(read_binary_type(reader)?),
+            false => None,
+        };
+        Ok(BinaryTypeGetResp { binary_type })
+    }
+}
+
+/// Put Binary Type 3003
+pub(crate) struct BinaryTypePutReq<'a> {
+    pub(crate) binary_type: &'a BinaryType,
+}
+
+impl WriteableReq for BinaryTypePutReq<'_> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        get_binary_type_bytes(self.binary_type).and_then(|bytes| writer.write_all(&bytes))
+    }
+
+    fn size(&self) -> usize {
+        get_binary_type_bytes(self.binary_type).unwrap().len()
+    }
+}
+
+/// Get Binary Type Name 3000
+pub(crate) struct BinaryTypeNameGetReq {
+    pub(crate) platform_id: PlatformId,
+    pub(crate) type_id: i32,
+}
+
+impl WriteableReq for BinaryTypeNameGetReq {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, self.platform_id as u8)?;
+        write_i32(writer, self.type_id)
+    }
+
+    fn size(&self) -> usize {
+        1 + 4 // platform id, type id
+    }
+}
+
+pub(crate) struct BinaryTypeNameGetResp {
+    pub(crate) type_name: Option<String>,
+}
+
+impl ReadableReq for BinaryTypeNameGetResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let type_name = String::read(reader)?;
+        Ok(BinaryTypeNameGetResp { type_name })
+    }
+}
+
+/// Register Binary Type Name 3001
+pub(crate) struct BinaryTypeNameRegisterReq<'a> {
+    pub(crate) platform_id: PlatformId,
+    pub(crate) type_id: i32,
+    pub(crate) type_name: &'a str,
+}
+
+impl WriteableReq for BinaryTypeNameRegisterReq<'_> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, self.platform_id as u8)?;
+        write_i32(writer, self.type_id)?;
+        write_string_type_code(writer, self.type_name)
+    }
+
+    fn size(&self) -> usize {
+        1 + 4 + self.type_name.len() + 5 // platform id, type id, string itself, type code, len
+    }
+}
+
+pub(crate) struct BinaryTypeNameRegisterResp {
+    pub(crate) registered: bool,
+}
+
+impl ReadableReq for BinaryTypeNameRegisterResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let registered = read_bool(reader)?;
+        Ok(BinaryTypeNameRegisterResp { registered })
+    }
+}
@@ -146,8 +146,11 @@ pub(crate) struct CacheGetConfigResp {
 
 impl ReadableReq for CacheGetConfigResp {
     fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        // the length, the configuration is the rest of the response
         let _ = read_i32(reader)?;
-        let config = read_cache_configuration(reader)?;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let config = read_cache_configuration(&mut &bytes[..])?;
         Ok(CacheGetConfigResp { config })
     }
 }
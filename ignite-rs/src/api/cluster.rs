@@ -0,0 +1,85 @@
+use std::io;
+use std::io::{Read, Write};
+
+use crate::error::IgniteResult;
+use crate::protocol::{read_i32, read_i64, read_uuid, write_i64};
+use crate::{ReadableReq, ReadableType, WriteableReq};
+
+/// Topology version which stands for the current topology
+pub(crate) const CURRENT_TOPOLOGY: i64 = -1;
+
+/// Cluster Group Get Nodes Endpoints 5102. Returns the nodes which have joined
+/// or left between the topology versions, all nodes if the start version is unknown
+pub(crate) struct NodesEndpointsReq {
+    pub(crate) start_topology: i64,
+    pub(crate) end_topology: i64,
+}
+
+impl WriteableReq for NodesEndpointsReq {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i64(writer, self.start_topology)?;
+        write_i64(writer, self.end_topology)
+    }
+
+    fn size(&self) -> usize {
+        8 + 8 // start and end topology versions
+    }
+}
+
+/// Thin client endpoints of a server node
+pub(crate) struct NodeEndpoints {
+    pub(crate) node_id: u128,
+    pub(crate) port: i32,
+    /// Host names and IP addresses of the node
+    pub(crate) hosts: Vec<String>,
+}
+
+impl NodeEndpoints {
+    /// Returns the endpoints as "host:port" addresses
+    pub(crate) fn addrs(&self) -> Vec<String> {
+        self.hosts
+            .iter()
+            .map(|host| match host.contains(':') {
+                true => format!("[{}]:{}", host, self.port), // IPv6
+                false => format!("{}:{}", host, self.port),
+            })
+            .collect()
+    }
+}
+
+pub(crate) struct NodesEndpointsResp {
+    pub(crate) _topology: i64,
+    pub(crate) added: Vec<NodeEndpoints>,
+    pub(crate) _removed: Vec<u128>,
+}
+
+impl ReadableReq for NodesEndpointsResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let topology = read_i64(reader)?;
+        let mut added = Vec::new();
+        for _ in 0..read_i32(reader)? {
+            let node_id = read_uuid(reader)?;
+            let port = read_i32(reader)?;
+            let mut hosts = Vec::new();
+            for _ in 0..read_i32(reader)? {
+                if let Some(host) = String::read(reader)? {
+                    hosts.push(host);
+                }
+            }
+            added.push(NodeEndpoints {
+                node_id,
+                port,
+                hosts,
+            });
+        }
+        let mut removed = Vec::new();
+        for _ in 0..read_i32(reader)? {
+            removed.push(read_uuid(reader)?);
+        }
+        Ok(NodesEndpointsResp {
+            _topology: topology,
+            added,
+            _removed: removed,
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::io::{Read, Write};
+
+use crate::error::IgniteResult;
+use crate::protocol::{read_i64, write_i64};
+use crate::{ReadableReq, WriteableReq};
+
+/// Heartbeat 4, Get Idle Timeout 5. Both have no payload
+pub(crate) struct EmptyReq {}
+
+impl WriteableReq for EmptyReq {
+    fn write(&self, _: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        0
+    }
+}
+
+/// Operation with the payload built by the user, see `Ignite::raw_request`
+pub(crate) struct RawReq<'a>(pub(crate) &'a [u8]);
+
+impl WriteableReq for RawReq<'_> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(self.0)
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Resource Close 0. Closes the query cursor which is left open on the node
+pub(crate) struct ResourceCloseReq {
+    pub(crate) resource_id: i64,
+}
+
+impl WriteableReq for ResourceCloseReq {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i64(writer, self.resource_id)
+    }
+
+    fn size(&self) -> usize {
+        8
+    }
+}
+
+/// Idle timeout of the node in milliseconds. Idle connections are closed by the node
+/// after this time, 0 means that they are kept open forever
+pub(crate) struct IdleTimeoutResp {
+    pub(crate) idle_timeout_ms: i64,
+}
+
+impl ReadableReq for IdleTimeoutResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        Ok(IdleTimeoutResp {
+            idle_timeout_ms: read_i64(reader)?,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use std::io;
+use std::io::{Read, Write};
+use std::mem::size_of;
+
+use crate::error::IgniteResult;
+use crate::protocol::{
+    read_i32, read_i64, read_u8, write_bool, write_i32, write_i64, write_null, write_u8,
+};
+use crate::{OpaqueObject, ReadableReq, ReadableType, WriteableReq};
+
+/// Query Continuous 5000, without the filter, so the events of all the keys are sent
+pub(crate) struct ContinuousQueryReq {
+    pub(crate) cache_id: i32,
+    /// Number of the events which the node buffers before it sends them
+    pub(crate) page_size: i32,
+    /// How long the node waits for the buffer to fill, 0 to wait until it is full
+    pub(crate) time_interval_ms: i64,
+    pub(crate) include_expired: bool,
+}
+
+impl WriteableReq for ContinuousQueryReq {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i32(writer, self.cache_id)?;
+        write_u8(writer, 0)?; // flags
+        write_i32(writer, self.page_size)?;
+        write_i64(writer, self.time_interval_ms)?;
+        write_bool(writer, self.include_expired)?;
+        write_null(writer) // filter
+    }
+
+    fn size(&self) -> usize {
+        size_of::<i32>() + 1 + size_of::<i32>() + size_of::<i64>() + 1 + 1
+    }
+}
+
+/// Id of the query, which the notifications of its events carry
+pub(crate) struct ContinuousQueryResp {
+    pub(crate) query_id: i64,
+}
+
+impl ReadableReq for ContinuousQueryResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        Ok(ContinuousQueryResp {
+            query_id: read_i64(reader)?,
+        })
+    }
+}
+
+/// Query Continuous Event Notification 5001. The values are skipped, as only the keys are used
+pub(crate) struct CacheEventsNotification<K: ReadableType> {
+    /// Keys of the created, updated, removed or expired entries
+    pub(crate) keys: Vec<Option<K>>,
+}
+
+impl<K: ReadableType> ReadableReq for CacheEventsNotification<K> {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let count = read_i32(reader)?;
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            keys.push(K::read(reader)?);
+            OpaqueObject::read(reader)?; // old value
+            OpaqueObject::read(reader)?; // new value
+            read_u8(reader)?; // event type
+        }
+        Ok(CacheEventsNotification { keys })
+    }
+}
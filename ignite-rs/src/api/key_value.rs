@@ -1,7 +1,11 @@
-use crate::cache::CachePeekMode;
+use crate::api::{CursorPage, OpCode};
+use crate::cache::{CachePeekMode, ExpiryPolicy};
 use crate::error::IgniteResult;
-use crate::protocol::{read_bool, read_i32, read_i64, write_bool, write_i32, write_null, write_u8};
-use crate::{ReadableReq, ReadableType, WritableType, WriteableReq};
+use crate::protocol::cache_config::write_expiry_policy;
+use crate::protocol::{
+    read_bool, read_i32, read_i64, write_bool, write_i32, write_i64, write_null, write_u8,
+};
+use crate::{ClientConfig, ReadableReq, ReadableType, WritableType, WriteableReq};
 
 use std::io;
 use std::io::{Read, Write};
@@ -10,96 +14,238 @@ use std::mem::size_of;
 // https://apacheignite.readme.io/docs/binary-client-protocol-key-value-operations#op_cache_get
 const MAGIC_BYTE: u8 = 0;
 const CACHE_ID_MAGIC_BYTE_SIZE: usize = 5;
+/// The flag of the operations which keep the values in binary form on the nodes too
+const FLAG_KEEP_BINARY: u8 = 0x01;
+/// The flag of the operations which set the expiry policy of the entries they touch
+const FLAG_WITH_EXPIRY_POLICY: u8 = 0x04;
+/// Create, update and access time to live
+const EXPIRY_POLICY_SIZE: usize = 3 * size_of::<i64>();
 
+/// Cache id and the flags which precede the arguments of the key-value operations
+#[derive(Clone, Copy)]
+pub(crate) struct CacheHeader {
+    pub(crate) id: i32,
+    pub(crate) expiry_policy: Option<ExpiryPolicy>,
+    pub(crate) keep_binary: bool,
+}
+
+impl CacheHeader {
+    #[cfg(test)]
+    pub(crate) fn new(id: i32) -> CacheHeader {
+        CacheHeader {
+            id,
+            expiry_policy: None,
+            keep_binary: false,
+        }
+    }
+
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i32(writer, self.id)?;
+        let mut flags = MAGIC_BYTE;
+        if self.keep_binary {
+            flags |= FLAG_KEEP_BINARY;
+        }
+        match &self.expiry_policy {
+            Some(policy) => {
+                write_u8(writer, flags | FLAG_WITH_EXPIRY_POLICY)?;
+                write_expiry_policy(writer, policy)
+            }
+            None => write_u8(writer, flags),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self.expiry_policy {
+            Some(_) => CACHE_ID_MAGIC_BYTE_SIZE + EXPIRY_POLICY_SIZE,
+            None => CACHE_ID_MAGIC_BYTE_SIZE,
+        }
+    }
+}
+
+/// Most entries and bytes which a single request of `get_all`, `put_all` or `remove_keys` carries,
+/// see `ClientConfig::batch_max_entries` and `ClientConfig::batch_max_bytes`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct BatchLimits {
+    pub(crate) max_entries: Option<usize>,
+    pub(crate) max_bytes: Option<usize>,
+}
+
+impl BatchLimits {
+    pub(crate) fn new(conf: &ClientConfig) -> BatchLimits {
+        BatchLimits {
+            max_entries: conf.batch_max_entries,
+            max_bytes: conf.batch_max_bytes,
+        }
+    }
+
+    /// Splits the items into the chunks which fit the limits. An item which alone exceeds
+    /// the bytes limit gets a chunk of its own. Empty items give a single empty chunk,
+    /// so the request is still sent
+    pub(crate) fn split<'a, T>(&self, items: &'a [T], size: impl Fn(&T) -> usize) -> Vec<&'a [T]> {
+        if self.max_entries.is_none() && self.max_bytes.is_none() {
+            return vec![items];
+        }
+        let max_entries = self.max_entries.unwrap_or(usize::MAX);
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut bytes = 0;
+        for (i, item) in items.iter().enumerate() {
+            let item_bytes = self.max_bytes.map_or(0, |_| size(item));
+            let full = i - start >= max_entries
+                || self
+                    .max_bytes
+                    .is_some_and(|max| i > start && bytes + item_bytes > max);
+            if full {
+                chunks.push(&items[start..i]);
+                start = i;
+                bytes = 0;
+            }
+            bytes += item_bytes;
+        }
+        chunks.push(&items[start..]);
+        chunks
+    }
+}
+
+/// Entries of `put_all` which are written as they come, so the caller does not collect them first
+#[derive(Default)]
+pub(crate) struct EncodedPairs {
+    pub(crate) count: usize,
+    bytes: Vec<u8>,
+}
+
+/// Writes the entries of `put_all` into the batches which fit the limits
+pub(crate) struct PairsBuffer {
+    pairs: EncodedPairs,
+    limits: BatchLimits,
+}
+
+impl PairsBuffer {
+    pub(crate) fn new(limits: BatchLimits) -> PairsBuffer {
+        PairsBuffer {
+            pairs: EncodedPairs::default(),
+            limits,
+        }
+    }
+
+    /// Writes the entry. Returns the batch to send if it is full, which the entry is not in
+    /// if it has made the batch exceed the bytes limit
+    pub(crate) fn push(
+        &mut self,
+        key: &dyn WritableType,
+        value: &dyn WritableType,
+    ) -> io::Result<Option<EncodedPairs>> {
+        let start = self.pairs.bytes.len();
+        key.write(&mut self.pairs.bytes)?;
+        value.write(&mut self.pairs.bytes)?;
+        self.pairs.count += 1;
+        let too_large = self
+            .limits
+            .max_bytes
+            .is_some_and(|max| self.pairs.bytes.len() > max);
+        if too_large && self.pairs.count > 1 {
+            let entry = self.pairs.bytes.split_off(start);
+            self.pairs.count -= 1;
+            let full = self.take();
+            self.pairs = EncodedPairs {
+                count: 1,
+                bytes: entry,
+            };
+            return Ok(Some(full));
+        }
+        let full = self
+            .limits
+            .max_entries
+            .is_some_and(|max| self.pairs.count >= max);
+        Ok(if full { Some(self.take()) } else { None })
+    }
+
+    /// Returns the entries written since the last batch
+    pub(crate) fn take(&mut self) -> EncodedPairs {
+        std::mem::take(&mut self.pairs)
+    }
+}
+
+/// Single keys are type-erased, so any type the cache's key borrows as could be passed,
+/// like &str for String keys
 pub(crate) enum CacheReq<'a, K: WritableType, V: WritableType> {
-    Get(i32, &'a K),
-    GetAll(i32, &'a [K]),
-    Put(i32, &'a K, &'a V),
-    PutAll(i32, &'a [(K, V)]),
-    ContainsKey(i32, &'a K),
-    ContainsKeys(i32, &'a [K]),
-    GetAndPut(i32, &'a K, &'a V),
-    GetAndReplace(i32, &'a K, &'a V),
-    GetAndRemove(i32, &'a K),
-    PutIfAbsent(i32, &'a K, &'a V),
-    GetAndPutIfAbsent(i32, &'a K, &'a V),
-    Replace(i32, &'a K, &'a V),
-    ReplaceIfEquals(i32, &'a K, &'a V, &'a V),
-    Clear(i32),
-    ClearKey(i32, &'a K),
-    ClearKeys(i32, &'a [K]),
-    RemoveKey(i32, &'a K),
-    RemoveIfEquals(i32, &'a K, &'a V),
-    GetSize(i32, Vec<CachePeekMode>),
-    RemoveKeys(i32, &'a [K]),
-    RemoveAll(i32),
-    QueryScan(i32, i32), // cache ID, page size,
+    Get(CacheHeader, &'a dyn WritableType),
+    GetAll(CacheHeader, &'a [K]),
+    Put(CacheHeader, &'a dyn WritableType, &'a V),
+    PutAll(CacheHeader, &'a EncodedPairs),
+    ContainsKey(CacheHeader, &'a dyn WritableType),
+    ContainsKeys(CacheHeader, &'a [K]),
+    GetAndPut(CacheHeader, &'a dyn WritableType, &'a V),
+    GetAndReplace(CacheHeader, &'a dyn WritableType, &'a V),
+    GetAndRemove(CacheHeader, &'a dyn WritableType),
+    PutIfAbsent(CacheHeader, &'a dyn WritableType, &'a V),
+    GetAndPutIfAbsent(CacheHeader, &'a dyn WritableType, &'a V),
+    Replace(CacheHeader, &'a dyn WritableType, &'a V),
+    ReplaceIfEquals(CacheHeader, &'a dyn WritableType, &'a V, &'a V),
+    Clear(CacheHeader),
+    ClearKey(CacheHeader, &'a dyn WritableType),
+    ClearKeys(CacheHeader, &'a [K]),
+    RemoveKey(CacheHeader, &'a dyn WritableType),
+    RemoveIfEquals(CacheHeader, &'a dyn WritableType, &'a V),
+    GetSize(CacheHeader, Vec<CachePeekMode>),
+    RemoveKeys(CacheHeader, &'a [K]),
+    RemoveAll(CacheHeader),
+    QueryScan(CacheHeader, i32, i32), // page size, partition or -1 for all
 }
 
 impl<'a, K: WritableType, V: WritableType> WriteableReq for CacheReq<'a, K, V> {
     fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
         match self {
-            CacheReq::Get(id, key)
-            | CacheReq::ContainsKey(id, key)
-            | CacheReq::GetAndRemove(id, key)
-            | CacheReq::ClearKey(id, key)
-            | CacheReq::RemoveKey(id, key) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
+            CacheReq::Get(header, key)
+            | CacheReq::ContainsKey(header, key)
+            | CacheReq::GetAndRemove(header, key)
+            | CacheReq::ClearKey(header, key)
+            | CacheReq::RemoveKey(header, key) => {
+                header.write(writer)?;
                 key.write(writer)?;
                 Ok(())
             }
-            CacheReq::GetAll(id, keys)
-            | CacheReq::ContainsKeys(id, keys)
-            | CacheReq::ClearKeys(id, keys)
-            | CacheReq::RemoveKeys(id, keys) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
+            CacheReq::GetAll(header, keys)
+            | CacheReq::ContainsKeys(header, keys)
+            | CacheReq::ClearKeys(header, keys)
+            | CacheReq::RemoveKeys(header, keys) => {
+                header.write(writer)?;
                 write_i32(writer, keys.len() as i32)?;
                 for k in *keys {
                     k.write(writer)?;
                 }
                 Ok(())
             }
-            CacheReq::Put(id, key, value)
-            | CacheReq::GetAndPut(id, key, value)
-            | CacheReq::GetAndReplace(id, key, value)
-            | CacheReq::PutIfAbsent(id, key, value)
-            | CacheReq::GetAndPutIfAbsent(id, key, value)
-            | CacheReq::Replace(id, key, value)
-            | CacheReq::RemoveIfEquals(id, key, value) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
+            CacheReq::Put(header, key, value)
+            | CacheReq::GetAndPut(header, key, value)
+            | CacheReq::GetAndReplace(header, key, value)
+            | CacheReq::PutIfAbsent(header, key, value)
+            | CacheReq::GetAndPutIfAbsent(header, key, value)
+            | CacheReq::Replace(header, key, value)
+            | CacheReq::RemoveIfEquals(header, key, value) => {
+                header.write(writer)?;
                 key.write(writer)?;
                 value.write(writer)?;
                 Ok(())
             }
-            CacheReq::PutAll(id, pairs) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
-                write_i32(writer, pairs.len() as i32)?;
-                for pair in *pairs {
-                    pair.0.write(writer)?;
-                    pair.1.write(writer)?;
-                }
-                Ok(())
+            CacheReq::PutAll(header, pairs) => {
+                header.write(writer)?;
+                write_i32(writer, pairs.count as i32)?;
+                writer.write_all(&pairs.bytes)
             }
-            CacheReq::ReplaceIfEquals(id, key, old, new) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
+            CacheReq::ReplaceIfEquals(header, key, old, new) => {
+                header.write(writer)?;
                 key.write(writer)?;
                 old.write(writer)?;
                 new.write(writer)?;
                 Ok(())
             }
-            CacheReq::Clear(id) | CacheReq::RemoveAll(id) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
+            CacheReq::Clear(header) | CacheReq::RemoveAll(header) => {
+                header.write(writer)?;
                 Ok(())
             }
-            CacheReq::GetSize(id, modes) => {
-                write_i32(writer, *id)?;
-                write_u8(writer, MAGIC_BYTE)?;
+            CacheReq::GetSize(header, modes) => {
+                header.write(writer)?;
                 write_i32(writer, modes.len() as i32)?;
                 for mode in modes {
                     write_u8(writer, mode.clone() as u8)?;
@@ -107,12 +253,12 @@ impl<'a, K: WritableType, V: WritableType> WriteableReq for CacheReq<'a, K, V> {
                 Ok(())
             }
             // https://ignite.apache.org/docs/latest/binary-client-protocol/sql-and-scan-queries#op_query_scan
-            CacheReq::QueryScan(id, pg_sz) => {
-                write_i32(writer, *id)?;
+            CacheReq::QueryScan(header, pg_sz, partition) => {
+                write_i32(writer, header.id)?;
                 write_u8(writer, 1u8)?; // 1 to keep the value in binary form
                 write_null(writer)?; // Not possible to pass filter object unless Java or .NET
                 write_i32(writer, *pg_sz)?;
-                write_i32(writer, -1)?; // negative to query entire cache
+                write_i32(writer, *partition)?; // negative to query entire cache
                 write_bool(writer, false)?; // can be executed anywhere?
                 Ok(())
             }
@@ -121,61 +267,72 @@ impl<'a, K: WritableType, V: WritableType> WriteableReq for CacheReq<'a, K, V> {
 
     fn size(&self) -> usize {
         match self {
-            CacheReq::Get(_, key)
-            | CacheReq::ContainsKey(_, key)
-            | CacheReq::GetAndRemove(_, key)
-            | CacheReq::ClearKey(_, key)
-            | CacheReq::RemoveKey(_, key) => CACHE_ID_MAGIC_BYTE_SIZE + key.size(),
-            CacheReq::GetAll(_, keys)
-            | CacheReq::ContainsKeys(_, keys)
-            | CacheReq::ClearKeys(_, keys)
-            | CacheReq::RemoveKeys(_, keys) => {
-                let mut size = CACHE_ID_MAGIC_BYTE_SIZE;
+            CacheReq::Get(header, key)
+            | CacheReq::ContainsKey(header, key)
+            | CacheReq::GetAndRemove(header, key)
+            | CacheReq::ClearKey(header, key)
+            | CacheReq::RemoveKey(header, key) => header.size() + key.size(),
+            CacheReq::GetAll(header, keys)
+            | CacheReq::ContainsKeys(header, keys)
+            | CacheReq::ClearKeys(header, keys)
+            | CacheReq::RemoveKeys(header, keys) => {
+                let mut size = header.size();
                 size += 4; // len
                 for k in *keys {
                     size += k.size();
                 }
                 size
             }
-            CacheReq::Put(_, key, value)
-            | CacheReq::GetAndPut(_, key, value)
-            | CacheReq::GetAndReplace(_, key, value)
-            | CacheReq::PutIfAbsent(_, key, value)
-            | CacheReq::GetAndPutIfAbsent(_, key, value)
-            | CacheReq::Replace(_, key, value)
-            | CacheReq::RemoveIfEquals(_, key, value) => {
-                CACHE_ID_MAGIC_BYTE_SIZE + key.size() + value.size()
-            }
-            CacheReq::PutAll(_, pairs) => {
-                let mut size = CACHE_ID_MAGIC_BYTE_SIZE;
-                size += 4; //len
-                for pair in *pairs {
-                    size += pair.0.size();
-                    size += pair.1.size();
-                }
-                size
+            CacheReq::Put(header, key, value)
+            | CacheReq::GetAndPut(header, key, value)
+            | CacheReq::GetAndReplace(header, key, value)
+            | CacheReq::PutIfAbsent(header, key, value)
+            | CacheReq::GetAndPutIfAbsent(header, key, value)
+            | CacheReq::Replace(header, key, value)
+            | CacheReq::RemoveIfEquals(header, key, value) => {
+                header.size() + key.size() + value.size()
             }
-            CacheReq::ReplaceIfEquals(_, key, old, new) => {
-                CACHE_ID_MAGIC_BYTE_SIZE + key.size() + old.size() + new.size()
+            CacheReq::PutAll(header, pairs) => header.size() + 4 + pairs.bytes.len(), // len
+            CacheReq::ReplaceIfEquals(header, key, old, new) => {
+                header.size() + key.size() + old.size() + new.size()
             }
-            CacheReq::Clear(_) | CacheReq::RemoveAll(_) => CACHE_ID_MAGIC_BYTE_SIZE,
-            CacheReq::GetSize(_, modes) => {
-                let mut size = CACHE_ID_MAGIC_BYTE_SIZE;
+            CacheReq::Clear(header) | CacheReq::RemoveAll(header) => header.size(),
+            CacheReq::GetSize(header, modes) => {
+                let mut size = header.size();
                 size += 4; //len
                 for _ in modes {
                     size += 1;
                 }
                 size
             }
-            CacheReq::QueryScan(_, _) => {
+            CacheReq::QueryScan(_, _, _) => {
                 CACHE_ID_MAGIC_BYTE_SIZE
                     + size_of::<u8>() // Filter object: Null
                     + size_of::<i32>() // Cursor page size
-                    + size_of::<i32>() // Partition count
+                    + size_of::<i32>() // Partition
                     + size_of::<u8>() // local only flag
             }
         }
     }
+
+    fn affinity_key(&self) -> Option<(i32, &dyn WritableType)> {
+        match self {
+            CacheReq::Get(header, key)
+            | CacheReq::Put(header, key, _)
+            | CacheReq::ContainsKey(header, key)
+            | CacheReq::GetAndPut(header, key, _)
+            | CacheReq::GetAndReplace(header, key, _)
+            | CacheReq::GetAndRemove(header, key)
+            | CacheReq::PutIfAbsent(header, key, _)
+            | CacheReq::GetAndPutIfAbsent(header, key, _)
+            | CacheReq::Replace(header, key, _)
+            | CacheReq::ReplaceIfEquals(header, key, _, _)
+            | CacheReq::ClearKey(header, key)
+            | CacheReq::RemoveKey(header, key)
+            | CacheReq::RemoveIfEquals(header, key, _) => Some((header.id, *key)),
+            _ => None,
+        }
+    }
 }
 
 pub(crate) struct CacheDataObjectResp<V: ReadableType> {
@@ -208,11 +365,48 @@ impl<K: ReadableType, V: ReadableType> ReadableReq for CachePairsResp<K, V> {
 
 pub(crate) struct QueryScanResp<K: ReadableType, V: ReadableType> {
     pub(crate) val: Vec<(Option<K>, Option<V>)>,
+    /// Set if the node keeps more results
+    pub(crate) cursor_id: Option<i64>,
 }
 
 impl<K: ReadableType, V: ReadableType> ReadableReq for QueryScanResp<K, V> {
     fn read(reader: &mut impl Read) -> IgniteResult<Self> {
-        let _cursor_id = read_i64(reader)?;
+        let cursor_id = read_i64(reader)?;
+        let page = QueryCursorPageResp::read(reader)?;
+        Ok(QueryScanResp {
+            cursor_id: Some(cursor_id).filter(|_| page.more),
+            val: page.val,
+        })
+    }
+
+    fn open_cursor(&self) -> Option<i64> {
+        self.cursor_id
+    }
+}
+
+/// Query Scan Cursor Get Page 2001. The next page of the cursor which the scan has left open
+pub(crate) struct QueryCursorPageReq {
+    pub(crate) cursor_id: i64,
+}
+
+impl WriteableReq for QueryCursorPageReq {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i64(writer, self.cursor_id)
+    }
+
+    fn size(&self) -> usize {
+        size_of::<i64>()
+    }
+}
+
+pub(crate) struct QueryCursorPageResp<K: ReadableType, V: ReadableType> {
+    pub(crate) val: Vec<(Option<K>, Option<V>)>,
+    /// False if it is the last page, the node has closed the cursor then
+    pub(crate) more: bool,
+}
+
+impl<K: ReadableType, V: ReadableType> ReadableReq for QueryCursorPageResp<K, V> {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
         let count = read_i32(reader)?;
         let mut pairs: Vec<(Option<K>, Option<V>)> = Vec::new();
         for _ in 0..count {
@@ -220,8 +414,16 @@ impl<K: ReadableType, V: ReadableType> ReadableReq for QueryScanResp<K, V> {
             let val = V::read(reader)?;
             pairs.push((key, val));
         }
-        let _more = read_bool(reader)?; // TODO: get more results
-        Ok(QueryScanResp { val: pairs })
+        let more = read_bool(reader)?;
+        Ok(QueryCursorPageResp { val: pairs, more })
+    }
+}
+
+impl<K: ReadableType, V: ReadableType> CursorPage for QueryCursorPageResp<K, V> {
+    const OP_CODE: OpCode = OpCode::QueryScanCursorGetPage;
+
+    fn more(&self) -> bool {
+        self.more
     }
 }
 
@@ -246,3 +448,60 @@ impl ReadableReq for CacheBoolResp {
         Ok(CacheBoolResp { flag })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_limits() {
+        let items = [1usize, 2, 3, 4, 5];
+        assert_eq!(
+            BatchLimits::default().split(&items, |_| 0),
+            vec![&items[..]]
+        );
+        let limits = BatchLimits {
+            max_entries: Some(2),
+            max_bytes: None,
+        };
+        assert_eq!(
+            limits.split(&items, |_| 0),
+            vec![&items[0..2], &items[2..4], &items[4..]]
+        );
+        // the items are sized by their values, 4 alone exceeds the limit
+        let limits = BatchLimits {
+            max_entries: None,
+            max_bytes: Some(3),
+        };
+        assert_eq!(
+            limits.split(&items, |item| *item),
+            vec![&items[0..2], &items[2..3], &items[3..4], &items[4..]]
+        );
+        let empty: [usize; 0] = [];
+        assert_eq!(limits.split(&empty, |item| *item), vec![&empty[..]]);
+    }
+
+    #[test]
+    fn test_pairs_buffer() {
+        let limits = BatchLimits {
+            max_entries: Some(3),
+            max_bytes: Some(12),
+        };
+        let mut buffer = PairsBuffer::new(limits);
+        // i32 keys and values take 5 bytes each, so the second entry goes to the next batch
+        assert!(buffer.push(&1, &1).unwrap().is_none());
+        let full = buffer.push(&2, &2).unwrap().unwrap();
+        assert_eq!(full.count, 1);
+        assert_eq!(full.bytes.len(), 10);
+        assert_eq!(buffer.take().count, 1);
+        let mut buffer = PairsBuffer::new(BatchLimits {
+            max_bytes: None,
+            ..limits
+        });
+        for i in 0..2 {
+            assert!(buffer.push(&i, &i).unwrap().is_none());
+        }
+        assert_eq!(buffer.push(&2, &2).unwrap().unwrap().count, 3);
+        assert_eq!(buffer.take().count, 0);
+    }
+}
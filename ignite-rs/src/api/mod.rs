@@ -1,8 +1,19 @@
+pub(crate) mod affinity;
+pub(crate) mod binary;
 pub(crate) mod cache_config;
+pub(crate) mod cluster;
+pub(crate) mod connection;
+pub(crate) mod continuous;
 pub(crate) mod key_value;
+pub(crate) mod sql;
 
+#[derive(Clone, Copy)]
 pub(crate) enum OpCode {
     Handshake = 1,
+    // connection - https://ignite.apache.org/docs/latest/binary-client-protocol/binary-client-protocol#connection-operations
+    ResourceClose = 0,
+    Heartbeat = 4,
+    GetIdleTimeout = 5,
     //cache configuration
     CacheGetNames = 1050,
     CacheCreateWithName = 1051,
@@ -33,8 +44,87 @@ pub(crate) enum OpCode {
     CacheRemoveKeys = 1018,
     CacheRemoveAll = 1019,
     CacheGetSize = 1020,
+    // partition awareness - https://ignite.apache.org/docs/latest/binary-client-protocol/binary-client-protocol#partition-awareness
+    CachePartitions = 1101,
     // sql & scan queries - https://ignite.apache.org/docs/latest/binary-client-protocol/sql-and-scan-queries
     QueryScan = 2000,
+    QueryScanCursorGetPage = 2001,
+    QuerySqlFields = 2004,
+    QuerySqlFieldsCursorGetPage = 2005,
+    // binary types - https://ignite.apache.org/docs/latest/binary-client-protocol/binary-type-metadata
+    GetBinaryTypeName = 3000,
+    RegisterBinaryTypeName = 3001,
+    GetBinaryType = 3002,
+    PutBinaryType = 3003,
+    // continuous queries
+    QueryContinuous = 5000,
+    QueryContinuousEventNotification = 5001,
+    // cluster groups - https://ignite.apache.org/docs/latest/binary-client-protocol/cluster-api
+    ClusterGroupGetNodesEndpoints = 5102,
+}
+
+impl OpCode {
+    /// Whether the operation could be sent again if it is not known if the first attempt succeeded.
+    /// Repeating it leaves the same data and returns the same result
+    pub(crate) fn is_idempotent(self) -> bool {
+        matches!(
+            self,
+            OpCode::Heartbeat
+                | OpCode::GetIdleTimeout
+                | OpCode::CacheGetNames
+                | OpCode::CacheGetOrCreateWithName
+                | OpCode::CacheGetOrCreateWithConfiguration
+                | OpCode::CacheGetConfiguration
+                | OpCode::CacheGet
+                | OpCode::CachePut
+                | OpCode::CacheGetAll
+                | OpCode::CachePutAll
+                | OpCode::CacheContainsKey
+                | OpCode::CacheContainsKeys
+                | OpCode::CacheClear
+                | OpCode::CacheClearKey
+                | OpCode::CacheClearKeys
+                | OpCode::CacheRemoveKeys
+                | OpCode::CacheRemoveAll
+                | OpCode::CacheGetSize
+                | OpCode::CachePartitions
+                | OpCode::ClusterGroupGetNodesEndpoints
+                | OpCode::QueryScan
+                | OpCode::GetBinaryTypeName
+                | OpCode::RegisterBinaryTypeName
+                | OpCode::GetBinaryType
+                | OpCode::PutBinaryType
+        )
+    }
+
+    /// Whether the operation changes no data in the cluster
+    pub(crate) fn is_read_only(self) -> bool {
+        matches!(
+            self,
+            OpCode::Heartbeat
+                | OpCode::GetIdleTimeout
+                | OpCode::CacheGetNames
+                | OpCode::CacheGetConfiguration
+                | OpCode::CacheGet
+                | OpCode::CacheGetAll
+                | OpCode::CacheContainsKey
+                | OpCode::CacheContainsKeys
+                | OpCode::CacheGetSize
+                | OpCode::CachePartitions
+                | OpCode::ClusterGroupGetNodesEndpoints
+                | OpCode::QueryScan
+                | OpCode::GetBinaryTypeName
+                | OpCode::GetBinaryType
+        )
+    }
+}
+
+/// Page of the rows of a query cursor, which is fetched with the operation of its query
+pub(crate) trait CursorPage {
+    const OP_CODE: OpCode;
+
+    /// False if it is the last page, the node has closed the cursor then
+    fn more(&self) -> bool;
 }
 
 impl Into<i16> for OpCode {
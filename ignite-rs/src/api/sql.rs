@@ -0,0 +1,207 @@
+use std::io;
+use std::io::{Read, Write};
+
+use crate::api::{CursorPage, OpCode};
+use crate::cache::DFLT_PAGE_SIZE;
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{
+    read_bool, read_i32, read_i64, write_bool, write_i32, write_i64, write_null, write_u8,
+};
+use crate::{AnyValue, ReadableReq, ReadableType, WritableType, WriteableReq};
+
+/// Kinds of the statements which the node accepts in the query
+#[derive(Clone, Copy)]
+pub(crate) enum StatementType {
+    Any = 0,
+    Update = 2,
+}
+
+/// Query SQL Fields 2004. The schema of the cache is used if the schema is not set
+pub(crate) struct SqlFieldsReq<'a> {
+    pub(crate) cache_id: i32,
+    pub(crate) schema: Option<&'a str>,
+    pub(crate) page_size: i32,
+    /// -1 for no limit
+    pub(crate) max_rows: i32,
+    pub(crate) query: &'a str,
+    pub(crate) args: Vec<&'a dyn WritableType>,
+    pub(crate) statement_type: StatementType,
+    pub(crate) distributed_joins: bool,
+    pub(crate) enforce_join_order: bool,
+    pub(crate) collocated: bool,
+    pub(crate) lazy: bool,
+    /// 0 for no timeout
+    pub(crate) timeout_ms: i64,
+}
+
+impl<'a> SqlFieldsReq<'a> {
+    /// Returns the request of the statement with the default options
+    pub(crate) fn new(
+        cache_id: i32,
+        query: &'a str,
+        args: Vec<&'a dyn WritableType>,
+        statement_type: StatementType,
+    ) -> SqlFieldsReq<'a> {
+        SqlFieldsReq {
+            cache_id,
+            schema: None,
+            page_size: DFLT_PAGE_SIZE,
+            max_rows: -1,
+            query,
+            args,
+            statement_type,
+            distributed_joins: false,
+            enforce_join_order: false,
+            collocated: false,
+            lazy: false,
+            timeout_ms: 0,
+        }
+    }
+}
+
+impl WriteableReq for SqlFieldsReq<'_> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_i32(writer, self.cache_id)?;
+        write_u8(writer, 0)?; // flags
+        match self.schema {
+            Some(schema) => schema.write(writer)?,
+            None => write_null(writer)?,
+        }
+        write_i32(writer, self.page_size)?;
+        write_i32(writer, self.max_rows)?;
+        self.query.write(writer)?;
+        write_i32(writer, self.args.len() as i32)?;
+        for arg in &self.args {
+            arg.write(writer)?;
+        }
+        write_u8(writer, self.statement_type as u8)?;
+        write_bool(writer, self.distributed_joins)?;
+        write_bool(writer, false)?; // local
+        write_bool(writer, false)?; // replicated only
+        write_bool(writer, self.enforce_join_order)?;
+        write_bool(writer, self.collocated)?;
+        write_bool(writer, self.lazy)?;
+        write_i64(writer, self.timeout_ms)?;
+        write_bool(writer, true) // include the field names
+    }
+
+    fn size(&self) -> usize {
+        let schema = self.schema.map_or(1, |schema| schema.size());
+        let args: usize = self.args.iter().map(|arg| arg.size()).sum();
+        4 + 1 + schema + 4 + 4 + self.query.size() + 4 + args + 1 + 6 + 8 + 1
+    }
+}
+
+/// First page of the rows, with the names of the columns
+pub(crate) struct SqlFieldsResp {
+    pub(crate) columns: Vec<String>,
+    pub(crate) rows: Vec<Vec<AnyValue>>,
+    /// Set if the node keeps more rows
+    pub(crate) cursor_id: Option<i64>,
+}
+
+impl SqlFieldsResp {
+    /// Returns the number of the rows which the update statement has changed
+    pub(crate) fn updated(&self) -> Option<i64> {
+        match self.rows.first()?.first()? {
+            AnyValue::Long(count) => Some(*count),
+            _ => None,
+        }
+    }
+}
+
+impl ReadableReq for SqlFieldsResp {
+    fn read(reader: &mut impl Read) -> IgniteResult<Self> {
+        let cursor_id = read_i64(reader)?;
+        let count = read_i32(reader)?;
+        let mut columns = Vec::new();
+        for _ in 0..count {
+            columns.push(String::read(reader)?.unwrap_or_default());
+        }
+        let mut rows = Vec::new();
+        for _ in 0..read_i32(reader)? {
+            let mut row = Vec::new();
+            for _ in 0..count {
+                row.push(AnyValue::read(reader)?.unwrap_or(AnyValue::Null));
+            }
+            rows.push(row);
+        }
+        let more = read_bool(reader)?;
+        Ok(SqlFieldsResp {
+            columns,
+            rows,
+            cursor_id: Some(cursor_id).filter(|_| more),
+        })
+    }
+
+    fn open_cursor(&self) -> Option<i64> {
+        self.cursor_id
+    }
+}
+
+/// Query SQL Fields Cursor Get Page 2005, see `QueryCursorPageReq` for the request
+pub(crate) struct SqlFieldsPageResp {
+    pub(crate) rows: Vec<Vec<AnyValue>>,
+    /// False if it is the last page, the node has closed the cursor then
+    pub(crate) more: bool,
+}
+
+impl SqlFieldsPageResp {
+    /// Reads the page of a query with that many columns. The page does not repeat
+    /// the number of the columns, so it is taken from the first page
+    pub(crate) fn read(reader: &mut impl Read, columns: usize) -> IgniteResult<Self> {
+        let count = read_i32(reader)?;
+        let mut rows = Vec::new();
+        for _ in 0..count {
+            let mut row = Vec::with_capacity(columns);
+            for _ in 0..columns {
+                row.push(AnyValue::read(reader)?.unwrap_or(AnyValue::Null));
+            }
+            rows.push(row);
+        }
+        let more = read_bool(reader)?;
+        if reader.read(&mut [0u8; 1])? != 0 {
+            return Err(IgniteError::from(
+                "Page of the SQL query has more values than its columns",
+            ));
+        }
+        Ok(SqlFieldsPageResp { rows, more })
+    }
+}
+
+impl CursorPage for SqlFieldsPageResp {
+    const OP_CODE: OpCode = OpCode::QuerySqlFieldsCursorGetPage;
+
+    fn more(&self) -> bool {
+        self.more
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_page() {
+        // two rows of two columns, more rows
+        let mut page = Vec::new();
+        write_i32(&mut page, 2).unwrap();
+        "Ann".write(&mut page).unwrap();
+        31i32.write(&mut page).unwrap();
+        "Bob".write(&mut page).unwrap();
+        write_null(&mut page).unwrap();
+        write_bool(&mut page, true).unwrap();
+        let resp = SqlFieldsPageResp::read(&mut Cursor::new(&page), 2).unwrap();
+        assert_eq!(resp.rows.len(), 2);
+        assert_eq!(resp.rows[1][0], AnyValue::String("Bob".to_owned()));
+        assert_eq!(resp.rows[1][1], AnyValue::Null);
+        assert!(resp.more);
+
+        // the payload does not match the number of the columns
+        assert!(SqlFieldsPageResp::read(&mut Cursor::new(&page), 1).is_err());
+        assert!(SqlFieldsPageResp::read(&mut Cursor::new(&page), 3).is_err());
+        page.push(0);
+        assert!(SqlFieldsPageResp::read(&mut Cursor::new(&page), 2).is_err());
+    }
+}
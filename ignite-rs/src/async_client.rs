@@ -0,0 +1,2418 @@
+//! Asynchronous client. Requests and responses are the same as of the blocking client.
+//! They are encoded and decoded in memory, so only the socket IO is asynchronous
+//! and the futures do not hold borrowed values.
+//!
+//! The client is not bound to a runtime: it runs over any `AsyncTransport`.
+//! Transports are provided for tokio streams (`tokio` feature) and for futures-io streams
+//! (`futures-io` feature), which are used by async-std and smol.
+//!
+//! The requests share the connection: each one is sent with its own id and the responses are
+//! matched by the ids, so a slow request does not hold the other ones. If a future is dropped
+//! in the middle of sending a message or the transport fails, the connection is closed
+//! and the following requests fail with `ErrorKind::Disconnected` error. A future which is dropped
+//! while waiting for its response leaves the connection usable, the message it was reading
+//! is read by the next request. The asynchronous client does not reconnect.
+
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::hash::Hash;
+use std::io;
+use std::io::Cursor;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use futures_util::lock::Mutex;
+use futures_util::stream::{self, Stream};
+
+#[cfg(feature = "tokio")]
+use crate::address::translated;
+use crate::api::binary::{
+    BinaryTypeGetReq, BinaryTypeGetResp, BinaryTypeNameGetReq, BinaryTypeNameGetResp,
+    BinaryTypeNameRegisterReq, BinaryTypeNameRegisterResp, BinaryTypePutReq,
+};
+use crate::api::cache_config::{
+    CacheCreateWithConfigReq, CacheCreateWithNameReq, CacheDestroyReq, CacheGetConfigReq,
+    CacheGetConfigResp, CacheGetNamesReq, CacheGetNamesResp, CacheGetOrCreateWithConfigReq,
+    CacheGetOrCreateWithNameReq,
+};
+use crate::api::connection::{EmptyReq, RawReq, ResourceCloseReq};
+use crate::api::key_value::{
+    BatchLimits, CacheBoolResp, CacheDataObjectResp, CacheHeader, CacheReq, CacheSizeResp,
+    EncodedPairs, PairsBuffer, QueryCursorPageReq, QueryCursorPageResp, QueryScanResp,
+};
+use crate::api::sql::{SqlFieldsPageResp, SqlFieldsReq, SqlFieldsResp, StatementType};
+use crate::api::{CursorPage, OpCode};
+use crate::binary::{register_binary_type, BinaryType, DynamicBinaryObject, PlatformId};
+use crate::cache::{
+    cache_ops, check_expiry_policy, check_template, delete_statement, found_entries,
+    keys_statement, sql_key, update_conflict, updated_rows, CacheConfiguration, CachePeekMode,
+    ExpiryPolicy, DFLT_PAGE_SIZE, UPDATE_ATTEMPTS,
+};
+use crate::connection::{closed, Connection};
+use crate::entry::AsyncCacheEntry;
+use crate::error::{ErrorKind, IgniteError, IgniteResult};
+use crate::features::Features;
+use crate::handshake::{read_handshake_resp, write_handshake_req, HandshakeResp, ProtocolVersion};
+use crate::interceptor::{CacheInterceptor, Intercepted};
+use crate::protocol::{read_i64, with_numeric_widening, with_string_decoding};
+use crate::sql::{SqlFieldsQuery, SqlRow};
+#[cfg(feature = "tokio")]
+use crate::utils::shuffled;
+use crate::utils::string_to_java_hashcode;
+use crate::{
+    AnyValue, ClientConfig, OpaqueObject, RawResponse, ReadableReq, ReadableType, StringDecoding,
+    WritableType, WriteableReq,
+};
+
+/// Position of the request id in the request: after its length and operation code
+const REQ_ID_OFFSET: usize = 6;
+#[cfg(feature = "tokio")]
+const DFLT_READ_BUF_SIZE: usize = 8192;
+#[cfg(feature = "tokio")]
+const DFLT_WRITE_BUF_SIZE: usize = 8192;
+
+/// Future returned by `AsyncTransport`
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// Byte stream which the asynchronous client sends its requests over.
+/// Implement it to run the client on a runtime which has no transport provided.
+/// A request could be sent while the other ones wait for their responses, so `send` and `receive`
+/// are called concurrently. Two sends or two receives never overlap
+pub trait AsyncTransport: Send + Sync {
+    /// Writes the whole message and flushes it
+    fn send<'a>(&'a self, msg: &'a [u8]) -> TransportFuture<'a, ()>;
+    /// Reads the next message without its length
+    fn receive(&self) -> TransportFuture<'_, Vec<u8>>;
+    /// Closes the stream when the client is closed. Does nothing by default
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Transport over a tokio stream, like `tokio::net::TcpStream`
+#[cfg(feature = "tokio")]
+pub struct TokioTransport<S> {
+    reader: Mutex<tokio::io::ReadHalf<S>>,
+    writer: Mutex<tokio::io::WriteHalf<S>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite> TokioTransport<S> {
+    pub fn new(stream: S) -> TokioTransport<S> {
+        let (reader, writer) = tokio::io::split(stream);
+        TokioTransport {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S> AsyncTransport for TokioTransport<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send,
+{
+    fn send<'a>(&'a self, msg: &'a [u8]) -> TransportFuture<'a, ()> {
+        use tokio::io::AsyncWriteExt;
+        Box::pin(async move {
+            let mut writer = self.writer.lock().await;
+            writer.write_all(msg).await?;
+            writer.flush().await
+        })
+    }
+
+    fn receive(&self) -> TransportFuture<'_, Vec<u8>> {
+        use tokio::io::AsyncReadExt;
+        Box::pin(async move {
+            let mut reader = self.reader.lock().await;
+            let len = reader.read_i32_le().await?;
+            let mut resp = vec![0u8; len as usize];
+            reader.read_exact(&mut resp).await?;
+            Ok(resp)
+        })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        use tokio::io::AsyncWriteExt;
+        Box::pin(async move { self.writer.lock().await.shutdown().await })
+    }
+}
+
+/// Transport over a futures-io stream, like `async_std::net::TcpStream` or `smol::net::TcpStream`
+#[cfg(feature = "futures-io")]
+pub struct FuturesIoTransport<S> {
+    reader: Mutex<futures_util::io::ReadHalf<S>>,
+    writer: Mutex<futures_util::io::WriteHalf<S>>,
+}
+
+#[cfg(feature = "futures-io")]
+impl<S: futures_util::io::AsyncRead + futures_util::io::AsyncWrite> FuturesIoTransport<S> {
+    pub fn new(stream: S) -> FuturesIoTransport<S> {
+        let (reader, writer) = futures_util::io::AsyncReadExt::split(stream);
+        FuturesIoTransport {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<S> AsyncTransport for FuturesIoTransport<S>
+where
+    S: futures_util::io::AsyncRead + futures_util::io::AsyncWrite + Send,
+{
+    fn send<'a>(&'a self, msg: &'a [u8]) -> TransportFuture<'a, ()> {
+        use futures_util::io::AsyncWriteExt;
+        Box::pin(async move {
+            let mut writer = self.writer.lock().await;
+            writer.write_all(msg).await?;
+            writer.flush().await
+        })
+    }
+
+    fn receive(&self) -> TransportFuture<'_, Vec<u8>> {
+        use futures_util::io::AsyncReadExt;
+        Box::pin(async move {
+            let mut reader = self.reader.lock().await;
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len).await?;
+            let mut resp = vec![0u8; i32::from_le_bytes(len) as usize];
+            reader.read_exact(&mut resp).await?;
+            Ok(resp)
+        })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        use futures_util::io::AsyncWriteExt;
+        Box::pin(async move { self.writer.lock().await.close().await })
+    }
+}
+
+/// Create new asynchronous Ignite client on tokio using provided configuration
+/// Returned client has only one TCP connection with cluster, to any of the configured nodes.
+/// `tcp_connect_timeout` is applied, read and write timeouts are not: drop the futures instead
+#[cfg(feature = "tokio")]
+pub async fn new_async_client(conf: ClientConfig) -> IgniteResult<AsyncClient> {
+    conf.validate()?;
+    #[cfg(any(feature = "tls", feature = "native-tls"))]
+    if conf.tls.is_some() {
+        return Err(IgniteError::from(
+            "TLS is not supported by new_async_client, use new_async_client_with_transport!",
+        ));
+    }
+
+    if conf.proxy.is_some() {
+        return Err(IgniteError::from(
+            "Proxies are not supported by new_async_client, use new_async_client_with_transport!",
+        ));
+    }
+
+    let mut errors = Vec::new();
+    let mut connected = None;
+    for addr in shuffled(&conf.addrs) {
+        let target = match translated(&conf, &addr) {
+            Some(target) => target,
+            None => continue,
+        };
+        let connect = tokio::net::TcpStream::connect(&target);
+        let result = match conf.tcp_connect_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, connect).await {
+                Ok(result) => result,
+                Err(elapsed) => Err(io::Error::new(io::ErrorKind::TimedOut, elapsed)),
+            },
+            None => connect.await,
+        };
+        match result {
+            Ok(stream) => {
+                connected = Some(stream);
+                break;
+            }
+            Err(err) => errors.push(format!("{}: {}", addr, err)),
+        }
+    }
+    let stream = connected.ok_or_else(|| {
+        IgniteError::from(format!("Could not connect to any node! {}", errors.join("; ")).as_str())
+    })?;
+    if let Some(nodelay) = conf.tcp_nodelay {
+        stream.set_nodelay(nodelay)?;
+    }
+    if let Some(ttl) = conf.tcp_ttl {
+        stream.set_ttl(ttl)?;
+    }
+    let stream = stream.into_std()?;
+    Connection::configure_socket(socket2::SockRef::from(&stream), &conf)?;
+    let stream = tokio::net::TcpStream::from_std(stream)?;
+    let stream = tokio::io::BufStream::with_capacity(
+        conf.tcp_read_buff_size.unwrap_or(DFLT_READ_BUF_SIZE),
+        conf.tcp_write_buff_size.unwrap_or(DFLT_WRITE_BUF_SIZE),
+        stream,
+    );
+    new_async_client_with_transport(TokioTransport::new(stream), conf).await
+}
+
+/// Create new asynchronous Ignite client over an already connected transport.
+/// The protocol handshake is done here. `addr` and TCP settings of the configuration are ignored
+pub async fn new_async_client_with_transport(
+    transport: impl AsyncTransport + 'static,
+    conf: ClientConfig,
+) -> IgniteResult<AsyncClient> {
+    AsyncClient::new(Box::new(transport), conf).await
+}
+
+/// Connection which is shared by the concurrent requests. Each request has its own id,
+/// which the node puts into the response. The request which reads a response of another one
+/// keeps it for that request
+pub(crate) struct AsyncConnection {
+    transport: Arc<dyn AsyncTransport>,
+    /// Right to send a request
+    sending: Mutex<()>,
+    /// Right to read the next response, with the read which is not completed yet
+    receiving: Mutex<Option<TransportFuture<'static, Vec<u8>>>>,
+    in_flight: InFlightLimit,
+    responses: std::sync::Mutex<Responses>,
+    next_request_id: AtomicI64,
+    /// Set if sending or receiving was not completed, as the stream is out of sync then
+    broken: AtomicBool,
+    /// Set by `close`, no requests are sent then
+    closed: AtomicBool,
+    /// Query cursors which are left open on the node
+    cursors: std::sync::Mutex<Vec<i64>>,
+    /// Cursors of the streams which are dropped before their last page, see `drop_cursor`
+    dropped_cursors: std::sync::Mutex<Vec<i64>>,
+    /// Protocol version and features agreed on in the handshake
+    features: Features,
+    string_decoding: StringDecoding,
+    numeric_widening: bool,
+    batch_limits: BatchLimits,
+    interceptors: Vec<Arc<dyn CacheInterceptor>>,
+}
+
+#[derive(Default)]
+struct Responses {
+    /// Responses which are read by other requests, by request ids
+    received: HashMap<i64, Vec<u8>>,
+    /// Ids of the requests which futures are dropped before their responses came
+    abandoned: HashSet<i64>,
+}
+
+/// Forgets the response of the request if its future is dropped
+struct PendingRequest<'a> {
+    conn: &'a AsyncConnection,
+    id: i64,
+    done: bool,
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut responses = self.conn.responses.lock().unwrap();
+        if responses.received.remove(&self.id).is_none() {
+            responses.abandoned.insert(self.id);
+        }
+    }
+}
+
+/// Marks the connection broken unless the IO is completed
+struct IoGuard<'a> {
+    broken: &'a AtomicBool,
+    completed: bool,
+}
+
+impl Drop for IoGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.broken.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Limits the requests in flight. The waiting requests get their permits in the order they came in
+struct InFlightLimit {
+    max: usize,
+    state: std::sync::Mutex<InFlightState>,
+}
+
+#[derive(Default)]
+struct InFlightState {
+    in_flight: usize,
+    next_ticket: u64,
+    /// Tickets of the waiting requests with their wakers, the oldest first
+    waiters: VecDeque<(u64, Waker)>,
+    /// Wakers of the `drained` futures, which wait until no requests are in flight
+    drained: Vec<Waker>,
+}
+
+impl InFlightState {
+    /// Wakes the oldest waiting request if there is a free permit for it
+    fn wake_next(&self, max: usize) {
+        if self.in_flight < max {
+            if let Some((_, waker)) = self.waiters.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl InFlightLimit {
+    fn new(max: Option<usize>) -> InFlightLimit {
+        InFlightLimit {
+            max: max.unwrap_or(usize::MAX),
+            state: std::sync::Mutex::new(InFlightState::default()),
+        }
+    }
+
+    fn acquire(&self) -> AcquirePermit<'_> {
+        AcquirePermit {
+            limit: self,
+            ticket: None,
+        }
+    }
+
+    /// Waits until no requests are in flight
+    fn drained(&self) -> impl Future<Output = ()> + '_ {
+        futures_util::future::poll_fn(move |cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight == 0 {
+                return Poll::Ready(());
+            }
+            state.drained.push(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+/// Waits for the permit. Leaves the queue if dropped
+struct AcquirePermit<'a> {
+    limit: &'a InFlightLimit,
+    ticket: Option<u64>,
+}
+
+impl<'a> Future for AcquirePermit<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit<'a>> {
+        let limit = self.limit;
+        let mut state = limit.state.lock().unwrap();
+        let first = match self.ticket {
+            None => state.waiters.is_empty(),
+            Some(ticket) => state.waiters.front().map(|(front, _)| *front) == Some(ticket),
+        };
+        if first && state.in_flight < limit.max {
+            if self.ticket.take().is_some() {
+                state.waiters.pop_front();
+            }
+            state.in_flight += 1;
+            state.wake_next(limit.max);
+            return Poll::Ready(Permit { limit });
+        }
+        match self.ticket {
+            Some(ticket) => {
+                if let Some((_, waker)) = state.waiters.iter_mut().find(|(t, _)| *t == ticket) {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let ticket = state.next_ticket;
+                state.next_ticket += 1;
+                state.waiters.push_back((ticket, cx.waker().clone()));
+                self.ticket = Some(ticket);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for AcquirePermit<'_> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket {
+            let mut state = self.limit.state.lock().unwrap();
+            state.waiters.retain(|(t, _)| *t != ticket);
+            state.wake_next(self.limit.max);
+        }
+    }
+}
+
+/// Right to have a request in flight, which is given back when dropped
+struct Permit<'a> {
+    limit: &'a InFlightLimit,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limit.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.wake_next(self.limit.max);
+        if state.in_flight == 0 {
+            state.drained.drain(..).for_each(Waker::wake);
+        }
+    }
+}
+
+impl AsyncConnection {
+    pub(crate) async fn new(
+        transport: Box<dyn AsyncTransport>,
+        conf: &ClientConfig,
+    ) -> IgniteResult<AsyncConnection> {
+        let mut version = ProtocolVersion::LATEST;
+        let handshake = loop {
+            let mut req = Vec::new();
+            write_handshake_req(&mut req, conf, version)?;
+            transport.send(&req).await?;
+            let resp = transport.receive().await?;
+            match read_handshake_resp(&resp, version)? {
+                HandshakeResp::Accepted(handshake) => break handshake,
+                HandshakeResp::Retry(proposed) => version = proposed,
+            }
+        };
+
+        Ok(AsyncConnection {
+            transport: Arc::from(transport),
+            sending: Mutex::new(()),
+            receiving: Mutex::new(None),
+            in_flight: InFlightLimit::new(conf.max_in_flight_requests),
+            responses: std::sync::Mutex::new(Responses::default()),
+            next_request_id: AtomicI64::new(1),
+            broken: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            cursors: std::sync::Mutex::new(Vec::new()),
+            dropped_cursors: std::sync::Mutex::new(Vec::new()),
+            features: handshake.features,
+            string_decoding: conf.string_decoding,
+            numeric_widening: conf.numeric_widening,
+            batch_limits: BatchLimits::new(conf),
+            interceptors: conf.interceptors.clone(),
+        })
+    }
+
+    /// Fails if the connection is broken
+    fn check_broken(&self) -> IgniteResult<()> {
+        match self.broken.load(Ordering::Acquire) {
+            true => Err(IgniteError::new(
+                ErrorKind::Disconnected,
+                "Connection is closed!".to_owned(),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Awaits the transport IO. Fails if the connection is broken
+    async fn io<T>(&self, io: TransportFuture<'_, T>) -> IgniteResult<T> {
+        self.check_broken()?;
+        let mut guard = IoGuard {
+            broken: &self.broken,
+            completed: false,
+        };
+        let result = io
+            .await
+            .map_err(|err| IgniteError::new(ErrorKind::Disconnected, err.to_string()))?;
+        guard.completed = true;
+        Ok(result)
+    }
+
+    /// Reads the next response. The read is kept in `receiving` until it is completed,
+    /// so the request which has started it could be dropped in the middle of a message
+    async fn receive(
+        &self,
+        receiving: &mut Option<TransportFuture<'static, Vec<u8>>>,
+    ) -> IgniteResult<Vec<u8>> {
+        self.check_broken()?;
+        let read = receiving.get_or_insert_with(|| {
+            let transport = self.transport.clone();
+            Box::pin(async move { transport.receive().await })
+        });
+        let result = read.await;
+        *receiving = None;
+        result.map_err(|err| {
+            self.broken.store(true, Ordering::Release);
+            IgniteError::new(ErrorKind::Disconnected, err.to_string())
+        })
+    }
+
+    /// Sends the request and returns the response payload. The other requests could be sent
+    /// while this one waits for its response
+    async fn request(&self, msg: Vec<u8>) -> IgniteResult<Vec<u8>> {
+        self.exchange(msg).await?.into_payload()
+    }
+
+    /// Sends the request and returns the response, failed or not
+    async fn exchange(&self, mut msg: Vec<u8>) -> IgniteResult<RawResponse> {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        msg[REQ_ID_OFFSET..REQ_ID_OFFSET + 8].copy_from_slice(&id.to_le_bytes());
+        {
+            let _sending = self.sending.lock().await;
+            self.io(self.transport.send(&msg)).await?;
+        }
+        let mut pending = PendingRequest {
+            conn: self,
+            id,
+            done: false,
+        };
+        let resp = loop {
+            if let Some(resp) = self.take_response(id) {
+                break resp;
+            }
+            let mut receiving = self.receiving.lock().await;
+            // the response could have been read while this request waited
+            if let Some(resp) = self.take_response(id) {
+                break resp;
+            }
+            let resp = self.receive(&mut receiving).await?;
+            let resp_id = read_i64(&mut &resp[..])?;
+            if resp_id == id {
+                break resp;
+            }
+            let mut responses = self.responses.lock().unwrap();
+            if !responses.abandoned.remove(&resp_id) {
+                responses.received.insert(resp_id, resp);
+            }
+        };
+        pending.done = true;
+
+        let mut reader = Cursor::new(resp);
+        let header = Connection::read_resp_header(&mut reader, self.features.version())?;
+        Ok(RawResponse::new(header.flag, reader))
+    }
+
+    fn take_response(&self, id: i64) -> Option<Vec<u8>> {
+        self.responses.lock().unwrap().received.remove(&id)
+    }
+
+    /// Waits for the right to send a request. Fails if the connection is closed
+    async fn permit(&self) -> IgniteResult<Permit<'_>> {
+        let permit = self.in_flight.acquire().await;
+        if self.closed.load(Ordering::Acquire) {
+            return Err(closed());
+        }
+        self.close_dropped_cursors().await;
+        Ok(permit)
+    }
+
+    /// Queues the close of the cursor of a stream which is dropped before its last page.
+    /// The cursor is closed by a spawned future on tokio, by the next request otherwise
+    fn drop_cursor(self: &Arc<Self>, cursor_id: i64) {
+        self.cursors.lock().unwrap().retain(|id| *id != cursor_id);
+        self.dropped_cursors.lock().unwrap().push(cursor_id);
+        #[cfg(feature = "tokio")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let conn = self.clone();
+            runtime.spawn(async move {
+                let _permit = conn.permit().await;
+            });
+        }
+    }
+
+    /// Closes the queued cursors, see `drop_cursor`. The errors are ignored,
+    /// the request which has the permit fails on its own if the connection is broken
+    async fn close_dropped_cursors(&self) {
+        loop {
+            let resource_id = match self.dropped_cursors.lock().unwrap().pop() {
+                Some(resource_id) => resource_id,
+                None => return,
+            };
+            let msg =
+                Connection::encode_req(OpCode::ResourceClose, ResourceCloseReq { resource_id });
+            if let Ok(msg) = msg {
+                let _ = self.request(msg).await;
+            }
+        }
+    }
+
+    /// Stops the new requests and waits for the ones in flight,
+    /// then closes the query cursors left open on the node and the transport
+    async fn close(&self) -> IgniteResult<()> {
+        if self.closed.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        self.in_flight.drained().await;
+        let mut cursors = std::mem::take(&mut *self.cursors.lock().unwrap());
+        cursors.append(&mut self.dropped_cursors.lock().unwrap());
+        for resource_id in cursors {
+            let msg =
+                Connection::encode_req(OpCode::ResourceClose, ResourceCloseReq { resource_id });
+            if self.request(msg?).await.is_err() && self.broken.load(Ordering::Acquire) {
+                break;
+            }
+        }
+        let result = self.io(self.transport.close()).await;
+        self.broken.store(true, Ordering::Release);
+        result
+    }
+
+    /// Send message and read response header
+    pub(crate) async fn send(&self, msg: IgniteResult<Vec<u8>>) -> IgniteResult<()> {
+        let msg = msg?;
+        let _permit = self.permit().await?;
+        self.request(msg).await.map(|_| ())
+    }
+
+    /// Send message and return the response, failed or not
+    pub(crate) async fn send_raw(&self, msg: IgniteResult<Vec<u8>>) -> IgniteResult<RawResponse> {
+        let msg = msg?;
+        let _permit = self.permit().await?;
+        self.exchange(msg).await
+    }
+
+    /// Send message, read response header and return a response.
+    /// Binary types metadata which is required to read the payload is fetched
+    /// using the same connection and permit, and the payload is read again
+    pub(crate) async fn send_and_read<T: ReadableReq>(
+        &self,
+        msg: IgniteResult<Vec<u8>>,
+    ) -> IgniteResult<T> {
+        let msg = msg?;
+        let _permit = self.permit().await?;
+        let payload = self.request(msg).await?;
+        let resp: T = self
+            .read_payload(&payload, |reader| T::read(reader))
+            .await?;
+        if let Some(cursor) = resp.open_cursor() {
+            self.cursors.lock().unwrap().push(cursor);
+        }
+        Ok(resp)
+    }
+
+    /// Unpacks the response payload with the function. The caller holds the permit,
+    /// which is used to fetch the binary types metadata
+    async fn read_payload<T>(
+        &self,
+        payload: &[u8],
+        read_fn: impl Fn(&mut Cursor<&[u8]>) -> IgniteResult<T>,
+    ) -> IgniteResult<T> {
+        let mut fetched = Vec::<i32>::new();
+        loop {
+            let result = with_string_decoding(self.string_decoding, || {
+                with_numeric_widening(self.numeric_widening, || read_fn(&mut Cursor::new(payload)))
+            });
+            match result {
+                Err(err) => match err.kind() {
+                    ErrorKind::UnknownBinaryType { type_id, .. } if !fetched.contains(&type_id) => {
+                        fetched.push(type_id);
+                        let msg = Connection::encode_req(
+                            OpCode::GetBinaryType,
+                            BinaryTypeGetReq { type_id },
+                        );
+                        let resp = self.request(msg?).await?;
+                        let resp = BinaryTypeGetResp::read(&mut Cursor::new(resp))?;
+                        if let Some(binary_type) = resp.binary_type {
+                            register_binary_type(binary_type);
+                        }
+                    }
+                    _ => return Err(err),
+                },
+                Ok(resp) => return Ok(resp),
+            }
+        }
+    }
+
+    /// Fetches the next page of the query cursor. The node closes the cursor after the last page
+    pub(crate) async fn cursor_page<T: CursorPage + ReadableReq>(
+        &self,
+        cursor_id: i64,
+    ) -> IgniteResult<T> {
+        self.cursor_page_with(cursor_id, |reader| T::read(reader))
+            .await
+    }
+
+    /// Same as `cursor_page`, but the page is read with the function
+    pub(crate) async fn cursor_page_with<T, F>(&self, cursor_id: i64, read_fn: F) -> IgniteResult<T>
+    where
+        T: CursorPage,
+        F: Fn(&mut Cursor<&[u8]>) -> IgniteResult<T>,
+    {
+        let msg = Connection::encode_req(T::OP_CODE, QueryCursorPageReq { cursor_id })?;
+        let _permit = self.permit().await?;
+        let payload = self.request(msg).await?;
+        let resp = self.read_payload(&payload, read_fn).await?;
+        if !resp.more() {
+            self.cursors.lock().unwrap().retain(|id| *id != cursor_id);
+        }
+        Ok(resp)
+    }
+}
+
+/// Asynchronous Ignite Client. Same as Client, but all the operations return futures
+/// Uses single connection, which is shared by the clones of the client
+#[derive(Clone)]
+pub struct AsyncClient {
+    _conf: ClientConfig,
+    conn: Arc<AsyncConnection>,
+}
+
+impl AsyncClient {
+    async fn new(
+        transport: Box<dyn AsyncTransport>,
+        conf: ClientConfig,
+    ) -> IgniteResult<AsyncClient> {
+        let conn = AsyncConnection::new(transport, &conf).await?;
+        Ok(AsyncClient {
+            _conf: conf,
+            conn: Arc::new(conn),
+        })
+    }
+
+    fn cache<K: WritableType + ReadableType, V: WritableType + ReadableType>(
+        &self,
+        name: &str,
+    ) -> AsyncCache<K, V> {
+        AsyncCache::new(
+            string_to_java_hashcode(name),
+            name.to_owned(),
+            self.conn.clone(),
+        )
+    }
+
+    /// Returns the protocol version and the features negotiated with the node
+    pub fn features(&self) -> Features {
+        self.conn.features.clone()
+    }
+
+    /// Closes the client with all its clones and caches, so the service could drain on shutdown.
+    /// New operations fail with `ErrorKind::Closed`, the ones in flight are awaited.
+    /// Then the query cursors left open on the node are closed, as well as the transport.
+    /// Wrap it into a timeout of the runtime to bound the wait
+    pub async fn close(&self) -> IgniteResult<()> {
+        self.conn.close().await
+    }
+
+    /// Sends a heartbeat, so the node does not close the idle connection.
+    /// There are no background heartbeats, as the client is not bound to a runtime.
+    /// Needs protocol v1.7.0 with the heartbeat feature, see `features()`
+    pub async fn heartbeat(&self) -> IgniteResult<()> {
+        let msg = Connection::encode_req(OpCode::Heartbeat, EmptyReq {});
+        self.conn.send(msg).await
+    }
+
+    /// Returns names of caches currently available in cluster
+    pub async fn get_cache_names(&self) -> IgniteResult<Vec<String>> {
+        let msg = Connection::encode_req(OpCode::CacheGetNames, CacheGetNamesReq {});
+        let resp: CacheGetNamesResp = self.conn.send_and_read(msg).await?;
+        Ok(resp.names)
+    }
+
+    /// Creates a new cache with provided name and default configuration.
+    /// Fails if cache with this name already exists
+    pub async fn create_cache<K: WritableType + ReadableType, V: WritableType + ReadableType>(
+        &self,
+        name: &str,
+    ) -> IgniteResult<AsyncCache<K, V>> {
+        let msg = Connection::encode_req(
+            OpCode::CacheCreateWithName,
+            CacheCreateWithNameReq::from(name),
+        );
+        self.conn.send(msg).await?;
+        Ok(self.cache(name))
+    }
+
+    /// Creates a new cache with the configuration of the template which the nodes have.
+    /// The nodes match the templates by the names, so the name should start with the template
+    /// name without the trailing `*`, otherwise `ErrorKind::InvalidConfig` is returned
+    pub async fn create_cache_from_template<
+        K: WritableType + ReadableType,
+        V: WritableType + ReadableType,
+    >(
+        &self,
+        template: &str,
+        name: &str,
+    ) -> IgniteResult<AsyncCache<K, V>> {
+        check_template(template, name)?;
+        self.create_cache(name).await
+    }
+
+    /// Returns or creates a new cache with provided name and default configuration.
+    pub async fn get_or_create_cache<
+        K: WritableType + ReadableType,
+        V: WritableType + ReadableType,
+    >(
+        &self,
+        name: &str,
+    ) -> IgniteResult<AsyncCache<K, V>> {
+        let msg = Connection::encode_req(
+            OpCode::CacheGetOrCreateWithName,
+            CacheGetOrCreateWithNameReq::from(name),
+        );
+        self.conn.send(msg).await?;
+        Ok(self.cache(name))
+    }
+
+    /// Creates a new cache with provided configuration, see `CacheConfiguration::builder`.
+    /// Fails if cache with this name already exists, or `ErrorKind::InvalidConfig`
+    /// if the configuration is not valid
+    pub async fn create_cache_with_config<
+        K: WritableType + ReadableType,
+        V: WritableType + ReadableType,
+    >(
+        &self,
+        config: &CacheConfiguration,
+    ) -> IgniteResult<AsyncCache<K, V>> {
+        config.check(&self.conn.features)?;
+        let msg = Connection::encode_req(
+            OpCode::CacheCreateWithConfiguration,
+            CacheCreateWithConfigReq { config },
+        );
+        self.conn.send(msg).await?;
+        Ok(self.cache(&config.name))
+    }
+
+    /// Returns the cache or creates it with provided configuration.
+    /// The configuration of the existing cache is not changed
+    pub async fn get_or_create_cache_with_config<
+        K: WritableType + ReadableType,
+        V: WritableType + ReadableType,
+    >(
+        &self,
+        config: &CacheConfiguration,
+    ) -> IgniteResult<AsyncCache<K, V>> {
+        config.check(&self.conn.features)?;
+        let msg = Connection::encode_req(
+            OpCode::CacheGetOrCreateWithConfiguration,
+            CacheGetOrCreateWithConfigReq { config },
+        );
+        self.conn.send(msg).await?;
+        Ok(self.cache(&config.name))
+    }
+
+    /// Returns a configuration of the requested cache.
+    /// Fails if there is no such cache
+    pub async fn get_cache_config(&self, name: &str) -> IgniteResult<CacheConfiguration> {
+        let msg =
+            Connection::encode_req(OpCode::CacheGetConfiguration, CacheGetConfigReq::from(name));
+        let resp: CacheGetConfigResp = self.conn.send_and_read(msg).await?;
+        Ok(resp.config)
+    }
+
+    /// Destroys the cache. All the data is removed.
+    /// The handles of the cache fail afterwards, so tests and tenants could drop the caches they create
+    pub async fn destroy_cache(&self, name: &str) -> IgniteResult<()> {
+        let msg = Connection::encode_req(OpCode::CacheDestroy, CacheDestroyReq::from(name));
+        self.conn.send(msg).await
+    }
+
+    /// Returns metadata of the binary type as the cluster knows it.
+    /// Returns None if the type is not registered
+    pub async fn get_binary_type(&self, type_id: i32) -> IgniteResult<Option<BinaryType>> {
+        let msg = Connection::encode_req(OpCode::GetBinaryType, BinaryTypeGetReq { type_id });
+        let resp: BinaryTypeGetResp = self.conn.send_and_read(msg).await?;
+        if let Some(binary_type) = &resp.binary_type {
+            register_binary_type(binary_type.clone());
+        }
+        Ok(resp.binary_type)
+    }
+
+    /// Registers (or updates) metadata of the binary type in the cluster.
+    /// New fields and schemas are merged with already registered ones
+    pub async fn put_binary_type(&self, binary_type: &BinaryType) -> IgniteResult<()> {
+        let msg = Connection::encode_req(OpCode::PutBinaryType, BinaryTypePutReq { binary_type });
+        self.conn.send(msg).await?;
+        register_binary_type(binary_type.clone());
+        Ok(())
+    }
+
+    /// Returns the type name registered for the type id on the platform.
+    /// Returns None if there is no such mapping
+    pub async fn get_binary_type_name(
+        &self,
+        platform_id: PlatformId,
+        type_id: i32,
+    ) -> IgniteResult<Option<String>> {
+        let msg = Connection::encode_req(
+            OpCode::GetBinaryTypeName,
+            BinaryTypeNameGetReq {
+                platform_id,
+                type_id,
+            },
+        );
+        let resp: BinaryTypeNameGetResp = self.conn.send_and_read(msg).await?;
+        Ok(resp.type_name)
+    }
+
+    /// Registers the type name for the type id on the platform.
+    /// Returns false if the mapping was not registered
+    pub async fn register_binary_type_name(
+        &self,
+        platform_id: PlatformId,
+        type_id: i32,
+        type_name: &str,
+    ) -> IgniteResult<bool> {
+        let msg = Connection::encode_req(
+            OpCode::RegisterBinaryTypeName,
+            BinaryTypeNameRegisterReq {
+                platform_id,
+                type_id,
+                type_name,
+            },
+        );
+        let resp: BinaryTypeNameRegisterResp = self.conn.send_and_read(msg).await?;
+        Ok(resp.registered)
+    }
+
+    /// Sends the operation of the binary protocol with the payload as is and returns the response,
+    /// see `Ignite::raw_request`
+    pub async fn raw_request(&self, op_code: i16, payload: &[u8]) -> IgniteResult<RawResponse> {
+        let msg = Connection::encode_req(op_code, RawReq(payload));
+        self.conn.send_raw(msg).await
+    }
+
+    /// Runs the SQL fields query which is not bound to a cache, see `Ignite::query_sql_fields`
+    pub async fn query_sql_fields(
+        &self,
+        query: &SqlFieldsQuery,
+    ) -> IgniteResult<impl Stream<Item = IgniteResult<SqlRow>>> {
+        let msg = Connection::encode_req(OpCode::QuerySqlFields, query.req(0));
+        let resp = self.conn.send_and_read(msg).await?;
+        Ok(sql_rows(self.conn.clone(), resp))
+    }
+}
+
+/// Expands a cache operation into an asynchronous method
+macro_rules! async_cache_op {
+    (
+        $(#[$attr:meta])*
+        fn $name:ident $(<$q:ident>)? $(<$lt:lifetime>)? ($($arg:ident: $ty:ty),*) -> $ret:ty
+            $(where K: $bound:ident $(+ $more:ident)*)?
+            = $code:ident, $req:ident($($req_arg:expr),*) => |$resp:ident: $resp_ty:ty| $map:expr
+    ) => {
+        $(#[$attr])*
+        pub async fn $name$(<$q>)?$(<$lt>)?(&self, $($arg: $ty),*) -> IgniteResult<$ret>
+        where
+            $(K: $bound $(+ $more)*,)?
+            $(K: $lt, V: $lt,)?
+            $(K: Borrow<$q>, $q: WritableType + ?Sized)?
+        {
+            // the request is not held across the await, its keys are not Sync
+            let (intercepted, msg) = {
+                let req = CacheReq::$req::<K, V>(self.header(), $($req_arg),*);
+                let intercepted = self.intercept(stringify!($name), req.affinity_key().map(|(_, key)| key))?;
+                (intercepted, Connection::encode_req(OpCode::$code, &req))
+            };
+            intercepted.finish(
+                self.conn
+                    .send_and_read(msg)
+                    .await
+                    .map(|$resp: $resp_ty| $map)
+            )
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        fn $name:ident $(<$q:ident>)? $(<$lt:lifetime>)? ($($arg:ident: $ty:ty),*) -> $ret:ty
+            $(where K: $bound:ident $(+ $more:ident)*)?
+            = $code:ident, $req:ident($($req_arg:expr),*)
+    ) => {
+        $(#[$attr])*
+        pub async fn $name$(<$q>)?$(<$lt>)?(&self, $($arg: $ty),*) -> IgniteResult<$ret>
+        where
+            $(K: $bound $(+ $more)*,)?
+            $(K: $lt, V: $lt,)?
+            $(K: Borrow<$q>, $q: WritableType + ?Sized)?
+        {
+            // the request is not held across the await, its keys are not Sync
+            let (intercepted, msg) = {
+                let req = CacheReq::$req::<K, V>(self.header(), $($req_arg),*);
+                let intercepted = self.intercept(stringify!($name), req.affinity_key().map(|(_, key)| key))?;
+                (intercepted, Connection::encode_req(OpCode::$code, &req))
+            };
+            intercepted.finish(self.conn.send(msg).await)
+        }
+    };
+}
+
+/// Asynchronous Ignite key-value cache. Same as Cache, but all the operations return futures.
+/// All caches created from the single AsyncClient shares the common TCP connection
+pub struct AsyncCache<K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    id: i32,
+    pub _name: String,
+    conn: Arc<AsyncConnection>,
+    /// Time to live of the entries which the operations touch, see `with_expiry_policy`
+    expiry_policy: Option<ExpiryPolicy>,
+    /// Whether the nodes keep the values binary, see `with_keep_binary`
+    keep_binary: bool,
+    /// Interceptors of the client, then the ones of the handle, see `with_interceptor`
+    interceptors: Vec<Arc<dyn CacheInterceptor>>,
+    k_phantom: PhantomData<K>,
+    v_phantom: PhantomData<V>,
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Clone for AsyncCache<K, V> {
+    fn clone(&self) -> Self {
+        AsyncCache {
+            expiry_policy: self.expiry_policy,
+            keep_binary: self.keep_binary,
+            interceptors: self.interceptors.clone(),
+            ..AsyncCache::new(self.id, self._name.clone(), self.conn.clone())
+        }
+    }
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> AsyncCache<K, V> {
+    fn new(id: i32, name: String, conn: Arc<AsyncConnection>) -> AsyncCache<K, V> {
+        let interceptors = conn.interceptors.clone();
+        AsyncCache {
+            id,
+            _name: name,
+            conn,
+            expiry_policy: None,
+            keep_binary: false,
+            interceptors,
+            k_phantom: PhantomData,
+            v_phantom: PhantomData,
+        }
+    }
+
+    /// Returns the handle of the same cache, which operations are passed to the interceptor,
+    /// see `Cache::with_interceptor`
+    pub fn with_interceptor(
+        &self,
+        interceptor: impl CacheInterceptor + 'static,
+    ) -> AsyncCache<K, V> {
+        let mut cache = self.clone();
+        cache.interceptors.push(Arc::new(interceptor));
+        cache
+    }
+
+    /// Returns the handle of the same cache, which operations set the time to live of the entries
+    /// they create, update or read, instead of the expiry policy of the cache.
+    /// Fails with `ErrorKind::InvalidConfig` if the node is older than protocol v1.6.0
+    pub fn with_expiry_policy(&self, policy: ExpiryPolicy) -> IgniteResult<AsyncCache<K, V>> {
+        check_expiry_policy(&self._name, &self.conn.features)?;
+        Ok(AsyncCache {
+            expiry_policy: Some(policy),
+            ..self.clone()
+        })
+    }
+
+    /// Returns the handle of the same cache, which values are `DynamicBinaryObject`s, so the tools
+    /// could inspect the entries of any type. The nodes do not deserialize the values either,
+    /// like for the cache store, so the classes of the values are not needed on the nodes
+    pub fn with_keep_binary(&self) -> AsyncCache<K, DynamicBinaryObject> {
+        AsyncCache {
+            id: self.id,
+            _name: self._name.clone(),
+            conn: self.conn.clone(),
+            expiry_policy: self.expiry_policy,
+            keep_binary: true,
+            interceptors: self.interceptors.clone(),
+            k_phantom: PhantomData,
+            v_phantom: PhantomData,
+        }
+    }
+
+    /// Deletes the rows of the cache's table which match the SQL predicate, see `Cache::delete_where`.
+    /// The arguments are `Sync`, so the future could be sent to another thread
+    pub async fn delete_where(
+        &self,
+        predicate: &str,
+        args: &[&(dyn WritableType + Sync)],
+    ) -> IgniteResult<i64> {
+        let intercepted = self.intercept("delete_where", None)?;
+        let deleted = async {
+            let msg = Connection::encode_req(
+                OpCode::CacheGetConfiguration,
+                CacheGetConfigReq::from(&self._name),
+            );
+            let config: CacheGetConfigResp = self.conn.send_and_read(msg).await?;
+            let query = delete_statement(&self._name, &config.config, predicate)?;
+            let msg = {
+                let args = args.iter().map(|&arg| arg as &dyn WritableType).collect();
+                let req = SqlFieldsReq::new(self.id, &query, args, StatementType::Update);
+                Connection::encode_req(OpCode::QuerySqlFields, req)
+            };
+            updated_rows(&self.conn.send_and_read(msg).await?)
+        };
+        intercepted.finish(deleted.await)
+    }
+
+    /// Runs the SQL fields query and returns the stream of its rows, see `Cache::query_sql_fields`.
+    /// The cursor of the stream which is dropped early is closed, see `SqlPages`
+    pub async fn query_sql_fields(
+        &self,
+        query: &SqlFieldsQuery,
+    ) -> IgniteResult<impl Stream<Item = IgniteResult<SqlRow>>> {
+        let intercepted = self.intercept("query_sql_fields", None)?;
+        let msg = Connection::encode_req(OpCode::QuerySqlFields, query.req(self.id));
+        let resp = intercepted.finish(self.conn.send_and_read(msg).await)?;
+        Ok(sql_rows(self.conn.clone(), resp))
+    }
+
+    /// Returns all the keys of the cache, see `Cache::keys`
+    pub async fn keys(&self) -> IgniteResult<Vec<K>> {
+        use futures_util::StreamExt;
+        let msg = Connection::encode_req(
+            OpCode::CacheGetConfiguration,
+            CacheGetConfigReq::from(&self._name),
+        );
+        let config: CacheGetConfigResp = self.conn.send_and_read(msg).await?;
+        if let Some(query) = keys_statement(&self._name, &config.config) {
+            let query = SqlFieldsQuery::new(&query);
+            let mut rows = Box::pin(self.query_sql_fields(&query).await?);
+            let mut keys = Vec::new();
+            while let Some(row) = rows.next().await {
+                keys.push(sql_key(row?)?);
+            }
+            return Ok(keys);
+        }
+        let opaque = AsyncCache::<K, OpaqueObject> {
+            id: self.id,
+            _name: self._name.clone(),
+            conn: self.conn.clone(),
+            expiry_policy: self.expiry_policy,
+            keep_binary: true,
+            interceptors: self.interceptors.clone(),
+            k_phantom: PhantomData,
+            v_phantom: PhantomData,
+        };
+        let mut entries = Box::pin(opaque.entries(DFLT_PAGE_SIZE));
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next().await {
+            keys.push(entry?.0);
+        }
+        Ok(keys)
+    }
+
+    /// Returns the value of the key, or computes and puts it if the key is absent,
+    /// see `Cache::get_or_insert_with`
+    pub async fn get_or_insert_with<Q>(
+        &self,
+        key: &Q,
+        compute: impl FnOnce() -> V,
+    ) -> IgniteResult<V>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        if let Some(value) = self.get(key).await? {
+            return Ok(value);
+        }
+        let value = compute();
+        Ok(self
+            .get_and_put_if_absent(key, &value)
+            .await?
+            .unwrap_or(value))
+    }
+
+    /// Updates the present value optimistically, see `Cache::update`
+    pub async fn update<Q>(
+        &self,
+        key: &Q,
+        mut update: impl FnMut(&V) -> V,
+    ) -> IgniteResult<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let current = match self.get(key).await? {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let new = update(&current);
+            if self.replace_if_equals(key, &current, &new).await? {
+                return Ok(Some(new));
+            }
+        }
+        Err(update_conflict(&self._name))
+    }
+
+    /// Returns the entry of the key, for the read-modify-write without the lost updates,
+    /// see `Cache::entry`
+    pub fn entry(&self, key: K) -> AsyncCacheEntry<'_, K, V> {
+        AsyncCacheEntry::new(self, key)
+    }
+
+    /// Returns the handle of the same cache which has only the read operations,
+    /// so the code it is given to could not change the data
+    pub fn read_only(&self) -> AsyncReadOnlyCache<K, V> {
+        AsyncReadOnlyCache {
+            cache: self.clone(),
+        }
+    }
+
+    /// Returns the stream of the entries of the cache, which fetches them by pages of the size
+    /// with a scan query, like `Cache::entries`. Yields the error if a page could not be fetched,
+    /// and ends after it. The cursor of the stream which is dropped early is closed, see `AsyncScan`
+    pub fn entries(&self, page_size: i32) -> impl Stream<Item = IgniteResult<(K, V)>> {
+        let scan = AsyncScan {
+            cache: self.clone(),
+            page_size,
+            page: Vec::new().into_iter(),
+            started: false,
+            cursor_id: None,
+        };
+        stream::unfold(Some(scan), |scan| async move {
+            let mut scan = scan?;
+            loop {
+                // the nodes do not keep NULLs, so the entries have both the key and the value
+                if let Some(entry) = scan.page.next() {
+                    if let (Some(key), Some(value)) = entry {
+                        return Some((Ok((key, value)), Some(scan)));
+                    }
+                    continue;
+                }
+                let page = match (scan.started, scan.cursor_id) {
+                    (false, _) => {
+                        let msg = Connection::encode_req(
+                            OpCode::QueryScan,
+                            CacheReq::QueryScan::<K, V>(scan.cache.header(), scan.page_size, -1),
+                        );
+                        scan.cache
+                            .conn
+                            .send_and_read(msg)
+                            .await
+                            .map(|resp: QueryScanResp<K, V>| (resp.val, resp.cursor_id))
+                    }
+                    (true, Some(cursor_id)) => scan
+                        .cache
+                        .conn
+                        .cursor_page::<QueryCursorPageResp<K, V>>(cursor_id)
+                        .await
+                        .map(|resp| (resp.val, resp.more.then_some(cursor_id))),
+                    (true, None) => return None,
+                };
+                match page {
+                    Ok((entries, cursor_id)) => {
+                        scan.page = entries.into_iter();
+                        scan.cursor_id = cursor_id;
+                        scan.started = true;
+                    }
+                    Err(err) => return Some((Err(err), None)),
+                }
+            }
+        })
+    }
+
+    fn header(&self) -> CacheHeader {
+        CacheHeader {
+            id: self.id,
+            expiry_policy: self.expiry_policy,
+            keep_binary: self.keep_binary,
+        }
+    }
+
+    /// Returns the values of the keys which are in the cache, see `Cache::get_all`
+    pub async fn get_all(&self, keys: &[K]) -> IgniteResult<HashMap<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        let intercepted = self.intercept("get_all", None)?;
+        let values = async {
+            let mut values = HashMap::new();
+            for chunk in self.conn.batch_limits.split(keys, K::size) {
+                let msg = Connection::encode_req(
+                    OpCode::CacheGetAll,
+                    CacheReq::GetAll::<K, V>(self.header(), chunk),
+                );
+                values.extend(found_entries(self.conn.send_and_read(msg).await?));
+            }
+            Ok(values)
+        };
+        intercepted.finish(values.await)
+    }
+
+    /// Puts the entries, owned or borrowed, writing them into the request as they are drained
+    /// from the iterator, see `Cache::put_all`
+    pub async fn put_all<KB, VB>(
+        &self,
+        pairs: impl IntoIterator<Item = (KB, VB)>,
+    ) -> IgniteResult<()>
+    where
+        KB: Borrow<K>,
+        VB: Borrow<V>,
+    {
+        self.put_pairs("put_all", stream::iter(pairs)).await
+    }
+
+    /// Puts the entries of the stream, like the rows of a query of another database,
+    /// writing them into the request as they come, see `Cache::put_all`
+    pub async fn put_all_stream<KB, VB>(
+        &self,
+        pairs: impl Stream<Item = (KB, VB)>,
+    ) -> IgniteResult<()>
+    where
+        KB: Borrow<K>,
+        VB: Borrow<V>,
+    {
+        self.put_pairs("put_all_stream", pairs).await
+    }
+
+    async fn put_pairs<KB, VB>(
+        &self,
+        name: &'static str,
+        pairs: impl Stream<Item = (KB, VB)>,
+    ) -> IgniteResult<()>
+    where
+        KB: Borrow<K>,
+        VB: Borrow<V>,
+    {
+        use futures_util::StreamExt;
+        let intercepted = self.intercept(name, None)?;
+        let put = async {
+            let mut buffer = PairsBuffer::new(self.conn.batch_limits);
+            let mut pairs = Box::pin(pairs);
+            while let Some((key, value)) = pairs.next().await {
+                if let Some(batch) = buffer.push(key.borrow(), value.borrow())? {
+                    self.send_pairs(&batch).await?;
+                }
+            }
+            let rest = buffer.take();
+            if rest.count > 0 {
+                self.send_pairs(&rest).await?;
+            }
+            Ok(())
+        };
+        intercepted.finish(put.await)
+    }
+
+    async fn send_pairs(&self, pairs: &EncodedPairs) -> IgniteResult<()> {
+        let msg = Connection::encode_req(
+            OpCode::CachePutAll,
+            CacheReq::PutAll::<K, V>(self.header(), pairs),
+        );
+        self.conn.send(msg).await
+    }
+
+    /// Removes the keys, see `Cache::remove_keys`
+    pub async fn remove_keys(&self, keys: &[K]) -> IgniteResult<()> {
+        let intercepted = self.intercept("remove_keys", None)?;
+        let removed = async {
+            for chunk in self.conn.batch_limits.split(keys, K::size) {
+                let msg = Connection::encode_req(
+                    OpCode::CacheRemoveKeys,
+                    CacheReq::RemoveKeys::<K, V>(self.header(), chunk),
+                );
+                self.conn.send(msg).await?;
+            }
+            Ok(())
+        };
+        intercepted.finish(removed.await)
+    }
+
+    fn intercept(
+        &self,
+        name: &'static str,
+        key: Option<&dyn WritableType>,
+    ) -> IgniteResult<Intercepted<'_>> {
+        Intercepted::start(&self.interceptors, name, &self._name, key)
+    }
+
+    cache_ops!(async_cache_op);
+}
+
+/// Handle of the cache which has only the read operations of `AsyncCache`,
+/// see `AsyncCache::read_only`
+pub struct AsyncReadOnlyCache<K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: AsyncCache<K, V>,
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Clone
+    for AsyncReadOnlyCache<K, V>
+{
+    fn clone(&self) -> Self {
+        self.cache.read_only()
+    }
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> AsyncReadOnlyCache<K, V> {
+    pub async fn get<Q>(&self, key: &Q) -> IgniteResult<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        self.cache.get(key).await
+    }
+
+    /// Returns the values of the keys which are in the cache, see `AsyncCache::get_all`
+    pub async fn get_all(&self, keys: &[K]) -> IgniteResult<HashMap<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        self.cache.get_all(keys).await
+    }
+
+    pub async fn contains_key<Q>(&self, key: &Q) -> IgniteResult<bool>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        self.cache.contains_key(key).await
+    }
+
+    pub async fn contains_keys(&self, keys: &[K]) -> IgniteResult<bool> {
+        self.cache.contains_keys(keys).await
+    }
+
+    pub async fn get_size(&self) -> IgniteResult<i64> {
+        self.cache.get_size().await
+    }
+
+    pub async fn get_size_peek_mode(&self, mode: CachePeekMode) -> IgniteResult<i64> {
+        self.cache.get_size_peek_mode(mode).await
+    }
+
+    pub async fn get_size_peek_modes(&self, modes: Vec<CachePeekMode>) -> IgniteResult<i64> {
+        self.cache.get_size_peek_modes(modes).await
+    }
+
+    pub async fn query_scan(&self, page_size: i32) -> IgniteResult<Vec<(Option<K>, Option<V>)>> {
+        self.cache.query_scan(page_size).await
+    }
+
+    /// Returns the stream of the entries of the cache, see `AsyncCache::entries`
+    pub fn entries(&self, page_size: i32) -> impl Stream<Item = IgniteResult<(K, V)>> {
+        self.cache.entries(page_size)
+    }
+}
+
+/// State of the stream of `AsyncCache::entries`. The cursor is closed when it is dropped
+/// before the last page, or with the client if the close could not be sent before
+struct AsyncScan<K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: AsyncCache<K, V>,
+    page_size: i32,
+    page: std::vec::IntoIter<(Option<K>, Option<V>)>,
+    started: bool,
+    /// Set while the node keeps more pages
+    cursor_id: Option<i64>,
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Drop for AsyncScan<K, V> {
+    fn drop(&mut self) {
+        if let Some(cursor_id) = self.cursor_id {
+            self.cache.conn.drop_cursor(cursor_id);
+        }
+    }
+}
+
+/// State of the stream of the rows of an SQL fields query. The cursor is closed when it is dropped
+/// before the last page, or with the client if the close could not be sent before
+struct SqlPages {
+    conn: Arc<AsyncConnection>,
+    columns: Arc<Vec<String>>,
+    page: std::vec::IntoIter<Vec<AnyValue>>,
+    /// Set while the node keeps more pages
+    cursor_id: Option<i64>,
+}
+
+impl Drop for SqlPages {
+    fn drop(&mut self) {
+        if let Some(cursor_id) = self.cursor_id {
+            self.conn.drop_cursor(cursor_id);
+        }
+    }
+}
+
+/// Returns the stream of the rows of the first page, then of the pages which the node keeps
+fn sql_rows(
+    conn: Arc<AsyncConnection>,
+    first: SqlFieldsResp,
+) -> impl Stream<Item = IgniteResult<SqlRow>> {
+    let pages = SqlPages {
+        conn,
+        columns: Arc::new(first.columns),
+        page: first.rows.into_iter(),
+        cursor_id: first.cursor_id,
+    };
+    stream::unfold(Some(pages), |pages| async move {
+        let mut pages = pages?;
+        loop {
+            if let Some(values) = pages.page.next() {
+                let row = SqlRow::new(pages.columns.clone(), values);
+                return Some((Ok(row), Some(pages)));
+            }
+            let cursor_id = pages.cursor_id?;
+            let columns = pages.columns.len();
+            let read_page = |reader: &mut Cursor<&[u8]>| SqlFieldsPageResp::read(reader, columns);
+            match pages.conn.cursor_page_with(cursor_id, read_page).await {
+                Ok(page) => {
+                    pages.cursor_id = Some(cursor_id).filter(|_| page.more);
+                    pages.page = page.rows.into_iter();
+                }
+                Err(err) => return Some((Err(err), None)),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::QueryEntity;
+    use crate::interceptor::CacheOperation;
+    use crate::protocol::cache_config::write_query_entities;
+    use crate::protocol::{write_bool, write_i16, write_i32, write_i64};
+    use crate::Versioned;
+    use std::time::Duration;
+
+    /// Replies with the prepared responses and keeps the requests
+    struct ScriptedTransport {
+        requests: Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+        responses: std::sync::Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl ScriptedTransport {
+        fn new(responses: Vec<Vec<u8>>) -> ScriptedTransport {
+            ScriptedTransport {
+                requests: Arc::default(),
+                responses: std::sync::Mutex::new(responses),
+            }
+        }
+    }
+
+    impl AsyncTransport for ScriptedTransport {
+        fn send<'a>(&'a self, msg: &'a [u8]) -> TransportFuture<'a, ()> {
+            self.requests.lock().unwrap().push(msg.to_vec());
+            Box::pin(async { Ok(()) })
+        }
+
+        fn receive(&self) -> TransportFuture<'_, Vec<u8>> {
+            let resp = self.responses.lock().unwrap().remove(0);
+            Box::pin(async move { Ok(resp) })
+        }
+    }
+
+    /// Returns the client over the transport which replies with the handshake, then with
+    /// the responses, and the requests which the transport keeps
+    async fn scripted_client(
+        responses: Vec<Vec<u8>>,
+        conf: ClientConfig,
+    ) -> (AsyncClient, Arc<std::sync::Mutex<Vec<Vec<u8>>>>) {
+        let mut script = vec![vec![1, 101, 101]];
+        script.extend(responses);
+        let transport = ScriptedTransport::new(script);
+        let requests = transport.requests.clone();
+        let client = new_async_client_with_transport(transport, conf)
+            .await
+            .unwrap();
+        (client, requests)
+    }
+
+    /// Response with the payload which is not a typed value
+    fn raw_resp(request_id: i64, payload: &[u8]) -> Vec<u8> {
+        let mut resp = vec![];
+        write_i64(&mut resp, request_id).unwrap();
+        write_i16(&mut resp, 0).unwrap(); // flags
+        resp.extend_from_slice(payload);
+        resp
+    }
+
+    /// Response of the configuration, with the properties in the order the node sends them
+    fn config_resp(request_id: i64, config: &CacheConfiguration) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let w = &mut bytes;
+        write_i32(w, config.atomicity_mode.clone() as i32).unwrap();
+        write_i32(w, config.num_backup).unwrap();
+        write_i32(w, config.cache_mode.clone() as i32).unwrap();
+        write_bool(w, config.copy_on_read).unwrap();
+        config.data_region_name.write(w).unwrap();
+        write_bool(w, config.eager_ttl).unwrap();
+        write_bool(w, config.statistics_enabled).unwrap();
+        config.group_name.write(w).unwrap();
+        write_i64(w, config.default_lock_timeout_ms).unwrap();
+        write_i32(w, config.max_concurrent_async_operations).unwrap();
+        write_i32(w, config.max_query_iterators).unwrap();
+        config.name.write(w).unwrap();
+        write_bool(w, config.onheap_cache_enabled).unwrap();
+        write_i32(w, config.partition_loss_policy.clone() as i32).unwrap();
+        write_i32(w, config.query_detail_metrics_size).unwrap();
+        write_i32(w, config.query_parallelism).unwrap();
+        write_bool(w, config.read_from_backup).unwrap();
+        write_i32(w, config.rebalance_batch_size).unwrap();
+        write_i64(w, config.rebalance_batches_prefetch_count).unwrap();
+        write_i64(w, config.rebalance_delay_ms).unwrap();
+        write_i32(w, config.rebalance_mode.clone() as i32).unwrap();
+        write_i32(w, config.rebalance_order).unwrap();
+        write_i64(w, config.rebalance_throttle_ms).unwrap();
+        write_i64(w, config.rebalance_timeout_ms).unwrap();
+        write_bool(w, config.sql_escape_all).unwrap();
+        write_i32(w, config.sql_index_max_size).unwrap();
+        config.sql_schema.write(w).unwrap();
+        write_i32(w, config.write_synchronization_mode.clone() as i32).unwrap();
+        write_i32(w, 0).unwrap(); // cache key configurations
+        let entities = config.query_entities.as_deref().unwrap_or_default();
+        write_query_entities(w, entities).unwrap();
+        let mut payload = Vec::new();
+        write_i32(&mut payload, bytes.len() as i32).unwrap();
+        payload.extend(bytes);
+        raw_resp(request_id, &payload)
+    }
+
+    fn success_resp(request_id: i64, payload: impl WritableType) -> Vec<u8> {
+        let mut resp = vec![];
+        write_i64(&mut resp, request_id).unwrap();
+        write_i16(&mut resp, 0).unwrap(); // flags
+        payload.write(&mut resp).unwrap();
+        resp
+    }
+
+    #[tokio::test]
+    async fn test_async_get() {
+        let (client, requests) = scripted_client(
+            vec![success_resp(1, "value".to_owned())],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, String>("test");
+        // futures of the client could be spawned on multi-threaded runtimes
+        let value = tokio::spawn(async move { cache.get("key").await.unwrap() });
+        assert_eq!(value.await.unwrap(), Some("value".to_owned()));
+        let get = &requests.lock().unwrap()[1];
+        assert_eq!(get[4..6], (OpCode::CacheGet as i16).to_le_bytes());
+        assert_eq!(get[6..14], 1i64.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_async_get_all() {
+        let mut get_all = raw_resp(1, &2i32.to_le_bytes()); // found entries
+        for entry in &["a", "1", "b", "2"] {
+            entry.to_string().write(&mut get_all).unwrap();
+        }
+        let (client, _) = scripted_client(vec![get_all], ClientConfig::new("any")).await;
+        let cache = client.cache::<String, String>("test");
+        let keys = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let values = cache.get_all(&keys).await.unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["a"], "1");
+        assert_eq!(values["b"], "2");
+    }
+
+    #[tokio::test]
+    async fn test_async_get_all_batches() {
+        let mut first = raw_resp(1, &2i32.to_le_bytes());
+        for entry in &["a", "1", "b", "2"] {
+            entry.to_string().write(&mut first).unwrap();
+        }
+        let mut second = raw_resp(2, &1i32.to_le_bytes());
+        for entry in &["c", "3"] {
+            entry.to_string().write(&mut second).unwrap();
+        }
+        let mut conf = ClientConfig::new("any");
+        conf.batch_max_entries = Some(2);
+        let (client, requests) = scripted_client(vec![first, second], conf).await;
+        let cache = client.cache::<String, String>("test");
+        let keys = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let values = cache.get_all(&keys).await.unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["c"], "3");
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1][19..23], 2i32.to_le_bytes());
+        assert_eq!(requests[2][19..23], 1i32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_async_entry() {
+        let (client, requests) = scripted_client(
+            vec![
+                success_resp(1, 1i32),
+                raw_resp(2, &[0]), // changed by another client
+                success_resp(3, 5i32),
+                raw_resp(4, &[1]),
+                raw_resp(5, &[101]), // absent
+                raw_resp(6, &[101]),
+            ],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, i32>("test");
+        let hits = cache.entry("a".to_owned()).and_modify(|hits| hits + 1);
+        assert_eq!(hits.or_insert(1).await.unwrap(), 6);
+        let hits = cache.entry("b".to_owned()).and_modify(|hits| hits + 1);
+        assert_eq!(hits.or_insert(1).await.unwrap(), 1);
+        let op_codes: Vec<_> = requests.lock().unwrap()[1..]
+            .iter()
+            .map(|req| i16::from_le_bytes([req[4], req[5]]))
+            .collect();
+        let expected: Vec<_> = [
+            OpCode::CacheGet,
+            OpCode::CacheReplaceIfEquals,
+            OpCode::CacheGet,
+            OpCode::CacheReplaceIfEquals,
+            OpCode::CacheGet,
+            OpCode::CacheGetAndPutIfAbsent,
+        ]
+        .iter()
+        .map(|code| *code as i16)
+        .collect();
+        assert_eq!(op_codes, expected);
+    }
+
+    #[tokio::test]
+    async fn test_async_get_or_insert_with() {
+        let (client, _) = scripted_client(
+            vec![
+                success_resp(1, 7i32),
+                raw_resp(2, &[101]),   // absent
+                success_resp(3, 8i32), // put by another client meanwhile
+            ],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, i32>("test");
+        let value = cache.get_or_insert_with("a", || panic!("the key is present"));
+        assert_eq!(value.await.unwrap(), 7);
+        // the value of the winner is returned, not the computed one
+        assert_eq!(cache.get_or_insert_with("b", || 1).await.unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_async_update() {
+        let mut script = Vec::new();
+        let mut request_id = 1;
+        let mut current = Versioned::new(1i32);
+        for _ in 0..UPDATE_ATTEMPTS {
+            // the value is changed by another client each time
+            script.push(success_resp(request_id, current.clone()));
+            script.push(raw_resp(request_id + 1, &[0]));
+            current = current.next(current.value);
+            request_id += 2;
+        }
+        script.push(success_resp(request_id, current.clone()));
+        script.push(raw_resp(request_id + 1, &[1]));
+        let (client, requests) = scripted_client(script, ClientConfig::new("any")).await;
+        let cache = client.cache::<String, Versioned<i32>>("test");
+        let err = cache
+            .update("a", |total| total.next(total.value + 1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+        let total = cache.update("a", |total| total.next(total.value + 1)).await;
+        assert_eq!(total.unwrap(), Some(current.next(2)));
+        let requests = requests.lock().unwrap();
+        let replace = requests.last().unwrap();
+        assert_eq!(
+            replace[4..6],
+            (OpCode::CacheReplaceIfEquals as i16).to_le_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_put_all() {
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[])], ClientConfig::new("any")).await;
+        let cache = client.cache::<i32, String>("test");
+        let entries: std::collections::BTreeMap<i32, String> =
+            (0..3).map(|i| (i, i.to_string())).collect();
+        cache.put_all(&entries).await.unwrap();
+        let put_all = &requests.lock().unwrap()[1];
+        assert_eq!(put_all[4..6], (OpCode::CachePutAll as i16).to_le_bytes());
+        assert_eq!(put_all[19..23], 3i32.to_le_bytes());
+        let mut expected = Vec::new();
+        for (key, value) in &entries {
+            key.write(&mut expected).unwrap();
+            value.write(&mut expected).unwrap();
+        }
+        assert_eq!(put_all[23..], expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_async_put_all_stream() {
+        let mut conf = ClientConfig::new("any");
+        conf.batch_max_entries = Some(2);
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[]), raw_resp(2, &[])], conf).await;
+        let cache = client.cache::<i32, String>("test");
+        let rows = stream::iter((0..3).map(|i| (i, i.to_string())));
+        cache.put_all_stream(rows).await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1][19..23], 2i32.to_le_bytes());
+        assert_eq!(requests[2][19..23], 1i32.to_le_bytes());
+        let mut expected = Vec::new();
+        2.write(&mut expected).unwrap();
+        "2".write(&mut expected).unwrap();
+        assert_eq!(requests[2][23..], expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_async_query_sql_fields() {
+        use futures_util::StreamExt;
+        // cursor id, the columns, a row, more rows
+        let mut first = Vec::new();
+        write_i64(&mut first, 7).unwrap();
+        write_i32(&mut first, 2).unwrap();
+        "NAME".write(&mut first).unwrap();
+        "AGE".write(&mut first).unwrap();
+        write_i32(&mut first, 1).unwrap();
+        "Ann".write(&mut first).unwrap();
+        31i32.write(&mut first).unwrap();
+        first.push(1);
+        // a row with NULL age, no more rows
+        let mut page = Vec::new();
+        write_i32(&mut page, 1).unwrap();
+        "Bob".write(&mut page).unwrap();
+        page.push(101);
+        page.push(0);
+        let (client, requests) = scripted_client(
+            vec![raw_resp(1, &first), raw_resp(2, &page)],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<i64, String>("people");
+        let query = SqlFieldsQuery::new("SELECT name, age FROM Person WHERE age > ?").arg(30);
+        // the futures and the stream could be sent to another thread
+        let rows = tokio::spawn(async move {
+            let rows = cache.query_sql_fields(&query).await.unwrap();
+            rows.map(Result::unwrap).collect::<Vec<SqlRow>>().await
+        });
+        let rows = rows.await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].columns(), ["NAME", "AGE"]);
+        assert_eq!(rows[0].get::<String>(0).unwrap().unwrap(), "Ann");
+        assert_eq!(rows[0].get_by_name::<i32>("age").unwrap(), Some(31));
+        assert_eq!(rows[1].get::<i32>(1).unwrap(), None);
+        assert!(rows[1].get::<i32>(2).is_err());
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[1][4..6],
+            (OpCode::QuerySqlFields as i16).to_le_bytes()
+        );
+        assert_eq!(
+            requests[2][4..6],
+            (OpCode::QuerySqlFieldsCursorGetPage as i16).to_le_bytes()
+        );
+        assert_eq!(requests[2][14..22], 7i64.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_async_query_sql_fields_dropped() {
+        use futures_util::StreamExt;
+        // cursor id, the column, a row, more rows
+        let mut first = Vec::new();
+        write_i64(&mut first, 7).unwrap();
+        write_i32(&mut first, 1).unwrap();
+        "NAME".write(&mut first).unwrap();
+        write_i32(&mut first, 1).unwrap();
+        "Ann".write(&mut first).unwrap();
+        first.push(1);
+        let (client, requests) = scripted_client(
+            vec![raw_resp(1, &first), raw_resp(2, &[]), raw_resp(3, &[])],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<i64, String>("people");
+        let query = SqlFieldsQuery::new("SELECT name FROM Person");
+        let mut rows = Box::pin(cache.query_sql_fields(&query).await.unwrap());
+        assert!(rows.next().await.unwrap().is_ok());
+        drop(rows);
+        // the cursor of the dropped stream is closed before the next request
+        client.heartbeat().await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[2][4..6],
+            (OpCode::ResourceClose as i16).to_le_bytes()
+        );
+        assert_eq!(requests[2][14..22], 7i64.to_le_bytes());
+        assert!(client.conn.cursors.lock().unwrap().is_empty());
+    }
+
+    /// Records the operations with their key bytes, rejects `remove_key`
+    #[derive(Default)]
+    struct Recorder {
+        ops: std::sync::Mutex<Vec<(String, Vec<u8>, bool)>>,
+    }
+
+    impl CacheInterceptor for Recorder {
+        fn before(&self, op: &CacheOperation) -> IgniteResult<()> {
+            if op.name == "remove_key" {
+                return Err(IgniteError::from("read-only"));
+            }
+            Ok(())
+        }
+
+        fn after(&self, op: &CacheOperation, error: Option<&IgniteError>, _duration: Duration) {
+            let key = op.key.map(<[u8]>::to_vec).unwrap_or_default();
+            let name = format!("{}:{}", op.cache, op.name);
+            self.ops.lock().unwrap().push((name, key, error.is_some()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_interceptors() {
+        let recorder = Arc::new(Recorder::default());
+        let mut conf = ClientConfig::new("any");
+        conf.interceptors.push(recorder.clone());
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[]), raw_resp(2, &[0])], conf).await;
+        let cache = client.cache::<i32, String>("test");
+        cache.put(&7, &"seven".to_owned()).await.unwrap();
+        cache.put_all(vec![(1, "one".to_owned())]).await.unwrap();
+        let err = cache.remove_key(&7).await.unwrap_err();
+        assert_eq!(err.to_string(), "read-only");
+        // the rejected operation is not sent
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        let mut key = Vec::new();
+        7.write(&mut key).unwrap();
+        assert_eq!(
+            *recorder.ops.lock().unwrap(),
+            vec![
+                ("test:put".to_owned(), key, false),
+                ("test:put_all".to_owned(), Vec::new(), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_contains_keys() {
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[0])], ClientConfig::new("any")).await;
+        let cache = client.cache::<i32, String>("test");
+        assert!(!cache.contains_keys(&[1, 2]).await.unwrap());
+        let contains_keys = &requests.lock().unwrap()[1];
+        assert_eq!(
+            contains_keys[4..6],
+            (OpCode::CacheContainsKeys as i16).to_le_bytes()
+        );
+        assert_eq!(contains_keys[19..23], 2i32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_async_remove_keys() {
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[])], ClientConfig::new("any")).await;
+        let cache = client.cache::<i32, String>("test");
+        cache.remove_keys(&[1, 2, 3]).await.unwrap();
+        let remove_keys = &requests.lock().unwrap()[1];
+        assert_eq!(
+            remove_keys[4..6],
+            (OpCode::CacheRemoveKeys as i16).to_le_bytes()
+        );
+        assert_eq!(remove_keys[19..23], 3i32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_async_entries() {
+        use futures_util::StreamExt;
+        let mut scan = raw_resp(1, &7i64.to_le_bytes()); // cursor
+        write_i32(&mut scan, 2).unwrap();
+        for entry in &[1, 10, 2, 20] {
+            entry.write(&mut scan).unwrap();
+        }
+        scan.push(1); // more
+        let mut page = raw_resp(2, &1i32.to_le_bytes());
+        for entry in &[3, 30] {
+            entry.write(&mut page).unwrap();
+        }
+        page.push(0);
+        let (client, requests) = scripted_client(vec![scan, page], ClientConfig::new("any")).await;
+        let cache = client.cache::<i32, i32>("test");
+        let entries: Vec<_> = cache.entries(2).collect().await;
+        let entries: Vec<_> = entries.into_iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![(1, 10), (2, 20), (3, 30)]);
+        let page = &requests.lock().unwrap()[2];
+        assert_eq!(
+            page[4..6],
+            (OpCode::QueryScanCursorGetPage as i16).to_le_bytes()
+        );
+        assert_eq!(page[14..22], 7i64.to_le_bytes());
+        // the node has closed the cursor after the last page
+        assert!(client.conn.cursors.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_async_entries_dropped() {
+        use futures_util::StreamExt;
+        let mut scan = raw_resp(1, &7i64.to_le_bytes()); // cursor
+        write_i32(&mut scan, 1).unwrap();
+        for entry in &[1, 10] {
+            entry.write(&mut scan).unwrap();
+        }
+        scan.push(1); // more
+        let (client, requests) = scripted_client(
+            vec![scan, raw_resp(2, &[]), success_resp(3, 5)],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<i32, i32>("test");
+        let mut entries = Box::pin(cache.entries(1));
+        assert_eq!(entries.next().await.unwrap().unwrap(), (1, 10));
+        drop(entries);
+        // the cursor of the dropped stream is closed before the next request
+        assert_eq!(cache.get(&5).await.unwrap(), Some(5));
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[2][4..6],
+            (OpCode::ResourceClose as i16).to_le_bytes()
+        );
+        assert_eq!(requests[2][14..22], 7i64.to_le_bytes());
+        assert!(client.conn.cursors.lock().unwrap().is_empty());
+        assert!(client.conn.dropped_cursors.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_async_keys() {
+        let mut scan = raw_resp(2, &7i64.to_le_bytes()); // cursor
+        write_i32(&mut scan, 2).unwrap();
+        for (key, value) in &[(1, "a"), (2, "b")] {
+            key.write(&mut scan).unwrap();
+            value.to_string().write(&mut scan).unwrap();
+        }
+        scan.push(0);
+        let config = config_resp(1, &CacheConfiguration::new("test"));
+        let (client, requests) =
+            scripted_client(vec![config, scan], ClientConfig::new("any")).await;
+        // the values are skipped, so they are not read as the value type
+        let cache = client.cache::<i32, i64>("test");
+        assert_eq!(cache.keys().await.unwrap(), vec![1, 2]);
+        let scan = &requests.lock().unwrap()[2];
+        assert_eq!(scan[4..6], (OpCode::QueryScan as i16).to_le_bytes());
+        assert_eq!(scan[18] & 0x01, 0x01); // keep binary
+    }
+
+    #[tokio::test]
+    async fn test_async_keys_sql() {
+        let mut config = CacheConfiguration::new("people");
+        config.query_entities = Some(vec![QueryEntity::new("java.lang.Integer", "Person")]);
+        // cursor id, the column, the rows, no more rows
+        let mut select = Vec::new();
+        write_i64(&mut select, 7).unwrap();
+        write_i32(&mut select, 1).unwrap();
+        "_KEY".write(&mut select).unwrap();
+        write_i32(&mut select, 2).unwrap();
+        1i32.write(&mut select).unwrap();
+        2i32.write(&mut select).unwrap();
+        select.push(0);
+        let (client, requests) = scripted_client(
+            vec![config_resp(1, &config), raw_resp(2, &select)],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<i32, i64>("people");
+        assert_eq!(cache.keys().await.unwrap(), vec![1, 2]);
+        let requests = requests.lock().unwrap();
+        let select = &requests[2];
+        assert_eq!(select[4..6], (OpCode::QuerySqlFields as i16).to_le_bytes());
+        let mut query = &select[28..];
+        assert_eq!(
+            String::read(&mut query).unwrap().unwrap(),
+            "SELECT _key FROM Person"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_read_only() {
+        let (client, requests) =
+            scripted_client(vec![success_resp(1, 5i32)], ClientConfig::new("any")).await;
+        let cache = client.cache::<i32, i32>("test").read_only();
+        assert_eq!(cache.clone().get(&1).await.unwrap(), Some(5));
+        let get = &requests.lock().unwrap()[1];
+        assert_eq!(get[4..6], (OpCode::CacheGet as i16).to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_async_create_cache_with_config() {
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[])], ClientConfig::new("any")).await;
+        let invalid = CacheConfiguration {
+            num_backup: -1,
+            ..CacheConfiguration::new("orders")
+        };
+        let err = client
+            .create_cache_with_config::<i32, String>(&invalid)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidConfig);
+        assert_eq!(requests.lock().unwrap().len(), 1); // the handshake only
+
+        let config = CacheConfiguration::builder("orders")
+            .num_backup(1)
+            .build()
+            .unwrap();
+        let cache = client
+            .get_or_create_cache_with_config::<i32, String>(&config)
+            .await
+            .unwrap();
+        assert_eq!(cache._name, "orders");
+        let create = &requests.lock().unwrap()[1];
+        assert_eq!(
+            create[4..6],
+            (OpCode::CacheGetOrCreateWithConfiguration as i16).to_le_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_destroy_cache() {
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[])], ClientConfig::new("any")).await;
+        client.destroy_cache("tenant-1").await.unwrap();
+        let destroy = &requests.lock().unwrap()[1];
+        assert_eq!(destroy[4..6], (OpCode::CacheDestroy as i16).to_le_bytes());
+        assert_eq!(
+            destroy[14..18],
+            string_to_java_hashcode("tenant-1").to_le_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_with_expiry_policy() {
+        let (client, requests) = scripted_client(
+            vec![raw_resp(1, &[]), raw_resp(2, &[])],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<i32, i32>("sessions");
+        let sessions = cache
+            .with_expiry_policy(ExpiryPolicy::created(std::time::Duration::from_secs(60)))
+            .unwrap();
+        sessions.put(&1, &1).await.unwrap();
+        sessions.clone().put(&2, &2).await.unwrap();
+        for put in &requests.lock().unwrap()[1..] {
+            assert_eq!(put[18], 0x04);
+            assert_eq!(put[19..27], 60_000i64.to_le_bytes());
+            assert_eq!(put[27..35], (-2i64).to_le_bytes());
+            assert_eq!(put[35..43], (-2i64).to_le_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_with_keep_binary() {
+        let person = crate::binary::BinaryObjectBuilder::new("Person")
+            .set("name", &"Ann".to_owned())
+            .unwrap()
+            .build();
+        let (client, requests) = scripted_client(
+            vec![success_resp(1, person.clone())],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<i32, String>("people").with_keep_binary();
+        let value = cache.get(&1).await.unwrap().unwrap();
+        assert_eq!(value, person);
+        assert_eq!(value.get::<String>("name").unwrap().as_deref(), Some("Ann"));
+        assert_eq!(requests.lock().unwrap()[1][18], 0x01);
+    }
+
+    #[tokio::test]
+    async fn test_async_get_and_put() {
+        let (client, requests) = scripted_client(
+            vec![
+                success_resp(1, "old".to_owned()),
+                raw_resp(2, &[101]), // null
+            ],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, String>("test");
+        let previous = cache.get_and_put("key", &"new".to_owned()).await.unwrap();
+        assert_eq!(previous.as_deref(), Some("old"));
+        let previous = cache.get_and_put("other", &"new".to_owned()).await.unwrap();
+        assert_eq!(previous, None);
+        let get_and_put = &requests.lock().unwrap()[1];
+        assert_eq!(
+            get_and_put[4..6],
+            (OpCode::CacheGetAndPut as i16).to_le_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_replace_if_equals() {
+        let (client, requests) =
+            scripted_client(vec![raw_resp(1, &[1])], ClientConfig::new("any")).await;
+        let cache = client.cache::<String, i64>("counters");
+        assert!(cache.replace_if_equals("hits", &1, &2).await.unwrap());
+        let replace = &requests.lock().unwrap()[1];
+        assert_eq!(
+            replace[4..6],
+            (OpCode::CacheReplaceIfEquals as i16).to_le_bytes()
+        );
+        let mut expected = Vec::new();
+        for value in &[1i64, 2] {
+            value.write(&mut expected).unwrap();
+        }
+        // the key, then the expected and the new values
+        assert!(replace.ends_with(&expected));
+    }
+
+    #[tokio::test]
+    async fn test_responses_out_of_order() {
+        let (client, _) = scripted_client(
+            vec![
+                success_resp(2, "second".to_owned()),
+                success_resp(1, "first".to_owned()),
+            ],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, String>("test");
+        let (first, second) = futures_util::future::join(cache.get("a"), cache.get("b")).await;
+        assert_eq!(first.unwrap(), Some("first".to_owned()));
+        assert_eq!(second.unwrap(), Some("second".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request() {
+        let (client, _) = scripted_client(
+            vec![
+                success_resp(1, "abandoned".to_owned()),
+                success_resp(2, "value".to_owned()),
+            ],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, String>("test");
+        // the request is sent, but its future is dropped while it waits for the response
+        let receiving = client.conn.receiving.lock().await;
+        let mut cancelled = Box::pin(cache.get("a"));
+        let waker = futures_util::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        drop(cancelled);
+        drop(receiving);
+        // the response of the cancelled request is skipped
+        assert_eq!(cache.get("b").await.unwrap(), Some("value".to_owned()));
+        let responses = client.conn.responses.lock().unwrap();
+        assert!(responses.received.is_empty() && responses.abandoned.is_empty());
+    }
+
+    /// Scripted transport which messages come in two parts, so a read could be dropped
+    /// in the middle of a message
+    struct SplitTransport(ScriptedTransport);
+
+    impl AsyncTransport for SplitTransport {
+        fn send<'a>(&'a self, msg: &'a [u8]) -> TransportFuture<'a, ()> {
+            self.0.send(msg)
+        }
+
+        fn receive(&self) -> TransportFuture<'_, Vec<u8>> {
+            let resp = self.0.responses.lock().unwrap().remove(0);
+            let mut parts = vec![resp[..1].to_vec(), resp[1..].to_vec()].into_iter();
+            let mut msg = Vec::new();
+            Box::pin(futures_util::future::poll_fn(move |cx| {
+                match parts.next() {
+                    Some(part) if parts.len() > 0 => {
+                        msg.extend(part);
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                    Some(part) => {
+                        msg.extend(part);
+                        Poll::Ready(Ok(std::mem::take(&mut msg)))
+                    }
+                    None => panic!("the message is read twice"),
+                }
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_cancelled_while_reading() {
+        let transport = SplitTransport(ScriptedTransport::new(vec![
+            vec![1, 101, 101],
+            success_resp(1, "abandoned".to_owned()),
+            success_resp(2, "value".to_owned()),
+        ]));
+        let client = new_async_client_with_transport(transport, ClientConfig::new("any"))
+            .await
+            .unwrap();
+        let cache = client.cache::<String, String>("test");
+        // the future is dropped when the first part of its response is read
+        let mut cancelled = Box::pin(cache.get("a"));
+        let waker = futures_util::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        drop(cancelled);
+        assert!(client.conn.receiving.lock().await.is_some());
+        // the next request completes the read and skips the response of the cancelled one
+        assert_eq!(cache.get("b").await.unwrap(), Some("value".to_owned()));
+        assert!(!client.conn.broken.load(Ordering::Acquire));
+        let responses = client.conn.responses.lock().unwrap();
+        assert!(responses.received.is_empty() && responses.abandoned.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight_requests() {
+        let mut conf = ClientConfig::new("any");
+        conf.max_in_flight_requests = Some(1);
+        let (client, requests) = scripted_client(
+            vec![
+                success_resp(1, "a".to_owned()),
+                success_resp(2, "b".to_owned()),
+                success_resp(3, "c".to_owned()),
+            ],
+            conf,
+        )
+        .await;
+        let cache = client.cache::<String, String>("test");
+        let receiving = client.conn.receiving.lock().await;
+        let mut gets: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|key| Box::pin(cache.get(*key)))
+            .collect();
+        let waker = futures_util::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        for get in gets.iter_mut().rev() {
+            assert!(get.as_mut().poll(&mut cx).is_pending());
+        }
+        // only the first request is sent, the other ones wait for it
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        drop(receiving);
+        let values = futures_util::future::join_all(gets).await;
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|value| value.unwrap().unwrap())
+            .collect();
+        // the requests are sent in the order they came in, the last one first
+        assert_eq!(values, ["c", "b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_async_close() {
+        let (client, requests) = scripted_client(
+            vec![success_resp(1, "value".to_owned())],
+            ClientConfig::new("any"),
+        )
+        .await;
+        let cache = client.cache::<String, String>("test");
+        let receiving = client.conn.receiving.lock().await;
+        let mut in_flight = Box::pin(cache.get("a"));
+        let mut close = Box::pin(client.close());
+        let waker = futures_util::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(in_flight.as_mut().poll(&mut cx).is_pending());
+        // the request in flight is awaited, the new ones fail
+        assert!(close.as_mut().poll(&mut cx).is_pending());
+        let err = cache.get("b").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Closed);
+        drop(receiving);
+        assert_eq!(in_flight.await.unwrap(), Some("value".to_owned()));
+        close.await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_tcp() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        async fn read_msg(stream: &mut TcpStream) -> Vec<u8> {
+            let len = stream.read_i32_le().await.unwrap();
+            let mut msg = vec![0u8; len as usize];
+            stream.read_exact(&mut msg).await.unwrap();
+            msg
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_msg(&mut stream).await;
+            stream.write_all(&[3, 0, 0, 0, 1, 101, 101]).await.unwrap(); // handshake succeeded
+            let req = read_msg(&mut stream).await;
+            let request_id = read_i64(&mut &req[2..10]).unwrap();
+            let mut resp = success_resp(request_id, true);
+            let mut msg = vec![];
+            write_i32(&mut msg, resp.len() as i32).unwrap();
+            msg.append(&mut resp);
+            stream.write_all(&msg).await.unwrap();
+        });
+
+        let client = new_async_client(ClientConfig::new(&addr)).await.unwrap();
+        let cache = client.cache::<i32, String>("test");
+        assert!(cache.contains_key(&1).await.unwrap());
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+//! Strategies which choose the node for the requests which are not routed by their keys
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::utils::random;
+
+/// Node which a request could be sent to
+pub struct NodeLoad<'a> {
+    /// Address of the node as `host:port`, as configured or discovered
+    pub addr: &'a str,
+    /// Requests of the client which are sent to the node and wait for their responses
+    pub in_flight: usize,
+    /// Idle connections to the node in the pool
+    pub idle: usize,
+}
+
+/// Chooses the node for a request. Consulted for each request which is not sent to the primary
+/// node of its key. A new connection is opened to the chosen node if the pool has a free slot,
+/// otherwise any idle connection is used
+pub trait LoadBalancer: Send + Sync {
+    /// Returns the index of the chosen node. `nodes` is never empty
+    fn choose(&self, nodes: &[NodeLoad]) -> usize;
+}
+
+/// Takes the nodes in turn. The default strategy
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl LoadBalancer for RoundRobin {
+    fn choose(&self, nodes: &[NodeLoad]) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % nodes.len()
+    }
+}
+
+/// Takes a random node
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomNode;
+
+impl LoadBalancer for RandomNode {
+    fn choose(&self, nodes: &[NodeLoad]) -> usize {
+        (random() % nodes.len() as u64) as usize
+    }
+}
+
+/// Takes the node with the fewest requests in flight, a random one of them if there are several
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeastOutstanding;
+
+impl LoadBalancer for LeastOutstanding {
+    fn choose(&self, nodes: &[NodeLoad]) -> usize {
+        let least = nodes.iter().map(|node| node.in_flight).min().unwrap_or(0);
+        let candidates: Vec<usize> = (0..nodes.len())
+            .filter(|i| nodes[*i].in_flight == least)
+            .collect();
+        candidates[(random() % candidates.len() as u64) as usize]
+    }
+}
+
+/// Takes a random node with the probability proportional to its weight, for the nodes
+/// of different capacity. The nodes which have no weight set get the default one
+#[derive(Debug, Clone)]
+pub struct Weighted {
+    /// Weights by node addresses
+    pub weights: HashMap<String, u32>,
+    pub default_weight: u32,
+}
+
+impl Weighted {
+    pub fn new(weights: HashMap<String, u32>) -> Weighted {
+        Weighted {
+            weights,
+            default_weight: 1,
+        }
+    }
+
+    fn weight(&self, node: &NodeLoad) -> u64 {
+        *self.weights.get(node.addr).unwrap_or(&self.default_weight) as u64
+    }
+}
+
+impl LoadBalancer for Weighted {
+    fn choose(&self, nodes: &[NodeLoad]) -> usize {
+        let total: u64 = nodes.iter().map(|node| self.weight(node)).sum();
+        if total == 0 {
+            return 0;
+        }
+        let mut point = random() % total;
+        for (i, node) in nodes.iter().enumerate() {
+            match point.checked_sub(self.weight(node)) {
+                Some(rest) => point = rest,
+                None => return i,
+            }
+        }
+        nodes.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes<'a>(addrs: &[&'a str], in_flight: &[usize]) -> Vec<NodeLoad<'a>> {
+        addrs
+            .iter()
+            .zip(in_flight)
+            .map(|(addr, in_flight)| NodeLoad {
+                addr,
+                in_flight: *in_flight,
+                idle: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_balancers() {
+        let nodes = nodes(&["a:1", "b:1", "c:1"], &[2, 0, 1]);
+        let round_robin = RoundRobin::default();
+        let chosen: Vec<usize> = (0..4).map(|_| round_robin.choose(&nodes)).collect();
+        assert_eq!(chosen, [0, 1, 2, 0]);
+        assert_eq!(LeastOutstanding.choose(&nodes), 1);
+        let weighted = Weighted {
+            weights: vec![("b:1".to_owned(), 5)].into_iter().collect(),
+            default_weight: 0,
+        };
+        assert!((0..10).all(|_| weighted.choose(&nodes) == 1));
+    }
+}
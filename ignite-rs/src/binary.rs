@@ -0,0 +1,565 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Cursor, Read, Write};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::complex_obj::read_complex_obj;
+use crate::protocol::{
+    handles, read_i64, read_u8, write_i32, write_u16, write_u8, TypeCode, COMPLEX_OBJ_HEADER_LEN,
+    FLAG_HAS_SCHEMA, FLAG_USER_TYPE, HAS_RAW_DATA,
+};
+use crate::utils::{bytes_to_java_hashcode, get_schema_id_from_field_ids, string_to_java_hashcode};
+use crate::{ReadableType, TypeInfo, UserType, WritableType};
+
+/// Binary type metadata. Describes the fields and schemas of a complex object type.
+/// The metadata which the clients fetch is cached for the whole process, whichever
+/// cluster the client is connected to
+/// https://ignite.apache.org/docs/latest/binary-client-protocol/binary-type-metadata
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinaryType {
+    pub type_id: i32,
+    pub type_name: String,
+    pub affinity_key_field_name: Option<String>,
+    pub fields: Vec<BinaryField>,
+    pub is_enum: bool,
+    /// Enum values names and ordinals. Empty if the type is not an enum
+    pub enum_values: Vec<(String, i32)>,
+    pub schemas: Vec<BinarySchema>,
+}
+
+/// Field of a binary type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinaryField {
+    pub name: String,
+    /// Type code of the field's values
+    pub type_code: i32,
+    pub field_id: i32,
+}
+
+/// Schema of a binary type. Defines the order of fields in objects written with it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinarySchema {
+    pub schema_id: i32,
+    pub field_ids: Vec<i32>,
+}
+
+impl BinaryType {
+    /// Returns ordered field ids of the schema, if this type has such schema
+    pub fn schema(&self, schema_id: i32) -> Option<&[i32]> {
+        self.schemas
+            .iter()
+            .find(|s| s.schema_id == schema_id)
+            .map(|s| s.field_ids.as_slice())
+    }
+
+    /// Returns the field with such id, if any
+    pub fn field(&self, field_id: i32) -> Option<&BinaryField> {
+        self.fields.iter().find(|f| f.field_id == field_id)
+    }
+}
+
+/// Binary types metadata known by this process.
+/// Types are identified by IDs which are derived from the type names, so metadata
+/// could be shared by all the clients. The clients of different clusters share it too,
+/// so the same type should have compatible schemas in all of them
+fn registry() -> &'static RwLock<HashMap<i32, Arc<BinaryType>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<i32, Arc<BinaryType>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Returns cached metadata of the type
+pub(crate) fn get_binary_type(type_id: i32) -> Option<Arc<BinaryType>> {
+    registry().read().unwrap().get(&type_id).cloned()
+}
+
+/// Caches metadata of the type. The fields and schemas of previously cached metadata
+/// are merged into it, like the nodes do, so the objects of the older schemas are still read
+pub(crate) fn register_binary_type(mut binary_type: BinaryType) {
+    let mut registry = registry().write().unwrap();
+    if let Some(cached) = registry.get(&binary_type.type_id) {
+        for field in &cached.fields {
+            if binary_type.field(field.field_id).is_none() {
+                binary_type.fields.push(field.clone());
+            }
+        }
+        for schema in &cached.schemas {
+            if binary_type.schema(schema.schema_id).is_none() {
+                binary_type.schemas.push(schema.clone());
+            }
+        }
+    }
+    registry.insert(binary_type.type_id, Arc::new(binary_type));
+}
+
+/// Platform of a type name mapping. Type names are registered per platform,
+/// since the same type could have different names in Java and .NET
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlatformId {
+    Java = 0,
+    DotNet = 1,
+}
+
+/// Complex object of any type. Holds the serialized object and reads its fields on demand,
+/// so no Rust struct is required to read it. Written back as is.
+/// Field names are resolved to field ids the same way Java does: the name is lowercased and hashed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynamicBinaryObject {
+    type_id: i32,
+    schema_id: i32,
+    hash_code: i32,
+    field_ids: Vec<i32>,
+    data: Vec<u8>,
+}
+
+impl DynamicBinaryObject {
+    /// Type id of the object
+    pub fn type_id(&self) -> i32 {
+        self.type_id
+    }
+
+    /// Schema id of the object
+    pub fn schema_id(&self) -> i32 {
+        self.schema_id
+    }
+
+    /// Hash code of the object. Used by Ignite to compare keys
+    pub fn hash_code(&self) -> i32 {
+        self.hash_code
+    }
+
+    /// Type name of the object, if the type metadata was fetched from the cluster
+    pub fn type_name(&self) -> Option<String> {
+        get_binary_type(self.type_id).map(|t| t.type_name.clone())
+    }
+
+    /// Ids of the object's fields in schema order
+    pub fn field_ids(&self) -> &[i32] {
+        &self.field_ids
+    }
+
+    /// Names of the object's fields in schema order. Only the fields that are described
+    /// by the fetched type metadata are returned
+    pub fn field_names(&self) -> Vec<String> {
+        match get_binary_type(self.type_id) {
+            None => vec![],
+            Some(binary_type) => self
+                .field_ids
+                .iter()
+                .filter_map(|id| binary_type.field(*id).map(|f| f.name.clone()))
+                .collect(),
+        }
+    }
+
+    /// Returns true if the object has such field
+    pub fn has_field(&self, name: &str) -> bool {
+        self.field_ids.contains(&field_id(name))
+    }
+
+    /// Reads the value of the field. Returns None if the value is NULL or there is no such field
+    pub fn get<T: ReadableType>(&self, name: &str) -> IgniteResult<Option<T>> {
+        self.get_by_id(field_id(name))
+    }
+
+    /// Reads the value of the field with provided id
+    pub fn get_by_id<T: ReadableType>(&self, field_id: i32) -> IgniteResult<Option<T>> {
+        handles::read_bytes(self.data.clone(), 0, |reader| {
+            read_u8(reader)?; // type code
+            read_complex_obj(reader, |obj| obj.read_field::<T>(field_id))
+        })
+    }
+
+    /// Bytes of the serialized object, including header
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Field id as calculated by Java for the field name
+fn field_id(name: &str) -> i32 {
+    string_to_java_hashcode(name.to_lowercase().as_str())
+}
+
+impl WritableType for DynamicBinaryObject {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.data)
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// The binary objects of any type are not checked
+impl TypeInfo for DynamicBinaryObject {
+    fn cluster_type_name() -> Option<String> {
+        None
+    }
+}
+
+impl ReadableType for DynamicBinaryObject {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::ComplexObj => read_complex_obj(reader, |obj| {
+                Ok(Some(DynamicBinaryObject {
+                    type_id: obj.type_id,
+                    schema_id: obj.schema_id,
+                    hash_code: obj.hash_code,
+                    field_ids: obj.field_ids(),
+                    data: obj.data().to_vec(),
+                }))
+            }),
+            _ => Err(IgniteError::from("Complex object expected!")),
+        }
+    }
+}
+
+/// Ignite's internal unique id (org.apache.ignite.lang.IgniteUuid). Used by Ignite for ids of
+/// transactions, compute jobs, etc. Unlike java.util.UUID, it is a complex object
+/// with a global id (UUID of the node) and a local id in its raw data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IgniteUuid {
+    /// most significant 64 bits of the UUID come first
+    pub global_id: u128,
+    pub local_id: i64,
+}
+
+impl IgniteUuid {
+    const TYPE_NAME: &'static str = "org.apache.ignite.lang.IgniteUuid";
+    const RAW_DATA_LEN: usize = 8 + 8 + 8; // global id, local id
+
+    /// Type id of org.apache.ignite.lang.IgniteUuid
+    pub fn type_id() -> i32 {
+        field_id(IgniteUuid::TYPE_NAME)
+    }
+
+    fn raw_data(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(IgniteUuid::RAW_DATA_LEN);
+        raw.extend_from_slice(&((self.global_id >> 64) as i64).to_le_bytes()); // most significant bits
+        raw.extend_from_slice(&(self.global_id as i64).to_le_bytes()); // least significant bits
+        raw.extend_from_slice(&self.local_id.to_le_bytes());
+        raw
+    }
+}
+
+impl UserType for IgniteUuid {
+    fn user_type_id() -> i32 {
+        IgniteUuid::type_id()
+    }
+}
+
+impl WritableType for IgniteUuid {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let raw = self.raw_data();
+        write_u8(writer, TypeCode::ComplexObj as u8)?;
+        write_u8(writer, 1)?; // version. always 1
+        write_u16(writer, FLAG_USER_TYPE | HAS_RAW_DATA)?; // flags. no schema
+        write_i32(writer, IgniteUuid::type_id())?;
+        write_i32(writer, bytes_to_java_hashcode(&raw))?; // hash code
+        write_i32(writer, COMPLEX_OBJ_HEADER_LEN + raw.len() as i32)?; // length. including header
+        write_i32(writer, 0)?; // schema id. no schema
+        write_i32(writer, COMPLEX_OBJ_HEADER_LEN)?; // raw data offset
+        writer.write_all(&raw)
+    }
+
+    fn size(&self) -> usize {
+        COMPLEX_OBJ_HEADER_LEN as usize + IgniteUuid::RAW_DATA_LEN
+    }
+}
+
+impl ReadableType for IgniteUuid {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::ComplexObj => read_complex_obj(reader, |obj| {
+                if obj.type_id != IgniteUuid::type_id() {
+                    return Err(IgniteError::from(
+                        format!(
+                            "Type ID mismatch: expected {}, got {}",
+                            IgniteUuid::type_id(),
+                            obj.type_id
+                        )
+                        .as_str(),
+                    ));
+                }
+                let mut raw = Cursor::new(
+                    obj.raw_data()
+                        .ok_or_else(|| IgniteError::from("IgniteUuid has no raw data!"))?,
+                );
+                let most = read_i64(&mut raw)? as u64 as u128;
+                let least = read_i64(&mut raw)? as u64 as u128;
+                let local_id = read_i64(&mut raw)?;
+                Ok(Some(IgniteUuid {
+                    global_id: most << 64 | least,
+                    local_id,
+                }))
+            }),
+            _ => Err(IgniteError::from("Complex object expected!")),
+        }
+    }
+}
+
+/// Builds complex objects at runtime, field by field. Could be used when the schema is not known
+/// at compile time. The schema and the footer are generated from the fields that were set.
+///
+/// let mut builder = BinaryObjectBuilder::new("Person");
+/// builder.set("name", &"John".to_string())?.set("age", &42i32)?;
+/// let person: DynamicBinaryObject = builder.build();
+///
+/// The type metadata returned by binary_type() could be registered in the cluster,
+/// so other platforms are able to read the fields by names
+pub struct BinaryObjectBuilder {
+    type_name: String,
+    type_id: i32,
+    affinity_key_field_name: Option<String>,
+    /// field names and serialized values, in insertion order
+    fields: Vec<(String, Vec<u8>)>,
+}
+
+impl BinaryObjectBuilder {
+    /// Creates a builder for the type. Type id is calculated from the type name the same way Java does
+    pub fn new(type_name: &str) -> BinaryObjectBuilder {
+        BinaryObjectBuilder::with_type_id(type_name, field_id(type_name))
+    }
+
+    /// Creates a builder for the type with explicitly provided type id
+    pub fn with_type_id(type_name: &str, type_id: i32) -> BinaryObjectBuilder {
+        BinaryObjectBuilder {
+            type_name: type_name.to_owned(),
+            type_id,
+            affinity_key_field_name: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Sets the affinity key field name to be reported in the type metadata
+    pub fn affinity_key(&mut self, field_name: &str) -> &mut Self {
+        self.affinity_key_field_name = Some(field_name.to_owned());
+        self
+    }
+
+    /// Sets the value of the field. Replaces the previous value of the field, if any
+    pub fn set<T: WritableType>(&mut self, name: &str, value: &T) -> IgniteResult<&mut Self> {
+        let mut bytes = Vec::with_capacity(value.size());
+        value.write(&mut bytes)?;
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some(field) => field.1 = bytes,
+            None => self.fields.push((name.to_owned(), bytes)),
+        }
+        Ok(self)
+    }
+
+    /// Removes the field
+    pub fn remove(&mut self, name: &str) -> &mut Self {
+        self.fields.retain(|(n, _)| n != name);
+        self
+    }
+
+    fn field_ids(&self) -> Vec<i32> {
+        self.fields.iter().map(|(name, _)| field_id(name)).collect()
+    }
+
+    /// Serializes the object
+    pub fn build(&self) -> DynamicBinaryObject {
+        let field_ids = self.field_ids();
+        let schema_id = get_schema_id_from_field_ids(&field_ids);
+
+        let mut values: Vec<u8> = Vec::new();
+        let mut schema: Vec<u8> = Vec::new();
+        for ((_, bytes), id) in self.fields.iter().zip(field_ids.iter()) {
+            schema.extend_from_slice(&id.to_le_bytes()); // field id
+            schema.extend_from_slice(&(COMPLEX_OBJ_HEADER_LEN + values.len() as i32).to_le_bytes()); // field offset
+            values.extend_from_slice(bytes);
+        }
+        let hash_code = bytes_to_java_hashcode(&values);
+        let len = COMPLEX_OBJ_HEADER_LEN as usize + values.len() + schema.len();
+
+        let mut data: Vec<u8> = Vec::with_capacity(len);
+        data.push(TypeCode::ComplexObj as u8);
+        data.push(1); // version. always 1
+        data.extend_from_slice(&(FLAG_USER_TYPE | FLAG_HAS_SCHEMA).to_le_bytes()); // flags
+        data.extend_from_slice(&self.type_id.to_le_bytes());
+        data.extend_from_slice(&hash_code.to_le_bytes());
+        data.extend_from_slice(&(len as i32).to_le_bytes()); // length. including header
+        data.extend_from_slice(&schema_id.to_le_bytes());
+        data.extend_from_slice(&(COMPLEX_OBJ_HEADER_LEN + values.len() as i32).to_le_bytes()); // schema offset
+        data.extend(values);
+        data.extend(schema);
+
+        DynamicBinaryObject {
+            type_id: self.type_id,
+            schema_id,
+            hash_code,
+            field_ids,
+            data,
+        }
+    }
+
+    /// Type metadata which describes objects created by this builder
+    pub fn binary_type(&self) -> BinaryType {
+        let field_ids = self.field_ids();
+        BinaryType {
+            type_id: self.type_id,
+            type_name: self.type_name.clone(),
+            affinity_key_field_name: self.affinity_key_field_name.clone(),
+            fields: self
+                .fields
+                .iter()
+                .zip(field_ids.iter())
+                .map(|((name, bytes), id)| BinaryField {
+                    name: name.clone(),
+                    type_code: bytes[0] as i32,
+                    field_id: *id,
+                })
+                .collect(),
+            is_enum: false,
+            enum_values: vec![],
+            schemas: vec![BinarySchema {
+                schema_id: get_schema_id_from_field_ids(&field_ids),
+                field_ids,
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::complex_obj::{
+        ComplexObject, ComplexObjectSchema, IgniteField, IgniteType, IgniteValue,
+    };
+    use crate::{Dedup, ObjArray};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_dynamic_obj_fields() {
+        let obj = ComplexObject {
+            schema: Arc::new(ComplexObjectSchema {
+                type_name: "Person".to_string(),
+                fields: vec![
+                    IgniteField {
+                        name: "NAME".to_string(),
+                        r#type: IgniteType::String,
+                    },
+                    IgniteField {
+                        name: "AGE".to_string(),
+                        r#type: IgniteType::Int,
+                    },
+                ],
+            }),
+            values: vec![IgniteValue::String("bob".to_string()), IgniteValue::Int(42)],
+        };
+        let mut bytes = vec![];
+        obj.write(&mut bytes).unwrap();
+
+        let actual = DynamicBinaryObject::read(&mut Cursor::new(bytes.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(actual.type_id(), string_to_java_hashcode("person"));
+        assert_eq!(actual.field_ids().len(), 2);
+        assert!(actual.has_field("name"));
+        assert!(!actual.has_field("address"));
+        assert_eq!(
+            actual.get::<String>("name").unwrap(),
+            Some("bob".to_string())
+        );
+        assert_eq!(actual.get::<i32>("Age").unwrap(), Some(42));
+        assert_eq!(actual.get::<i32>("address").unwrap(), None);
+
+        let mut written = vec![];
+        actual.write(&mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn test_builder_matches_complex_obj() {
+        let obj = ComplexObject {
+            schema: Arc::new(ComplexObjectSchema {
+                type_name: "Person".to_string(),
+                fields: vec![
+                    IgniteField {
+                        name: "NAME".to_string(),
+                        r#type: IgniteType::String,
+                    },
+                    IgniteField {
+                        name: "AGE".to_string(),
+                        r#type: IgniteType::Int,
+                    },
+                ],
+            }),
+            values: vec![IgniteValue::String("bob".to_string()), IgniteValue::Int(42)],
+        };
+        let mut expected = vec![];
+        obj.write(&mut expected).unwrap();
+
+        let mut builder = BinaryObjectBuilder::new("Person");
+        builder
+            .set("NAME", &"alice".to_string())
+            .unwrap()
+            .set("AGE", &42i32)
+            .unwrap()
+            .set("NAME", &"bob".to_string())
+            .unwrap();
+        let actual = builder.build();
+        assert_eq!(actual.bytes(), expected.as_slice());
+
+        let binary_type = builder.binary_type();
+        assert_eq!(binary_type.type_id, actual.type_id());
+        assert_eq!(
+            binary_type.schema(actual.schema_id()),
+            Some(actual.field_ids())
+        );
+        assert_eq!(binary_type.fields[1].type_code, TypeCode::Int as i32);
+    }
+
+    #[test]
+    fn test_ignite_uuid_round_trip() {
+        let expected = IgniteUuid {
+            global_id: 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210,
+            local_id: -42,
+        };
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        let actual = IgniteUuid::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn test_typed_obj_array() {
+        let uuid = IgniteUuid {
+            global_id: 1,
+            local_id: 2,
+        };
+        let expected = ObjArray(vec![Some(uuid), None]);
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        assert_eq!(bytes[1..5], IgniteUuid::type_id().to_le_bytes()); // component type id
+        let actual = ObjArray::<IgniteUuid>::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(expected));
+
+        let mut bytes = vec![];
+        vec![Some(uuid)].write(&mut bytes).unwrap(); // Object[]
+        assert!(ObjArray::<IgniteUuid>::read(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_dedup_write() {
+        let uuid = IgniteUuid {
+            global_id: 1,
+            local_id: 2,
+        };
+        let items = vec![Some(uuid), None, Some(uuid)];
+        let mut plain = vec![];
+        items.write(&mut plain).unwrap();
+        let value = Dedup(items.clone());
+        let mut bytes = vec![];
+        value.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), value.size());
+        assert_eq!(bytes.len(), plain.len() - uuid.size() + 5); // handle instead of the copy
+        let actual = Vec::<Option<IgniteUuid>>::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(items));
+    }
+}
@@ -1,7 +1,8 @@
 use std::convert::TryFrom;
 
 use crate::api::key_value::{
-    CacheBoolResp, CacheDataObjectResp, CachePairsResp, CacheReq, CacheSizeResp, QueryScanResp,
+    BatchLimits, CacheBoolResp, CacheDataObjectResp, CacheHeader, CachePairsResp, CacheReq,
+    CacheSizeResp, EncodedPairs, PairsBuffer, QueryCursorPageResp, QueryScanResp,
 };
 use crate::cache::AtomicityMode::{Atomic, Transactional};
 use crate::cache::CacheMode::{Local, Partitioned, Replicated};
@@ -11,15 +12,34 @@ use crate::cache::PartitionLossPolicy::{
 };
 use crate::cache::RebalanceMode::Async;
 use crate::cache::WriteSynchronizationMode::{FullAsync, FullSync, PrimarySync};
-use crate::error::{IgniteError, IgniteResult};
+use crate::error::{ErrorKind, IgniteError, IgniteResult};
+use crate::features::Features;
 
+use crate::api::binary::{BinaryTypeGetReq, BinaryTypeGetResp};
+use crate::api::cache_config::{CacheGetConfigReq, CacheGetConfigResp};
+use crate::api::continuous::ContinuousQueryReq;
+use crate::api::sql::{SqlFieldsReq, SqlFieldsResp, StatementType};
 use crate::api::OpCode;
-use crate::connection::Connection;
-use crate::{ReadableType, WritableType};
+use crate::binary::{BinaryType, DynamicBinaryObject};
+use crate::connection::{with_op_timeout, with_pinned, Connection, Pinned, Subscription};
+use crate::entry::CacheEntry;
+use crate::interceptor::{CacheInterceptor, Intercepted};
+use crate::near_cache::NearCache;
+use crate::prefixed::PrefixedCache;
+use crate::sql::{SqlFieldsCursor, SqlFieldsQuery, SqlRow};
+use crate::streamer::CacheWriter;
+use crate::{AnyValue, OpaqueObject, ReadableType, TypeInfo, WritableType, WriteableReq};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AtomicityMode {
     Transactional = 0,
     Atomic = 1,
@@ -37,7 +57,7 @@ impl TryFrom<i32> for AtomicityMode {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum CacheMode {
     Local = 0,
     Replicated = 1,
@@ -57,7 +77,7 @@ impl TryFrom<i32> for CacheMode {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PartitionLossPolicy {
     ReadOnlySafe = 0,
     ReadOnlyAll = 1,
@@ -81,7 +101,7 @@ impl TryFrom<i32> for PartitionLossPolicy {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum RebalanceMode {
     Sync = 0,
     Async = 1,
@@ -101,7 +121,7 @@ impl TryFrom<i32> for RebalanceMode {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum WriteSynchronizationMode {
     FullSync = 0,
     FullAsync = 1,
@@ -135,7 +155,7 @@ impl Into<u8> for CachePeekMode {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum IndexType {
     Sorted = 0,
     Fulltext = 1,
@@ -155,15 +175,17 @@ impl TryFrom<u8> for IndexType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CacheConfiguration {
     pub atomicity_mode: AtomicityMode,
     pub num_backup: i32,
     pub cache_mode: CacheMode,
     pub copy_on_read: bool,
+    /// Data region of the nodes which keeps the entries, the default region if None
     pub data_region_name: Option<String>,
     pub eager_ttl: bool,
     pub statistics_enabled: bool,
+    /// Cache group which shares the partitions and their metadata with the cache
     pub group_name: Option<String>,
     pub default_lock_timeout_ms: i64,
     pub max_concurrent_async_operations: i32,
@@ -187,6 +209,9 @@ pub struct CacheConfiguration {
     pub write_synchronization_mode: WriteSynchronizationMode,
     pub cache_key_configurations: Option<Vec<CacheKeyConfiguration>>,
     pub query_entities: Option<Vec<QueryEntity>>,
+    /// Time to live of the entries, the entries never expire if None.
+    /// Needs protocol v1.6.0 or newer
+    pub expiry_policy: Option<ExpiryPolicy>,
 }
 
 impl CacheConfiguration {
@@ -229,17 +254,313 @@ impl CacheConfiguration {
             write_synchronization_mode: WriteSynchronizationMode::PrimarySync,
             cache_key_configurations: None,
             query_entities: None,
+            expiry_policy: None,
+        }
+    }
+
+    /// Returns the builder of the configuration with the default properties
+    ///
+    /// let config = CacheConfiguration::builder("orders")
+    ///     .cache_mode(CacheMode::Partitioned)
+    ///     .num_backup(1)
+    ///     .write_synchronization_mode(WriteSynchronizationMode::FullSync)
+    ///     .build()?;
+    pub fn builder(name: &str) -> CacheConfigurationBuilder {
+        CacheConfigurationBuilder {
+            config: CacheConfiguration::new(name),
+        }
+    }
+
+    /// Checks the properties, returns `ErrorKind::InvalidConfig` error which lists all the problems
+    pub fn validate(&self) -> IgniteResult<()> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("name is empty".to_owned());
+        }
+        if self.num_backup < 0 {
+            errors.push(format!("num_backup ({}) is negative", self.num_backup));
+        }
+        if self.data_region_name.as_deref() == Some("") {
+            errors.push("data_region_name is empty".to_owned());
+        }
+        if self.group_name.as_deref() == Some("") {
+            errors.push("group_name is empty".to_owned());
+        }
+        if self.query_parallelism < 1 {
+            errors.push(format!(
+                "query_parallelism ({}) is less than 1",
+                self.query_parallelism
+            ));
+        }
+        if self.rebalance_batch_size <= 0 {
+            errors.push(format!(
+                "rebalance_batch_size ({}) is not positive",
+                self.rebalance_batch_size
+            ));
+        }
+        for key_config in self.cache_key_configurations.iter().flatten() {
+            if key_config.type_name.is_empty() || key_config.affinity_key_field_name.is_empty() {
+                errors.push("cache key configuration has empty names".to_owned());
+            }
+        }
+        for entity in self.query_entities.iter().flatten() {
+            if entity.key_type.is_empty() || entity.value_type.is_empty() {
+                errors.push("query entity has empty type names".to_owned());
+            }
+            for index in &entity.query_indexes {
+                let unknown = index.fields.iter().find(|(name, _)| {
+                    name != &entity.key_field
+                        && name != &entity.value_field
+                        && !entity.query_fields.iter().any(|field| &field.name == name)
+                });
+                if let Some((name, _)) = unknown {
+                    errors.push(format!(
+                        "index {:?} has unknown field {:?}",
+                        index.index_name, name
+                    ));
+                }
+            }
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(IgniteError::new(
+            ErrorKind::InvalidConfig,
+            format!(
+                "Invalid configuration of cache {:?}! {}",
+                self.name,
+                errors.join("; ")
+            ),
+        ))
+    }
+
+    /// Validates the configuration before the cache is created by it,
+    /// the node should support all the properties which are set
+    pub(crate) fn check(&self, features: &Features) -> IgniteResult<()> {
+        self.validate()?;
+        match self.expiry_policy {
+            Some(_) => check_expiry_policy(&self.name, features),
+            None => Ok(()),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+/// Fails if the node could not apply the expiry policies, which need protocol v1.6.0
+pub(crate) fn check_expiry_policy(name: &str, features: &Features) -> IgniteResult<()> {
+    if features.expiry_policy() {
+        return Ok(());
+    }
+    let (major, minor, patch) = features.protocol_version();
+    Err(IgniteError::new(
+        ErrorKind::InvalidConfig,
+        format!(
+            "Invalid configuration of cache {:?}! expiry_policy needs protocol v1.6.0, the node supports v{}.{}.{}",
+            name, major, minor, patch
+        ),
+    ))
+}
+
+/// Checks that the nodes apply the template to the cache, as they match the templates by the names
+/// of the caches: `orders*` to the caches whose names start with `orders`, `orders` to itself
+pub(crate) fn check_template(template: &str, name: &str) -> IgniteResult<()> {
+    let matches = match template.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == template,
+    };
+    match matches {
+        true => Ok(()),
+        false => Err(IgniteError::new(
+            ErrorKind::InvalidConfig,
+            format!(
+                "Invalid configuration of cache {:?}! template {:?} does not match the name",
+                name, template
+            ),
+        )),
+    }
+}
+
+/// Sets the function of the builder for each of the fields of `CacheConfiguration`
+macro_rules! cache_config_setters {
+    ($($(#[$attr:meta])* $field:ident: $ty:ty;)*) => {
+        $(
+            $(#[$attr])*
+            pub fn $field(mut self, $field: $ty) -> CacheConfigurationBuilder {
+                self.config.$field = $field.into();
+                self
+            }
+        )*
+    };
+}
+
+/// Builds `CacheConfiguration` and checks it, see `CacheConfiguration::builder`
+pub struct CacheConfigurationBuilder {
+    config: CacheConfiguration,
+}
+
+impl CacheConfigurationBuilder {
+    cache_config_setters! {
+        cache_mode: CacheMode;
+        atomicity_mode: AtomicityMode;
+        /// Number of the backup copies of each partition
+        num_backup: i32;
+        write_synchronization_mode: WriteSynchronizationMode;
+        partition_loss_policy: PartitionLossPolicy;
+        copy_on_read: bool;
+        read_from_backup: bool;
+        eager_ttl: bool;
+        statistics_enabled: bool;
+        /// Whether the entries are cached on the heap of the nodes too, the only eviction
+        /// the protocol configures: the eviction policies are set on the nodes
+        onheap_cache_enabled: bool;
+        /// Data region of the nodes which keeps the entries, as it is configured on the nodes
+        data_region_name: String;
+        /// Cache group of the cache. The caches of a group share the partitions, so they should
+        /// have the same affinity, cache mode, backups and data region
+        group_name: String;
+        max_concurrent_async_operations: i32;
+        max_query_iterators: i32;
+        query_detail_metrics_size: i32;
+        query_parallelism: i32;
+        sql_escape_all: bool;
+        sql_index_max_size: i32;
+        sql_schema: String;
+        rebalance_mode: RebalanceMode;
+        rebalance_batch_size: i32;
+        rebalance_batches_prefetch_count: i64;
+        rebalance_order: i32;
+        expiry_policy: ExpiryPolicy;
+    }
+
+    pub fn default_lock_timeout(mut self, timeout: Duration) -> CacheConfigurationBuilder {
+        self.config.default_lock_timeout_ms = timeout.as_millis() as i64;
+        self
+    }
+
+    /// Delay of the rebalancing after a node joins or leaves the cluster
+    pub fn rebalance_delay(mut self, delay: Duration) -> CacheConfigurationBuilder {
+        self.config.rebalance_delay_ms = delay.as_millis() as i64;
+        self
+    }
+
+    /// Pause between the rebalancing batches, so the rebalancing does not slow down the load
+    pub fn rebalance_throttle(mut self, throttle: Duration) -> CacheConfigurationBuilder {
+        self.config.rebalance_throttle_ms = throttle.as_millis() as i64;
+        self
+    }
+
+    pub fn rebalance_timeout(mut self, timeout: Duration) -> CacheConfigurationBuilder {
+        self.config.rebalance_timeout_ms = timeout.as_millis() as i64;
+        self
+    }
+
+    /// Collocates the entries of the key type by its field, see `CacheKeyConfiguration`
+    pub fn affinity_key(mut self, type_name: &str, field_name: &str) -> CacheConfigurationBuilder {
+        self.config
+            .cache_key_configurations
+            .get_or_insert_with(Vec::new)
+            .push(CacheKeyConfiguration {
+                type_name: type_name.to_owned(),
+                affinity_key_field_name: field_name.to_owned(),
+            });
+        self
+    }
+
+    pub fn query_entity(mut self, entity: QueryEntity) -> CacheConfigurationBuilder {
+        self.config
+            .query_entities
+            .get_or_insert_with(Vec::new)
+            .push(entity);
+        self
+    }
+
+    /// Returns the configuration, or `ErrorKind::InvalidConfig` error
+    /// which lists the problems found, see `CacheConfiguration::validate`
+    pub fn build(self) -> IgniteResult<CacheConfiguration> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Time to live of an entry after an operation, see `ExpiryPolicy`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpiryDuration {
+    /// The entry never expires
+    Eternal,
+    /// The operation keeps the time to live the entry has
+    Unchanged,
+    /// The entry expires after the duration, at once if it is zero
+    After(Duration),
+}
+
+impl ExpiryDuration {
+    /// Milliseconds as the protocol sends them
+    pub(crate) fn millis(self) -> i64 {
+        match self {
+            ExpiryDuration::Eternal => -1,
+            ExpiryDuration::Unchanged => -2,
+            ExpiryDuration::After(ttl) => ttl.as_millis() as i64,
+        }
+    }
+
+    pub(crate) fn from_millis(millis: i64) -> ExpiryDuration {
+        match millis {
+            -1 => ExpiryDuration::Eternal,
+            millis if millis < 0 => ExpiryDuration::Unchanged,
+            millis => ExpiryDuration::After(Duration::from_millis(millis as u64)),
+        }
+    }
+}
+
+/// Time to live of the entries after they are created, updated and read.
+/// The entries are removed by the nodes when it passes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExpiryPolicy {
+    pub create: ExpiryDuration,
+    pub update: ExpiryDuration,
+    pub access: ExpiryDuration,
+}
+
+impl ExpiryPolicy {
+    /// The entries expire after the duration since they are created, like sessions with a fixed lifetime
+    pub fn created(ttl: Duration) -> ExpiryPolicy {
+        ExpiryPolicy {
+            create: ExpiryDuration::After(ttl),
+            update: ExpiryDuration::Unchanged,
+            access: ExpiryDuration::Unchanged,
+        }
+    }
+
+    /// The entries expire after the duration since they are last written
+    pub fn modified(ttl: Duration) -> ExpiryPolicy {
+        ExpiryPolicy {
+            update: ExpiryDuration::After(ttl),
+            ..ExpiryPolicy::created(ttl)
+        }
+    }
+
+    /// The entries expire after the duration since they are last written or read
+    pub fn touched(ttl: Duration) -> ExpiryPolicy {
+        ExpiryPolicy {
+            access: ExpiryDuration::After(ttl),
+            ..ExpiryPolicy::modified(ttl)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct CacheKeyConfiguration {
     pub type_name: String,
     pub affinity_key_field_name: String,
 }
 
-#[derive(Clone, Debug)]
+/// SQL table over the entries of the cache, so they could be queried with SQL
+///
+/// let entity = QueryEntity::new("java.lang.Long", "Person")
+///     .table("PERSON")
+///     .field(QueryField::new("id", "java.lang.Long").key())
+///     .field(QueryField::new("name", "java.lang.String").not_null())
+///     .index(QueryIndex::new("PERSON_NAME", IndexType::Sorted).field("name", false));
+#[derive(Clone, Debug, PartialEq)]
 pub struct QueryEntity {
     pub(crate) key_type: String,
     pub(crate) value_type: String,
@@ -249,20 +570,181 @@ pub struct QueryEntity {
     pub(crate) query_fields: Vec<QueryField>,
     pub(crate) field_aliases: Vec<(String, String)>,
     pub(crate) query_indexes: Vec<QueryIndex>,
-    pub(crate) default_value: Option<String>, //TODO: find the issue where this field is listed
 }
 
-#[derive(Clone, Debug)]
+impl QueryEntity {
+    /// Creates the entity of the key and value type names, like `java.lang.Long` or the names
+    /// of the binary types. The table is named after the value type unless `table` is set
+    pub fn new(key_type: &str, value_type: &str) -> QueryEntity {
+        QueryEntity {
+            key_type: key_type.to_owned(),
+            value_type: value_type.to_owned(),
+            table: String::new(),
+            key_field: String::new(),
+            value_field: String::new(),
+            query_fields: Vec::new(),
+            field_aliases: Vec::new(),
+            query_indexes: Vec::new(),
+        }
+    }
+
+    pub fn table(mut self, table: &str) -> QueryEntity {
+        self.table = table.to_owned();
+        self
+    }
+
+    /// Sets the name of the column which is the whole key, for the keys of the primitive types
+    pub fn key_field(mut self, name: &str) -> QueryEntity {
+        self.key_field = name.to_owned();
+        self
+    }
+
+    /// Sets the name of the column which is the whole value, for the values of the primitive types
+    pub fn value_field(mut self, name: &str) -> QueryEntity {
+        self.value_field = name.to_owned();
+        self
+    }
+
+    pub fn field(mut self, field: QueryField) -> QueryEntity {
+        self.query_fields.push(field);
+        self
+    }
+
+    /// Sets the name of the column of the field, which is the name of the field by default
+    pub fn alias(mut self, field: &str, alias: &str) -> QueryEntity {
+        self.field_aliases
+            .push((field.to_owned(), alias.to_owned()));
+        self
+    }
+
+    pub fn index(mut self, index: QueryIndex) -> QueryEntity {
+        self.query_indexes.push(index);
+        self
+    }
+
+    pub fn key_type(&self) -> &str {
+        &self.key_type
+    }
+
+    pub fn value_type(&self) -> &str {
+        &self.value_type
+    }
+
+    /// Returns the name of the table, empty if it is named after the value type
+    pub fn table_name(&self) -> &str {
+        &self.table
+    }
+
+    pub fn key_field_name(&self) -> &str {
+        &self.key_field
+    }
+
+    pub fn value_field_name(&self) -> &str {
+        &self.value_field
+    }
+
+    pub fn fields(&self) -> &[QueryField] {
+        &self.query_fields
+    }
+
+    /// Returns the pairs of the field names and their column names
+    pub fn aliases(&self) -> &[(String, String)] {
+        &self.field_aliases
+    }
+
+    pub fn indexes(&self) -> &[QueryIndex] {
+        &self.query_indexes
+    }
+}
+
+/// Column of the `QueryEntity`
+#[derive(Clone, Debug, PartialEq)]
 pub struct QueryField {
     pub(crate) name: String,
     pub(crate) type_name: String,
     pub(crate) key_field: bool,
     pub(crate) not_null_constraint: bool,
+    pub(crate) default_value: Option<AnyValue>,
     pub(crate) precision: i32,
     pub(crate) scale: i32,
 }
 
-#[derive(Clone, Debug)]
+impl QueryField {
+    /// Creates the field of the type name, like `java.lang.String` or `java.math.BigDecimal`
+    pub fn new(name: &str, type_name: &str) -> QueryField {
+        QueryField {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+            key_field: false,
+            not_null_constraint: false,
+            default_value: None,
+            precision: -1,
+            scale: -1,
+        }
+    }
+
+    /// Marks the field as the one of the key, not of the value
+    pub fn key(mut self) -> QueryField {
+        self.key_field = true;
+        self
+    }
+
+    pub fn not_null(mut self) -> QueryField {
+        self.not_null_constraint = true;
+        self
+    }
+
+    /// Sets the value of the column which the SQL inserts leave out
+    pub fn default_value(mut self, value: AnyValue) -> QueryField {
+        self.default_value = Some(value);
+        self
+    }
+
+    /// Sets the maximum length of the strings, or the number of the digits of the decimals
+    pub fn precision(mut self, precision: i32) -> QueryField {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the number of the digits after the point of the decimals
+    pub fn scale(mut self, scale: i32) -> QueryField {
+        self.scale = scale;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn is_key_field(&self) -> bool {
+        self.key_field
+    }
+
+    pub fn is_not_null(&self) -> bool {
+        self.not_null_constraint
+    }
+
+    pub fn get_default_value(&self) -> Option<&AnyValue> {
+        self.default_value.as_ref()
+    }
+
+    /// Returns the precision, -1 if it is not set
+    pub fn get_precision(&self) -> i32 {
+        self.precision
+    }
+
+    /// Returns the scale, -1 if it is not set
+    pub fn get_scale(&self) -> i32 {
+        self.scale
+    }
+}
+
+/// Index of the `QueryEntity`
+#[derive(Clone, Debug, PartialEq)]
 pub struct QueryIndex {
     pub(crate) index_name: String,
     pub(crate) index_type: IndexType,
@@ -270,218 +752,1519 @@ pub struct QueryIndex {
     pub(crate) fields: Vec<(String, bool)>,
 }
 
+impl QueryIndex {
+    pub fn new(name: &str, index_type: IndexType) -> QueryIndex {
+        QueryIndex {
+            index_name: name.to_owned(),
+            index_type,
+            inline_size: -1,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Adds the field to the index, in the descending order or the ascending one
+    pub fn field(mut self, name: &str, descending: bool) -> QueryIndex {
+        self.fields.push((name.to_owned(), descending));
+        self
+    }
+
+    /// Sets the number of the bytes of the field values which are kept in the index pages,
+    /// computed by the node if it is not set
+    pub fn inline_size(mut self, inline_size: i32) -> QueryIndex {
+        self.inline_size = inline_size;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.index_name
+    }
+
+    pub fn index_type(&self) -> IndexType {
+        self.index_type.clone()
+    }
+
+    pub fn get_inline_size(&self) -> i32 {
+        self.inline_size
+    }
+
+    /// Returns the names of the fields with whether they are in the descending order
+    pub fn fields(&self) -> &[(String, bool)] {
+        &self.fields
+    }
+}
+
 /// Ignite key-value cache. This cache is strongly typed and reading/writing some other
 /// types leads to errors.
-/// All caches created from the single IgniteClient shares the common TCP connection
+/// All caches created from the single IgniteClient shares the common TCP connection.
+/// The cache is a handle which could be cloned and shared between threads.
+///
+/// Ignite does not store NULL values: a NULL value is rejected by the server and reads of absent
+/// keys return NULL. So `Option<V>` could be used as the value type, but `Some(None)` is never read
+/// and writing `None` fails. Use a dedicated value, like an enum variant, to mark "present but empty" keys
 pub struct Cache<K: WritableType + ReadableType, V: WritableType + ReadableType> {
     id: i32,
     pub _name: String,
     conn: Arc<Connection>,
+    /// Time limit of each operation, see `with_timeout`
+    timeout: Option<Duration>,
+    /// Connection which the operations are sent over, see `Client::pin`
+    pinned: Option<Arc<Pinned>>,
+    /// Time to live of the entries which the operations touch, see `with_expiry_policy`
+    expiry_policy: Option<ExpiryPolicy>,
+    /// Whether the nodes keep the values binary, see `with_keep_binary`
+    keep_binary: bool,
+    /// Interceptors of the client, then the ones of the handle, see `with_interceptor`
+    interceptors: Vec<Arc<dyn CacheInterceptor>>,
     k_phantom: PhantomData<K>,
     v_phantom: PhantomData<V>,
 }
 
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Clone for Cache<K, V> {
+    fn clone(&self) -> Self {
+        Cache {
+            timeout: self.timeout,
+            pinned: self.pinned.clone(),
+            expiry_policy: self.expiry_policy,
+            keep_binary: self.keep_binary,
+            interceptors: self.interceptors.clone(),
+            ..Cache::new(self.id, self._name.clone(), self.conn.clone())
+        }
+    }
+}
+
+/// Returns the found entries of the `CacheGetAll` response
+pub(crate) fn found_entries<K: ReadableType + Hash + Eq, V: ReadableType>(
+    resp: CachePairsResp<K, V>,
+) -> HashMap<K, V> {
+    resp.val
+        .into_iter()
+        .filter_map(|(key, value)| Some((key?, value?)))
+        .collect()
+}
+
+/// Cache operations. Defined once for the blocking and the asynchronous caches,
+/// so both APIs stay the same. Each operation is passed to the `$op` macro as
+/// `fn name<Q>(args) -> Output = OpCode, CacheReq(args) => |resp: Resp| output`.
+/// `<Q>` marks the operations which accept borrowed keys, `<'p>` the ones which take
+/// the borrowed entries, `where K: ...` adds the bounds
+/// of the keys which the operation needs, `=> ...` is omitted for the operations
+/// without the response payload
+macro_rules! cache_ops {
+    ($op:ident) => {
+        $op! {
+            /// https://ignite.apache.org/docs/latest/binary-client-protocol/sql-and-scan-queries#op_query_scan
+            fn query_scan(page_size: i32) -> Vec<(Option<K>, Option<V>)>
+                = QueryScan, QueryScan(page_size, -1) => |resp: QueryScanResp<K, V>| resp.val
+        }
+        $op! {
+            fn get<Q>(key: &Q) -> Option<V>
+                = CacheGet, Get(&key) => |resp: CacheDataObjectResp<V>| resp.val
+        }
+        $op! {
+            fn put<Q>(key: &Q, value: &V) -> () = CachePut, Put(&key, value)
+        }
+        $op! {
+            /// Checks if the key is in the cache, without reading its value
+            fn contains_key<Q>(key: &Q) -> bool
+                = CacheContainsKey, ContainsKey(&key) => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            /// Checks if all the keys are in the cache in a single request, without reading the values
+            fn contains_keys(keys: &[K]) -> bool
+                = CacheContainsKeys, ContainsKeys(keys) => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            /// Puts the value and returns the previous one atomically, None if there was none
+            fn get_and_put<Q>(key: &Q, value: &V) -> Option<V>
+                = CacheGetAndPut, GetAndPut(&key, value) => |resp: CacheDataObjectResp<V>| resp.val
+        }
+        $op! {
+            /// Replaces the value only if the key is in the cache and returns the previous one.
+            /// Returns None and puts nothing if the key is absent
+            fn get_and_replace<Q>(key: &Q, value: &V) -> Option<V>
+                = CacheGetAndReplace, GetAndReplace(&key, value)
+                => |resp: CacheDataObjectResp<V>| resp.val
+        }
+        $op! {
+            /// Removes the key and returns its value atomically, None if the key is absent.
+            /// The default retry policy does not send it again if the connection breaks,
+            /// so a value could not be taken twice
+            fn get_and_remove<Q>(key: &Q) -> Option<V>
+                = CacheGetAndRemove, GetAndRemove(&key) => |resp: CacheDataObjectResp<V>| resp.val
+        }
+        $op! {
+            fn put_if_absent<Q>(key: &Q, value: &V) -> bool
+                = CachePutIfAbsent, PutIfAbsent(&key, value) => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            fn get_and_put_if_absent<Q>(key: &Q, value: &V) -> Option<V>
+                = CacheGetAndPutIfAbsent, GetAndPutIfAbsent(&key, value)
+                => |resp: CacheDataObjectResp<V>| resp.val
+        }
+        $op! {
+            /// Puts the value only if the key is in the cache. Returns false if it is not
+            fn replace<Q>(key: &Q, value: &V) -> bool
+                = CacheReplace, Replace(&key, value) => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            /// Puts the new value only if the current one equals the old one, compare-and-swap.
+            /// Returns false if the value has changed or the key is absent
+            fn replace_if_equals<Q>(key: &Q, old: &V, new: &V) -> bool
+                = CacheReplaceIfEquals, ReplaceIfEquals(&key, old, new)
+                => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            /// Clears the cache without notifying the listeners and the cache store
+            fn clear() -> () = CacheClear, Clear()
+        }
+        $op! {
+            /// Clears the key without notifying the listeners and the cache store
+            fn clear_key<Q>(key: &Q) -> () = CacheClearKey, ClearKey(&key)
+        }
+        $op! {
+            /// Clears the keys in a single request, skipping the listeners and the cache store
+            fn clear_keys(keys: &[K]) -> () = CacheClearKeys, ClearKeys(keys)
+        }
+        $op! {
+            /// Removes the key. Returns false if it is absent
+            fn remove_key<Q>(key: &Q) -> bool
+                = CacheRemoveKey, RemoveKey(&key) => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            /// Removes the key only if its value equals the given one, so a concurrent change
+            /// is not lost. Returns false if the value has changed or the key is absent
+            fn remove_if_equals<Q>(key: &Q, value: &V) -> bool
+                = CacheRemoveIfEquals, RemoveIfEquals(&key, value) => |resp: CacheBoolResp| resp.flag
+        }
+        $op! {
+            fn get_size() -> i64
+                = CacheGetSize, GetSize(Vec::new()) => |resp: CacheSizeResp| resp.size
+        }
+        $op! {
+            fn get_size_peek_mode(mode: CachePeekMode) -> i64
+                = CacheGetSize, GetSize(vec![mode]) => |resp: CacheSizeResp| resp.size
+        }
+        $op! {
+            fn get_size_peek_modes(modes: Vec<CachePeekMode>) -> i64
+                = CacheGetSize, GetSize(modes) => |resp: CacheSizeResp| resp.size
+        }
+        $op! {
+            /// Removes all the entries, notifying the listeners and the cache store, unlike `clear`
+            fn remove_all() -> () = CacheRemoveAll, RemoveAll()
+        }
+    };
+}
+
+#[cfg(feature = "async")]
+pub(crate) use cache_ops;
+
+/// Expands a cache operation into a blocking method
+macro_rules! blocking_cache_op {
+    (
+        $(#[$attr:meta])*
+        fn $name:ident $(<$q:ident>)? $(<$lt:lifetime>)? ($($arg:ident: $ty:ty),*) -> $ret:ty
+            $(where K: $bound:ident $(+ $more:ident)*)?
+            = $code:ident, $req:ident($($req_arg:expr),*) => |$resp:ident: $resp_ty:ty| $map:expr
+    ) => {
+        $(#[$attr])*
+        pub fn $name$(<$q>)?$(<$lt>)?(&self, $($arg: $ty),*) -> IgniteResult<$ret>
+        where
+            $(K: $bound $(+ $more)*,)?
+            $(K: $lt, V: $lt,)?
+            $(K: Borrow<$q>, $q: WritableType + ?Sized)?
+        {
+            let req = CacheReq::$req::<K, V>(self.header(), $($req_arg),*);
+            let intercepted = self.intercept(stringify!($name), req.affinity_key().map(|(_, key)| key))?;
+            intercepted.finish(with_pinned(self.pinned.as_ref(), || with_op_timeout(self.timeout, || {
+                self.conn
+                    .send_and_read(OpCode::$code, &req)
+                    .map(|$resp: $resp_ty| $map)
+            })))
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        fn $name:ident $(<$q:ident>)? $(<$lt:lifetime>)? ($($arg:ident: $ty:ty),*) -> $ret:ty
+            $(where K: $bound:ident $(+ $more:ident)*)?
+            = $code:ident, $req:ident($($req_arg:expr),*)
+    ) => {
+        $(#[$attr])*
+        pub fn $name$(<$q>)?$(<$lt>)?(&self, $($arg: $ty),*) -> IgniteResult<$ret>
+        where
+            $(K: $bound $(+ $more)*,)?
+            $(K: $lt, V: $lt,)?
+            $(K: Borrow<$q>, $q: WritableType + ?Sized)?
+        {
+            let req = CacheReq::$req::<K, V>(self.header(), $($req_arg),*);
+            let intercepted = self.intercept(stringify!($name), req.affinity_key().map(|(_, key)| key))?;
+            intercepted.finish(with_pinned(self.pinned.as_ref(), || with_op_timeout(self.timeout, || {
+                self.conn.send(OpCode::$code, &req)
+            })))
+        }
+    };
+}
+
 impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Cache<K, V> {
     pub(crate) fn new(id: i32, name: String, conn: Arc<Connection>) -> Cache<K, V> {
+        let interceptors = conn.interceptors().to_vec();
         Cache {
             id,
             _name: name,
             conn,
+            timeout: None,
+            pinned: None,
+            expiry_policy: None,
+            keep_binary: false,
+            interceptors,
             k_phantom: PhantomData,
             v_phantom: PhantomData,
         }
     }
 
-    /// https://ignite.apache.org/docs/latest/binary-client-protocol/sql-and-scan-queries#op_query_scan
-    pub fn query_scan(&self, page_size: i32) -> IgniteResult<Vec<(Option<K>, Option<V>)>> {
-        self.conn
-            .send_and_read(
-                OpCode::QueryScan,
-                CacheReq::QueryScan::<K, V>(self.id, page_size),
-            )
-            .map(|resp: QueryScanResp<K, V>| resp.val)
+    /// Returns the handle of the same cache, which operations fail with `ErrorKind::Timeout` error
+    /// if they could not be completed in time. The time includes waiting for a connection
+    /// from the pool and the retries. The connection of the timed out request is closed
+    pub fn with_timeout(&self, timeout: Duration) -> Cache<K, V> {
+        Cache {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
     }
 
-    pub fn get(&self, key: &K) -> IgniteResult<Option<V>> {
-        self.conn
-            .send_and_read(OpCode::CacheGet, CacheReq::Get::<K, V>(self.id, key))
-            .map(|resp: CacheDataObjectResp<V>| resp.val)
+    /// Returns the handle of the same cache, which operations are passed to the interceptor
+    /// after the interceptors of the client and of this handle
+    pub fn with_interceptor(&self, interceptor: impl CacheInterceptor + 'static) -> Cache<K, V> {
+        let mut cache = self.clone();
+        cache.interceptors.push(Arc::new(interceptor));
+        cache
     }
 
-    pub fn get_all(&self, keys: &[K]) -> IgniteResult<Vec<(Option<K>, Option<V>)>> {
-        self.conn
-            .send_and_read(OpCode::CacheGetAll, CacheReq::GetAll::<K, V>(self.id, keys))
-            .map(|resp: CachePairsResp<K, V>| resp.val)
+    /// Returns the handle of the same cache, which operations set the time to live of the entries
+    /// they create, update or read, instead of the expiry policy of the cache.
+    /// Fails with `ErrorKind::InvalidConfig` if the node is older than protocol v1.6.0
+    ///
+    /// let sessions = cache.with_expiry_policy(ExpiryPolicy::touched(Duration::from_secs(30 * 60)))?;
+    pub fn with_expiry_policy(&self, policy: ExpiryPolicy) -> IgniteResult<Cache<K, V>> {
+        check_expiry_policy(&self._name, &self.conn.features())?;
+        Ok(Cache {
+            expiry_policy: Some(policy),
+            ..self.clone()
+        })
     }
 
-    pub fn put(&self, key: &K, value: &V) -> IgniteResult<()> {
-        self.conn
-            .send(OpCode::CachePut, CacheReq::Put::<K, V>(self.id, key, value))
+    /// Returns the handle of the same cache, which values are `DynamicBinaryObject`s, so the tools
+    /// could inspect the entries of any type. The nodes do not deserialize the values either,
+    /// like for the cache store, so the classes of the values are not needed on the nodes
+    pub fn with_keep_binary(&self) -> Cache<K, DynamicBinaryObject> {
+        Cache {
+            id: self.id,
+            _name: self._name.clone(),
+            conn: self.conn.clone(),
+            timeout: self.timeout,
+            pinned: self.pinned.clone(),
+            expiry_policy: self.expiry_policy,
+            keep_binary: true,
+            interceptors: self.interceptors.clone(),
+            k_phantom: PhantomData,
+            v_phantom: PhantomData,
+        }
     }
 
-    pub fn put_all(&self, pairs: &[(K, V)]) -> IgniteResult<()> {
-        self.conn.send(
-            OpCode::CachePutAll,
-            CacheReq::PutAll::<K, V>(self.id, pairs),
-        )
+    /// Returns the handle of the same cache, which values are read and written as `W`
+    pub(crate) fn with_value_type<W: WritableType + ReadableType>(&self) -> Cache<K, W> {
+        Cache {
+            id: self.id,
+            _name: self._name.clone(),
+            conn: self.conn.clone(),
+            timeout: self.timeout,
+            pinned: self.pinned.clone(),
+            expiry_policy: self.expiry_policy,
+            keep_binary: self.keep_binary,
+            interceptors: self.interceptors.clone(),
+            k_phantom: PhantomData,
+            v_phantom: PhantomData,
+        }
     }
 
-    pub fn contains_key(&self, key: &K) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheContainsKey,
-                CacheReq::ContainsKey::<K, V>(self.id, key),
-            )
-            .map(|resp: CacheBoolResp| resp.flag)
+    /// Checks the key and value types against the binary metadata and the query entities
+    /// of the cluster, so a mismatch is found before the first put, not by the readers
+    /// of the entries. Returns `ErrorKind::TypeMismatch` error which lists all the problems:
+    /// another type registered with the id of a user type, a type which is not the one
+    /// of the query entities, or a field of the query entities which the type does not have
+    ///
+    /// let people = client.get_or_create_cache::<i64, Person>("people")?;
+    /// people.check_types()?;
+    pub fn check_types(&self) -> IgniteResult<()>
+    where
+        K: TypeInfo,
+        V: TypeInfo,
+    {
+        with_pinned(self.pinned.as_ref(), || {
+            with_op_timeout(self.timeout, || {
+                let config: CacheGetConfigResp = self.conn.send_and_read(
+                    OpCode::CacheGetConfiguration,
+                    CacheGetConfigReq::from(&self._name),
+                )?;
+                let entities = config.config.query_entities.unwrap_or_default();
+                let mut errors = Vec::new();
+                type_errors::<K>(true, self.binary_type::<K>()?, &entities, &mut errors);
+                type_errors::<V>(false, self.binary_type::<V>()?, &entities, &mut errors);
+                if errors.is_empty() {
+                    return Ok(());
+                }
+                Err(IgniteError::new(
+                    ErrorKind::TypeMismatch,
+                    format!(
+                        "Types of cache {:?} do not match the cluster! {}",
+                        self._name,
+                        errors.join("; ")
+                    ),
+                ))
+            })
+        })
     }
 
-    pub fn contains_keys(&self, keys: &[K]) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheContainsKeys,
-                CacheReq::ContainsKeys::<K, V>(self.id, keys),
-            )
-            .map(|resp: CacheBoolResp| resp.flag)
+    /// Deletes the rows of the cache's table which match the SQL predicate, like `age > ?`,
+    /// with a DELETE statement on the nodes, so the keys are not scanned by the client.
+    /// Returns the number of the deleted rows. The table is found in the configuration
+    /// of the cache, which should have a single query entity
+    ///
+    /// let deleted = people.delete_where("age > ?", &[&65])?;
+    pub fn delete_where(&self, predicate: &str, args: &[&dyn WritableType]) -> IgniteResult<i64> {
+        let intercepted = self.intercept("delete_where", None)?;
+        intercepted.finish(with_pinned(self.pinned.as_ref(), || {
+            with_op_timeout(self.timeout, || {
+                let config: CacheGetConfigResp = self.conn.send_and_read(
+                    OpCode::CacheGetConfiguration,
+                    CacheGetConfigReq::from(&self._name),
+                )?;
+                let query = delete_statement(&self._name, &config.config, predicate)?;
+                let req = SqlFieldsReq::new(self.id, &query, args.to_vec(), StatementType::Update);
+                updated_rows(&self.conn.send_and_read(OpCode::QuerySqlFields, req)?)
+            })
+        }))
     }
 
-    pub fn get_and_put(&self, key: &K, value: &V) -> IgniteResult<Option<V>> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheGetAndPut,
-                CacheReq::GetAndPut::<K, V>(self.id, key, value),
-            )
-            .map(|resp: CacheDataObjectResp<V>| resp.val)
+    /// Runs the SQL fields query, like a SELECT over the table of the cache, and returns
+    /// the iterator over its rows. The schema of the cache is used unless the query sets one.
+    /// The first page is fetched before it returns, so the errors of the statement are returned here
+    ///
+    /// let query = SqlFieldsQuery::new("SELECT name FROM Person WHERE age > ?").arg(30);
+    /// let names: Vec<SqlRow> = people.query_sql_fields(&query)?.collect::<IgniteResult<_>>()?;
+    pub fn query_sql_fields(&self, query: &SqlFieldsQuery) -> IgniteResult<SqlFieldsCursor> {
+        let intercepted = self.intercept("query_sql_fields", None)?;
+        intercepted.finish(SqlFieldsCursor::open(
+            self.conn.clone(),
+            self.pinned.clone(),
+            self.timeout,
+            self.id,
+            query,
+        ))
     }
 
-    pub fn get_and_replace(&self, key: &K, value: &V) -> IgniteResult<Option<V>> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheGetAndReplace,
-                CacheReq::GetAndReplace::<K, V>(self.id, key, value),
-            )
-            .map(|resp: CacheDataObjectResp<V>| resp.val)
+    /// Returns the binary metadata of the user type, None if it is not registered yet
+    fn binary_type<T: TypeInfo>(&self) -> IgniteResult<Option<BinaryType>> {
+        match T::cluster_type_id() {
+            Some(type_id) => self
+                .conn
+                .send_and_read(OpCode::GetBinaryType, BinaryTypeGetReq { type_id })
+                .map(|resp: BinaryTypeGetResp| resp.binary_type),
+            None => Ok(None),
+        }
     }
 
-    pub fn get_and_remove(&self, key: &K) -> IgniteResult<Option<V>> {
+    /// Returns the partition of the key, computed on the client the same way as
+    /// RendezvousAffinityFunction does it on the nodes. Fails for the keys of the types
+    /// which have affinity key fields or which hash code the client could not compute
+    pub fn partition_for<Q>(&self, key: &Q) -> IgniteResult<i32>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
         self.conn
-            .send_and_read(
-                OpCode::CacheGetAndRemove,
-                CacheReq::GetAndRemove::<K, V>(self.id, key),
-            )
-            .map(|resp: CacheDataObjectResp<V>| resp.val)
+            .key_partition(self.id, &key)
+            .map(|(partition, _)| partition)
     }
 
-    pub fn put_if_absent(&self, key: &K, value: &V) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CachePutIfAbsent,
-                CacheReq::PutIfAbsent::<K, V>(self.id, key, value),
-            )
-            .map(|resp: CacheBoolResp| resp.flag)
+    /// Returns the id of the primary node of the key, see `partition_for`.
+    /// None if the partition has no primary node at the moment
+    pub fn primary_node_for<Q>(&self, key: &Q) -> IgniteResult<Option<u128>>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        self.conn.key_partition(self.id, &key).map(|(_, node)| node)
     }
 
-    pub fn get_and_put_if_absent(&self, key: &K, value: &V) -> IgniteResult<Option<V>> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheGetAndPutIfAbsent,
-                CacheReq::GetAndPutIfAbsent::<K, V>(self.id, key, value),
-            )
-            .map(|resp: CacheDataObjectResp<V>| resp.val)
+    /// Returns the primary nodes of the partitions of the cache, which the client has fetched
+    /// for the current affinity topology version
+    pub fn partition_map(&self) -> IgniteResult<CachePartitionMap> {
+        self.conn.cache_partition_map(self.id, false)
     }
 
-    pub fn replace(&self, key: &K, value: &V) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheReplace,
-                CacheReq::Replace::<K, V>(self.id, key, value),
-            )
-            .map(|resp: CacheBoolResp| resp.flag)
+    /// Fetches the partition map of the cache again, see `partition_map`
+    pub fn refresh_partition_map(&self) -> IgniteResult<CachePartitionMap> {
+        self.conn.cache_partition_map(self.id, true)
     }
 
-    pub fn replace_if_equals(&self, key: &K, old: &V, new: &V) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheReplaceIfEquals,
-                CacheReq::ReplaceIfEquals::<K, V>(self.id, key, old, new),
-            )
-            .map(|resp: CacheBoolResp| resp.flag)
+    /// Reads all the entries with a scan query per partition, running up to `parallelism`
+    /// of them at once, each over its own connection from the pool. The entries come through
+    /// the channel as they are read, in no particular order. An error ends the scan,
+    /// and so does dropping the receiver
+    ///
+    /// for entry in cache.scan_partitions(4, 1024)? {
+    ///     let (key, value) = entry?;
+    /// }
+    pub fn scan_partitions(
+        &self,
+        parallelism: usize,
+        page_size: i32,
+    ) -> IgniteResult<Receiver<IgniteResult<(K, V)>>>
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let partitions = self.conn.partitions(self.id)?;
+        let next = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = sync_channel(page_size.max(1) as usize);
+        for _ in 0..parallelism.clamp(1, partitions.max(1)) {
+            let (cache, next, stop, sender) =
+                (self.clone(), next.clone(), stop.clone(), sender.clone());
+            thread::spawn(move || {
+                while !stop.load(Ordering::Acquire) {
+                    let partition = next.fetch_add(1, Ordering::AcqRel);
+                    if partition >= partitions {
+                        return;
+                    }
+                    for entry in cache.partition_entries(partition as i32, page_size) {
+                        let failed = entry.is_err();
+                        if sender.send(entry).is_err() || failed {
+                            stop.store(true, Ordering::Release);
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        Ok(receiver)
     }
 
-    pub fn clear(&self) -> IgniteResult<()> {
-        self.conn
-            .send(OpCode::CacheClear, CacheReq::Clear::<K, V>(self.id))
+    /// Returns the handle of the same cache which has only the read operations,
+    /// so the code it is given to could not change the data
+    pub fn read_only(&self) -> ReadOnlyCache<K, V> {
+        ReadOnlyCache {
+            cache: self.clone(),
+        }
     }
 
-    pub fn clear_key(&self, key: &K) -> IgniteResult<()> {
-        self.conn.send(
-            OpCode::CacheClearKey,
-            CacheReq::ClearKey::<K, V>(self.id, key),
-        )
+    /// Returns the handle of the same cache, which keeps up to `max_entries` of the recently read
+    /// entries in the process for the time to live, for the read-mostly data. The entries changed
+    /// by any client are dropped by a continuous query, which runs over a connection of its own
+    pub fn with_near_cache(
+        &self,
+        max_entries: usize,
+        ttl: Duration,
+    ) -> IgniteResult<NearCache<K, V>>
+    where
+        K: Hash + Eq + Clone + Send + 'static,
+        V: Clone + Send + 'static,
+    {
+        NearCache::new(self.clone(), max_entries, ttl)
     }
 
-    pub fn clear_keys(&self, keys: &[K]) -> IgniteResult<()> {
-        self.conn.send(
-            OpCode::CacheClearKeys,
-            CacheReq::ClearKeys::<K, V>(self.id, keys),
-        )
+    /// Starts the continuous query of the cache, which passes the payloads of the notifications
+    /// of its events to the closure as soon as they happen, see `Connection::subscribe`
+    pub(crate) fn subscribe(
+        &self,
+        on_events: impl FnMut(IgniteResult<&[u8]>) + Send + 'static,
+    ) -> IgniteResult<Subscription> {
+        let req = ContinuousQueryReq {
+            cache_id: self.id,
+            page_size: 1,
+            time_interval_ms: 0,
+            include_expired: true,
+        };
+        self.conn.subscribe(req, on_events)
     }
 
-    pub fn remove_key(&self, key: &K) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheRemoveKey,
-                CacheReq::RemoveKey::<K, V>(self.id, key),
-            )
-            .map(|resp: CacheBoolResp| resp.flag)
+    /// Returns all the keys of the cache. If the cache has a single query entity, the keys are
+    /// selected from its table by SQL, so the values are not read at all. Otherwise the entries
+    /// are scanned with the values kept binary on the nodes, and the client skips their bytes
+    pub fn keys(&self) -> IgniteResult<Vec<K>> {
+        let config: CacheGetConfigResp = with_pinned(self.pinned.as_ref(), || {
+            with_op_timeout(self.timeout, || {
+                self.conn.send_and_read(
+                    OpCode::CacheGetConfiguration,
+                    CacheGetConfigReq::from(&self._name),
+                )
+            })
+        })?;
+        match keys_statement(&self._name, &config.config) {
+            Some(query) => self
+                .query_sql_fields(&SqlFieldsQuery::new(&query))?
+                .map(|row| sql_key(row?))
+                .collect(),
+            None => self
+                .with_opaque_values()
+                .iter()
+                .map(|entry| entry.map(|(key, _)| key))
+                .collect(),
+        }
     }
 
-    pub fn remove_if_equals(&self, key: &K, value: &V) -> IgniteResult<bool> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheRemoveIfEquals,
-                CacheReq::RemoveIfEquals::<K, V>(self.id, key, value),
+    fn with_opaque_values(&self) -> Cache<K, OpaqueObject> {
+        Cache {
+            id: self.id,
+            _name: self._name.clone(),
+            conn: self.conn.clone(),
+            timeout: self.timeout,
+            pinned: self.pinned.clone(),
+            expiry_policy: self.expiry_policy,
+            keep_binary: true,
+            interceptors: self.interceptors.clone(),
+            k_phantom: PhantomData,
+            v_phantom: PhantomData,
+        }
+    }
+
+    /// Returns the iterator over the entries of the cache, see `entries`
+    pub fn iter(&self) -> CacheEntries<K, V> {
+        self.entries(DFLT_PAGE_SIZE)
+    }
+
+    /// Returns the iterator over the entries of the cache, which fetches them by pages of the size
+    /// with a scan query. The cursor of the query is closed when the iterator is done or dropped.
+    /// The entries which are changed during the iteration may or may not be returned
+    pub fn entries(&self, page_size: i32) -> CacheEntries<K, V> {
+        self.partition_entries(-1, page_size)
+    }
+
+    /// Returns the iterator over the entries of a single partition, see `entries`
+    pub fn partition_entries(&self, partition: i32, page_size: i32) -> CacheEntries<K, V> {
+        CacheEntries {
+            cache: self.clone(),
+            page_size,
+            partition,
+            page: Vec::new().into_iter(),
+            cursor_id: None,
+            state: ScanState::NotStarted,
+        }
+    }
+
+    /// Returns the value of the key, or computes and puts it if the key is absent, for memoization.
+    /// When several clients compute the value at once, the first one put wins and all of them
+    /// return it. The timeout applies to each request, not to the computation
+    ///
+    /// let report = reports.get_or_insert_with(&day, || build_report(day))?;
+    pub fn get_or_insert_with<Q>(&self, key: &Q, compute: impl FnOnce() -> V) -> IgniteResult<V>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        if let Some(value) = self.get(key)? {
+            return Ok(value);
+        }
+        let value = compute();
+        Ok(self.get_and_put_if_absent(key, &value)?.unwrap_or(value))
+    }
+
+    /// Updates the present value optimistically: reads it, applies the function and puts the result
+    /// with `replace_if_equals`, again if the value has changed meanwhile. Returns the new value,
+    /// None if the key is absent. Fails with `ErrorKind::Conflict` after 16 lost attempts.
+    /// Use `Versioned` values, so a value changed and changed back is not taken for the same
+    ///
+    /// let total = totals.update(&day, |total| total.next(total.value + amount))?;
+    pub fn update<Q>(&self, key: &Q, mut update: impl FnMut(&V) -> V) -> IgniteResult<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let current = match self.get(key)? {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let new = update(&current);
+            if self.replace_if_equals(key, &current, &new)? {
+                return Ok(Some(new));
+            }
+        }
+        Err(update_conflict(&self._name))
+    }
+
+    /// Returns the entry of the key, for the read-modify-write without the lost updates
+    ///
+    /// let balance = accounts.entry(id).and_modify(|balance| balance + 10).or_insert(10)?;
+    pub fn entry(&self, key: K) -> CacheEntry<'_, K, V> {
+        CacheEntry::new(self, key)
+    }
+
+    /// Returns the writer which buffers the entries and puts them in batches,
+    /// for the bulk loading of the cache
+    pub fn writer(&self) -> CacheWriter<K, V> {
+        CacheWriter::new(self.clone())
+    }
+
+    fn header(&self) -> CacheHeader {
+        CacheHeader {
+            id: self.id,
+            expiry_policy: self.expiry_policy,
+            keep_binary: self.keep_binary,
+        }
+    }
+
+    /// Returns the handle of the same cache, which operations are sent over the pinned connection
+    pub(crate) fn pinned_to(self, pinned: &Arc<Pinned>) -> Cache<K, V> {
+        Cache {
+            pinned: Some(pinned.clone()),
+            ..self
+        }
+    }
+
+    /// Returns the values of the keys which are in the cache. The keys which are not found
+    /// are absent from the map. The keys are sent in a single request, or in several ones
+    /// if they exceed `ClientConfig::batch_max_entries` or `ClientConfig::batch_max_bytes`
+    pub fn get_all(&self, keys: &[K]) -> IgniteResult<HashMap<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        let intercepted = self.intercept("get_all", None)?;
+        intercepted.finish(with_pinned(self.pinned.as_ref(), || {
+            with_op_timeout(self.timeout, || {
+                let mut values = HashMap::new();
+                for chunk in self.batch_limits().split(keys, K::size) {
+                    let resp = self.conn.send_and_read(
+                        OpCode::CacheGetAll,
+                        CacheReq::GetAll::<K, V>(self.header(), chunk),
+                    )?;
+                    values.extend(found_entries(resp));
+                }
+                Ok(values)
+            })
+        }))
+    }
+
+    /// Puts the entries, owned or borrowed, like the ones of a map or an iterator over a source.
+    /// The entries are written into the request as they are drained from the iterator, so they
+    /// are not collected first. The entries are sent in a single request, or in several ones
+    /// if they exceed the batch limits, see `get_all`. The requests are not atomic together then,
+    /// so a failed one leaves the entries of the previous ones in the cache.
+    /// The timeout applies to each request, not to draining the iterator
+    ///
+    /// cache.put_all(&entries)?; // HashMap<K, V>
+    /// cache.put_all(rows.map(|row| (row.id, row)))?;
+    pub fn put_all<KB, VB>(&self, pairs: impl IntoIterator<Item = (KB, VB)>) -> IgniteResult<()>
+    where
+        KB: Borrow<K>,
+        VB: Borrow<V>,
+    {
+        let mut buffer = PairsBuffer::new(self.batch_limits());
+        let intercepted = self.intercept("put_all", None)?;
+        intercepted.finish(with_pinned(self.pinned.as_ref(), || {
+            for (key, value) in pairs {
+                if let Some(batch) = buffer.push(key.borrow(), value.borrow())? {
+                    self.send_pairs(&batch)?;
+                }
+            }
+            let rest = buffer.take();
+            if rest.count > 0 {
+                self.send_pairs(&rest)?;
+            }
+            Ok(())
+        }))
+    }
+
+    fn send_pairs(&self, pairs: &EncodedPairs) -> IgniteResult<()> {
+        with_op_timeout(self.timeout, || {
+            self.conn.send(
+                OpCode::CachePutAll,
+                CacheReq::PutAll::<K, V>(self.header(), pairs),
             )
-            .map(|resp: CacheBoolResp| resp.flag)
+        })
+    }
+
+    /// Removes the keys, notifying the listeners and the cache store. The keys are sent
+    /// in a single request, or in several ones if they exceed the batch limits, see `put_all`
+    pub fn remove_keys(&self, keys: &[K]) -> IgniteResult<()> {
+        let intercepted = self.intercept("remove_keys", None)?;
+        intercepted.finish(with_pinned(self.pinned.as_ref(), || {
+            with_op_timeout(self.timeout, || {
+                for chunk in self.batch_limits().split(keys, K::size) {
+                    self.conn.send(
+                        OpCode::CacheRemoveKeys,
+                        CacheReq::RemoveKeys::<K, V>(self.header(), chunk),
+                    )?;
+                }
+                Ok(())
+            })
+        }))
+    }
+
+    fn batch_limits(&self) -> BatchLimits {
+        self.conn.batch_limits()
+    }
+
+    /// Calls the interceptors before the operation, see `Intercepted::finish`
+    fn intercept(
+        &self,
+        name: &'static str,
+        key: Option<&dyn WritableType>,
+    ) -> IgniteResult<Intercepted<'_>> {
+        Intercepted::start(&self.interceptors, name, &self._name, key)
+    }
+
+    cache_ops!(blocking_cache_op);
+}
+
+/// Lists the problems of the key or value type, see `Cache::check_types`
+fn type_errors<T: TypeInfo>(
+    key: bool,
+    binary_type: Option<BinaryType>,
+    entities: &[QueryEntity],
+    errors: &mut Vec<String>,
+) {
+    let name = match T::cluster_type_name() {
+        Some(name) => name,
+        None => return,
+    };
+    let role = if key { "key" } else { "value" };
+    if let Some(binary_type) = binary_type {
+        if !same_type_name(&binary_type.type_name, &name) {
+            errors.push(format!(
+                "{} type {} has the id of type {}",
+                role, name, binary_type.type_name
+            ));
+        }
+    }
+    for entity in entities {
+        let entity_type = if key {
+            &entity.key_type
+        } else {
+            &entity.value_type
+        };
+        if !same_type_name(entity_type, &name) {
+            errors.push(format!(
+                "{} type {} is not {} of the query entity",
+                role, name, entity_type
+            ));
+            continue;
+        }
+        if T::cluster_type_id().is_none() {
+            continue;
+        }
+        let fields = T::cluster_field_names();
+        // the columns of the whole key or value are not the fields
+        let columns = entity.query_fields.iter().filter(|field| {
+            field.key_field == key
+                && field.name != entity.key_field
+                && field.name != entity.value_field
+        });
+        for column in columns {
+            if !fields
+                .iter()
+                .any(|field| field.eq_ignore_ascii_case(&column.name))
+            {
+                errors.push(format!(
+                    "{} type {} has no field {} of the query entity",
+                    role, name, column.name
+                ));
+            }
+        }
+    }
+}
+
+/// Compares the type names without the packages, as the classes of the user types are mapped
+/// to their simple names, and the ids of the names are case-insensitive
+fn same_type_name(a: &str, b: &str) -> bool {
+    let simple = |name: &str| name.rsplit('.').next().unwrap_or_default().to_lowercase();
+    simple(a) == simple(b)
+}
+
+/// Primary nodes of the partitions of a cache, see `Cache::partition_map`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachePartitionMap {
+    /// Affinity topology version which the map is valid for, major and minor
+    pub topology: (i64, i32),
+    /// Primary node of each partition, None if the partition has none at the moment
+    pub nodes: Vec<Option<u128>>,
+}
+
+impl CachePartitionMap {
+    /// Returns the partitions which primary node is the given one
+    pub fn partitions_of(&self, node_id: u128) -> Vec<i32> {
+        (0..self.nodes.len() as i32)
+            .filter(|partition| self.nodes[*partition as usize] == Some(node_id))
+            .collect()
+    }
+}
+
+impl<V: WritableType + ReadableType> Cache<String, V> {
+    /// Returns the handle of the same cache, which keys are in the namespace of the prefix,
+    /// like the ones of a tenant. The keys are prefixed with the prefix and `:` on write, and
+    /// both are stripped on read. Fails with `ErrorKind::InvalidConfig` if the prefix has `:`
+    ///
+    /// let orders = cache.with_key_prefix(&tenant_id)?;
+    pub fn with_key_prefix(&self, prefix: &str) -> IgniteResult<PrefixedCache<V>> {
+        PrefixedCache::new(self.clone(), prefix)
+    }
+}
+
+/// Handle of the cache which has only the read operations of `Cache`, see `Cache::read_only`
+pub struct ReadOnlyCache<K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: Cache<K, V>,
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Clone for ReadOnlyCache<K, V> {
+    fn clone(&self) -> Self {
+        self.cache.read_only()
+    }
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> ReadOnlyCache<K, V> {
+    /// Returns the read-only handle of the same cache with the timeout, see `Cache::with_timeout`
+    pub fn with_timeout(&self, timeout: Duration) -> ReadOnlyCache<K, V> {
+        self.cache.with_timeout(timeout).read_only()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> IgniteResult<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        self.cache.get(key)
+    }
+
+    /// Returns the values of the keys which are in the cache, see `Cache::get_all`
+    pub fn get_all(&self, keys: &[K]) -> IgniteResult<HashMap<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        self.cache.get_all(keys)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> IgniteResult<bool>
+    where
+        K: Borrow<Q>,
+        Q: WritableType + ?Sized,
+    {
+        self.cache.contains_key(key)
+    }
+
+    pub fn contains_keys(&self, keys: &[K]) -> IgniteResult<bool> {
+        self.cache.contains_keys(keys)
     }
 
     pub fn get_size(&self) -> IgniteResult<i64> {
-        let modes = Vec::new();
-        self.conn
-            .send_and_read(
-                OpCode::CacheGetSize,
-                CacheReq::GetSize::<K, V>(self.id, modes),
-            )
-            .map(|resp: CacheSizeResp| resp.size)
+        self.cache.get_size()
     }
 
     pub fn get_size_peek_mode(&self, mode: CachePeekMode) -> IgniteResult<i64> {
-        let modes = vec![mode];
-        self.conn
-            .send_and_read(
-                OpCode::CacheGetSize,
-                CacheReq::GetSize::<K, V>(self.id, modes),
-            )
-            .map(|resp: CacheSizeResp| resp.size)
+        self.cache.get_size_peek_mode(mode)
     }
 
     pub fn get_size_peek_modes(&self, modes: Vec<CachePeekMode>) -> IgniteResult<i64> {
-        self.conn
-            .send_and_read(
-                OpCode::CacheGetSize,
-                CacheReq::GetSize::<K, V>(self.id, modes),
+        self.cache.get_size_peek_modes(modes)
+    }
+
+    pub fn query_scan(&self, page_size: i32) -> IgniteResult<Vec<(Option<K>, Option<V>)>> {
+        self.cache.query_scan(page_size)
+    }
+
+    /// Returns the iterator over the entries of the cache, see `Cache::iter`
+    pub fn iter(&self) -> CacheEntries<K, V> {
+        self.cache.iter()
+    }
+
+    /// Returns the iterator over the entries of the cache, see `Cache::entries`
+    pub fn entries(&self, page_size: i32) -> CacheEntries<K, V> {
+        self.cache.entries(page_size)
+    }
+}
+
+/// Number of the entries which `Cache::iter` fetches at once
+pub(crate) const DFLT_PAGE_SIZE: i32 = 1024;
+
+/// Compare-and-swaps which `Cache::update` tries before it gives up
+pub(crate) const UPDATE_ATTEMPTS: usize = 16;
+
+/// Error of the update which has lost all the compare-and-swaps to the concurrent ones
+pub(crate) fn update_conflict(cache_name: &str) -> IgniteError {
+    IgniteError::new(
+        ErrorKind::Conflict,
+        format!(
+            "Value in cache {:?} has changed concurrently {} times, update is not applied",
+            cache_name, UPDATE_ATTEMPTS
+        ),
+    )
+}
+
+/// Returns the DELETE statement of the rows of the cache's table which match the predicate
+pub(crate) fn delete_statement(
+    cache_name: &str,
+    config: &CacheConfiguration,
+    predicate: &str,
+) -> IgniteResult<String> {
+    let table = entity_table(cache_name, config)?;
+    Ok(format!("DELETE FROM {} WHERE {}", table, predicate))
+}
+
+/// Returns the statement which selects the keys of the cache's table,
+/// None if the cache has no single query entity, so its keys are scanned
+pub(crate) fn keys_statement(cache_name: &str, config: &CacheConfiguration) -> Option<String> {
+    let table = entity_table(cache_name, config).ok()?;
+    Some(format!("SELECT _key FROM {}", table))
+}
+
+/// Returns the key of the row of the keys statement
+pub(crate) fn sql_key<K: ReadableType>(row: SqlRow) -> IgniteResult<K> {
+    row.get(0)?
+        .ok_or_else(|| IgniteError::from("Keys statement has returned a null key"))
+}
+
+/// Returns the table of the cache, quoted if the cache escapes the names. The cache should have
+/// a single query entity, its table is named after the value type unless the table name is set
+fn entity_table(cache_name: &str, config: &CacheConfiguration) -> IgniteResult<String> {
+    let entity = match config.query_entities.as_deref() {
+        Some([entity]) => entity,
+        _ => {
+            return Err(IgniteError::new(
+                ErrorKind::InvalidConfig,
+                format!("Cache {:?} should have a single query entity", cache_name),
+            ))
+        }
+    };
+    let table = match entity.table.as_str() {
+        "" => entity
+            .value_type
+            .rsplit(['.', '$'])
+            .next()
+            .unwrap_or_default(),
+        table => table,
+    };
+    Ok(match config.sql_escape_all {
+        true => format!("\"{}\"", table),
+        false => table.to_owned(),
+    })
+}
+
+/// Returns the number of the rows which the update statement has changed
+pub(crate) fn updated_rows(resp: &SqlFieldsResp) -> IgniteResult<i64> {
+    resp.updated()
+        .ok_or_else(|| IgniteError::from("Update statement has not returned the row count"))
+}
+
+enum ScanState {
+    NotStarted,
+    Fetching,
+    Done,
+}
+
+/// Iterator over the entries of the cache, see `Cache::entries`. Yields the error
+/// if a page could not be fetched, and ends after it
+pub struct CacheEntries<K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: Cache<K, V>,
+    page_size: i32,
+    /// Partition to scan, or -1 for the whole cache
+    partition: i32,
+    page: std::vec::IntoIter<(Option<K>, Option<V>)>,
+    /// Set while the node keeps more pages
+    cursor_id: Option<i64>,
+    state: ScanState,
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> CacheEntries<K, V> {
+    fn fetch(&mut self) -> IgniteResult<()> {
+        let cache = &self.cache;
+        let page_size = self.page_size;
+        let partition = self.partition;
+        let cursor_id = self.cursor_id;
+        let page = with_pinned(cache.pinned.as_ref(), || {
+            with_op_timeout(cache.timeout, || match cursor_id {
+                None => cache
+                    .conn
+                    .send_and_read(
+                        OpCode::QueryScan,
+                        CacheReq::QueryScan::<K, V>(cache.header(), page_size, partition),
+                    )
+                    .map(|resp: QueryScanResp<K, V>| (resp.val, resp.cursor_id)),
+                Some(cursor_id) => cache
+                    .conn
+                    .cursor_page::<QueryCursorPageResp<K, V>>(cursor_id)
+                    .map(|resp| (resp.val, resp.more.then_some(cursor_id))),
+            })
+        });
+        let (entries, cursor_id) = page?;
+        self.page = entries.into_iter();
+        self.cursor_id = cursor_id;
+        Ok(())
+    }
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Iterator
+    for CacheEntries<K, V>
+{
+    type Item = IgniteResult<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // the nodes do not keep NULLs, so the entries have both the key and the value
+            if let Some(entry) = self.page.next() {
+                if let (Some(key), Some(value)) = entry {
+                    return Some(Ok((key, value)));
+                }
+                continue;
+            }
+            match self.state {
+                ScanState::NotStarted => self.state = ScanState::Fetching,
+                ScanState::Fetching if self.cursor_id.is_some() => {}
+                _ => {
+                    self.state = ScanState::Done;
+                    return None;
+                }
+            }
+            if let Err(err) = self.fetch() {
+                self.state = ScanState::Done;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Drop for CacheEntries<K, V> {
+    fn drop(&mut self) {
+        if let Some(cursor_id) = self.cursor_id.take() {
+            let cache = &self.cache;
+            let _ = with_pinned(cache.pinned.as_ref(), || cache.conn.close_cursor(cursor_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::cache_config::CacheGetConfigResp;
+    use crate::connection::tests::{
+        partition_map_payload, read_req, write_handshake_resp, write_success,
+    };
+    use crate::handshake::ProtocolVersion;
+    use crate::protocol::cache_config::{
+        get_cache_configuration_bytes, read_query_entities, write_query_entities,
+    };
+    use crate::protocol::{
+        read_i16, read_i32, read_i64, write_bool, write_i32, write_i64, write_null,
+        write_string_type_code,
+    };
+    use crate::{ClientConfig, ReadableReq};
+    use std::io::{Cursor, Read};
+    use std::net::TcpListener;
+
+    /// The configuration as the node sends it, with the expiry policy of protocol v1.6.0
+    fn config_resp(expiry_policy: Option<[i64; 3]>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let w = &mut bytes;
+        write_i32(w, 1).unwrap(); // atomic
+        write_i32(w, 1).unwrap(); // backups
+        write_i32(w, 2).unwrap(); // partitioned
+        write_bool(w, true).unwrap();
+        write_string_type_code(w, "default").unwrap();
+        write_bool(w, true).unwrap();
+        write_bool(w, false).unwrap();
+        write_null(w).unwrap(); // group
+        write_i64(w, 0).unwrap();
+        write_i32(w, 500).unwrap();
+        write_i32(w, 1024).unwrap();
+        write_string_type_code(w, "sessions").unwrap();
+        write_bool(w, false).unwrap();
+        write_i32(w, 4).unwrap(); // ignore
+        write_i32(w, 0).unwrap();
+        write_i32(w, 1).unwrap();
+        write_bool(w, true).unwrap();
+        write_i32(w, 512 * 1024).unwrap();
+        write_i64(w, 2).unwrap();
+        write_i64(w, 0).unwrap();
+        write_i32(w, 1).unwrap(); // async
+        write_i32(w, 0).unwrap();
+        write_i64(w, 0).unwrap();
+        write_i64(w, 10000).unwrap();
+        write_bool(w, false).unwrap();
+        write_i32(w, -1).unwrap();
+        write_null(w).unwrap(); // schema
+        write_i32(w, 0).unwrap(); // full sync
+        write_i32(w, 0).unwrap(); // key configurations
+        write_i32(w, 0).unwrap(); // query entities
+        if let Some(millis) = expiry_policy {
+            write_bool(w, true).unwrap();
+            for millis in &millis {
+                write_i64(w, *millis).unwrap();
+            }
+        }
+        let mut resp = (bytes.len() as i32).to_le_bytes().to_vec();
+        resp.append(&mut bytes);
+        resp
+    }
+
+    #[test]
+    fn test_cache_config_builder() {
+        let config = CacheConfiguration::builder("orders")
+            .cache_mode(CacheMode::Replicated)
+            .num_backup(2)
+            .group_name("shop".to_owned())
+            .data_region_name("persistent".to_owned())
+            .rebalance_delay(Duration::from_secs(1))
+            .affinity_key("OrderKey", "customer_id")
+            .expiry_policy(ExpiryPolicy::created(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        assert_eq!(config.num_backup, 2);
+        assert_eq!(config.group_name.as_deref(), Some("shop"));
+        assert_eq!(config.data_region_name.as_deref(), Some("persistent"));
+        assert_eq!(config.rebalance_delay_ms, 1000);
+        assert_eq!(config.cache_key_configurations.as_ref().unwrap().len(), 1);
+
+        let bytes = get_cache_configuration_bytes(&config).unwrap();
+        assert_eq!(bytes[0..4], (bytes.len() as i32 - 4).to_le_bytes());
+        // the fixed properties, the group, the region, the key configurations and the expiry policy
+        assert_eq!(bytes[4..6], 29i16.to_le_bytes());
+        let mut expiry = vec![];
+        expiry.extend_from_slice(&407i16.to_le_bytes());
+        expiry.push(1);
+        for millis in &[60_000i64, -2, -2] {
+            expiry.extend_from_slice(&millis.to_le_bytes());
+        }
+        assert!(bytes.ends_with(&expiry));
+
+        let err = CacheConfiguration::builder("")
+            .num_backup(-1)
+            .group_name(String::new())
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidConfig);
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration of cache \"\"! name is empty; num_backup (-1) is negative; group_name is empty"
+        );
+    }
+
+    #[test]
+    fn test_query_entity() {
+        let entity = QueryEntity::new("java.lang.Long", "Person")
+            .table("PERSON")
+            .key_field("id")
+            .field(QueryField::new("id", "java.lang.Long").key())
+            .field(
+                QueryField::new("salary", "java.math.BigDecimal")
+                    .precision(10)
+                    .scale(2)
+                    .default_value(AnyValue::Int(0)),
+            )
+            .alias("salary", "PAY")
+            .index(QueryIndex::new("PERSON_SALARY", IndexType::Sorted).field("salary", true));
+        let mut bytes = Vec::new();
+        write_query_entities(&mut bytes, std::slice::from_ref(&entity)).unwrap();
+        let read = read_query_entities(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, vec![entity.clone()]);
+        assert_eq!(read[0].fields()[1].get_precision(), 10);
+        assert_eq!(read[0].value_field_name(), "");
+
+        let err = CacheConfiguration::builder("people")
+            .query_entity(
+                entity.index(QueryIndex::new("AGE", IndexType::Sorted).field("age", false)),
             )
-            .map(|resp: CacheSizeResp| resp.size)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration of cache \"people\"! index \"AGE\" has unknown field \"age\""
+        );
     }
 
-    pub fn remove_keys(&self, keys: &[K]) -> IgniteResult<()> {
-        self.conn.send(
-            OpCode::CacheRemoveKeys,
-            CacheReq::RemoveKeys::<K, V>(self.id, keys),
-        )
+    #[test]
+    fn test_type_errors() {
+        struct Person;
+        impl TypeInfo for Person {
+            fn cluster_type_name() -> Option<String> {
+                Some("Person".to_owned())
+            }
+            fn cluster_type_id() -> Option<i32> {
+                Some(1)
+            }
+            fn cluster_field_names() -> Vec<&'static str> {
+                vec!["name"]
+            }
+        }
+
+        let entity = QueryEntity::new("java.lang.Long", "com.acme.Person")
+            .key_field("id")
+            .field(QueryField::new("id", "java.lang.Long").key())
+            .field(QueryField::new("NAME", "java.lang.String"));
+        let mut errors = Vec::new();
+        let entities = vec![entity];
+        type_errors::<i64>(true, None, &entities, &mut errors);
+        type_errors::<Person>(false, None, &entities, &mut errors);
+        assert!(errors.is_empty());
+
+        let entities = vec![entities[0]
+            .clone()
+            .field(QueryField::new("age", "java.lang.Integer"))];
+        let binary_type = BinaryType {
+            type_id: 1,
+            type_name: "Order".to_owned(),
+            affinity_key_field_name: None,
+            fields: vec![],
+            is_enum: false,
+            enum_values: vec![],
+            schemas: vec![],
+        };
+        type_errors::<i32>(true, None, &entities, &mut errors);
+        type_errors::<Person>(false, Some(binary_type), &entities, &mut errors);
+        assert_eq!(
+            errors,
+            vec![
+                "key type java.lang.Integer is not java.lang.Long of the query entity",
+                "value type Person has the id of type Order",
+                "value type Person has no field age of the query entity",
+            ]
+        );
     }
 
-    pub fn remove_all(&self) -> IgniteResult<()> {
-        self.conn
-            .send(OpCode::CacheRemoveAll, CacheReq::RemoveAll::<K, V>(self.id))
+    #[test]
+    fn test_read_cache_config() {
+        let resp = CacheGetConfigResp::read(&mut Cursor::new(config_resp(Some([-2, -1, 1000]))));
+        let config = resp.unwrap().config;
+        assert_eq!(config.name, "sessions");
+        assert_eq!(config.num_backup, 1);
+        assert_eq!(config.atomicity_mode, AtomicityMode::Atomic);
+        assert_eq!(
+            config.write_synchronization_mode,
+            WriteSynchronizationMode::FullSync
+        );
+        assert_eq!(config.data_region_name.as_deref(), Some("default"));
+        assert_eq!(config.group_name, None);
+        assert_eq!(
+            config.expiry_policy,
+            Some(ExpiryPolicy {
+                create: ExpiryDuration::Unchanged,
+                update: ExpiryDuration::Eternal,
+                access: ExpiryDuration::After(Duration::from_secs(1)),
+            })
+        );
+        // the nodes older than v1.6.0 do not send the expiry policy
+        let resp = CacheGetConfigResp::read(&mut Cursor::new(config_resp(None)));
+        assert_eq!(resp.unwrap().config.expiry_policy, None);
+    }
+
+    #[test]
+    fn test_check_template() {
+        assert!(check_template("orders*", "orders_2024").is_ok());
+        assert!(check_template("*", "orders").is_ok());
+        assert!(check_template("orders", "orders").is_ok());
+        let err = check_template("orders*", "payments").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidConfig);
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration of cache \"payments\"! template \"orders*\" does not match the name"
+        );
+    }
+
+    #[test]
+    fn test_check_expiry_policy() {
+        let features = Features::negotiated(ProtocolVersion::EXPIRY_POLICY, &[]);
+        assert!(check_expiry_policy("sessions", &features).is_ok());
+        let features = Features::negotiated(ProtocolVersion(1, 5, 0), &[]);
+        let err = check_expiry_policy("sessions", &features).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidConfig);
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration of cache \"sessions\"! expiry_policy needs protocol v1.6.0, the node supports v1.5.0"
+        );
+    }
+
+    #[test]
+    fn test_delete_where() {
+        let mut config = CacheConfiguration::new("people");
+        let err = delete_statement("people", &config, "age > ?").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidConfig);
+        config.query_entities = Some(vec![QueryEntity::new("java.lang.Long", "org.shop.Person")]);
+        let query = delete_statement("people", &config, "age > ?").unwrap();
+        assert_eq!(query, "DELETE FROM Person WHERE age > ?");
+        config.sql_escape_all = true;
+        config.query_entities = Some(vec![QueryEntity::new("long", "Person").table("adults")]);
+        let query = delete_statement("people", &config, "age > ?").unwrap();
+        assert_eq!(query, "DELETE FROM \"adults\" WHERE age > ?");
+        let query = keys_statement("people", &config).unwrap();
+        assert_eq!(query, "SELECT _key FROM \"adults\"");
+        config.query_entities = None;
+        assert_eq!(keys_statement("people", &config), None);
+
+        // cursor id, the column, a row with the count, no more rows
+        let mut resp = Vec::new();
+        write_i64(&mut resp, 1).unwrap();
+        write_i32(&mut resp, 1).unwrap();
+        "UPDATED".write(&mut resp).unwrap();
+        write_i32(&mut resp, 1).unwrap();
+        3i64.write(&mut resp).unwrap();
+        write_bool(&mut resp, false).unwrap();
+        let resp = SqlFieldsResp::read(&mut Cursor::new(resp)).unwrap();
+        assert_eq!(resp.open_cursor(), None);
+        assert_eq!(updated_rows(&resp).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_cache_entries() {
+        /// Entries i => i * 10 and whether the cursor has more
+        fn page(cursor_id: Option<i64>, keys: &[i32], more: bool) -> Vec<u8> {
+            let mut page = Vec::new();
+            if let Some(cursor_id) = cursor_id {
+                write_i64(&mut page, cursor_id).unwrap();
+            }
+            write_i32(&mut page, keys.len() as i32).unwrap();
+            for key in keys {
+                key.write(&mut page).unwrap();
+                (key * 10).write(&mut page).unwrap();
+            }
+            page.push(more as u8);
+            page
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::QueryScan as i16);
+            write_success(&mut stream, request_id, &page(Some(7), &[1, 2], true));
+            // the other requests go over the same connection between the pages
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::CacheClear as i16);
+            write_success(&mut stream, request_id, &[]);
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::QueryScanCursorGetPage as i16);
+            write_success(&mut stream, request_id, &page(None, &[3], false));
+            // the iterator which is dropped early closes its cursor
+            let (_, request_id) = read_req(&mut stream);
+            write_success(&mut stream, request_id, &page(Some(8), &[1], true));
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::ResourceClose as i16);
+            write_success(&mut stream, request_id, &[]);
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn);
+        let mut entries = cache.entries(2);
+        assert_eq!(entries.next().unwrap().unwrap(), (1, 10));
+        assert_eq!(entries.next().unwrap().unwrap(), (2, 20));
+        cache.clear().unwrap();
+        assert_eq!(entries.next().unwrap().unwrap(), (3, 30));
+        assert!(entries.next().is_none());
+        let mut entries = cache.iter();
+        assert_eq!(entries.next().unwrap().unwrap(), (1, 10));
+        drop(entries);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_scan_partitions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::CachePartitions as i16);
+            write_success(
+                &mut stream,
+                request_id,
+                &partition_map_payload(1, 1, &[0, 1]),
+            );
+            // a scan per partition, each has a single page with the key of the partition number
+            for partition in 0..2 {
+                let len = read_i32(&mut stream).unwrap();
+                let mut msg = vec![0u8; len as usize];
+                stream.read_exact(&mut msg).unwrap();
+                assert_eq!(read_i16(&mut &msg[..]).unwrap(), OpCode::QueryScan as i16);
+                assert_eq!(read_i32(&mut &msg[20..]).unwrap(), partition);
+                let mut page = Vec::new();
+                write_i64(&mut page, partition as i64).unwrap(); // cursor
+                write_i32(&mut page, 1).unwrap();
+                partition.write(&mut page).unwrap();
+                partition.write(&mut page).unwrap();
+                page.push(0); // no more pages
+                write_success(&mut stream, read_i64(&mut &msg[2..]).unwrap(), &page);
+            }
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn);
+        let entries = cache.scan_partitions(1, 16).unwrap();
+        let entries: Vec<_> = entries.into_iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![(0, 0), (1, 1)]);
+        server.join().unwrap();
+        // the map which is fetched for the scan is known
+        let map = cache.partition_map().unwrap();
+        assert_eq!(map.topology, (1, 0));
+        assert_eq!(map.partitions_of(1), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_cache_entry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let mut op_codes = Vec::new();
+            let value = |stream: &mut std::net::TcpStream, request_id, value: i32| {
+                let mut payload = Vec::new();
+                value.write(&mut payload).unwrap();
+                write_success(stream, request_id, &payload);
+            };
+            // changed by another client once, then replaced
+            for replaced in &[0u8, 1] {
+                let (op_code, request_id) = read_req(&mut stream);
+                op_codes.push(op_code);
+                value(&mut stream, request_id, 5 + *replaced as i32);
+                let (op_code, request_id) = read_req(&mut stream);
+                op_codes.push(op_code);
+                write_success(&mut stream, request_id, &[*replaced]);
+            }
+            // absent, then put
+            let (op_code, request_id) = read_req(&mut stream);
+            op_codes.push(op_code);
+            write_success(&mut stream, request_id, &[101]);
+            let (op_code, request_id) = read_req(&mut stream);
+            op_codes.push(op_code);
+            write_success(&mut stream, request_id, &[101]);
+            // changed by other clients every time
+            for _ in 0..UPDATE_ATTEMPTS {
+                let (_, request_id) = read_req(&mut stream);
+                value(&mut stream, request_id, 5);
+                let (_, request_id) = read_req(&mut stream);
+                write_success(&mut stream, request_id, &[0]);
+            }
+            op_codes
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn);
+        let hits = cache.entry(1).and_modify(|hits| hits + 1).or_insert(1);
+        assert_eq!(hits.unwrap(), 7);
+        let hits = cache.entry(2).and_modify(|hits| hits + 1).or_insert(1);
+        assert_eq!(hits.unwrap(), 1);
+        let err = cache.entry(1).and_modify(|hits| hits + 1).modify();
+        assert_eq!(err.unwrap_err().kind(), ErrorKind::Conflict);
+        let get = OpCode::CacheGet as i16;
+        let replace = OpCode::CacheReplaceIfEquals as i16;
+        let put_if_absent = OpCode::CacheGetAndPutIfAbsent as i16;
+        assert_eq!(
+            server.join().unwrap(),
+            vec![get, replace, get, replace, get, put_if_absent]
+        );
     }
 }
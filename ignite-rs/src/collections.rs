@@ -0,0 +1,339 @@
+//! Distributed queue and set built on the caches, as the thin protocol has no data structures.
+//! The queue keeps its items by sequence numbers in one cache and the head and tail counters
+//! in another one, which are moved with compare-and-swap. The set keeps its items as keys
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cache::Cache;
+use crate::error::{ErrorKind, IgniteError, IgniteResult};
+use crate::{Ignite, ReadableType, WritableType};
+
+/// Key of the counter of the next item to poll
+const HEAD: i32 = 0;
+/// Key of the counter of the next item to offer
+const TAIL: i32 = 1;
+
+/// How long `poll` waits for the item of a slot it has taken, which the producer has not put yet
+const SLOT_WAIT: Duration = Duration::from_secs(1);
+const SLOT_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+/// Value which `poll` puts into the slot it skips, so the late producer could not put its item there
+const TOMBSTONE: bool = false;
+
+/// FIFO queue shared by the clients, like a simple work queue.
+/// `offer` takes the next slot of the tail, then puts the item into it if it is absent, and `poll`
+/// takes the slot of the head, then removes its item. If the item is not put in `SLOT_WAIT`,
+/// `poll` puts a tombstone into the slot and takes the next one, and the producer which finds
+/// the tombstone offers the item again at the tail. So a slow or failed producer does not lose
+/// the item, but the delivery is at-most-once: the item removed by a failed consumer is lost.
+/// `poll` blocks the thread while it waits for the item of its slot, see `with_timeout` to limit the wait
+///
+/// ```ignore
+/// let jobs = IgniteQueue::<Job>::get_or_create(&client, "jobs")?;
+/// jobs.offer(&job)?;
+/// while let Some(job) = jobs.poll()? { ... }
+/// ```
+pub struct IgniteQueue<T: WritableType + ReadableType> {
+    items: Cache<i64, T>,
+    /// The items cache, which tombstones are written as the values
+    tombstones: Cache<i64, bool>,
+    counters: Cache<i32, i64>,
+    /// Time limit of each operation, see `with_timeout`
+    timeout: Option<Duration>,
+}
+
+impl<T: WritableType + ReadableType> Clone for IgniteQueue<T> {
+    fn clone(&self) -> Self {
+        IgniteQueue {
+            items: self.items.clone(),
+            tombstones: self.tombstones.clone(),
+            counters: self.counters.clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<T: WritableType + ReadableType> IgniteQueue<T> {
+    /// Returns the queue, creates its caches `name` and `name-counters` if they do not exist
+    pub fn get_or_create(ignite: &impl Ignite, name: &str) -> IgniteResult<IgniteQueue<T>> {
+        let items = ignite.get_or_create_cache::<i64, T>(name)?;
+        let counters = ignite.get_or_create_cache::<i32, i64>(&format!("{}-counters", name))?;
+        Ok(IgniteQueue::new(items, counters))
+    }
+
+    pub(crate) fn new(items: Cache<i64, T>, counters: Cache<i32, i64>) -> IgniteQueue<T> {
+        let tombstones = items.with_value_type();
+        IgniteQueue {
+            items,
+            tombstones,
+            counters,
+            timeout: None,
+        }
+    }
+
+    /// Returns the handle of the same queue, which requests are limited by the timeout,
+    /// see `Cache::with_timeout`. `poll` fails with `ErrorKind::Timeout` error if it has not
+    /// got an item in time, the slot it waits for is skipped then like after `SLOT_WAIT`
+    pub fn with_timeout(&self, timeout: Duration) -> IgniteQueue<T> {
+        IgniteQueue {
+            items: self.items.with_timeout(timeout),
+            tombstones: self.tombstones.with_timeout(timeout),
+            counters: self.counters.with_timeout(timeout),
+            timeout: Some(timeout),
+        }
+    }
+
+    /// Returns the value of the counter, puts 0 if it is absent
+    fn counter(&self, key: i32) -> IgniteResult<i64> {
+        match self.counters.get(&key)? {
+            Some(value) => Ok(value),
+            None => Ok(self.counters.get_and_put_if_absent(&key, &0)?.unwrap_or(0)),
+        }
+    }
+
+    /// Moves the counter from the value to the next one, returns false if another client has moved it
+    fn advance(&self, key: i32, value: i64) -> IgniteResult<bool> {
+        self.counters.replace_if_equals(&key, &value, &(value + 1))
+    }
+
+    /// Adds the item to the tail of the queue
+    pub fn offer(&self, item: &T) -> IgniteResult<()> {
+        loop {
+            let tail = self.counter(TAIL)?;
+            if !self.advance(TAIL, tail)? {
+                continue;
+            }
+            if self.items.put_if_absent(&tail, item)? {
+                return Ok(());
+            }
+            // the consumer has skipped the slot, its tombstone is not needed anymore
+            self.tombstones.remove_key(&tail)?;
+        }
+    }
+
+    /// Removes the item of the head of the queue. Returns None if the queue is empty
+    pub fn poll(&self) -> IgniteResult<Option<T>> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(poll_timeout());
+            }
+            let head = self.counter(HEAD)?;
+            if head >= self.counter(TAIL)? {
+                return Ok(None);
+            }
+            if !self.advance(HEAD, head)? {
+                continue;
+            }
+            let slot_deadline = match deadline {
+                Some(deadline) => deadline.min(Instant::now() + SLOT_WAIT),
+                None => Instant::now() + SLOT_WAIT,
+            };
+            loop {
+                if let Some(item) = self.items.get_and_remove(&head)? {
+                    return Ok(Some(item));
+                }
+                let now = Instant::now();
+                if now >= slot_deadline {
+                    if self.tombstones.put_if_absent(&head, &TOMBSTONE)? {
+                        // the producer offers the item again, the next slot is taken
+                        break;
+                    }
+                    // the item has been put meanwhile
+                    continue;
+                }
+                thread::sleep(SLOT_CHECK_INTERVAL.min(slot_deadline - now));
+            }
+        }
+    }
+
+    /// Returns the number of the items, including the ones which are being offered
+    pub fn len(&self) -> IgniteResult<i64> {
+        let head = self.counter(HEAD)?;
+        Ok((self.counter(TAIL)? - head).max(0))
+    }
+
+    pub fn is_empty(&self) -> IgniteResult<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+fn poll_timeout() -> IgniteError {
+    IgniteError::new(
+        ErrorKind::Timeout,
+        "Timed out waiting for an item of the queue!".to_owned(),
+    )
+}
+
+/// Set shared by the clients, which items are the keys of a cache
+///
+/// ```ignore
+/// let workers = IgniteSet::<String>::get_or_create(&client, "workers")?;
+/// workers.add(&"worker-1".to_owned())?;
+/// ```
+pub struct IgniteSet<T: WritableType + ReadableType> {
+    cache: Cache<T, bool>,
+}
+
+impl<T: WritableType + ReadableType> Clone for IgniteSet<T> {
+    fn clone(&self) -> Self {
+        IgniteSet {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<T: WritableType + ReadableType> IgniteSet<T> {
+    /// Returns the set, creates its cache if it does not exist
+    pub fn get_or_create(ignite: &impl Ignite, name: &str) -> IgniteResult<IgniteSet<T>> {
+        Ok(IgniteSet::new(ignite.get_or_create_cache::<T, bool>(name)?))
+    }
+
+    pub(crate) fn new(cache: Cache<T, bool>) -> IgniteSet<T> {
+        IgniteSet { cache }
+    }
+
+    /// Adds the item, returns false if it is in the set already
+    pub fn add(&self, item: &T) -> IgniteResult<bool> {
+        self.cache.put_if_absent(item, &true)
+    }
+
+    /// Removes the item, returns false if it is not in the set
+    pub fn remove(&self, item: &T) -> IgniteResult<bool> {
+        self.cache.remove_key(item)
+    }
+
+    pub fn contains(&self, item: &T) -> IgniteResult<bool> {
+        self.cache.contains_key(item)
+    }
+
+    pub fn len(&self) -> IgniteResult<i64> {
+        self.cache.get_size()
+    }
+
+    pub fn is_empty(&self) -> IgniteResult<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns all the items, see `Cache::keys`
+    pub fn items(&self) -> IgniteResult<Vec<T>> {
+        self.cache.keys()
+    }
+
+    pub fn clear(&self) -> IgniteResult<()> {
+        self.cache.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::OpCode;
+    use crate::connection::tests::{read_req, write_handshake_resp, write_success};
+    use crate::connection::Connection;
+    use crate::ClientConfig;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_ignite_queue() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let long = |value: i64| {
+                let mut bytes = Vec::new();
+                value.write(&mut bytes).unwrap();
+                bytes
+            };
+            let mut job = Vec::new();
+            "job".to_owned().write(&mut job).unwrap();
+            let payloads = vec![
+                // offer: the tail is absent, so it is put as 0, then moved to 1
+                vec![101],
+                vec![101],
+                vec![1],
+                vec![1],
+                // poll: the head is 0 and the tail is 1
+                long(0),
+                long(1),
+                vec![1],
+                job,
+                // poll: the queue is empty
+                long(1),
+                long(1),
+                // offer: the slot 1 has the tombstone, so the item is offered into the slot 2
+                long(1),
+                vec![1],
+                vec![0],
+                vec![1],
+                long(2),
+                vec![1],
+                vec![1],
+            ];
+            let mut op_codes = Vec::new();
+            for payload in payloads {
+                let (op_code, request_id) = read_req(&mut stream);
+                op_codes.push(op_code);
+                write_success(&mut stream, request_id, &payload);
+            }
+            op_codes
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let items = Cache::<i64, String>::new(1, "jobs".to_owned(), conn.clone());
+        let counters = Cache::<i32, i64>::new(2, "jobs-counters".to_owned(), conn);
+        let queue = IgniteQueue::new(items, counters);
+        queue.offer(&"job".to_owned()).unwrap();
+        assert_eq!(queue.poll().unwrap(), Some("job".to_owned()));
+        assert_eq!(queue.poll().unwrap(), None);
+        queue.offer(&"job".to_owned()).unwrap();
+        let op_codes = server.join().unwrap();
+        assert_eq!(op_codes[2], OpCode::CacheReplaceIfEquals as i16);
+        assert_eq!(op_codes[3], OpCode::CachePutIfAbsent as i16);
+        assert_eq!(op_codes[7], OpCode::CacheGetAndRemove as i16);
+        assert_eq!(op_codes[13], OpCode::CacheRemoveKey as i16);
+        assert_eq!(op_codes[16], OpCode::CachePutIfAbsent as i16);
+    }
+
+    #[test]
+    fn test_ignite_queue_poll_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let long = |value: i64| {
+                let mut bytes = Vec::new();
+                value.write(&mut bytes).unwrap();
+                bytes
+            };
+            // poll: the head is 0 and the tail is 1, the head is moved to 1
+            for payload in [long(0), long(1), vec![1]] {
+                let (_, request_id) = read_req(&mut stream);
+                write_success(&mut stream, request_id, &payload);
+            }
+            // the item of the slot 0 is never put, so the slot gets the tombstone
+            loop {
+                let (op_code, request_id) = read_req(&mut stream);
+                if op_code == OpCode::CachePutIfAbsent as i16 {
+                    write_success(&mut stream, request_id, &[1]);
+                    return;
+                }
+                write_success(&mut stream, request_id, &[101]);
+            }
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let items = Cache::<i64, String>::new(1, "jobs".to_owned(), conn.clone());
+        let counters = Cache::<i32, i64>::new(2, "jobs-counters".to_owned(), conn);
+        let queue = IgniteQueue::new(items, counters).with_timeout(Duration::from_millis(100));
+        let started = Instant::now();
+        let err = queue.poll().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(started.elapsed() < SLOT_WAIT);
+        server.join().unwrap();
+    }
+}
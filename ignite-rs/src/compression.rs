@@ -0,0 +1,338 @@
+//! Client-side compression of the large values, like JSON documents.
+//! The values which are serialized into `Codec::THRESHOLD` bytes or more are compressed
+//! and stored as byte[] envelopes, the smaller ones are stored as is.
+//!
+//! The `lz4` and `zstd` features add the `Lz4` and `Zstd` codecs, other ones implement `Codec`.
+//!
+//! ```ignore
+//! use ignite_rs::compression::{Compressed, Lz4};
+//!
+//! let cache = client.get_or_create_cache::<String, Compressed<String, Lz4>>("documents")?;
+//! cache.put("key", &Compressed::new(json))?;
+//! ```
+//!
+//! Envelope, so the other clients could read the values:
+//! * magic bytes `IGNZ`
+//! * id of the codec, byte. `LZ4` (LZ4 block format) and `ZSTD` (Zstandard frame)
+//! * length of the serialized value, little-endian int
+//! * compressed serialized value, in Ignite binary format with its type code
+//!
+//! Envelopes whose length is negative or more than `MAX_RATIO` times the compressed size
+//! are rejected, the values which compress better than that are stored as is.
+//! Any byte[] which starts with `IGNZ` is taken for an envelope. So a small `Compressed<Vec<u8>, _>`
+//! value, which is stored as is, fails to read if it starts with `IGNZ` and the id of another codec.
+//!
+//! The nodes see the values as byte[], so they could not be queried with SQL
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Read, Write};
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{handles, write_i32, write_u8, TypeCode};
+use crate::{ReadableType, TypeInfo, WritableType};
+
+/// Codec id of LZ4 block format
+pub const LZ4: u8 = 1;
+/// Codec id of Zstandard frames
+pub const ZSTD: u8 = 2;
+
+const MAGIC: &[u8; 4] = b"IGNZ";
+/// Magic bytes, codec id and the length of the serialized value
+const ENVELOPE_HEADER_SIZE: usize = 4 + 1 + 4;
+/// Maximum ratio of the serialized and the compressed sizes, so a corrupt envelope
+/// could not make the codec allocate a huge buffer
+pub const MAX_RATIO: usize = 1024;
+
+/// Compression algorithm of `Compressed` values, see `Lz4` and `Zstd`
+pub trait Codec {
+    /// Id of the codec which is written into the envelope, see `LZ4` and `ZSTD`
+    const ID: u8;
+    /// Values which are serialized into fewer bytes are stored as is
+    const THRESHOLD: usize = 1024;
+
+    fn compress(data: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decompresses the data into `len` bytes
+    fn decompress(data: &[u8], len: usize) -> io::Result<Vec<u8>>;
+}
+
+/// LZ4 block format, with the `lz4_flex` crate
+#[cfg(feature = "lz4")]
+pub struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl Codec for Lz4 {
+    const ID: u8 = LZ4;
+
+    fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(lz4_flex::block::compress(data))
+    }
+
+    fn decompress(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        lz4_flex::block::decompress(data, len)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Zstandard frames of the default level, with the `zstd` crate
+#[cfg(feature = "zstd")]
+pub struct Zstd;
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    const ID: u8 = ZSTD;
+
+    fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    fn decompress(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+        zstd::bulk::decompress(data, len)
+    }
+}
+
+/// Value which is compressed with the codec if it is large, see the module docs
+pub struct Compressed<V, C: Codec> {
+    value: V,
+    /// Bytes which are written, encoded once by `size` or `write`, whichever is first
+    encoded: OnceLock<Vec<u8>>,
+    codec: PhantomData<C>,
+}
+
+impl<V, C: Codec> Compressed<V, C> {
+    pub fn new(value: V) -> Compressed<V, C> {
+        Compressed {
+            value,
+            encoded: OnceLock::new(),
+            codec: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> &V {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> V {
+        self.value
+    }
+}
+
+impl<V: Clone, C: Codec> Clone for Compressed<V, C> {
+    fn clone(&self) -> Self {
+        Compressed {
+            value: self.value.clone(),
+            encoded: self.encoded.clone(),
+            codec: PhantomData,
+        }
+    }
+}
+
+impl<V: fmt::Debug, C: Codec> fmt::Debug for Compressed<V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Compressed").field(&self.value).finish()
+    }
+}
+
+impl<V: PartialEq, C: Codec> PartialEq for Compressed<V, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<V: WritableType, C: Codec> Compressed<V, C> {
+    /// Returns the bytes which are written, encodes them the first time
+    fn encoded(&self) -> io::Result<&[u8]> {
+        if let Some(bytes) = self.encoded.get() {
+            return Ok(bytes);
+        }
+        let bytes = self.encode()?;
+        Ok(self.encoded.get_or_init(|| bytes))
+    }
+
+    /// Returns the envelope or the serialized value
+    fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.value.size());
+        self.value.write(&mut data)?;
+        if data.len() < C::THRESHOLD {
+            return Ok(data);
+        }
+        let compressed = C::compress(&data)?;
+        if data.len() > compressed.len() * MAX_RATIO {
+            return Ok(data); // would be rejected by the readers
+        }
+        let len = ENVELOPE_HEADER_SIZE + compressed.len();
+        let mut envelope = Vec::with_capacity(len + 5);
+        write_u8(&mut envelope, TypeCode::ArrByte as u8)?;
+        write_i32(&mut envelope, len as i32)?;
+        envelope.extend_from_slice(MAGIC);
+        write_u8(&mut envelope, C::ID)?;
+        write_i32(&mut envelope, data.len() as i32)?;
+        envelope.extend_from_slice(&compressed);
+        Ok(envelope)
+    }
+}
+
+impl<V: WritableType, C: Codec> WritableType for Compressed<V, C> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(self.encoded()?)
+    }
+
+    fn size(&self) -> usize {
+        // the size is known only when the value is compressed, the bytes are kept for the write
+        self.encoded().map_or(0, |bytes| bytes.len())
+    }
+}
+
+impl<V: ReadableType, C: Codec> ReadableType for Compressed<V, C> {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        if type_code != TypeCode::ArrByte {
+            return Ok(V::read_unwrapped(type_code, reader)?.map(Compressed::new));
+        }
+        let bytes = match Vec::<u8>::read_unwrapped(type_code, reader)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        if !bytes.starts_with(MAGIC) || bytes.len() < ENVELOPE_HEADER_SIZE {
+            // byte[] value which is not compressed
+            let mut data = vec![TypeCode::ArrByte as u8];
+            data.extend_from_slice(&(bytes.len() as i32).to_le_bytes());
+            data.extend_from_slice(&bytes);
+            return Ok(V::read(&mut Cursor::new(data))?.map(Compressed::new));
+        }
+        if bytes[4] != C::ID {
+            return Err(IgniteError::from(
+                format!("Value is compressed with codec {}, not {}", bytes[4], C::ID).as_str(),
+            ));
+        }
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&bytes[5..ENVELOPE_HEADER_SIZE]);
+        let len = i32::from_le_bytes(len);
+        let compressed = &bytes[ENVELOPE_HEADER_SIZE..];
+        if len < 0 || len as usize > compressed.len() * MAX_RATIO {
+            return Err(IgniteError::from(
+                format!("Invalid length {} of the compressed value", len).as_str(),
+            ));
+        }
+        let data = C::decompress(compressed, len as usize)?;
+        let value = handles::read_bytes(data, 0, |reader| V::read(reader))?;
+        Ok(value.map(Compressed::new))
+    }
+}
+
+/// The values are stored as byte[] or as is, so they are not checked
+impl<V, C: Codec> TypeInfo for Compressed<V, C> {
+    fn cluster_type_name() -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COMPRESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Run-length encoding of the bytes, as pairs of count and byte
+    struct RunLength;
+
+    impl Codec for RunLength {
+        const ID: u8 = 100;
+        const THRESHOLD: usize = 16;
+
+        fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+            COMPRESSIONS.fetch_add(1, Ordering::SeqCst);
+            let mut out: Vec<u8> = Vec::new();
+            for &byte in data {
+                match out.len() {
+                    len if len >= 2 && out[len - 1] == byte && out[len - 2] < u8::MAX => {
+                        out[len - 2] += 1
+                    }
+                    _ => out.extend_from_slice(&[1, byte]),
+                }
+            }
+            Ok(out)
+        }
+
+        fn decompress(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+            let mut out = Vec::with_capacity(len);
+            for pair in data.chunks(2) {
+                out.extend(std::iter::repeat_n(pair[1], pair[0] as usize));
+            }
+            Ok(out)
+        }
+    }
+
+    fn round_trip<V: WritableType + ReadableType, C: Codec>(value: V) -> (Vec<u8>, V) {
+        let compressed = Compressed::<V, C>::new(value);
+        let size = compressed.size();
+        let mut bytes = Vec::new();
+        compressed.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        let read = Compressed::<V, C>::read(&mut Cursor::new(bytes.clone()));
+        (bytes, read.unwrap().unwrap().into_inner())
+    }
+
+    #[test]
+    fn test_compressed() {
+        let small = "short".to_owned();
+        let (bytes, read) = round_trip::<_, RunLength>(small.clone());
+        assert_eq!(bytes[0], TypeCode::String as u8);
+        assert_eq!(read, small);
+
+        let large = "a".repeat(1000);
+        let compressions = COMPRESSIONS.load(Ordering::SeqCst);
+        let (bytes, read) = round_trip::<_, RunLength>(large.clone());
+        // the size and the write share the compressed bytes
+        assert_eq!(COMPRESSIONS.load(Ordering::SeqCst), compressions + 1);
+        assert_eq!(bytes[0], TypeCode::ArrByte as u8);
+        assert_eq!(&bytes[5..9], MAGIC);
+        assert!(bytes.len() < 100);
+        assert_eq!(read, large);
+
+        // byte[] values which are not compressed are read as is
+        let (_, read) = round_trip::<_, RunLength>(vec![1u8, 2, 3]);
+        assert_eq!(read, vec![1u8, 2, 3]);
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        let (mut bytes, _) = round_trip::<_, RunLength>("a".repeat(1000));
+        bytes[14..18].copy_from_slice(&(-1i32).to_le_bytes());
+        let read = Compressed::<String, RunLength>::read(&mut Cursor::new(bytes.clone()));
+        assert!(read.is_err());
+
+        bytes[14..18].copy_from_slice(&i32::MAX.to_le_bytes());
+        let read = Compressed::<String, RunLength>::read(&mut Cursor::new(bytes));
+        assert!(read.is_err());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4() {
+        let large = "lz4 ".repeat(1000);
+        let (bytes, read) = round_trip::<_, Lz4>(large.clone());
+        assert_eq!(bytes[9], LZ4);
+        assert!(bytes.len() < 500);
+        assert_eq!(read, large);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let large = "zstd ".repeat(1000);
+        let (bytes, read) = round_trip::<_, Zstd>(large.clone());
+        assert_eq!(bytes[9], ZSTD);
+        assert!(bytes.len() < 500);
+        assert_eq!(read, large);
+
+        // compresses better than MAX_RATIO, so it is stored as is
+        let huge = "z".repeat(MAX_RATIO * 100);
+        let (bytes, read) = round_trip::<_, Zstd>(huge.clone());
+        assert_eq!(bytes[0], TypeCode::String as u8);
+        assert_eq!(read, huge);
+    }
+}
@@ -1,108 +1,1541 @@
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
 
-use crate::api::OpCode;
-use crate::error::{IgniteError, IgniteResult};
-use crate::handshake::handshake;
+use crate::address::translated;
+use crate::affinity::{PartitionMap, TopologyVersion};
+use crate::api::affinity::{CachePartitionsReq, CachePartitionsResp};
+use crate::api::binary::{BinaryTypeGetReq, BinaryTypeGetResp};
+use crate::api::cluster::{NodesEndpointsReq, NodesEndpointsResp, CURRENT_TOPOLOGY};
+use crate::api::connection::{EmptyReq, IdleTimeoutResp, RawReq, ResourceCloseReq};
+use crate::api::continuous::{ContinuousQueryReq, ContinuousQueryResp};
+use crate::api::key_value::{BatchLimits, QueryCursorPageReq};
+use crate::api::{CursorPage, OpCode};
+use crate::balancer::NodeLoad;
+use crate::binary::register_binary_type;
+use crate::cache::CachePartitionMap;
+use crate::error::{ErrorKind, IgniteError, IgniteResult};
+use crate::events::{notify, ConnectionEvent};
+use crate::features::{Feature, Features};
+use crate::handshake::{handshake, ProtocolVersion};
+use crate::interceptor::CacheInterceptor;
 use crate::protocol::Flag::{Failure, Success};
-use crate::protocol::{read_i32, read_i64, write_i16, write_i32, write_i64, Flag};
-use crate::{ClientConfig, ReadableReq};
+use crate::protocol::{
+    read_i16, read_i32, read_i64, with_numeric_widening, with_string_decoding, write_i16,
+    write_i32, write_i64, Flag, StringDecoding,
+};
+use crate::retry::RetryContext;
+#[cfg(any(feature = "tls", feature = "native-tls"))]
+use crate::tls::TlsStream;
+use crate::utils::shuffled;
+use crate::{ClientConfig, PoolStatus, RawResponse, ReadableReq, WritableType};
 use crate::{ReadableType, WriteableReq};
 use bufstream::BufStream;
-#[cfg(feature = "ssl")]
-use rustls;
+use socket2::{SockRef, TcpKeepalive};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io;
+use std::io::Cursor;
 use std::option::Option::Some;
-#[allow(unused_imports)]
-use std::sync::{Arc, Mutex};
-#[cfg(feature = "ssl")]
-use webpki;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
-const DFLT_READ_BUF_SIZE: usize = 1024;
-const DFLT_WRITE_BUF_SIZE: usize = 1024;
+const DFLT_READ_BUF_SIZE: usize = 8192;
+const DFLT_WRITE_BUF_SIZE: usize = 8192;
 const REQ_HEADER_SIZE_BYTES: i32 = 10;
+const FLAG_ERROR: i16 = 1;
+const FLAG_TOPOLOGY_CHANGED: i16 = 2;
+const FLAG_NOTIFICATION: i16 = 4;
+const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_millis(100);
+/// How long dropping the client waits for the nodes to close the cursors
+const CLOSE_ON_DROP_TIMEOUT: Duration = Duration::from_secs(1);
 
+/// Ids of the requests, which the nodes echo in the responses
+static NEXT_REQUEST_ID: AtomicI64 = AtomicI64::new(1);
+
+thread_local! {
+    /// Deadline of the operation which the thread does, see `with_op_timeout`
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// Connection which the requests of the thread are sent over, see `with_pinned`
+    static PINNED: RefCell<Option<Arc<Pinned>>> = const { RefCell::new(None) };
+    /// Query cursor which the requests of the thread are sent to, see `with_cursor`
+    static CURSOR: Cell<Option<i64>> = const { Cell::new(None) };
+}
+
+/// Limits the time of the requests within the closure. A request which could not be completed
+/// in time fails with `ErrorKind::Timeout` error. Its connection is closed, as the response could come later
+pub(crate) fn with_op_timeout<T, F: FnOnce() -> T>(timeout: Option<Duration>, op: F) -> T {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let prev = DEADLINE.with(|cell| cell.replace(deadline));
+    // restore the deadline even if the operation has panicked
+    struct Restore(Option<Instant>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEADLINE.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(prev);
+    op()
+}
+
+/// Sends the requests within the closure over the pinned connection, if it is given
+pub(crate) fn with_pinned<T, F: FnOnce() -> T>(pinned: Option<&Arc<Pinned>>, op: F) -> T {
+    let pinned = match pinned {
+        Some(pinned) => pinned.clone(),
+        None => return op(),
+    };
+    let prev = PINNED.with(|cell| cell.replace(Some(pinned)));
+    struct Restore(Option<Arc<Pinned>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            PINNED.with(|cell| *cell.borrow_mut() = self.0.take());
+        }
+    }
+    let _restore = Restore(prev);
+    op()
+}
+
+/// Sends the requests within the closure over the connection which has opened the query cursor,
+/// as the cursor exists on it only. The requests wait for the connection if it is checked out
+fn with_cursor<T, F: FnOnce() -> T>(cursor_id: i64, op: F) -> T {
+    let prev = CURSOR.with(|cell| cell.replace(Some(cursor_id)));
+    struct Restore(Option<i64>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURSOR.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(prev);
+    op()
+}
+
+pub(crate) fn closed() -> IgniteError {
+    IgniteError::new(ErrorKind::Closed, "Client is closed!".to_owned())
+}
+
+fn timed_out() -> IgniteError {
+    IgniteError::new(ErrorKind::Timeout, "Operation timed out!".to_owned())
+}
+
+type Stream = BufStream<NodeStream>;
+
+/// TCP stream, which is encrypted if TLS is configured
+enum NodeStream {
+    Plain(TcpStream),
+    #[cfg(any(feature = "tls", feature = "native-tls"))]
+    Tls(TlsStream<TcpStream>),
+}
+
+impl NodeStream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            NodeStream::Plain(stream) => stream,
+            #[cfg(any(feature = "tls", feature = "native-tls"))]
+            NodeStream::Tls(stream) => stream.get_ref(),
+        }
+    }
+}
+
+impl Read for NodeStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            NodeStream::Plain(stream) => stream.read(buf),
+            #[cfg(any(feature = "tls", feature = "native-tls"))]
+            NodeStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for NodeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            NodeStream::Plain(stream) => stream.write(buf),
+            #[cfg(any(feature = "tls", feature = "native-tls"))]
+            NodeStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            NodeStream::Plain(stream) => stream.flush(),
+            #[cfg(any(feature = "tls", feature = "native-tls"))]
+            NodeStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Pooled TCP connections with the cluster.
+/// Each request checks out an idle connection, so concurrent requests do not wait for each other
+/// while less than `pool_max_connections` are open. Connections are used in round-robin order.
+/// With partition awareness, single-key requests check out a connection to the primary node of the key
 pub struct Connection {
-    #[cfg(not(feature = "ssl"))]
-    stream: Mutex<BufStream<TcpStream>>,
-    #[cfg(feature = "ssl")]
-    stream: Mutex<BufStream<rustls::StreamOwned<rustls::ClientSession, TcpStream>>>,
+    conf: ClientConfig,
+    max_connections: usize,
+    pool: Mutex<Pool>,
+    released: Condvar,
+    /// Addresses of the nodes by their ids
+    nodes: Mutex<HashMap<u128, String>>,
+    /// Endpoints of the nodes which are learned from the cluster, by node ids
+    discovered: Mutex<HashMap<u128, Vec<String>>>,
+    affinity: Mutex<Affinity>,
+    /// Set by `close`, the connections are closed as they are released then
+    closed: AtomicBool,
+    /// Features negotiated with the node of the first connection
+    features: Features,
+    string_decoding: StringDecoding,
+    numeric_widening: bool,
+}
+
+struct Pool {
+    idle: VecDeque<PooledStream>,
+    open: usize,
+    /// Checked out connections by node addresses
+    in_flight: HashMap<String, usize>,
+    /// Connections closed because they broke
+    evicted: u64,
+    /// Query cursors of the checked out connections, which come back to the pool
+    lent_cursors: HashSet<i64>,
+}
+
+impl Pool {
+    /// Checks out the idle connection
+    fn take(&mut self, index: usize) -> Option<PooledStream> {
+        let stream = self.idle.remove(index)?;
+        self.lent_cursors.extend(&stream.cursors);
+        Some(stream)
+    }
+
+    /// Counts off the checked out connection to the node
+    fn done(&mut self, addr: &str) {
+        if let Some(count) = self.in_flight.get_mut(addr) {
+            *count -= 1;
+            if *count == 0 {
+                self.in_flight.remove(addr);
+            }
+        }
+    }
+}
+
+/// Partition maps of the caches, which are valid for the affinity topology version
+#[derive(Default)]
+struct Affinity {
+    topology: Option<TopologyVersion>,
+    /// None if the cache could not be routed by keys
+    caches: HashMap<i32, Option<Arc<PartitionMap>>>,
+}
+
+/// Operation code with the properties which the retry policy gets
+#[derive(Clone, Copy)]
+struct Op {
+    code: i16,
+    idempotent: bool,
+    read_only: bool,
+}
+
+impl From<OpCode> for Op {
+    fn from(op_code: OpCode) -> Op {
+        Op {
+            code: op_code.into(),
+            idempotent: op_code.is_idempotent(),
+            read_only: op_code.is_read_only(),
+        }
+    }
+}
+
+/// Common header of the responses
+pub(crate) struct RespHeader {
+    pub(crate) request_id: i64,
+    pub(crate) flag: Flag,
+    /// New affinity topology version, if it has changed
+    pub(crate) topology: Option<TopologyVersion>,
+}
+
+/// Remembers if reading or writing has failed, so the broken connection is not reused
+struct PooledStream {
+    stream: Stream,
+    addr: String,
+    /// Protocol version and features agreed on in the handshake
+    features: Features,
+    node_id: Option<u128>,
+    /// The latest affinity topology version reported by the node
+    topology: Option<TopologyVersion>,
+    /// When the connection was returned to the pool
+    last_used: Instant,
+    /// Query cursors which are left open on the node
+    cursors: Vec<i64>,
+    failed: bool,
+}
+
+impl PooledStream {
+    /// Limits the socket timeouts by the time left until the deadline.
+    /// Restores the configured timeouts if there is no deadline
+    fn limit_timeouts(&self, conf: &ClientConfig, deadline: Option<Instant>) -> IgniteResult<()> {
+        let (read_timeout, write_timeout) = match deadline {
+            None => (conf.tcp_read_timeout, conf.tcp_write_timeout),
+            Some(deadline) => {
+                let left = deadline
+                    .checked_duration_since(Instant::now())
+                    .filter(|left| !left.is_zero())
+                    .ok_or_else(timed_out)?;
+                (
+                    Some(conf.tcp_read_timeout.map_or(left, |t| t.min(left))),
+                    Some(conf.tcp_write_timeout.map_or(left, |t| t.min(left))),
+                )
+            }
+        };
+        let tcp = self.stream.get_ref().tcp();
+        tcp.set_read_timeout(read_timeout)?;
+        tcp.set_write_timeout(write_timeout)?;
+        Ok(())
+    }
+}
+
+impl Read for PooledStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.stream.read(buf);
+        // end of the stream means that the connection is closed by the node
+        self.failed |= matches!(result, Err(_) | Ok(0)) && !buf.is_empty();
+        result
+    }
+}
+
+impl Write for PooledStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.stream.write(buf);
+        self.failed |= result.is_err();
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.stream.flush();
+        self.failed |= result.is_err();
+        result
+    }
+}
+
+/// Continuous query which runs while it is not dropped, see `Connection::subscribe`
+pub(crate) struct Subscription {
+    tcp: TcpStream,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // the node stops the query when its connection is closed
+        let _ = self.tcp.shutdown(Shutdown::Both);
+    }
+}
+
+/// Connection which is checked out of the pool for a sequence of requests.
+/// It is returned to the pool when released or dropped
+pub(crate) struct Pinned {
+    conn: Arc<Connection>,
+    /// None if released or broken
+    stream: Mutex<Option<PooledStream>>,
+    pub(crate) addr: String,
+    pub(crate) node_id: Option<u128>,
+}
+
+impl Pinned {
+    /// Checks out a connection, to the node if it is given
+    pub(crate) fn new(conn: &Arc<Connection>, node: Option<u128>) -> IgniteResult<Pinned> {
+        let stream = conn.acquire(node)?;
+        if node.is_some() && stream.node_id != node {
+            conn.release(Some(stream));
+            return Err(IgniteError::new(
+                ErrorKind::Disconnected,
+                format!("Could not connect to node {:x}!", node.unwrap_or_default()),
+            ));
+        }
+        Ok(Pinned {
+            conn: conn.clone(),
+            addr: stream.addr.clone(),
+            node_id: stream.node_id,
+            stream: Mutex::new(Some(stream)),
+        })
+    }
+
+    /// Returns the connection to the pool
+    pub(crate) fn release(&self) {
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            self.conn.release(Some(stream));
+        }
+    }
+
+    /// Sends the request over the pinned connection. It is not retried, as it should not go
+    /// over another connection. A broken connection is closed
+    fn request<T>(
+        &self,
+        request: impl Fn(&mut PooledStream) -> IgniteResult<T>,
+    ) -> IgniteResult<T> {
+        let conn = &self.conn;
+        if conn.is_closed() {
+            self.release();
+            return Err(closed());
+        }
+        let mut pinned = self.stream.lock().unwrap();
+        let stream = pinned.as_mut().ok_or_else(|| {
+            IgniteError::new(
+                ErrorKind::Disconnected,
+                "Pinned connection is released or broken!".to_owned(),
+            )
+        })?;
+        let deadline = DEADLINE.with(Cell::get);
+        if deadline.is_some() {
+            stream.limit_timeouts(&conn.conf, deadline)?;
+        }
+        let result = request(stream);
+        if deadline.is_some() {
+            stream.failed |= stream.limit_timeouts(&conn.conf, None).is_err();
+        }
+        if let Some(topology) = stream.topology.take() {
+            conn.on_topology_changed(topology);
+        }
+        if !stream.failed {
+            return result;
+        }
+        if let Err(err) = &result {
+            notify(&conn.conf, || ConnectionEvent::Disconnected {
+                addr: self.addr.clone(),
+                reason: err.to_string(),
+            });
+        }
+        conn.release(pinned.take());
+        match result {
+            Err(err) if err.kind() == ErrorKind::Timeout => Err(err),
+            Err(err) => Err(IgniteError::new(ErrorKind::Disconnected, err.desc)),
+            result => result,
+        }
+    }
+}
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 impl Connection {
     pub(crate) fn new(conf: &ClientConfig) -> IgniteResult<Connection> {
-        match TcpStream::connect(&conf.addr) {
+        if conf.addrs.is_empty() {
+            return Err(IgniteError::from("No node addresses are configured!"));
+        }
+        if conf.pool_max_connections == 0 {
+            return Err(IgniteError::from(
+                "pool_max_connections should be positive!",
+            ));
+        }
+        let mut idle = VecDeque::new();
+        let mut max_connections = conf.pool_max_connections;
+        if conf.partition_awareness || conf.warm_up {
+            // a connection to each node is required to route the requests
+            max_connections = max_connections.max(conf.addrs.len());
+        }
+        if conf.partition_awareness {
+            let mut errors = Vec::new();
+            for addr in &conf.addrs {
+                match Connection::connect_addr(conf, addr) {
+                    Ok(stream) => idle.push_back(stream),
+                    Err(err) if err.kind() == ErrorKind::Disconnected => {
+                        errors.push(format!("{}: {}", addr, err))
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            if idle.is_empty() {
+                return Err(IgniteError::new(
+                    ErrorKind::Disconnected,
+                    format!("Could not connect to any node! {}", errors.join("; ")),
+                ));
+            }
+        } else {
+            let opened = conf.pool_min_connections.max(1).min(max_connections);
+            for _ in 0..opened {
+                idle.push_back(Connection::connect(conf, &conf.addrs)?);
+            }
+        }
+        let nodes = idle
+            .iter()
+            .filter_map(|stream| Some((stream.node_id?, stream.addr.clone())))
+            .collect();
+        let features = idle[0].features.clone();
+        Ok(Connection {
+            conf: conf.clone(),
+            features,
+            max_connections,
+            pool: Mutex::new(Pool {
+                open: idle.len(),
+                idle,
+                in_flight: HashMap::new(),
+                evicted: 0,
+                lent_cursors: HashSet::new(),
+            }),
+            released: Condvar::new(),
+            nodes: Mutex::new(nodes),
+            discovered: Mutex::new(HashMap::new()),
+            affinity: Mutex::new(Affinity::default()),
+            closed: AtomicBool::new(false),
+            string_decoding: conf.string_decoding,
+            numeric_widening: conf.numeric_widening,
+        })
+    }
+
+    /// Opens a new TCP connection to any of the nodes. The nodes are tried in random order.
+    /// Fails with `Disconnected` error if none of the nodes is available
+    fn connect(conf: &ClientConfig, addrs: &[String]) -> IgniteResult<PooledStream> {
+        let mut errors = Vec::new();
+        for addr in shuffled(addrs) {
+            match Connection::connect_addr(conf, &addr) {
+                Ok(stream) => return Ok(stream),
+                Err(err) if err.kind() == ErrorKind::Disconnected => {
+                    errors.push(format!("{}: {}", addr, err))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(IgniteError::new(
+            ErrorKind::Disconnected,
+            format!("Could not connect to any node! {}", errors.join("; ")),
+        ))
+    }
+
+    /// Opens a new TCP connection to the node and does the handshake
+    fn connect_addr(conf: &ClientConfig, addr: &str) -> IgniteResult<PooledStream> {
+        let stream = Connection::connect_tcp(addr, conf)
+            .map_err(|err| IgniteError::new(ErrorKind::Disconnected, err.to_string()))?;
+        notify(conf, || ConnectionEvent::Connected {
+            addr: addr.to_owned(),
+        });
+
+        // apply tcp configs
+        Connection::configure_tcp(&stream, conf)?;
+
+        // wrap in tls stream if it is configured
+        #[cfg(any(feature = "tls", feature = "native-tls"))]
+        let stream = match &conf.tls {
+            Some(tls) => NodeStream::Tls(TlsStream::wrap(tls, stream)?),
+            None => NodeStream::Plain(stream),
+        };
+        #[cfg(not(any(feature = "tls", feature = "native-tls")))]
+        let stream = NodeStream::Plain(stream);
+
+        // wrap in buffered stream
+        let mut buffered_stream = BufStream::with_capacities(
+            conf.tcp_read_buff_size.unwrap_or(DFLT_READ_BUF_SIZE),
+            conf.tcp_write_buff_size.unwrap_or(DFLT_WRITE_BUF_SIZE),
+            stream,
+        );
+
+        // try initial handshake
+        let handshake = handshake(&mut buffered_stream, conf)?;
+        notify(conf, || ConnectionEvent::HandshakeCompleted {
+            addr: addr.to_owned(),
+            node_id: handshake.node_id,
+        });
+        Ok(PooledStream {
+            stream: buffered_stream,
+            addr: addr.to_owned(),
+            features: handshake.features,
+            node_id: handshake.node_id,
+            topology: None,
+            last_used: Instant::now(),
+            cursors: Vec::new(),
+            failed: false,
+        })
+    }
+
+    /// Opens a TCP connection to the node, through the proxy if it is configured
+    fn connect_tcp(addr: &str, conf: &ClientConfig) -> io::Result<TcpStream> {
+        let addr = translated(conf, addr).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Address is not translated!")
+        })?;
+        let proxy = match &conf.proxy {
+            Some(proxy) => proxy,
+            None => return Connection::connect_direct(&addr, conf.tcp_connect_timeout),
+        };
+        let mut stream = Connection::connect_direct(proxy.addr(), conf.tcp_connect_timeout)?;
+        // the proxy should answer in time too, the configured timeouts are applied later
+        stream.set_read_timeout(conf.tcp_connect_timeout)?;
+        stream.set_write_timeout(conf.tcp_connect_timeout)?;
+        proxy.tunnel(&mut stream, &addr)?;
+        Ok(stream)
+    }
+
+    /// Opens a TCP connection, trying each of the resolved addresses in random order.
+    /// The host name is resolved on every attempt, so the nodes which have moved to other IPs are found
+    fn connect_direct(addr: &str, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        for socket_addr in shuffled(&addr.to_socket_addrs()?.collect::<Vec<_>>()) {
+            let result = match timeout {
+                Some(timeout) => TcpStream::connect_timeout(&socket_addr, timeout),
+                None => TcpStream::connect(socket_addr),
+            };
+            match result {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Address is not resolved!")
+        }))
+    }
+
+    /// Opens a new connection to the node with the address, or to any node.
+    /// A slot in the pool should be reserved for it
+    fn open(&self, addr: Option<&str>) -> IgniteResult<PooledStream> {
+        let result = match addr {
+            Some(addr) => Connection::connect_addr(&self.conf, addr),
+            None => Connection::connect(&self.conf, &self.addrs()),
+        };
+        match &result {
             Ok(stream) => {
-                // apply tcp configs
-                Connection::configure_tcp(&stream, conf)?;
+                if let Some(node_id) = stream.node_id {
+                    let mut nodes = self.nodes.lock().unwrap();
+                    nodes.insert(node_id, stream.addr.clone());
+                }
+            }
+            Err(_) => self.release(None),
+        }
+        result
+    }
+
+    /// Checks out a connection for the request, preferably to the given node.
+    /// Waits for an idle connection if `pool_max_connections` are open already
+    fn acquire(&self, node: Option<u128>) -> IgniteResult<PooledStream> {
+        let stream = self.checkout(node)?;
+        let mut pool = self.pool.lock().unwrap();
+        *pool.in_flight.entry(stream.addr.clone()).or_default() += 1;
+        Ok(stream)
+    }
+
+    /// Takes an idle connection or opens a new one. Requests which are not routed to a node
+    /// go to the node chosen by the load balancer, or over any idle connection if the pool is full
+    fn checkout(&self, node: Option<u128>) -> IgniteResult<PooledStream> {
+        let pool_deadline = self.conf.pool_acquire_timeout.map(|t| Instant::now() + t);
+        let deadline = match (pool_deadline, DEADLINE.with(Cell::get)) {
+            (Some(pool_deadline), Some(op_deadline)) => Some(pool_deadline.min(op_deadline)),
+            (pool_deadline, op_deadline) => pool_deadline.or(op_deadline),
+        };
+        let mut pool = self.pool.lock().unwrap();
+        if self.is_closed() {
+            return Err(closed());
+        }
+        if let Some(cursor_id) = CURSOR.with(Cell::get) {
+            return self.checkout_cursor(pool, cursor_id, deadline);
+        }
+        if let Some(node) = node {
+            let idle = pool.idle.iter().position(|idle| idle.node_id == Some(node));
+            if let Some(stream) = idle.and_then(|i| pool.take(i)) {
+                return Ok(stream);
+            }
+            let addr = self.nodes.lock().unwrap().get(&node).cloned();
+            if let Some(addr) = addr.filter(|_| pool.open < self.max_connections) {
+                pool.open += 1;
+                drop(pool);
+                if let Ok(stream) = self.open(Some(&addr)) {
+                    return Ok(stream);
+                }
+                // the node is not available, any other one would do
+                pool = self.pool.lock().unwrap();
+            }
+        }
+        if let Some(addr) = self.balanced_addr(&pool) {
+            let idle = pool.idle.iter().position(|idle| idle.addr == addr);
+            if let Some(stream) = idle.and_then(|i| pool.take(i)) {
+                return Ok(stream);
+            }
+            if pool.open < self.max_connections {
+                pool.open += 1;
+                drop(pool);
+                if let Ok(stream) = self.open(Some(&addr)) {
+                    return Ok(stream);
+                }
+                pool = self.pool.lock().unwrap();
+            }
+        }
+        loop {
+            if self.is_closed() {
+                return Err(closed());
+            }
+            if let Some(stream) = pool.take(0) {
+                return Ok(stream);
+            }
+            if pool.open < self.max_connections {
+                pool.open += 1;
+                drop(pool);
+                return self.open(None);
+            }
+            pool = match deadline {
+                None => self.released.wait(pool).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(IgniteError::new(
+                            ErrorKind::Timeout,
+                            "Timed out waiting for a connection from the pool!".to_owned(),
+                        ));
+                    }
+                    self.released.wait_timeout(pool, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// Takes the connection which has opened the query cursor, waiting for it if it is checked out.
+    /// Fails if the connection is closed, as the cursor is closed with it
+    fn checkout_cursor(
+        &self,
+        mut pool: MutexGuard<Pool>,
+        cursor_id: i64,
+        deadline: Option<Instant>,
+    ) -> IgniteResult<PooledStream> {
+        loop {
+            if self.is_closed() {
+                return Err(closed());
+            }
+            let idle = pool
+                .idle
+                .iter()
+                .position(|idle| idle.cursors.contains(&cursor_id));
+            if let Some(stream) = idle.and_then(|i| pool.take(i)) {
+                return Ok(stream);
+            }
+            if !pool.lent_cursors.contains(&cursor_id) {
+                return Err(IgniteError::from(
+                    format!("Query cursor {} is closed with its connection!", cursor_id).as_str(),
+                ));
+            }
+            pool = match deadline {
+                None => self.released.wait(pool).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(IgniteError::new(
+                            ErrorKind::Timeout,
+                            "Timed out waiting for the connection of the query cursor!".to_owned(),
+                        ));
+                    }
+                    self.released.wait_timeout(pool, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// Asks the load balancer which node the request goes to. None if there is a single node
+    fn balanced_addr(&self, pool: &Pool) -> Option<String> {
+        let addrs = self.addrs();
+        if addrs.len() < 2 {
+            return None;
+        }
+        let nodes: Vec<NodeLoad> = addrs
+            .iter()
+            .map(|addr| NodeLoad {
+                addr,
+                in_flight: pool.in_flight.get(addr).copied().unwrap_or(0),
+                idle: pool.idle.iter().filter(|idle| idle.addr == *addr).count(),
+            })
+            .collect();
+        let chosen = self.conf.load_balancer.choose(&nodes);
+        addrs.get(chosen).cloned()
+    }
+
+    /// Returns the connection to the pool. Broken connections are closed, as well as
+    /// the idle connections to the same node, so the next requests go to other nodes.
+    /// Closes the connection if the client is closed
+    fn release(&self, stream: Option<PooledStream>) {
+        let mut pool = self.pool.lock().unwrap();
+        if let Some(stream) = &stream {
+            pool.done(&stream.addr);
+            for cursor_id in &stream.cursors {
+                pool.lent_cursors.remove(cursor_id);
+            }
+        }
+        if self.is_closed() {
+            pool.open -= 1;
+            drop(pool);
+            self.released.notify_all();
+            if let Some(stream) = stream {
+                Connection::shutdown(stream, &self.conf, None);
+            }
+            return;
+        }
+        match stream {
+            Some(mut stream) if !stream.failed => {
+                stream.last_used = Instant::now();
+                pool.idle.push_back(stream)
+            }
+            Some(stream) => {
+                let before = pool.idle.len();
+                pool.idle.retain(|idle| idle.addr != stream.addr);
+                let evicted = 1 + before - pool.idle.len();
+                pool.open -= evicted;
+                pool.evicted += evicted as u64;
+            }
+            None => pool.open -= 1,
+        }
+        self.released.notify_all();
+    }
+
+    /// Opens a connection to each of the known nodes which has none, while the pool has free slots.
+    /// Fails if some of the nodes could not be connected
+    pub(crate) fn warm_up(&self) -> IgniteResult<()> {
+        let mut errors = Vec::new();
+        for addr in self.addrs() {
+            {
+                let mut pool = self.pool.lock().unwrap();
+                if self.is_closed() {
+                    return Err(closed());
+                }
+                let connected = pool.idle.iter().any(|idle| idle.addr == addr)
+                    || pool.in_flight.contains_key(&addr);
+                if connected {
+                    continue;
+                }
+                if pool.open >= self.max_connections {
+                    break;
+                }
+                pool.open += 1;
+            }
+            match self.open(Some(&addr)) {
+                Ok(stream) => self.release(Some(stream)),
+                Err(err) if err.kind() == ErrorKind::Disconnected => {
+                    errors.push(format!("{}: {}", addr, err))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(IgniteError::new(
+            ErrorKind::Disconnected,
+            format!("Could not connect to some nodes! {}", errors.join("; ")),
+        ))
+    }
+
+    /// Closes the connection which could not be reused, though its node is not considered broken
+    fn discard(&self, stream: PooledStream) {
+        let mut pool = self.pool.lock().unwrap();
+        pool.done(&stream.addr);
+        pool.open -= 1;
+        self.released.notify_all();
+    }
+
+    pub(crate) fn features(&self) -> Features {
+        self.features.clone()
+    }
+
+    pub(crate) fn batch_limits(&self) -> BatchLimits {
+        BatchLimits::new(&self.conf)
+    }
+
+    pub(crate) fn interceptors(&self) -> &[Arc<dyn CacheInterceptor>] {
+        &self.conf.interceptors
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Stops the new requests and waits for the ones in flight until the timeout.
+    /// The idle connections are closed at once, the busy ones when their requests complete
+    pub(crate) fn close(&self, timeout: Duration) -> IgniteResult<()> {
+        let deadline = Instant::now() + timeout;
+        self.close_idle(deadline);
+        let mut pool = self.pool.lock().unwrap();
+        while pool.open > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(IgniteError::new(
+                    ErrorKind::Timeout,
+                    format!("Timed out waiting for {} requests in flight!", pool.open),
+                ));
+            }
+            pool = self.released.wait_timeout(pool, deadline - now).unwrap().0;
+        }
+        Ok(())
+    }
+
+    /// Marks the client closed and closes the idle connections
+    fn close_idle(&self, deadline: Instant) {
+        let idle: Vec<PooledStream> = {
+            let mut pool = self.pool.lock().unwrap();
+            self.closed.store(true, Ordering::Release);
+            pool.open -= pool.idle.len();
+            pool.idle.drain(..).collect()
+        };
+        // the requests which wait for a connection fail
+        self.released.notify_all();
+        for stream in idle {
+            Connection::shutdown(stream, &self.conf, Some(deadline));
+        }
+    }
+
+    /// Closes the query cursors which are left open on the node, then the connection
+    fn shutdown(mut stream: PooledStream, conf: &ClientConfig, deadline: Option<Instant>) {
+        if stream.limit_timeouts(conf, deadline).is_ok() {
+            for resource_id in std::mem::take(&mut stream.cursors) {
+                let req = ResourceCloseReq { resource_id };
+                let _ = Connection::send_safe(&mut stream, OpCode::ResourceClose, &req);
+                if stream.failed {
+                    break;
+                }
+            }
+        }
+        let _ = stream.stream.get_ref().tcp().shutdown(Shutdown::Both);
+    }
+
+    /// Starts the thread which sends heartbeats over the idle connections,
+    /// if they are enabled and the node supports them. The thread stops with the client
+    pub(crate) fn start_heartbeats(conn: &Arc<Connection>) {
+        let mut interval = match conn.conf.heartbeat_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if !conn.features.supports(Feature::Heartbeat) {
+            return;
+        }
+        // the node closes the connections which are idle longer than its timeout
+        let resp: IgniteResult<IdleTimeoutResp> =
+            conn.send_and_read(OpCode::GetIdleTimeout, EmptyReq {});
+        if let Ok(IdleTimeoutResp { idle_timeout_ms }) = resp {
+            if idle_timeout_ms > 0 {
+                let idle_timeout = Duration::from_millis(idle_timeout_ms as u64);
+                interval = interval.min(idle_timeout / 3).max(MIN_HEARTBEAT_INTERVAL);
+            }
+        }
+        let conn = Arc::downgrade(conn);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match conn.upgrade() {
+                Some(conn) if !conn.is_closed() => conn.ping_idle(interval, true),
+                _ => return,
+            }
+        });
+    }
+
+    /// Starts the thread which checks the idle connections, if the health checks are enabled.
+    /// The thread stops with the client
+    pub(crate) fn start_health_checks(conn: &Arc<Connection>) {
+        let interval = match conn.conf.pool_health_check_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let conn = Arc::downgrade(conn);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match conn.upgrade() {
+                Some(conn) if !conn.is_closed() => conn.check_health(interval),
+                _ => return,
+            }
+        });
+    }
 
-                // wrap in tls stream if this feature enabled
-                #[cfg(feature = "ssl")]
-                let mut stream = Connection::wrap_tls_stream(&conf.tls_conf, stream)?;
+    /// Pings the connections which are idle for the interval, closing the dead ones,
+    /// and opens new connections instead of them up to `pool_min_connections`
+    fn check_health(&self, interval: Duration) {
+        self.ping_idle(interval, false);
+        let min_connections = self.conf.pool_min_connections.min(self.max_connections);
+        loop {
+            {
+                let mut pool = self.pool.lock().unwrap();
+                if self.is_closed() || pool.open >= min_connections {
+                    return;
+                }
+                pool.open += 1;
+            }
+            match self.open(None) {
+                Ok(stream) => self.release(Some(stream)),
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Returns the snapshot of the pool
+    pub(crate) fn status(&self) -> PoolStatus {
+        let pool = self.pool.lock().unwrap();
+        let mut idle = HashMap::new();
+        for stream in &pool.idle {
+            *idle.entry(stream.addr.clone()).or_default() += 1;
+        }
+        PoolStatus {
+            open: pool.open,
+            max: self.max_connections,
+            idle,
+            in_flight: pool.in_flight.clone(),
+            evicted: pool.evicted,
+        }
+    }
+
+    /// Learns the endpoints of all the nodes from the cluster, if the discovery is enabled
+    /// and the node supports it. Starts the thread which refreshes them, it stops with the client
+    pub(crate) fn start_discovery(conn: &Arc<Connection>) {
+        let interval = match conn.conf.discovery_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if !conn
+            .features
+            .supports(Feature::ClusterGroupGetNodesEndpoints)
+        {
+            return;
+        }
+        let _ = conn.discover();
+        let conn = Arc::downgrade(conn);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match conn.upgrade() {
+                Some(conn) if !conn.is_closed() => {
+                    let _ = conn.discover();
+                }
+                _ => return,
+            }
+        });
+    }
 
-                // wrap in buffered stream
-                let mut buffered_stream = BufStream::with_capacities(
-                    conf.tcp_read_buff_size.unwrap_or(DFLT_READ_BUF_SIZE),
-                    conf.tcp_write_buff_size.unwrap_or(DFLT_WRITE_BUF_SIZE),
-                    stream,
-                );
+    /// Replaces the discovered endpoints with the current ones of the cluster
+    fn discover(&self) -> IgniteResult<()> {
+        let resp: NodesEndpointsResp = self.send_and_read(
+            OpCode::ClusterGroupGetNodesEndpoints,
+            NodesEndpointsReq {
+                start_topology: CURRENT_TOPOLOGY,
+                end_topology: CURRENT_TOPOLOGY,
+            },
+        )?;
+        let discovered: HashMap<u128, Vec<String>> = resp
+            .added
+            .iter()
+            .map(|node| {
+                let addrs = node.addrs().into_iter();
+                // the endpoints which could not be reached are not translated
+                let reachable = addrs.filter(|addr| translated(&self.conf, addr).is_some());
+                (node.node_id, reachable.collect::<Vec<_>>())
+            })
+            .filter(|(_, addrs)| !addrs.is_empty())
+            .collect();
+        if discovered.is_empty() {
+            return Err(IgniteError::from("No node endpoints are discovered!"));
+        }
+        // requests could be routed to the nodes which are not connected yet
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.retain(|node_id, _| discovered.contains_key(node_id));
+        for (node_id, addrs) in &discovered {
+            nodes.entry(*node_id).or_insert_with(|| addrs[0].clone());
+        }
+        *self.discovered.lock().unwrap() = discovered;
+        Ok(())
+    }
+
+    /// Returns the configured addresses and the discovered ones
+    fn addrs(&self) -> Vec<String> {
+        let mut addrs = self.conf.addrs.clone();
+        for node_addrs in self.discovered.lock().unwrap().values() {
+            for addr in node_addrs {
+                if !addrs.contains(addr) {
+                    addrs.push(addr.clone());
+                }
+            }
+        }
+        addrs
+    }
+
+    /// Sends heartbeats over the connections which are idle for the interval, or requests
+    /// the cache names over the ones to the nodes which do not support heartbeats, unless
+    /// `heartbeats_only` is set. The broken ones are closed, so the dead nodes are detected
+    /// before the next request
+    fn ping_idle(&self, interval: Duration, heartbeats_only: bool) {
+        let stale: VecDeque<PooledStream> = {
+            let mut pool = self.pool.lock().unwrap();
+            let (stale, fresh): (VecDeque<PooledStream>, _) =
+                pool.idle.drain(..).partition(|stream| {
+                    stream.last_used.elapsed() >= interval
+                        && (!heartbeats_only || stream.features.supports(Feature::Heartbeat))
+                });
+            pool.idle = fresh;
+            for stream in &stale {
+                pool.lent_cursors.extend(&stream.cursors);
+            }
+            stale
+        };
+        for mut stream in stale {
+            let result = match stream.features.supports(Feature::Heartbeat) {
+                true => Connection::send_safe(&mut stream, OpCode::Heartbeat, &EmptyReq {}),
+                false => Connection::send_safe(&mut stream, OpCode::CacheGetNames, &EmptyReq {}),
+            };
+            if let (true, Err(err)) = (stream.failed, &result) {
+                notify(&self.conf, || ConnectionEvent::Disconnected {
+                    addr: stream.addr.clone(),
+                    reason: err.to_string(),
+                });
+            }
+            self.release(Some(stream));
+        }
+    }
+
+    /// Returns the primary node of the key of a single-key request, if partition awareness is enabled
+    fn primary_node(&self, data: &impl WriteableReq) -> Option<u128> {
+        if !self.conf.partition_awareness {
+            return None;
+        }
+        let (cache_id, key) = data.affinity_key()?;
+        self.partition_map(cache_id)?.primary_node(key)
+    }
+
+    /// Returns the partition map of the cache. Fetches it if it is not known yet
+    fn partition_map(&self, cache_id: i32) -> Option<Arc<PartitionMap>> {
+        if let Some(map) = self.affinity.lock().unwrap().caches.get(&cache_id) {
+            return map.clone();
+        }
+        let resp: IgniteResult<CachePartitionsResp> = self.send_and_read(
+            OpCode::CachePartitions,
+            CachePartitionsReq {
+                cache_ids: &[cache_id],
+            },
+        );
+        let mut affinity = self.affinity.lock().unwrap();
+        match resp {
+            Ok(resp) => {
+                if affinity.topology < Some(resp.topology) {
+                    affinity.caches.clear();
+                    affinity.topology = Some(resp.topology);
+                }
+                // the maps of an older topology are fetched again by the next request
+                if affinity.topology > Some(resp.topology) {
+                    return None;
+                }
+                affinity.caches.extend(resp.caches);
+            }
+            // requests to this cache are not routed until the topology changes
+            Err(_) => {
+                affinity.caches.insert(cache_id, None);
+            }
+        }
+        affinity.caches.get(&cache_id).cloned().flatten()
+    }
+
+    /// Returns the number of the partitions of the cache
+    pub(crate) fn partitions(&self, cache_id: i32) -> IgniteResult<usize> {
+        self.known_partition_map(cache_id)
+            .map(|map| map.nodes.len())
+    }
+
+    /// Returns the partition of the key and its primary node, if the node is known
+    pub(crate) fn key_partition(
+        &self,
+        cache_id: i32,
+        key: &dyn WritableType,
+    ) -> IgniteResult<(i32, Option<u128>)> {
+        let map = self.known_partition_map(cache_id)?;
+        match map.partition(key) {
+            Some(partition) => Ok((partition as i32, map.nodes[partition])),
+            None => Err(IgniteError::new(
+                ErrorKind::Other,
+                "Partition of the key could not be computed! \
+                Its type has an affinity key field or no known hash code"
+                    .to_owned(),
+            )),
+        }
+    }
+
+    /// Returns the partition map of the cache with its affinity topology version.
+    /// `refresh` fetches it again instead of returning the known one
+    pub(crate) fn cache_partition_map(
+        &self,
+        cache_id: i32,
+        refresh: bool,
+    ) -> IgniteResult<CachePartitionMap> {
+        if refresh {
+            self.affinity.lock().unwrap().caches.remove(&cache_id);
+        }
+        let map = self.known_partition_map(cache_id)?;
+        let topology = self.affinity.lock().unwrap().topology.unwrap_or_default();
+        Ok(CachePartitionMap {
+            topology,
+            nodes: map.nodes.to_vec(),
+        })
+    }
+
+    fn known_partition_map(&self, cache_id: i32) -> IgniteResult<Arc<PartitionMap>> {
+        self.partition_map(cache_id).ok_or_else(|| {
+            IgniteError::new(
+                ErrorKind::Other,
+                format!("Partitions of cache {} are not known!", cache_id),
+            )
+        })
+    }
+
+    /// Forgets the partition maps if the topology has changed since they were fetched
+    fn on_topology_changed(&self, topology: TopologyVersion) {
+        let mut affinity = self.affinity.lock().unwrap();
+        if affinity.topology < Some(topology) {
+            affinity.caches.clear();
+            affinity.topology = Some(topology);
+        }
+    }
 
-                // try initial handshake
-                match handshake(&mut buffered_stream, conf) {
-                    Ok(_) => Ok(Connection {
-                        stream: Mutex::new(buffered_stream),
-                    }),
-                    Err(err) => Err(err),
+    /// Sends the request over a pooled connection. If the connection breaks,
+    /// the request is retried with a new one according to the reconnection policy
+    fn with_retries<T>(
+        &self,
+        op: impl Into<Op>,
+        node: Option<u128>,
+        request: impl Fn(&mut PooledStream) -> IgniteResult<T>,
+    ) -> IgniteResult<T> {
+        let pinned = PINNED.with(|cell| cell.borrow().clone());
+        if let Some(pinned) = pinned.filter(|pinned| std::ptr::eq(&*pinned.conn, self)) {
+            return pinned.request(request);
+        }
+        let op = op.into();
+        let policy = &self.conf.reconnect;
+        let deadline = DEADLINE.with(Cell::get);
+        let mut retry = 0;
+        // the connection which has failed the previous attempt
+        let mut failed_addr: Option<String> = None;
+        loop {
+            let (err, sent) = match self.acquire(node) {
+                // nothing is sent yet
+                Err(err) if err.kind() == ErrorKind::Disconnected => (err, false),
+                Err(err) => return Err(err),
+                Ok(mut stream) => {
+                    if let Some(from) = failed_addr.take().filter(|from| *from != stream.addr) {
+                        notify(&self.conf, || ConnectionEvent::Failover {
+                            from,
+                            to: stream.addr.clone(),
+                        });
+                    }
+                    if deadline.is_some() {
+                        if let Err(err) = stream.limit_timeouts(&self.conf, deadline) {
+                            self.release(Some(stream));
+                            return Err(err);
+                        }
+                    }
+                    let result = request(&mut stream);
+                    let failed = stream.failed;
+                    let topology = stream.topology.take();
+                    if let (true, Err(err)) = (failed, &result) {
+                        notify(&self.conf, || ConnectionEvent::Disconnected {
+                            addr: stream.addr.clone(),
+                            reason: err.to_string(),
+                        });
+                        failed_addr = Some(stream.addr.clone());
+                    }
+                    match deadline {
+                        // the response could come later, but the node is not considered broken
+                        Some(_)
+                            if failed
+                                && matches!(&result, Err(err) if err.kind() == ErrorKind::Timeout) =>
+                        {
+                            self.discard(stream)
+                        }
+                        Some(_) => {
+                            stream.failed |= stream.limit_timeouts(&self.conf, None).is_err();
+                            self.release(Some(stream))
+                        }
+                        None => self.release(Some(stream)),
+                    }
+                    if let Some(topology) = topology {
+                        self.on_topology_changed(topology);
+                    }
+                    match result {
+                        // the node may be hung, waiting for it again would not help
+                        Err(err) if failed && err.kind() == ErrorKind::Timeout => return Err(err),
+                        Err(err) if failed => {
+                            (IgniteError::new(ErrorKind::Disconnected, err.desc), true)
+                        }
+                        result => return result,
+                    }
+                }
+            };
+            if retry >= policy.max_retries {
+                return Err(err);
+            }
+            let ctx = RetryContext {
+                op_code: op.code,
+                idempotent: op.idempotent,
+                read_only: op.read_only,
+                sent,
+                retry,
+                error: &err,
+            };
+            if !self.conf.retry_policy.should_retry(&ctx) {
+                return Err(err);
+            }
+            if let Some(deadline) = deadline {
+                if Instant::now() + policy.backoff(retry) >= deadline {
+                    return Err(timed_out());
                 }
             }
-            Err(err) => Err(IgniteError::from(err)),
+            notify(&self.conf, || ConnectionEvent::ReconnectAttempt {
+                attempt: retry + 1,
+                delay: policy.backoff(retry),
+            });
+            thread::sleep(policy.backoff(retry));
+            retry += 1;
         }
     }
 
-    /// Send message and read response header. Acquires lock
+    /// Send message and read response header. Checks out a connection
     pub(crate) fn send(&self, op_code: OpCode, data: impl WriteableReq) -> IgniteResult<()> {
-        let sock_lock = &mut *self.stream.lock().unwrap(); //acquire lock on socket
-        Connection::send_safe(sock_lock, op_code, data)
+        let node = self.primary_node(&data);
+        self.with_retries(op_code, node, |stream| {
+            Connection::send_safe(stream, op_code, &data).map(|_| ())
+        })
     }
 
-    /// Send message, read response header and return a response. Acquires lock
+    /// Send message, read response header and return a response. Checks out a connection
     pub(crate) fn send_and_read<T: ReadableReq>(
         &self,
         op_code: OpCode,
         data: impl WriteableReq,
     ) -> IgniteResult<T> {
-        let sock_lock = &mut *self.stream.lock().unwrap(); //acquire lock on socket
-        Connection::send_and_read_safe(sock_lock, op_code, data)
+        let node = self.primary_node(&data);
+        self.with_retries(op_code, node, |stream| {
+            with_string_decoding(self.string_decoding, || {
+                with_numeric_widening(self.numeric_widening, || {
+                    Connection::send_and_read_safe(stream, op_code, &data)
+                })
+            })
+        })
     }
 
-    fn send_safe<RW: Read + Write>(
-        con: &mut RW,
+    /// Fetches the next page of the query cursor over the connection which has opened it.
+    /// The node closes the cursor after the last page
+    pub(crate) fn cursor_page<T: CursorPage + ReadableReq>(
+        &self,
+        cursor_id: i64,
+    ) -> IgniteResult<T> {
+        self.cursor_page_with(cursor_id, |reader| T::read(reader))
+    }
+
+    /// Same as `cursor_page`, but the page is read with the function
+    pub(crate) fn cursor_page_with<T, F>(&self, cursor_id: i64, read_fn: F) -> IgniteResult<T>
+    where
+        T: CursorPage,
+        F: Fn(&mut Cursor<&[u8]>) -> IgniteResult<T>,
+    {
+        let op_code = T::OP_CODE;
+        let resp = with_cursor(cursor_id, || {
+            self.with_retries(op_code, None, |stream| {
+                let req = QueryCursorPageReq { cursor_id };
+                let resp: T = with_string_decoding(self.string_decoding, || {
+                    with_numeric_widening(self.numeric_widening, || {
+                        let payload = Connection::send_safe(stream, op_code, &req)?;
+                        Connection::read_payload_with(stream, &payload, &read_fn)
+                    })
+                })?;
+                if !resp.more() {
+                    stream.cursors.retain(|id| *id != cursor_id);
+                }
+                Ok(resp)
+            })
+        })?;
+        if !resp.more() {
+            self.pool.lock().unwrap().lent_cursors.remove(&cursor_id);
+        }
+        Ok(resp)
+    }
+
+    /// Closes the query cursor before its last page is fetched
+    pub(crate) fn close_cursor(&self, cursor_id: i64) -> IgniteResult<()> {
+        let result = with_cursor(cursor_id, || {
+            self.with_retries(OpCode::ResourceClose, None, |stream| {
+                stream.cursors.retain(|id| *id != cursor_id);
+                let req = ResourceCloseReq {
+                    resource_id: cursor_id,
+                };
+                Connection::send_safe(stream, OpCode::ResourceClose, &req).map(|_| ())
+            })
+        });
+        self.pool.lock().unwrap().lent_cursors.remove(&cursor_id);
+        result
+    }
+
+    /// Starts the continuous query over a connection of its own, which is not pooled, as the node
+    /// sends the events over the connection which has started the query. The thread of the query
+    /// passes the payloads of its notifications to `on_events`, and the error if the connection
+    /// is lost, then stops. The query is stopped when the subscription is dropped
+    pub(crate) fn subscribe(
+        &self,
+        req: ContinuousQueryReq,
+        mut on_events: impl FnMut(IgniteResult<&[u8]>) + Send + 'static,
+    ) -> IgniteResult<Subscription> {
+        if self.is_closed() {
+            return Err(closed());
+        }
+        let mut stream = Connection::connect(&self.conf, &self.addrs())?;
+        // the notifications are told apart from the responses by the flags of the header
+        if stream.features.version() < ProtocolVersion::PARTITION_AWARENESS {
+            return Err(IgniteError::new(
+                ErrorKind::InvalidConfig,
+                "Continuous queries require protocol v1.4.0 or newer!".to_owned(),
+            ));
+        }
+        let resp: ContinuousQueryResp =
+            Connection::send_and_read_safe(&mut stream, OpCode::QueryContinuous, &req)?;
+        let tcp = stream.stream.get_ref().tcp();
+        // the events could come rarely, so the reads wait for them as long as it takes
+        tcp.set_read_timeout(None)?;
+        let subscription = Subscription {
+            tcp: tcp.try_clone()?,
+        };
+        thread::spawn(move || loop {
+            match Connection::read_notification(&mut stream, resp.query_id) {
+                Ok(Some(payload)) => on_events(Ok(&payload)),
+                Ok(None) => {}
+                Err(err) => {
+                    on_events(Err(err));
+                    return;
+                }
+            }
+        });
+        Ok(subscription)
+    }
+
+    /// Reads the next message of the continuous query's connection. Returns the payload of
+    /// the events of the query, None for the other messages
+    fn read_notification(
+        stream: &mut PooledStream,
+        query_id: i64,
+    ) -> IgniteResult<Option<Vec<u8>>> {
+        let len = read_i32(stream)?;
+        let mut msg = vec![0u8; len.max(0) as usize];
+        stream.read_exact(&mut msg)?;
+        let mut reader = Cursor::new(msg);
+        let resource_id = read_i64(&mut reader)?;
+        let flags = read_i16(&mut reader)?;
+        if flags & FLAG_TOPOLOGY_CHANGED != 0 {
+            read_i64(&mut reader)?;
+            read_i32(&mut reader)?;
+        }
+        if flags & FLAG_NOTIFICATION == 0 {
+            return Ok(None);
+        }
+        let op_code = read_i16(&mut reader)?;
+        if flags & FLAG_ERROR != 0 {
+            let status = read_i32(&mut reader)?;
+            let err_msg = String::read(&mut reader)?.unwrap_or_default();
+            return Err(IgniteError::from(
+                format!(
+                    "Continuous query has failed with status {}: {}",
+                    status, err_msg
+                )
+                .as_str(),
+            ));
+        }
+        if resource_id != query_id || op_code != OpCode::QueryContinuousEventNotification as i16 {
+            return Ok(None);
+        }
+        let start = reader.position() as usize;
+        Ok(Some(reader.into_inner().split_off(start)))
+    }
+
+    /// Sends the operation with the raw payload, which is not retried unless
+    /// the retry policy allows it for the operations which are neither idempotent nor read-only
+    pub(crate) fn send_raw(&self, op_code: i16, payload: &[u8]) -> IgniteResult<RawResponse> {
+        let op = Op {
+            code: op_code,
+            idempotent: false,
+            read_only: false,
+        };
+        self.with_retries(op, None, |stream| {
+            Connection::exchange(stream, op_code, &RawReq(payload))
+        })
+    }
+
+    /// Send message and read the response. Returns the response payload
+    fn send_safe(
+        con: &mut PooledStream,
         op_code: OpCode,
-        payload: impl WriteableReq,
-    ) -> IgniteResult<()> {
-        // write common message header
-        Connection::write_req_header(con, payload.size(), op_code as i16)?;
+        payload: &impl WriteableReq,
+    ) -> IgniteResult<Vec<u8>> {
+        Connection::exchange(con, op_code.into(), payload)?.into_payload()
+    }
 
-        // write payload
-        payload.write(con)?;
+    /// Send message and read the response, failed or not
+    fn exchange(
+        con: &mut PooledStream,
+        op_code: i16,
+        payload: &impl WriteableReq,
+    ) -> IgniteResult<RawResponse> {
+        // the message is serialized first, so it is sent in as few writes as possible
+        let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        let mut msg = Vec::with_capacity(payload.size() + REQ_HEADER_SIZE_BYTES as usize + 4);
+        Connection::write_req_header(&mut msg, payload.size(), op_code, request_id)?;
+        payload.write(&mut msg)?;
+        con.write_all(&msg)?;
 
         // flush write buffer
         con.flush()?;
 
         //read response
-        match Connection::read_resp_header(con)? {
-            Flag::Success => Ok(()),
-            Flag::Failure { err_msg } => Err(IgniteError::from(err_msg.as_str())),
+        let len = read_i32(con)?;
+        let mut resp = vec![0u8; len as usize];
+        con.read_exact(&mut resp)?;
+        let mut reader = Cursor::new(resp);
+        let header = Connection::read_resp_header(&mut reader, con.features.version())?;
+        if header.request_id != request_id {
+            // the stream is out of sync, so the connection must not be reused
+            con.failed = true;
+            return Err(IgniteError::new(
+                ErrorKind::Disconnected,
+                format!(
+                    "Response to request {} is received instead of {}!",
+                    header.request_id, request_id
+                ),
+            ));
+        }
+        if header.topology.is_some() {
+            con.topology = header.topology;
         }
+        Ok(RawResponse::new(header.flag, reader))
     }
 
-    fn send_and_read_safe<T: ReadableReq, RW: Read + Write>(
-        buf: &mut RW,
+    fn send_and_read_safe<T: ReadableReq>(
+        con: &mut PooledStream,
         op_code: OpCode,
-        data: impl WriteableReq,
+        data: &impl WriteableReq,
+    ) -> IgniteResult<T> {
+        let payload = Connection::send_safe(con, op_code, data)?; //send request and read the response
+        let resp: T = Connection::read_payload(con, &payload)?; //unpack the input bytes into an actual type
+        if let Some(cursor) = resp.open_cursor() {
+            con.cursors.push(cursor);
+        }
+        Ok(resp)
+    }
+
+    /// Unpacks the response payload into an actual type.
+    /// Binary types metadata which is required to read the payload is fetched
+    /// using the same connection, and the payload is read again
+    fn read_payload<T: ReadableReq>(con: &mut PooledStream, payload: &[u8]) -> IgniteResult<T> {
+        Connection::read_payload_with(con, payload, |reader| T::read(reader))
+    }
+
+    /// Same as `read_payload`, but the payload is read with the function
+    fn read_payload_with<T>(
+        con: &mut PooledStream,
+        payload: &[u8],
+        read_fn: impl Fn(&mut Cursor<&[u8]>) -> IgniteResult<T>,
     ) -> IgniteResult<T> {
-        Connection::send_safe(buf, op_code, data)?; //send request and read the response
-        T::read(buf) //unpack the input bytes into an actual type
+        let mut fetched = Vec::<i32>::new();
+        loop {
+            match read_fn(&mut Cursor::new(payload)) {
+                Err(err) => match err.kind() {
+                    ErrorKind::UnknownBinaryType { type_id, .. } if !fetched.contains(&type_id) => {
+                        fetched.push(type_id);
+                        let resp: BinaryTypeGetResp = Connection::send_and_read_safe(
+                            con,
+                            OpCode::GetBinaryType,
+                            &BinaryTypeGetReq { type_id },
+                        )?;
+                        if let Some(binary_type) = resp.binary_type {
+                            register_binary_type(binary_type);
+                        }
+                    }
+                    _ => return Err(err),
+                },
+                result => return result,
+            }
+        }
     }
 
     /// Returns binary repr of standard request header
@@ -110,37 +1543,65 @@ impl Connection {
         writer: &mut dyn Write,
         payload_len: usize,
         op_code: i16,
+        request_id: i64,
     ) -> io::Result<()> {
         write_i32(writer, payload_len as i32 + REQ_HEADER_SIZE_BYTES)?;
         write_i16(writer, op_code)?;
-        write_i64(writer, 0)?;
+        write_i64(writer, request_id)?;
         Ok(())
     }
 
-    /// Reads standard response header
-    fn read_resp_header(reader: &mut impl Read) -> IgniteResult<Flag> {
-        let _ = read_i32(reader)?;
-        let _ = read_i64(reader)?;
-        match read_i32(reader)? {
-            0 => Ok(Success),
-            _ => {
-                let err_msg = String::read(reader)?;
-                Ok(Failure {
-                    err_msg: err_msg.unwrap(),
-                })
-            }
+    /// Reads the id and the status of the response and the new affinity topology version,
+    /// if the node reports that it has changed. The length of the response is already consumed
+    pub(crate) fn read_resp_header(
+        reader: &mut impl Read,
+        version: ProtocolVersion,
+    ) -> IgniteResult<RespHeader> {
+        let request_id = read_i64(reader)?;
+        // older nodes send the status instead of the flags
+        if version < ProtocolVersion::PARTITION_AWARENESS {
+            let flag = match read_i32(reader)? {
+                0 => Success,
+                status => Failure {
+                    status,
+                    err_msg: String::read(reader)?.unwrap_or_default(),
+                },
+            };
+            return Ok(RespHeader {
+                request_id,
+                flag,
+                topology: None,
+            });
         }
+        let flags = read_i16(reader)?;
+        let topology = match flags & FLAG_TOPOLOGY_CHANGED {
+            0 => None,
+            _ => Some((read_i64(reader)?, read_i32(reader)?)),
+        };
+        let flag = match flags & FLAG_ERROR {
+            0 => Success,
+            _ => Failure {
+                status: read_i32(reader)?,
+                err_msg: String::read(reader)?.unwrap_or_default(),
+            },
+        };
+        Ok(RespHeader {
+            request_id,
+            flag,
+            topology,
+        })
     }
 
-    #[cfg(feature = "ssl")]
-    fn wrap_tls_stream(
-        conf: &(rustls::ClientConfig, String),
-        stream: TcpStream,
-    ) -> IgniteResult<rustls::StreamOwned<rustls::ClientSession, TcpStream>> {
-        let hostname = webpki::DNSNameRef::try_from_ascii_str(&conf.1)?;
-        let tls_session = rustls::ClientSession::new(&Arc::new(conf.0.clone()), hostname);
-        let tls_stream = rustls::StreamOwned::new(tls_session, stream);
-        Ok(tls_stream)
+    /// Returns the request with its header as bytes
+    #[cfg(feature = "async")]
+    pub(crate) fn encode_req(
+        op_code: impl Into<i16>,
+        payload: impl WriteableReq,
+    ) -> IgniteResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(payload.size() + REQ_HEADER_SIZE_BYTES as usize);
+        Connection::write_req_header(&mut bytes, payload.size(), op_code.into(), 0)?;
+        payload.write(&mut bytes)?;
+        Ok(bytes)
     }
 
     fn configure_tcp(stream: &TcpStream, conf: &ClientConfig) -> io::Result<()> {
@@ -155,6 +1616,756 @@ impl Connection {
         if let Some(ttl) = conf.tcp_ttl {
             stream.set_ttl(ttl)?;
         }
-        Ok(())
+        Connection::configure_socket(SockRef::from(stream), conf)
+    }
+
+    /// Sets the socket buffer sizes and enables TCP keepalive probes if they are configured
+    pub(crate) fn configure_socket(socket: SockRef<'_>, conf: &ClientConfig) -> io::Result<()> {
+        if let Some(size) = conf.tcp_send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = conf.tcp_recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        let time = match conf.tcp_keepalive {
+            Some(time) => time,
+            None => return Ok(()),
+        };
+        let keepalive = TcpKeepalive::new().with_time(time);
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            windows
+        ))]
+        let keepalive = match conf.tcp_keepalive_interval {
+            Some(interval) => keepalive.with_interval(interval),
+            None => keepalive,
+        };
+        socket.set_tcp_keepalive(&keepalive)
+    }
+}
+
+impl Drop for Connection {
+    /// Closes the cursors left open on the nodes and the connections, the background threads stop
+    fn drop(&mut self) {
+        if !self.is_closed() {
+            self.close_idle(Instant::now() + CLOSE_ON_DROP_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::api::key_value::{CacheHeader, CacheReq};
+    use crate::balancer::LeastOutstanding;
+    use crate::cache::Cache;
+    use crate::retry::{RetryAll, RetryPolicy, RetryReadOnly};
+    use crate::ReconnectPolicy;
+    use crate::{AnyValue, WritableType};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answers the handshake with the port of the node as its id
+    pub(crate) fn write_handshake_resp(stream: &mut TcpStream) {
+        let node_id = stream.local_addr().unwrap().port() as u128;
+        write_i32(stream, 19).unwrap();
+        stream.write_all(&[1, 101]).unwrap(); // success, no features
+        AnyValue::Uuid(node_id).write(stream).unwrap();
+    }
+
+    /// Reads the request and returns its operation code and id
+    pub(crate) fn read_req(stream: &mut TcpStream) -> (i16, i64) {
+        let len = read_i32(stream).unwrap();
+        let mut msg = vec![0u8; len as usize];
+        stream.read_exact(&mut msg).unwrap();
+        match msg[0] {
+            1 => (OpCode::Handshake as i16, 0), // the handshake has a single byte code
+            _ => (
+                read_i16(&mut &msg[..]).unwrap(),
+                read_i64(&mut &msg[2..]).unwrap(),
+            ),
+        }
+    }
+
+    /// Answers the request with the success status and the payload
+    pub(crate) fn write_success(stream: &mut TcpStream, request_id: i64, payload: &[u8]) {
+        write_i32(stream, 10 + payload.len() as i32).unwrap();
+        write_i64(stream, request_id).unwrap();
+        write_i16(stream, 0).unwrap();
+        stream.write_all(payload).unwrap();
+    }
+
+    /// Partition map of the topology with the major version, the cache 1 has
+    /// the partitions which are all on the node
+    pub(crate) fn partition_map_payload(major: i64, node_id: u128, partitions: &[i32]) -> Vec<u8> {
+        let mut map = Vec::new();
+        write_i64(&mut map, major).unwrap();
+        write_i32(&mut map, 0).unwrap();
+        write_i32(&mut map, 1).unwrap(); // groups
+        map.push(1); // applicable
+        write_i32(&mut map, 1).unwrap(); // caches
+        write_i32(&mut map, 1).unwrap(); // cache id
+        write_i32(&mut map, 0).unwrap(); // key types with affinity key fields
+        write_i32(&mut map, 1).unwrap(); // nodes
+        AnyValue::Uuid(node_id).write(&mut map).unwrap();
+        write_i32(&mut map, partitions.len() as i32).unwrap();
+        for partition in partitions {
+            write_i32(&mut map, *partition).unwrap();
+        }
+        map
+    }
+
+    /// Accepts the connections and answers their handshakes
+    fn serve_handshakes(listener: TcpListener, connections: usize) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let mut stream = stream.unwrap();
+                let len = read_i32(&mut stream).unwrap();
+                stream.read_exact(&mut vec![0u8; len as usize]).unwrap();
+                write_handshake_resp(&mut stream);
+                thread::spawn(move || stream.read(&mut [0u8; 1]));
+            }
+        })
+    }
+
+    #[test]
+    fn test_pool_checkout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.pool_max_connections = 2;
+        conf.pool_acquire_timeout = Some(Duration::from_millis(10));
+        let server = serve_handshakes(listener, 2);
+
+        let conn = Connection::new(&conf).unwrap();
+        let first = conn.acquire(None).unwrap();
+        let second = conn.acquire(None).unwrap(); // opened on demand
+        assert!(conn.acquire(None).is_err()); // no more than 2 connections
+        conn.release(Some(first));
+        let third = conn.acquire(None).unwrap();
+        assert_eq!(conn.pool.lock().unwrap().open, 2);
+
+        conn.release(Some(PooledStream {
+            failed: true,
+            ..second
+        }));
+        conn.release(Some(third));
+        assert_eq!(conn.pool.lock().unwrap().open, 1); // the broken one is closed
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_shared_client() {
+        fn assert_shared<T: Clone + Send + Sync + 'static>(_: &T) {}
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = serve_handshakes(listener, 1);
+
+        let client = crate::new_client(conf).unwrap();
+        let cache = Cache::<String, String>::new(1, "test".to_owned(), client.conn.clone());
+        assert_shared(&client);
+        assert_shared(&cache);
+        let moved = client.clone();
+        let moved = thread::spawn(move || moved).join().unwrap();
+        assert!(Arc::ptr_eq(&client.conn, &moved.conn));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_failover() {
+        let dead = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_addr = dead.local_addr().unwrap().to_string();
+        drop(dead);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&dead_addr);
+        conf.addrs.push(listener.local_addr().unwrap().to_string());
+        conf.pool_max_connections = 2;
+        let server = serve_handshakes(listener, 2);
+
+        let conn = Connection::new(&conf).unwrap();
+        let first = conn.acquire(None).unwrap();
+        let second = conn.acquire(None).unwrap();
+        assert_eq!(first.addr, conf.addrs[1]);
+        // idle connections to the failed node are closed too
+        conn.release(Some(first));
+        conn.release(Some(PooledStream {
+            failed: true,
+            ..second
+        }));
+        assert_eq!(conn.pool.lock().unwrap().open, 0);
+        server.join().unwrap();
+
+        conf.addrs.remove(1);
+        assert!(Connection::new(&conf).is_err());
+    }
+
+    #[test]
+    fn test_routing_to_node() {
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second_id = second.local_addr().unwrap().port() as u128;
+        let mut conf = ClientConfig::new(&first.local_addr().unwrap().to_string());
+        conf.addrs.push(second.local_addr().unwrap().to_string());
+        conf.partition_awareness = true;
+        let servers = vec![serve_handshakes(first, 1), serve_handshakes(second, 2)];
+
+        // connections are opened to all nodes
+        let conn = Connection::new(&conf).unwrap();
+        assert_eq!(conn.pool.lock().unwrap().open, 2);
+        let stream = conn.acquire(Some(second_id)).unwrap();
+        assert_eq!(stream.node_id, Some(second_id));
+        // another connection to the busy node is opened, as the pool has a free slot
+        conn.release(None);
+        let another = conn.acquire(Some(second_id)).unwrap();
+        assert_eq!(another.addr, conf.addrs[1]);
+        // any node is used when the node is unknown
+        assert!(conn.acquire(Some(1)).is_ok());
+        for server in servers {
+            server.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_load_balancing() {
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&first.local_addr().unwrap().to_string());
+        conf.addrs.push(second.local_addr().unwrap().to_string());
+        conf.pool_max_connections = 2;
+        conf.load_balancer = Arc::new(LeastOutstanding);
+        let servers = vec![serve_handshakes(first, 1), serve_handshakes(second, 1)];
+
+        let conn = Connection::new(&conf).unwrap();
+        // the busy node is avoided, so the requests go to both nodes
+        let busy = conn.acquire(None).unwrap();
+        let other = conn.acquire(None).unwrap();
+        assert_ne!(busy.addr, other.addr);
+        assert_eq!(conn.pool.lock().unwrap().in_flight.len(), 2);
+        conn.release(Some(busy));
+        conn.release(Some(other));
+        assert!(conn.pool.lock().unwrap().in_flight.is_empty());
+        for server in servers {
+            server.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&first.local_addr().unwrap().to_string());
+        conf.addrs.push(second.local_addr().unwrap().to_string());
+        conf.warm_up = true;
+        let servers = vec![serve_handshakes(first, 1), serve_handshakes(second, 1)];
+
+        let conn = Connection::new(&conf).unwrap();
+        conn.warm_up().unwrap();
+        let pool = conn.pool.lock().unwrap();
+        assert_eq!(pool.open, 2);
+        assert_ne!(pool.idle[0].addr, pool.idle[1].addr);
+        drop(pool);
+        for server in servers {
+            server.join().unwrap();
+        }
+
+        // the node which is not available is reported
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        conf.addrs = vec![
+            listener.local_addr().unwrap().to_string(),
+            conf.addrs[0].clone(),
+        ];
+        let server = serve_handshakes(listener, 1);
+        let conn = Connection::new(&conf).unwrap();
+        let err = conn.warm_up().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Disconnected);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_raw_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            for status in 0..2 {
+                let len = read_i32(&mut stream).unwrap();
+                let mut msg = vec![0u8; len as usize];
+                stream.read_exact(&mut msg).unwrap();
+                assert_eq!(read_i16(&mut &msg[..]).unwrap(), 9000);
+                assert_eq!(&msg[10..], &[1, 2, 3]);
+                let mut resp = Vec::new();
+                write_i64(&mut resp, read_i64(&mut &msg[2..]).unwrap()).unwrap();
+                match status {
+                    0 => {
+                        write_i16(&mut resp, 0).unwrap();
+                        resp.extend_from_slice(&[4, 5]);
+                    }
+                    _ => {
+                        write_i16(&mut resp, FLAG_ERROR).unwrap();
+                        write_i32(&mut resp, 1001).unwrap();
+                        "unknown operation".to_owned().write(&mut resp).unwrap();
+                    }
+                }
+                write_i32(&mut stream, resp.len() as i32).unwrap();
+                stream.write_all(&resp).unwrap();
+            }
+        });
+
+        let conn = Connection::new(&conf).unwrap();
+        let resp = conn.send_raw(9000, &[1, 2, 3]).unwrap();
+        assert_eq!(resp.status, 0);
+        assert_eq!(resp.payload, vec![4, 5]);
+        let resp = conn.send_raw(9000, &[1, 2, 3]).unwrap();
+        assert_eq!(resp.status, 1001);
+        assert_eq!(resp.err_msg.as_deref(), Some("unknown operation"));
+        assert!(resp.payload.is_empty());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_health_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let conf = ClientConfig::new(&addr);
+        let server = thread::spawn(move || {
+            let mut streams = listener.incoming();
+            // the first connection is closed as if the node has restarted
+            let mut stream = streams.next().unwrap().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            drop(stream);
+            let mut stream = streams.next().unwrap().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+
+        let conn = Connection::new(&conf).unwrap();
+        conn.check_health(Duration::from_secs(0));
+        let status = conn.status();
+        assert_eq!(status.open, 1);
+        assert_eq!(status.evicted, 1);
+        assert_eq!(status.idle, vec![(addr, 1)].into_iter().collect());
+        assert!(status.in_flight.is_empty());
+        drop(conn);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_address_translator() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let external = listener.local_addr().unwrap().to_string();
+        let mut conf = ClientConfig::new("node-1.internal:10800");
+        conf.address_translator = Some(Arc::new(move |addr: &str| match addr {
+            "node-1.internal:10800" => Some(external.clone()),
+            _ => None,
+        }));
+        let server = serve_handshakes(listener, 1);
+
+        let conn = Connection::new(&conf).unwrap();
+        // the original address is kept
+        let pool = conn.pool.lock().unwrap();
+        assert_eq!(pool.idle[0].addr, "node-1.internal:10800");
+        drop(pool);
+        server.join().unwrap();
+    }
+
+    /// Answers the handshake and the request on every connection but the first,
+    /// which is closed as soon as the request comes
+    fn serve_after_disconnect(listener: TcpListener) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().take(2).enumerate() {
+                let mut stream = stream.unwrap();
+                read_req(&mut stream);
+                write_handshake_resp(&mut stream);
+                let (_, request_id) = read_req(&mut stream);
+                if i > 0 {
+                    write_i32(&mut stream, 10).unwrap();
+                    write_i64(&mut stream, request_id).unwrap();
+                    write_i16(&mut stream, 0).unwrap(); // flags
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.reconnect.initial_backoff = Duration::from_millis(1);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let listener_events = events.clone();
+        conf.connection_listener = Some(Arc::new(move |event: &ConnectionEvent| {
+            listener_events.lock().unwrap().push(event.clone())
+        }));
+        let addr = conf.addrs[0].clone();
+        let server = serve_after_disconnect(listener);
+
+        let conn = Connection::new(&conf).unwrap();
+        conn.send(
+            OpCode::CacheClear,
+            CacheReq::Clear::<i32, i32>(CacheHeader::new(1)),
+        )
+        .unwrap();
+        server.join().unwrap();
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0], ConnectionEvent::Connected { addr: addr.clone() });
+        assert!(matches!(&events[2], ConnectionEvent::Disconnected { addr: a, .. } if *a == addr));
+        assert_eq!(
+            events[3],
+            ConnectionEvent::ReconnectAttempt {
+                attempt: 1,
+                delay: Duration::from_millis(1)
+            }
+        );
+        assert!(matches!(
+            &events[5],
+            ConnectionEvent::HandshakeCompleted { .. }
+        ));
+    }
+
+    #[test]
+    fn test_no_retry_of_non_idempotent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.reconnect.initial_backoff = Duration::from_millis(1);
+        let _server = serve_after_disconnect(listener);
+
+        let conn = Connection::new(&conf).unwrap();
+        let err = conn
+            .send(
+                OpCode::CacheRemoveKey,
+                CacheReq::RemoveKey::<i32, i32>(CacheHeader::new(1), &1),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Disconnected);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.reconnect.initial_backoff = Duration::from_millis(1);
+        conf.retry_policy = Arc::new(RetryAll);
+        let server = serve_after_disconnect(listener);
+
+        let conn = Connection::new(&conf).unwrap();
+        conn.send(
+            OpCode::CacheRemoveKey,
+            CacheReq::RemoveKey::<i32, i32>(CacheHeader::new(1), &1),
+        )
+        .unwrap();
+        server.join().unwrap();
+        assert!(!RetryReadOnly.should_retry(&RetryContext {
+            op_code: OpCode::CachePut as i16,
+            idempotent: true,
+            read_only: false,
+            sent: true,
+            retry: 0,
+            error: &IgniteError::from("broken"),
+        }));
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.tcp_read_timeout = Some(Duration::from_millis(50));
+        conf.reconnect.initial_backoff = Duration::from_millis(1);
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let len = read_i32(&mut stream).unwrap();
+            stream.read_exact(&mut vec![0u8; len as usize]).unwrap();
+            write_handshake_resp(&mut stream);
+            // the request is never answered
+            stream.read_exact(&mut [0u8; 1]).unwrap();
+            thread::sleep(Duration::from_millis(200));
+        });
+
+        let conn = Connection::new(&conf).unwrap();
+        let err = conn
+            .send(
+                OpCode::CacheClear,
+                CacheReq::Clear::<i32, i32>(CacheHeader::new(1)),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert_eq!(conn.pool.lock().unwrap().open, 0); // not retried
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_op_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            // the request is never answered
+            read_req(&mut stream);
+            // waits until the client closes the connection
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn.clone());
+        let started = Instant::now();
+        let err = cache
+            .with_timeout(Duration::from_millis(50))
+            .clear()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(conn.pool.lock().unwrap().open, 0); // the connection is closed
+        drop(conn);
+        drop(cache);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            // the scan leaves the cursor open
+            let (_, request_id) = read_req(&mut stream);
+            write_i32(&mut stream, 23).unwrap();
+            write_i64(&mut stream, request_id).unwrap();
+            write_i16(&mut stream, 0).unwrap(); // flags
+            write_i64(&mut stream, 7).unwrap(); // cursor id
+            write_i32(&mut stream, 0).unwrap(); // no entries
+            stream.write_all(&[1]).unwrap(); // more results
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::ResourceClose as i16);
+            write_i32(&mut stream, 10).unwrap();
+            write_i64(&mut stream, request_id).unwrap();
+            write_i16(&mut stream, 0).unwrap();
+            // the connection is closed then
+            assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn.clone());
+        assert!(cache.query_scan(10).unwrap().is_empty());
+        conn.close(Duration::from_secs(5)).unwrap();
+        assert_eq!(cache.get(&1).unwrap_err().kind(), ErrorKind::Closed);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_connect_to_any_resolved_addr() {
+        // only one of the addresses of localhost is listened to, the other ones are refused
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("localhost:{}", listener.local_addr().unwrap().port());
+        for _ in 0..4 {
+            let timeout = Some(Duration::from_secs(5));
+            assert!(Connection::connect_direct(&addr, timeout).is_ok());
+            assert!(Connection::connect_direct(&addr, None).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_pinned() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.pool_acquire_timeout = Some(Duration::from_millis(10));
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            for _ in 0..2 {
+                let (_, request_id) = read_req(&mut stream);
+                write_i32(&mut stream, 11).unwrap();
+                write_i64(&mut stream, request_id).unwrap();
+                write_i16(&mut stream, 0).unwrap(); // flags
+                stream.write_all(&[1]).unwrap(); // true
+            }
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn.clone());
+        let pinned = Arc::new(Pinned::new(&conn, None).unwrap());
+        // the only connection is pinned, so the other requests wait for it
+        let err = cache.contains_key(&1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(cache.clone().pinned_to(&pinned).contains_key(&1).unwrap());
+        pinned.release();
+        assert!(cache.contains_key(&1).unwrap());
+        let err = cache.pinned_to(&pinned).contains_key(&1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Disconnected);
+        // the connection to another node could not be pinned
+        assert!(Pinned::new(&conn, Some(1)).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut conf = ClientConfig::new("any");
+        conf.tcp_keepalive = Some(Duration::from_secs(30));
+        conf.tcp_keepalive_interval = Some(Duration::from_secs(5));
+        conf.tcp_send_buffer_size = Some(256 * 1024);
+        Connection::configure_tcp(&stream, &conf).unwrap();
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.send_buffer_size().unwrap() >= 256 * 1024);
+        #[cfg(target_os = "linux")]
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_heartbeats() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.heartbeat_interval = Some(Duration::from_secs(60));
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(read_req(&mut stream).0, OpCode::Handshake as i16);
+            write_i32(&mut stream, 25).unwrap();
+            stream.write_all(&[1]).unwrap();
+            [0u8, 8].write(&mut stream).unwrap(); // heartbeat feature
+            AnyValue::Uuid(1).write(&mut stream).unwrap();
+            // idle timeout of 300 ms needs heartbeats every 100 ms
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::GetIdleTimeout as i16);
+            write_i32(&mut stream, 18).unwrap();
+            write_i64(&mut stream, request_id).unwrap();
+            write_i16(&mut stream, 0).unwrap(); // flags
+            write_i64(&mut stream, 300).unwrap();
+            assert_eq!(read_req(&mut stream).0, OpCode::Heartbeat as i16);
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        Connection::start_heartbeats(&conn);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_discovery() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.discovery_interval = Some(Duration::from_secs(60));
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_i32(&mut stream, 24).unwrap();
+            stream.write_all(&[1]).unwrap();
+            [8u8].write(&mut stream).unwrap(); // cluster endpoints feature
+            AnyValue::Uuid(1).write(&mut stream).unwrap();
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::ClusterGroupGetNodesEndpoints as i16);
+            let mut resp = Vec::new();
+            write_i64(&mut resp, request_id).unwrap();
+            write_i16(&mut resp, 0).unwrap(); // flags
+            write_i64(&mut resp, 5).unwrap(); // topology version
+            write_i32(&mut resp, 2).unwrap(); // added nodes
+            for (node_id, host) in &[(1u64, "127.0.0.1"), (2, "::1")] {
+                resp.extend_from_slice(&[0u8; 8]);
+                write_i64(&mut resp, *node_id as i64).unwrap();
+                write_i32(&mut resp, 10900).unwrap();
+                write_i32(&mut resp, 1).unwrap();
+                host.write(&mut resp).unwrap();
+            }
+            write_i32(&mut resp, 0).unwrap(); // removed nodes
+            write_i32(&mut stream, resp.len() as i32).unwrap();
+            stream.write_all(&resp).unwrap();
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        Connection::start_discovery(&conn);
+        server.join().unwrap();
+        let addrs = conn.addrs();
+        assert_eq!(addrs.len(), 3);
+        assert!(addrs.contains(&"127.0.0.1:10900".to_owned()));
+        assert!(addrs.contains(&"[::1]:10900".to_owned()));
+        assert_eq!(conn.nodes.lock().unwrap()[&2], "[::1]:10900");
+    }
+
+    #[test]
+    fn test_partition_map_refresh() {
+        fn write_resp(
+            stream: &mut TcpStream,
+            request_id: i64,
+            topology: Option<i64>,
+            payload: &[u8],
+        ) {
+            let mut resp = Vec::new();
+            write_i64(&mut resp, request_id).unwrap();
+            match topology {
+                Some(major) => {
+                    write_i16(&mut resp, FLAG_TOPOLOGY_CHANGED).unwrap();
+                    write_i64(&mut resp, major).unwrap();
+                    write_i32(&mut resp, 0).unwrap();
+                }
+                None => write_i16(&mut resp, 0).unwrap(),
+            }
+            resp.extend_from_slice(payload);
+            write_i32(stream, resp.len() as i32).unwrap();
+            stream.write_all(&resp).unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        conf.partition_awareness = true;
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let node_id = stream.local_addr().unwrap().port() as u128;
+            for major in 1..=2 {
+                // the partition map of the cache: all keys are on the node
+                let (op_code, request_id) = read_req(&mut stream);
+                assert_eq!(op_code, OpCode::CachePartitions as i16);
+                let map = partition_map_payload(major, node_id, &[0]);
+                write_resp(&mut stream, request_id, None, &map);
+                // the get reports that the topology has changed
+                let (op_code, request_id) = read_req(&mut stream);
+                assert_eq!(op_code, OpCode::CacheGet as i16);
+                write_resp(&mut stream, request_id, Some(major + 1), &[101]);
+            }
+            // a node which has not seen the new topology yet returns an older map
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::CachePartitions as i16);
+            let map = partition_map_payload(2, node_id, &[0]);
+            write_resp(&mut stream, request_id, None, &map);
+            let (op_code, request_id) = read_req(&mut stream);
+            assert_eq!(op_code, OpCode::CacheGet as i16);
+            write_resp(&mut stream, request_id, None, &[101]);
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn.clone());
+        assert_eq!(cache.get(&1).unwrap(), None);
+        assert!(conn.affinity.lock().unwrap().caches.is_empty());
+        // the map is fetched again for the new topology
+        assert_eq!(cache.get(&1).unwrap(), None);
+        assert_eq!(conn.affinity.lock().unwrap().topology, Some((3, 0)));
+        // the older map is not kept, nor does it move the topology back
+        assert_eq!(cache.get(&1).unwrap(), None);
+        server.join().unwrap();
+        let affinity = conn.affinity.lock().unwrap();
+        assert_eq!(affinity.topology, Some((3, 0)));
+        assert!(affinity.caches.is_empty());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(40), Duration::from_secs(5));
     }
 }
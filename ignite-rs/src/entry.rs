@@ -0,0 +1,227 @@
+//! Entry API of the caches, for the read-modify-write of a single key.
+//! The updates are compare-and-swap loops over `replace_if_equals` and `get_and_put_if_absent`,
+//! so a concurrent change of the key is never lost: the function is applied again to the new value.
+//! The updates fail with `ErrorKind::Conflict` after 16 lost attempts, like `Cache::update`
+use crate::cache::{update_conflict, Cache, UPDATE_ATTEMPTS};
+use crate::error::IgniteResult;
+use crate::{ReadableType, WritableType};
+
+#[cfg(feature = "async")]
+use crate::async_client::AsyncCache;
+
+/// Function which computes the new value of the key from the current one
+type Modifier<'a, V> = Box<dyn FnMut(&V) -> V + Send + 'a>;
+
+/// Key of the cache, see `Cache::entry`. `and_modify` sets the update of the present value,
+/// which is applied by `or_insert`, `or_insert_with`, `or_default` or `modify`
+///
+/// ```ignore
+/// let hits = cache.entry("page".to_owned()).and_modify(|hits| hits + 1).or_insert(1)?;
+/// ```
+pub struct CacheEntry<'a, K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: &'a Cache<K, V>,
+    key: K,
+    modifier: Option<Modifier<'a, V>>,
+}
+
+impl<'a, K: WritableType + ReadableType, V: WritableType + ReadableType> CacheEntry<'a, K, V> {
+    pub(crate) fn new(cache: &'a Cache<K, V>, key: K) -> CacheEntry<'a, K, V> {
+        CacheEntry {
+            cache,
+            key,
+            modifier: None,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the current value of the key
+    pub fn get(&self) -> IgniteResult<Option<V>> {
+        self.cache.get(&self.key)
+    }
+
+    /// Sets the function which updates the value if the key is in the cache.
+    /// It could be called several times, when the value is changed concurrently
+    pub fn and_modify(mut self, modifier: impl FnMut(&V) -> V + Send + 'a) -> Self {
+        self.modifier = Some(Box::new(modifier));
+        self
+    }
+
+    /// Puts the value if the key is absent, otherwise updates the present value with the
+    /// `and_modify` function or leaves it. Returns the value of the key afterwards
+    pub fn or_insert(self, value: V) -> IgniteResult<V> {
+        self.or_insert_with(|| value)
+    }
+
+    /// Same as `or_insert`, but the value is computed only if the key is absent
+    pub fn or_insert_with(mut self, default: impl FnOnce() -> V) -> IgniteResult<V> {
+        let mut default = Some(default);
+        let mut inserted: Option<V> = None;
+        for _ in 0..UPDATE_ATTEMPTS {
+            let current = match self.cache.get(&self.key)? {
+                Some(current) => current,
+                None => {
+                    let value = match inserted.take() {
+                        Some(value) => value,
+                        None => (default.take().expect("default is taken once"))(),
+                    };
+                    match self.cache.get_and_put_if_absent(&self.key, &value)? {
+                        None => return Ok(value),
+                        Some(_) => {
+                            // another client has put the key, so it is read again
+                            inserted = Some(value);
+                            continue;
+                        }
+                    }
+                }
+            };
+            match self.modifier.as_mut() {
+                Some(modifier) => {
+                    let new = modifier(&current);
+                    if self.cache.replace_if_equals(&self.key, &current, &new)? {
+                        return Ok(new);
+                    }
+                }
+                None => return Ok(current),
+            }
+        }
+        Err(update_conflict(&self.cache._name))
+    }
+
+    /// Same as `or_insert`, with the default value of the type
+    pub fn or_default(self) -> IgniteResult<V>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Updates the present value with the `and_modify` function. Returns the new value,
+    /// None if the key is absent
+    pub fn modify(mut self) -> IgniteResult<Option<V>> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let current = match self.cache.get(&self.key)? {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let new = match self.modifier.as_mut() {
+                Some(modifier) => modifier(&current),
+                None => return Ok(Some(current)),
+            };
+            if self.cache.replace_if_equals(&self.key, &current, &new)? {
+                return Ok(Some(new));
+            }
+        }
+        Err(update_conflict(&self.cache._name))
+    }
+}
+
+/// Key of the asynchronous cache, see `AsyncCache::entry` and `CacheEntry`
+#[cfg(feature = "async")]
+pub struct AsyncCacheEntry<'a, K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: &'a AsyncCache<K, V>,
+    key: K,
+    modifier: Option<Modifier<'a, V>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, K: WritableType + ReadableType, V: WritableType + ReadableType> AsyncCacheEntry<'a, K, V> {
+    pub(crate) fn new(cache: &'a AsyncCache<K, V>, key: K) -> AsyncCacheEntry<'a, K, V> {
+        AsyncCacheEntry {
+            cache,
+            key,
+            modifier: None,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the current value of the key
+    pub async fn get(&self) -> IgniteResult<Option<V>> {
+        self.cache.get(&self.key).await
+    }
+
+    /// Sets the function which updates the value if the key is in the cache,
+    /// see `CacheEntry::and_modify`
+    pub fn and_modify(mut self, modifier: impl FnMut(&V) -> V + Send + 'a) -> Self {
+        self.modifier = Some(Box::new(modifier));
+        self
+    }
+
+    /// Puts the value if the key is absent, see `CacheEntry::or_insert`
+    pub async fn or_insert(self, value: V) -> IgniteResult<V> {
+        self.or_insert_with(|| value).await
+    }
+
+    /// Same as `or_insert`, but the value is computed only if the key is absent
+    pub async fn or_insert_with(mut self, default: impl FnOnce() -> V) -> IgniteResult<V> {
+        let mut default = Some(default);
+        let mut inserted: Option<V> = None;
+        for _ in 0..UPDATE_ATTEMPTS {
+            let current = match self.cache.get(&self.key).await? {
+                Some(current) => current,
+                None => {
+                    let value = match inserted.take() {
+                        Some(value) => value,
+                        None => (default.take().expect("default is taken once"))(),
+                    };
+                    match self.cache.get_and_put_if_absent(&self.key, &value).await? {
+                        None => return Ok(value),
+                        Some(_) => {
+                            inserted = Some(value);
+                            continue;
+                        }
+                    }
+                }
+            };
+            match self.modifier.as_mut() {
+                Some(modifier) => {
+                    let new = modifier(&current);
+                    if self
+                        .cache
+                        .replace_if_equals(&self.key, &current, &new)
+                        .await?
+                    {
+                        return Ok(new);
+                    }
+                }
+                None => return Ok(current),
+            }
+        }
+        Err(update_conflict(&self.cache._name))
+    }
+
+    /// Same as `or_insert`, with the default value of the type
+    pub async fn or_default(self) -> IgniteResult<V>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default).await
+    }
+
+    /// Updates the present value, see `CacheEntry::modify`
+    pub async fn modify(mut self) -> IgniteResult<Option<V>> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let current = match self.cache.get(&self.key).await? {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let new = match self.modifier.as_mut() {
+                Some(modifier) => modifier(&current),
+                None => return Ok(Some(current)),
+            };
+            if self
+                .cache
+                .replace_if_equals(&self.key, &current, &new)
+                .await?
+            {
+                return Ok(Some(new));
+            }
+        }
+        Err(update_conflict(&self.cache._name))
+    }
+}
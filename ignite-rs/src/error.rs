@@ -1,14 +1,54 @@
 use std::fmt::{Display, Formatter};
+use std::io;
 use std::io::Error as IoError;
 use std::{convert, error};
-#[cfg(feature = "ssl")]
+#[cfg(feature = "tls")]
 use webpki::InvalidDNSNameError;
 
 pub type IgniteResult<T> = Result<T, IgniteError>;
 
+/// Kinds of errors that could be handled by the client or by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Any error without a dedicated kind
+    Other,
+    /// Binary type metadata is required to read an object, but the client has not got it yet
+    UnknownBinaryType { type_id: i32, schema_id: i32 },
+    /// Connection with the cluster is lost and could not be restored.
+    /// The operation may have been applied if it was sent before the connection broke
+    Disconnected,
+    /// The cluster has rejected the username and password of `ClientConfig`
+    AuthenticationError,
+    /// The node has not answered in time. The operation may have been applied
+    Timeout,
+    /// The client is closed, the operation is not sent
+    Closed,
+    /// The client or cache configuration is not valid, see `ClientConfig::validate`
+    /// and `CacheConfiguration::validate`
+    InvalidConfig,
+    /// The key or value type does not match the binary metadata or the query entities
+    /// of the cluster, see `Cache::check_types`
+    TypeMismatch,
+    /// The value of the key kept changing concurrently, so the optimistic update gave up,
+    /// see `Cache::update`
+    Conflict,
+}
+
 #[derive(Debug)]
 pub struct IgniteError {
     pub(crate) desc: String,
+    pub(crate) kind: ErrorKind,
+}
+
+impl IgniteError {
+    pub(crate) fn new(kind: ErrorKind, desc: String) -> IgniteError {
+        IgniteError { desc, kind }
+    }
+
+    /// Returns the kind of this error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
 }
 
 impl error::Error for IgniteError {}
@@ -21,36 +61,57 @@ impl Display for IgniteError {
 
 impl convert::From<IoError> for IgniteError {
     fn from(e: IoError) -> Self {
-        IgniteError {
-            desc: e.to_string(),
-        }
+        // socket timeouts are reported as WouldBlock on some platforms
+        let kind = match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ErrorKind::Timeout,
+            _ => ErrorKind::Other,
+        };
+        IgniteError::new(kind, e.to_string())
     }
 }
 
 impl convert::From<&str> for IgniteError {
     fn from(desc: &str) -> Self {
-        IgniteError {
-            desc: String::from(desc),
-        }
+        IgniteError::new(ErrorKind::Other, String::from(desc))
     }
 }
 
 impl convert::From<Option<String>> for IgniteError {
     fn from(desc: Option<String>) -> Self {
         match desc {
-            Some(desc) => IgniteError { desc },
-            None => IgniteError {
-                desc: "Ignite client error! No description provided".to_owned(),
-            },
+            Some(desc) => IgniteError::new(ErrorKind::Other, desc),
+            None => IgniteError::new(
+                ErrorKind::Other,
+                "Ignite client error! No description provided".to_owned(),
+            ),
         }
     }
 }
 
-#[cfg(feature = "ssl")]
+#[cfg(feature = "tls")]
 impl convert::From<InvalidDNSNameError> for IgniteError {
     fn from(err: InvalidDNSNameError) -> Self {
-        IgniteError {
-            desc: err.to_string(),
-        }
+        IgniteError::new(ErrorKind::Other, err.to_string())
+    }
+}
+
+#[cfg(feature = "native-tls")]
+impl convert::From<native_tls::Error> for IgniteError {
+    fn from(err: native_tls::Error) -> Self {
+        IgniteError::new(ErrorKind::Other, err.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for IgniteError {
+    fn custom<T: Display>(msg: T) -> Self {
+        IgniteError::new(ErrorKind::Other, msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for IgniteError {
+    fn custom<T: Display>(msg: T) -> Self {
+        IgniteError::new(ErrorKind::Other, msg.to_string())
     }
 }
@@ -0,0 +1,39 @@
+//! Events of the connections with the cluster, for logging, alerting or readiness probes
+use std::time::Duration;
+
+use crate::ClientConfig;
+
+/// Event of a connection of the blocking client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// TCP connection to the node is opened, the handshake follows
+    Connected { addr: String },
+    /// The node has accepted the handshake, so the connection could be used
+    HandshakeCompleted { addr: String, node_id: Option<u128> },
+    /// The connection has broken or timed out, so it is closed
+    Disconnected { addr: String, reason: String },
+    /// The request which hit the broken connection is sent again after the delay.
+    /// Attempts are counted from 1
+    ReconnectAttempt { attempt: u32, delay: Duration },
+    /// The request is sent to another node instead of the failed one
+    Failover { from: String, to: String },
+}
+
+/// Receives the connection events. Called on the thread which has caused the event,
+/// so it should return quickly
+pub trait ConnectionListener: Send + Sync {
+    fn on_event(&self, event: &ConnectionEvent);
+}
+
+impl<F: Fn(&ConnectionEvent) + Send + Sync> ConnectionListener for F {
+    fn on_event(&self, event: &ConnectionEvent) {
+        self(event)
+    }
+}
+
+/// Passes the event to the listener of the client, if there is one
+pub(crate) fn notify(conf: &ClientConfig, event: impl FnOnce() -> ConnectionEvent) {
+    if let Some(listener) = &conf.connection_listener {
+        listener.on_event(&event());
+    }
+}
@@ -0,0 +1,108 @@
+//! Optional capabilities of the protocol, negotiated in the handshake
+use crate::handshake::ProtocolVersion;
+
+/// Capabilities which nodes of protocol v1.7.0 and newer announce in the feature bitmask.
+/// The values are the bit numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    UserAttributes = 0,
+    ExecuteTaskByName = 1,
+    ClusterStates = 2,
+    ClusterGroupGetNodesEndpoints = 3,
+    ClusterGroups = 4,
+    ServiceInvoke = 5,
+    DefaultQueryTimeout = 6,
+    QueryPartitionsBatchSize = 7,
+    BinaryConfiguration = 8,
+    GetServiceDescriptors = 9,
+    ServiceInvokeCallContext = 10,
+    Heartbeat = 11,
+}
+
+/// Features which the client implements, so it announces them to the nodes
+const CLIENT_FEATURES: &[Feature] = &[
+    Feature::UserAttributes,
+    Feature::ClusterGroupGetNodesEndpoints,
+    Feature::Heartbeat,
+];
+
+/// Protocol version and features supported by both the client and the node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Features {
+    version: ProtocolVersion,
+    bitmask: Vec<u8>,
+}
+
+impl Features {
+    /// Keeps the features of the node which the client supports too
+    pub(crate) fn negotiated(version: ProtocolVersion, node_bitmask: &[u8]) -> Features {
+        let client = Features::client_bitmask();
+        let bitmask = client
+            .iter()
+            .zip(node_bitmask)
+            .map(|(client, node)| client & node)
+            .collect();
+        Features { version, bitmask }
+    }
+
+    /// Bitmask of the features which the client implements
+    pub(crate) fn client_bitmask() -> Vec<u8> {
+        let mut bitmask = Vec::new();
+        for feature in CLIENT_FEATURES {
+            let bit = *feature as usize;
+            if bitmask.len() <= bit / 8 {
+                bitmask.resize(bit / 8 + 1, 0);
+            }
+            bitmask[bit / 8] |= 1 << (bit % 8);
+        }
+        bitmask
+    }
+
+    pub(crate) fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// Protocol version as (major, minor, patch)
+    pub fn protocol_version(&self) -> (i16, i16, i16) {
+        (self.version.0, self.version.1, self.version.2)
+    }
+
+    /// Whether the feature is supported by both the client and the node
+    pub fn supports(&self, feature: Feature) -> bool {
+        let bit = feature as usize;
+        match self.bitmask.get(bit / 8) {
+            Some(byte) => byte & (1 << (bit % 8)) != 0,
+            None => false,
+        }
+    }
+
+    /// Whether requests could be routed to the primary nodes of the keys
+    pub fn partition_awareness(&self) -> bool {
+        self.version >= ProtocolVersion::PARTITION_AWARENESS
+    }
+
+    /// Whether expiry policies could be set on the caches and the operations
+    pub fn expiry_policy(&self) -> bool {
+        self.version >= ProtocolVersion::EXPIRY_POLICY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiated() {
+        let features = Features::negotiated(ProtocolVersion::LATEST, &[0xff, 0xff]);
+        for feature in CLIENT_FEATURES {
+            assert!(features.supports(*feature));
+        }
+        assert!(!features.supports(Feature::ServiceInvoke));
+        assert!(features.partition_awareness());
+
+        let features = Features::negotiated(ProtocolVersion(1, 4, 0), &[]);
+        assert!(!features.supports(Feature::Heartbeat));
+        assert!(!features.expiry_policy());
+        assert_eq!(features.protocol_version(), (1, 4, 0));
+    }
+}
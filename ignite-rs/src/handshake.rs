@@ -1,26 +1,114 @@
-use std::io::{Read, Write};
+use std::io::{Cursor, Read, Write};
 
 use crate::api::OpCode;
-use crate::error::{IgniteError, IgniteResult};
+use crate::error::{ErrorKind, IgniteError, IgniteResult};
+use crate::features::Features;
 use crate::protocol::{
-    read_i16, read_i32, read_u8, write_i16, write_i32, write_string_type_code, write_u8,
+    read_i16, read_i32, read_u8, read_uuid, write_i16, write_i32, write_i8, write_string_type_code,
+    write_u8, TypeCode,
 };
-use crate::{ClientConfig, ReadableType};
+use crate::{ClientConfig, ReadableType, WritableType};
+use std::convert::TryFrom;
 
 const MIN_HANDSHAKE_SIZE: usize = 8;
 const CLIENT_CODE: u8 = 2;
+const STATUS_AUTH_FAILED: i32 = 2000;
+/// Java's HashMap, the type of the user attributes map
+const HASH_MAP: i8 = 1;
 
-const V_MAJOR: i16 = 1;
-const V_MINOR: i16 = 2;
-const V_PATCH: i16 = 0;
+/// Version of the binary client protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct ProtocolVersion(pub(crate) i16, pub(crate) i16, pub(crate) i16);
 
-pub(crate) fn handshake<T: Read + Write>(conn: &mut T, conf: &ClientConfig) -> IgniteResult<()> {
+impl ProtocolVersion {
+    /// The newest version, which is tried first
+    pub(crate) const LATEST: ProtocolVersion = ProtocolVersion(1, 7, 0);
+    /// The oldest version the client could fall back to
+    pub(crate) const OLDEST: ProtocolVersion = ProtocolVersion(1, 0, 0);
+    /// Handshake with username and password
+    pub(crate) const AUTHENTICATION: ProtocolVersion = ProtocolVersion(1, 1, 0);
+    /// Node ids, response flags and affinity topology versions
+    pub(crate) const PARTITION_AWARENESS: ProtocolVersion = ProtocolVersion(1, 4, 0);
+    /// Expiry policies of the caches and the operations
+    pub(crate) const EXPIRY_POLICY: ProtocolVersion = ProtocolVersion(1, 6, 0);
+    /// Feature bitmask in the handshake
+    pub(crate) const BITMAP_FEATURES: ProtocolVersion = ProtocolVersion(1, 7, 0);
+}
+
+/// Result of the successful handshake
+pub(crate) struct Handshake {
+    pub(crate) features: Features,
+    pub(crate) node_id: Option<u128>,
+}
+
+/// Handshake response of the node
+pub(crate) enum HandshakeResp {
+    Accepted(Handshake),
+    /// The node has proposed an older version, which the client supports too
+    Retry(ProtocolVersion),
+}
+
+/// Does the handshake. Starts with the latest protocol version, then falls back
+/// to the one proposed by the node if the node is older than the client
+pub(crate) fn handshake<T: Read + Write>(
+    conn: &mut T,
+    conf: &ClientConfig,
+) -> IgniteResult<Handshake> {
+    let mut version = ProtocolVersion::LATEST;
+    loop {
+        write_handshake_req(conn, conf, version)?;
+
+        // send bytes
+        conn.flush()?;
+
+        // read the whole response, as its size depends on the node version
+        let len = read_i32(conn)?;
+        let mut resp = vec![0u8; len as usize];
+        conn.read_exact(&mut resp)?;
+        match read_handshake_resp(&resp, version)? {
+            HandshakeResp::Accepted(handshake) => return Ok(handshake),
+            HandshakeResp::Retry(proposed) => version = proposed,
+        }
+    }
+}
+
+/// Writes the whole handshake request, including its length
+pub(crate) fn write_handshake_req(
+    conn: &mut dyn Write,
+    conf: &ClientConfig,
+    version: ProtocolVersion,
+) -> IgniteResult<()> {
     let mut msg_size = MIN_HANDSHAKE_SIZE;
 
     if conf.username.is_some() != conf.password.is_some() {
         return Err(IgniteError::from("Both username and password expected!"));
     }
 
+    if conf.username.is_some() && version < ProtocolVersion::AUTHENTICATION {
+        return Err(IgniteError::new(
+            ErrorKind::AuthenticationError,
+            format!(
+                "Authentication is not supported by protocol v{}.{}.{}!",
+                version.0, version.1, version.2
+            ),
+        ));
+    }
+
+    if !conf.user_attributes.is_empty() && version < ProtocolVersion::BITMAP_FEATURES {
+        return Err(IgniteError::from(
+            format!(
+                "User attributes are not supported by protocol v{}.{}.{}!",
+                version.0, version.1, version.2
+            )
+            .as_str(),
+        ));
+    }
+
+    let features = Features::client_bitmask();
+    if version >= ProtocolVersion::BITMAP_FEATURES {
+        msg_size += features.size() + user_attributes_size(conf);
+    }
+
     if let Some(ref user) = conf.username {
         msg_size += user.len() + 4 + 1; // string itself, len, type code
     }
@@ -31,11 +119,16 @@ pub(crate) fn handshake<T: Read + Write>(conn: &mut T, conf: &ClientConfig) -> I
 
     write_i32(conn, msg_size as i32)?;
     write_u8(conn, OpCode::Handshake as u8)?;
-    write_i16(conn, V_MAJOR)?;
-    write_i16(conn, V_MINOR)?;
-    write_i16(conn, V_PATCH)?;
+    write_i16(conn, version.0)?;
+    write_i16(conn, version.1)?;
+    write_i16(conn, version.2)?;
     write_u8(conn, CLIENT_CODE)?;
 
+    if version >= ProtocolVersion::BITMAP_FEATURES {
+        features.write(conn)?;
+        write_user_attributes(conn, conf)?;
+    }
+
     if let Some(ref user) = conf.username {
         write_string_type_code(conn, user)?;
     }
@@ -43,32 +136,212 @@ pub(crate) fn handshake<T: Read + Write>(conn: &mut T, conf: &ClientConfig) -> I
     if let Some(ref pass) = conf.password {
         write_string_type_code(conn, pass)?;
     }
+    Ok(())
+}
 
-    // send bytes
-    conn.flush()?;
+/// Writes the user attributes as a map of strings, or NULL if there are none
+fn write_user_attributes(conn: &mut dyn Write, conf: &ClientConfig) -> IgniteResult<()> {
+    if conf.user_attributes.is_empty() {
+        write_u8(conn, TypeCode::Null as u8)?;
+        return Ok(());
+    }
+    write_u8(conn, TypeCode::Map as u8)?;
+    write_i32(conn, conf.user_attributes.len() as i32)?;
+    write_i8(conn, HASH_MAP)?;
+    for (name, value) in &conf.user_attributes {
+        write_string_type_code(conn, name)?;
+        write_string_type_code(conn, value)?;
+    }
+    Ok(())
+}
 
-    // read header
-    let _ = read_i32(conn)?;
-    match read_u8(conn)? {
-        1 => Ok(()),
-        _ => match read_handshake_err(conn) {
-            Ok(msg) => Err(IgniteError::from(msg.as_str())),
-            Err(err) => Err(err),
-        },
+fn user_attributes_size(conf: &ClientConfig) -> usize {
+    if conf.user_attributes.is_empty() {
+        return 1; // NULL
     }
+    let entries: usize = conf
+        .user_attributes
+        .iter()
+        .map(|(name, value)| name.len() + value.len() + 2 * (4 + 1))
+        .sum();
+    entries + 1 + 4 + 1 // entries, type code, len, map type
 }
 
-fn read_handshake_err<T: Read + Write>(conn: &mut T) -> IgniteResult<String> {
+/// Reads the handshake response to the request of the version, without its length
+pub(crate) fn read_handshake_resp(
+    resp: &[u8],
+    version: ProtocolVersion,
+) -> IgniteResult<HandshakeResp> {
+    let mut conn = Cursor::new(resp);
+    match read_u8(&mut conn)? {
+        1 => {
+            let node_features = match version < ProtocolVersion::BITMAP_FEATURES {
+                true => None,
+                false => Vec::<u8>::read(&mut conn)?,
+            };
+            let features = Features::negotiated(version, &node_features.unwrap_or_default());
+            let node_id = match version < ProtocolVersion::PARTITION_AWARENESS {
+                true => None,
+                false => match TypeCode::try_from(read_u8(&mut conn)?)? {
+                    TypeCode::Uuid => Some(read_uuid(&mut conn)?),
+                    _ => None,
+                },
+            };
+            Ok(HandshakeResp::Accepted(Handshake { features, node_id }))
+        }
+        _ => {
+            let (proposed, err) = read_handshake_err(&mut conn)?;
+            let supported = ProtocolVersion::OLDEST..version;
+            if supported.contains(&proposed) && err.kind() != ErrorKind::AuthenticationError {
+                Ok(HandshakeResp::Retry(proposed))
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Reads the version proposed by the node and the error
+fn read_handshake_err(conn: &mut Cursor<&[u8]>) -> IgniteResult<(ProtocolVersion, IgniteError)> {
     let major_v = read_i16(conn)?;
     let minor_v = read_i16(conn)?;
     let patch_v = read_i16(conn)?;
-    let err_msg = String::read(conn)?;
-
-    Ok(format!(
-        "Handshake error: v{}.{}.{} err: {}",
-        major_v,
-        minor_v,
-        patch_v,
-        err_msg.unwrap()
+    let err_msg = String::read(conn)?.unwrap_or_default();
+    // the status is sent by the nodes which support authentication
+    let status = match conn.get_ref().len() as u64 - conn.position() {
+        0 => None,
+        _ => Some(read_i32(conn)?),
+    };
+
+    let kind = match status {
+        Some(STATUS_AUTH_FAILED) => ErrorKind::AuthenticationError,
+        _ => ErrorKind::Other,
+    };
+    Ok((
+        ProtocolVersion(major_v, minor_v, patch_v),
+        IgniteError::new(
+            kind,
+            format!(
+                "Handshake error: v{}.{}.{} err: {}",
+                major_v, minor_v, patch_v, err_msg
+            ),
+        ),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the prepared responses and keeps the requests
+    struct Scripted {
+        responses: Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Write::write(&mut self.requests, buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn handshake_err(status: Option<i32>) -> Vec<u8> {
+        handshake_err_of(ProtocolVersion::LATEST, status)
+    }
+
+    fn handshake_err_of(proposed: ProtocolVersion, status: Option<i32>) -> Vec<u8> {
+        let mut resp = vec![0];
+        for v in &[proposed.0, proposed.1, proposed.2] {
+            write_i16(&mut resp, *v).unwrap();
+        }
+        write_string_type_code(&mut resp, "The user name or password is incorrect").unwrap();
+        if let Some(status) = status {
+            write_i32(&mut resp, status).unwrap();
+        }
+        resp
+    }
+
+    #[test]
+    fn test_auth_failure() {
+        let latest = ProtocolVersion::LATEST;
+        let err = read_handshake_resp(&handshake_err(Some(2000)), latest).err();
+        assert_eq!(err.unwrap().kind(), ErrorKind::AuthenticationError);
+        let err = read_handshake_resp(&handshake_err(None), latest).err();
+        assert_eq!(err.unwrap().kind(), ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_version_fallback() {
+        let mut responses = Vec::new();
+        let rejected = handshake_err_of(ProtocolVersion(1, 2, 0), None);
+        write_i32(&mut responses, rejected.len() as i32).unwrap();
+        responses.extend(rejected);
+        write_i32(&mut responses, 1).unwrap();
+        responses.push(1); // accepted without the node id
+        let mut conn = Scripted {
+            responses: Cursor::new(responses),
+            requests: Vec::new(),
+        };
+        let handshake = handshake(&mut conn, &ClientConfig::new("any")).unwrap();
+        assert_eq!(handshake.features.version(), ProtocolVersion(1, 2, 0));
+        assert_eq!(handshake.node_id, None);
+        assert_eq!(&conn.requests[5..11], &[1, 0, 7, 0, 0, 0]);
+        let first_len = conn.requests[0] as usize + 4;
+        assert_eq!(
+            &conn.requests[first_len + 5..first_len + 11],
+            &[1, 0, 2, 0, 0, 0]
+        );
+
+        // newer or unknown versions are not tried
+        let newer = handshake_err_of(ProtocolVersion(1, 8, 0), None);
+        assert!(read_handshake_resp(&newer, ProtocolVersion::LATEST).is_err());
+        let unknown = handshake_err_of(ProtocolVersion(0, 9, 0), None);
+        assert!(read_handshake_resp(&unknown, ProtocolVersion::LATEST).is_err());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let mut conf = ClientConfig::new("any");
+        conf.username = Some("ignite".into());
+        let mut req = Vec::new();
+        assert!(write_handshake_req(&mut req, &conf, ProtocolVersion::LATEST).is_err());
+        conf.password = Some("secret".into());
+        write_handshake_req(&mut req, &conf, ProtocolVersion::LATEST).unwrap();
+        assert_eq!(read_i32(&mut &req[..]).unwrap() as usize, req.len() - 4);
+        // the first version knows nothing about authentication
+        let err = write_handshake_req(&mut req, &conf, ProtocolVersion::OLDEST).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AuthenticationError);
+    }
+
+    #[test]
+    fn test_user_attributes() {
+        let mut conf = ClientConfig::new("any");
+        conf.user_attributes
+            .insert("tenant".to_owned(), "acme".to_owned());
+        let mut req = Vec::new();
+        write_handshake_req(&mut req, &conf, ProtocolVersion::LATEST).unwrap();
+        assert_eq!(read_i32(&mut &req[..]).unwrap() as usize, req.len() - 4);
+        let features_len = Features::client_bitmask().size();
+        let attributes = &req[12 + features_len..];
+        assert_eq!(
+            attributes[..6],
+            [TypeCode::Map as u8, 1, 0, 0, 0, HASH_MAP as u8]
+        );
+        assert_eq!(
+            String::read(&mut &attributes[6..]).unwrap().unwrap(),
+            "tenant"
+        );
+        // older nodes do not read the attributes
+        assert!(write_handshake_req(&mut req, &conf, ProtocolVersion(1, 6, 0)).is_err());
+    }
+}
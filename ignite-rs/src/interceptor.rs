@@ -0,0 +1,91 @@
+//! Hooks around the cache operations, for auditing, validation or shadow writes
+//! without wrapping each call of the caches
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::{IgniteError, IgniteResult};
+use crate::WritableType;
+
+/// Cache operation which is passed to the interceptors
+pub struct CacheOperation<'a> {
+    /// Name of the method, like "get" or "put_all"
+    pub name: &'static str,
+    /// Name of the cache
+    pub cache: &'a str,
+    /// Serialized key of the single-key operations, with its type code
+    pub key: Option<&'a [u8]>,
+}
+
+/// Intercepts the operations of the caches, see `ClientConfig::interceptors` and
+/// `Cache::with_interceptor`. Called on the thread or the task which runs the operation,
+/// so it should return quickly. The operations composed of the others, like `update`,
+/// are seen as the operations they consist of
+pub trait CacheInterceptor: Send + Sync {
+    /// Called before the operation is sent. The operation is not sent if it returns an error,
+    /// it fails with the error instead
+    fn before(&self, _op: &CacheOperation) -> IgniteResult<()> {
+        Ok(())
+    }
+
+    /// Called after the operation is completed, with its error if it has failed
+    fn after(&self, _op: &CacheOperation, _error: Option<&IgniteError>, _duration: Duration) {}
+}
+
+/// Runs the interceptors around a single operation. Owns the serialized key, so the futures
+/// of the asynchronous caches which hold it are still `Send`
+pub(crate) struct Intercepted<'a> {
+    interceptors: &'a [Arc<dyn CacheInterceptor>],
+    name: &'static str,
+    cache: &'a str,
+    key: Option<Vec<u8>>,
+    started: Instant,
+}
+
+impl<'a> Intercepted<'a> {
+    /// Calls `before` of the interceptors, returns the error of the first one which rejects the operation.
+    /// The key is serialized only if there are interceptors
+    pub(crate) fn start(
+        interceptors: &'a [Arc<dyn CacheInterceptor>],
+        name: &'static str,
+        cache: &'a str,
+        key: Option<&dyn WritableType>,
+    ) -> IgniteResult<Intercepted<'a>> {
+        let mut intercepted = Intercepted {
+            interceptors,
+            name,
+            cache,
+            key: None,
+            started: Instant::now(),
+        };
+        if interceptors.is_empty() {
+            return Ok(intercepted);
+        }
+        if let Some(key) = key {
+            let mut bytes = Vec::with_capacity(key.size());
+            key.write(&mut bytes)?;
+            intercepted.key = Some(bytes);
+        }
+        for interceptor in interceptors {
+            interceptor.before(&intercepted.op())?;
+        }
+        intercepted.started = Instant::now();
+        Ok(intercepted)
+    }
+
+    fn op(&self) -> CacheOperation<'_> {
+        CacheOperation {
+            name: self.name,
+            cache: self.cache,
+            key: self.key.as_deref(),
+        }
+    }
+
+    /// Calls `after` of the interceptors and passes the result through
+    pub(crate) fn finish<T>(self, result: IgniteResult<T>) -> IgniteResult<T> {
+        let duration = self.started.elapsed();
+        for interceptor in self.interceptors {
+            interceptor.after(&self.op(), result.as_ref().err(), duration);
+        }
+        result
+    }
+}
@@ -0,0 +1,397 @@
+//! Client-side near cache, which keeps the recently read entries of a cache in the process.
+//! A continuous query of the cache drops the entries which are changed by any client.
+//! If the connection of the query is lost, all the entries are dropped, and the changes
+//! which come after it are seen once the time to live of their entries is over
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use crate::api::continuous::CacheEventsNotification;
+use crate::cache::Cache;
+use crate::connection::Subscription;
+use crate::error::IgniteResult;
+use crate::{ReadableReq, ReadableType, WritableType};
+
+/// Entries kept in the process, the oldest ones are evicted first
+struct NearEntries<K, V> {
+    /// Values with the time and the sequence number of the store
+    entries: HashMap<K, (V, Instant, u64)>,
+    /// Keys in the order they were stored, with the sequence numbers. A key which is stored again
+    /// has several records, and only the one which matches the entry evicts it
+    order: VecDeque<(K, u64)>,
+    next_seq: u64,
+    /// Generations of the keys which are being read from the cluster, with the number of
+    /// the reads. The generation moves when the key is changed, so a read which has started
+    /// before the change does not store the value it has read
+    reads: HashMap<K, (u64, usize)>,
+    max_entries: usize,
+    ttl: Duration,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> NearEntries<K, V> {
+    fn new(max_entries: usize, ttl: Duration) -> NearEntries<K, V> {
+        NearEntries {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_seq: 0,
+            reads: HashMap::new(),
+            max_entries: max_entries.max(1),
+            ttl,
+        }
+    }
+
+    /// Returns the value if it has not expired yet
+    fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (value, stored, _) = self.entries.get(key)?;
+        Some(value.clone()).filter(|_| stored.elapsed() < self.ttl)
+    }
+
+    fn store(&mut self, key: K, value: V) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.entries
+            .insert(key.clone(), (value, Instant::now(), seq));
+        self.order.push_back((key, seq));
+        while self.entries.len() > self.max_entries || self.order.len() > 2 * self.max_entries {
+            match self.order.pop_front() {
+                Some((key, stored)) => {
+                    if self.entries.get(&key).map(|(_, _, seq)| *seq) == Some(stored) {
+                        self.entries.remove(&key);
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Returns the generation of the key, which the read from the cluster passes to `finish_read`
+    fn start_read(&mut self, key: &K) -> u64 {
+        let (generation, readers) = self.reads.entry(key.clone()).or_insert((0, 0));
+        *readers += 1;
+        *generation
+    }
+
+    /// Stores the value which is read, unless the key has changed since the read started
+    fn finish_read(&mut self, key: K, generation: u64, value: Option<V>) {
+        let current = match self.reads.get_mut(&key) {
+            Some((current, readers)) => {
+                let current = *current;
+                *readers -= 1;
+                if *readers == 0 {
+                    self.reads.remove(&key);
+                }
+                current
+            }
+            None => return,
+        };
+        if let Some(value) = value.filter(|_| current == generation) {
+            self.store(key, value);
+        }
+    }
+
+    /// Drops the entry of the key which is written. The write passes the returned generation to
+    /// `finish_read`, so the reads which are in flight do not store their values, and a change
+    /// which is notified before the write completes does not let it store the written one
+    fn start_write(&mut self, key: &K) -> u64 {
+        self.invalidate(key);
+        self.start_read(key)
+    }
+
+    fn invalidate<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.remove(key);
+        if let Some((generation, _)) = self.reads.get_mut(key) {
+            *generation += 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        for (generation, _) in self.reads.values_mut() {
+            *generation += 1;
+        }
+    }
+}
+
+/// Drops the entries of the keys of the events. Drops all the entries if the keys could not
+/// be read, or if the query has failed, as the changes would not be seen then
+fn on_events<K, V>(near: &Weak<Mutex<NearEntries<K, V>>>, events: IgniteResult<&[u8]>)
+where
+    K: ReadableType + Hash + Eq + Clone,
+    V: Clone,
+{
+    let near = match near.upgrade() {
+        Some(near) => near,
+        None => return,
+    };
+    let events =
+        events.and_then(|payload| CacheEventsNotification::<K>::read(&mut Cursor::new(payload)));
+    let mut near = near.lock().unwrap();
+    match events {
+        Ok(events) => {
+            for key in events.keys.iter().flatten() {
+                near.invalidate(key);
+            }
+        }
+        Err(_) => near.clear(),
+    }
+}
+
+/// Cache handle which serves the reads of the recent entries from the process,
+/// see `Cache::with_near_cache`. The clones share the entries and the continuous query,
+/// which is stopped when the last clone is dropped
+pub struct NearCache<K, V>
+where
+    K: WritableType + ReadableType + Hash + Eq + Clone,
+    V: WritableType + ReadableType + Clone,
+{
+    cache: Cache<K, V>,
+    near: Arc<Mutex<NearEntries<K, V>>>,
+    _subscription: Arc<Subscription>,
+}
+
+impl<K, V> Clone for NearCache<K, V>
+where
+    K: WritableType + ReadableType + Hash + Eq + Clone,
+    V: WritableType + ReadableType + Clone,
+{
+    fn clone(&self) -> Self {
+        NearCache {
+            cache: self.cache.clone(),
+            near: self.near.clone(),
+            _subscription: self._subscription.clone(),
+        }
+    }
+}
+
+impl<K, V> NearCache<K, V>
+where
+    K: WritableType + ReadableType + Hash + Eq + Clone,
+    V: WritableType + ReadableType + Clone,
+{
+    /// Starts the continuous query which drops the changed entries
+    pub(crate) fn new(
+        cache: Cache<K, V>,
+        max_entries: usize,
+        ttl: Duration,
+    ) -> IgniteResult<NearCache<K, V>>
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let near = Arc::new(Mutex::new(NearEntries::new(max_entries, ttl)));
+        let weak = Arc::downgrade(&near);
+        let subscription = cache.subscribe(move |events| on_events(&weak, events))?;
+        Ok(NearCache {
+            cache,
+            near,
+            _subscription: Arc::new(subscription),
+        })
+    }
+
+    /// Returns the cache the near cache is over, which operations skip the near cache
+    pub fn cache(&self) -> &Cache<K, V> {
+        &self.cache
+    }
+
+    /// Returns the value from the near cache, or reads it from the cluster and keeps it.
+    /// The keys which are absent are not kept, so they are read from the cluster every time
+    pub fn get(&self, key: &K) -> IgniteResult<Option<V>> {
+        if let Some(value) = self.near.lock().unwrap().get(key) {
+            return Ok(Some(value));
+        }
+        let generation = self.near.lock().unwrap().start_read(key);
+        let value = self.cache.get(key);
+        let read = value.as_ref().ok().cloned().flatten();
+        let mut near = self.near.lock().unwrap();
+        near.finish_read(key.clone(), generation, read);
+        value
+    }
+
+    /// Returns the values of the keys, reading the ones which are not in the near cache
+    /// from the cluster in a single request
+    pub fn get_all(&self, keys: &[K]) -> IgniteResult<HashMap<K, V>> {
+        let mut values = HashMap::new();
+        let mut missing = Vec::new();
+        let mut generations = Vec::new();
+        {
+            let mut near = self.near.lock().unwrap();
+            for key in keys {
+                match near.get(key) {
+                    Some(value) => {
+                        values.insert(key.clone(), value);
+                    }
+                    None => {
+                        generations.push(near.start_read(key));
+                        missing.push(key.clone());
+                    }
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(values);
+        }
+        let read = self.cache.get_all(&missing);
+        let mut near = self.near.lock().unwrap();
+        for (key, generation) in missing.into_iter().zip(generations) {
+            let value = read.as_ref().ok().and_then(|read| read.get(&key)).cloned();
+            near.finish_read(key, generation, value);
+        }
+        values.extend(read?);
+        Ok(values)
+    }
+
+    /// Puts the value into the cluster, then keeps it in the near cache
+    pub fn put(&self, key: &K, value: &V) -> IgniteResult<()> {
+        // the entry is dropped first, so a failed put does not leave the old value
+        let generation = self.near.lock().unwrap().start_write(key);
+        let put = self.cache.put(key, value);
+        let written = put.as_ref().ok().map(|_| value.clone());
+        let mut near = self.near.lock().unwrap();
+        near.finish_read(key.clone(), generation, written);
+        put
+    }
+
+    /// Removes the key from the cluster and the near cache. Returns false if it is absent
+    pub fn remove_key(&self, key: &K) -> IgniteResult<bool> {
+        self.near.lock().unwrap().invalidate(key);
+        let removed = self.cache.remove_key(key);
+        // the reads which have started meanwhile could have read the value before the remove
+        self.near.lock().unwrap().invalidate(key);
+        removed
+    }
+
+    /// Drops the key from the near cache, so the next `get` reads it from the cluster
+    pub fn invalidate(&self, key: &K) {
+        self.near.lock().unwrap().invalidate(key);
+    }
+
+    /// Drops all the entries of the near cache
+    pub fn invalidate_all(&self) {
+        self.near.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::OpCode;
+    use crate::connection::tests::{read_req, write_handshake_resp, write_success};
+    use crate::connection::Connection;
+    use crate::protocol::{write_i16, write_i32, write_i64};
+    use crate::ClientConfig;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[test]
+    fn test_near_entries() {
+        let mut near = NearEntries::new(2, Duration::from_secs(60));
+        near.store(1, "a");
+        near.store(2, "b");
+        // storing the key again does not make it the oldest one
+        near.store(1, "c");
+        near.store(3, "d");
+        assert_eq!(near.get(&1), Some("c"));
+        assert_eq!(near.get(&2), None);
+        assert_eq!(near.get(&3), Some("d"));
+        near.invalidate(&3);
+        assert_eq!(near.get(&3), None);
+
+        let mut near = NearEntries::new(2, Duration::from_millis(0));
+        near.store(1, "a");
+        assert_eq!(near.get(&1), None);
+    }
+
+    #[test]
+    fn test_stale_reads() {
+        let mut near = NearEntries::new(2, Duration::from_secs(60));
+        // the key is invalidated while it is read, so the read value is stale
+        let generation = near.start_read(&1);
+        near.invalidate(&1);
+        near.finish_read(1, generation, Some("old"));
+        assert_eq!(near.get(&1), None);
+        // the key is put while it is read, so the put value is kept
+        let generation = near.start_read(&1);
+        let written = near.start_write(&1);
+        near.finish_read(1, generation, Some("old"));
+        near.finish_read(1, written, Some("new"));
+        assert_eq!(near.get(&1), Some("new"));
+        // the key is changed by another client while it is put, so the put value is stale
+        let written = near.start_write(&1);
+        near.invalidate(&1);
+        near.finish_read(1, written, Some("newer"));
+        assert_eq!(near.get(&1), None);
+        // the reads of the other keys are not affected
+        let generation = near.start_read(&2);
+        near.invalidate(&1);
+        near.finish_read(2, generation, Some("b"));
+        assert_eq!(near.get(&2), Some("b"));
+        assert!(near.reads.is_empty());
+    }
+
+    #[test]
+    fn test_continuous_invalidation() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let (notify, notified) = channel();
+        let server = thread::spawn(move || {
+            let (mut pooled, _) = listener.accept().unwrap();
+            read_req(&mut pooled);
+            write_handshake_resp(&mut pooled);
+            // the continuous query has a connection of its own
+            let (mut query, _) = listener.accept().unwrap();
+            read_req(&mut query);
+            write_handshake_resp(&mut query);
+            let (op_code, request_id) = read_req(&mut query);
+            assert_eq!(op_code, OpCode::QueryContinuous as i16);
+            write_success(&mut query, request_id, &9i64.to_le_bytes());
+            let (op_code, request_id) = read_req(&mut pooled);
+            assert_eq!(op_code, OpCode::CacheGet as i16);
+            let mut value = Vec::new();
+            "a".to_owned().write(&mut value).unwrap();
+            write_success(&mut pooled, request_id, &value);
+            // another client updates the key
+            notified.recv().unwrap();
+            let mut msg = Vec::new();
+            write_i64(&mut msg, 9).unwrap();
+            write_i16(&mut msg, 4).unwrap(); // notification
+            write_i16(&mut msg, OpCode::QueryContinuousEventNotification as i16).unwrap();
+            write_i32(&mut msg, 1).unwrap();
+            1i32.write(&mut msg).unwrap();
+            "a".to_owned().write(&mut msg).unwrap();
+            "b".to_owned().write(&mut msg).unwrap();
+            msg.push(1); // updated
+            write_i32(&mut query, msg.len() as i32).unwrap();
+            query.write_all(&msg).unwrap();
+            // the query is stopped when the near cache is dropped
+            assert_eq!(query.read(&mut [0u8; 1]).unwrap(), 0);
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, String>::new(1, "test".to_owned(), conn);
+        let near = cache.with_near_cache(10, Duration::from_secs(60)).unwrap();
+        assert_eq!(near.get(&1).unwrap(), Some("a".to_owned()));
+        assert!(near.near.lock().unwrap().get(&1).is_some());
+        notify.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while near.near.lock().unwrap().get(&1).is_some() {
+            assert!(Instant::now() < deadline, "the entry is not invalidated");
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(near);
+        server.join().unwrap();
+    }
+}
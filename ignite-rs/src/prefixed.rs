@@ -0,0 +1,226 @@
+//! Namespaces of the keys, so several tenants could share a cache with string keys.
+//! The keys are prefixed with the prefix and the separator on write, and both are stripped on read.
+//! The prefixes may not have the separator, so no namespace is inside another one, like `t1` and `t10`.
+//! The scans read the entries of all the namespaces from the nodes and skip the ones of the other namespaces
+use std::collections::HashMap;
+
+use crate::cache::{Cache, CacheEntries};
+use crate::error::{ErrorKind, IgniteError, IgniteResult};
+use crate::{ReadableType, WritableType};
+
+/// Separator of the prefix and the key of the namespace
+pub const KEY_PREFIX_SEPARATOR: char = ':';
+
+/// Cache handle which keys are in the namespace of the prefix, see `Cache::with_key_prefix`
+pub struct PrefixedCache<V: WritableType + ReadableType> {
+    cache: Cache<String, V>,
+    /// The prefix with the separator
+    namespace: String,
+}
+
+impl<V: WritableType + ReadableType> Clone for PrefixedCache<V> {
+    fn clone(&self) -> Self {
+        PrefixedCache {
+            cache: self.cache.clone(),
+            namespace: self.namespace.clone(),
+        }
+    }
+}
+
+impl<V: WritableType + ReadableType> PrefixedCache<V> {
+    /// Fails with `ErrorKind::InvalidConfig` if the prefix has the separator
+    pub(crate) fn new(cache: Cache<String, V>, prefix: &str) -> IgniteResult<PrefixedCache<V>> {
+        if prefix.contains(KEY_PREFIX_SEPARATOR) {
+            return Err(IgniteError::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "Key prefix {:?} has the separator {:?}!",
+                    prefix, KEY_PREFIX_SEPARATOR
+                ),
+            ));
+        }
+        Ok(PrefixedCache {
+            cache,
+            namespace: format!("{}{}", prefix, KEY_PREFIX_SEPARATOR),
+        })
+    }
+
+    /// Returns the prefix, without the separator
+    pub fn prefix(&self) -> &str {
+        &self.namespace[..self.namespace.len() - KEY_PREFIX_SEPARATOR.len_utf8()]
+    }
+
+    /// Returns the cache the namespace is in, which keys are not prefixed
+    pub fn cache(&self) -> &Cache<String, V> {
+        &self.cache
+    }
+
+    /// Returns the key of the cache for the key of the namespace
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.namespace, key)
+    }
+
+    /// Returns the key of the namespace, None if the key of the cache is in another namespace
+    fn strip(&self, key: &str) -> Option<String> {
+        key.strip_prefix(&self.namespace).map(str::to_owned)
+    }
+
+    pub fn get(&self, key: &str) -> IgniteResult<Option<V>> {
+        self.cache.get(&self.key(key))
+    }
+
+    /// Returns the values of the keys which are in the namespace, see `Cache::get_all`
+    pub fn get_all(&self, keys: &[&str]) -> IgniteResult<HashMap<String, V>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.key(key)).collect();
+        let values = self.cache.get_all(&keys)?;
+        Ok(values
+            .into_iter()
+            .filter_map(|(key, value)| Some((self.strip(&key)?, value)))
+            .collect())
+    }
+
+    pub fn put(&self, key: &str, value: &V) -> IgniteResult<()> {
+        self.cache.put(&self.key(key), value)
+    }
+
+    /// Puts the entries, see `Cache::put_all`
+    pub fn put_all<'p>(&self, pairs: impl IntoIterator<Item = (&'p str, &'p V)>) -> IgniteResult<()>
+    where
+        V: 'p,
+    {
+        self.cache
+            .put_all(pairs.into_iter().map(|(key, value)| (self.key(key), value)))
+    }
+
+    pub fn put_if_absent(&self, key: &str, value: &V) -> IgniteResult<bool> {
+        self.cache.put_if_absent(&self.key(key), value)
+    }
+
+    pub fn get_and_put(&self, key: &str, value: &V) -> IgniteResult<Option<V>> {
+        self.cache.get_and_put(&self.key(key), value)
+    }
+
+    pub fn replace(&self, key: &str, value: &V) -> IgniteResult<bool> {
+        self.cache.replace(&self.key(key), value)
+    }
+
+    pub fn replace_if_equals(&self, key: &str, old: &V, new: &V) -> IgniteResult<bool> {
+        self.cache.replace_if_equals(&self.key(key), old, new)
+    }
+
+    pub fn contains_key(&self, key: &str) -> IgniteResult<bool> {
+        self.cache.contains_key(&self.key(key))
+    }
+
+    pub fn remove_key(&self, key: &str) -> IgniteResult<bool> {
+        self.cache.remove_key(&self.key(key))
+    }
+
+    pub fn get_and_remove(&self, key: &str) -> IgniteResult<Option<V>> {
+        self.cache.get_and_remove(&self.key(key))
+    }
+
+    /// Returns the iterator over the entries of the namespace, with the keys of the namespace.
+    /// The entries of the other namespaces are read too, and skipped
+    pub fn iter(&self) -> PrefixedEntries<V> {
+        PrefixedEntries {
+            entries: self.cache.iter(),
+            namespace: self.namespace.clone(),
+        }
+    }
+
+    /// Removes the entries of the namespace, which keys are found by a scan of the cache
+    pub fn remove_all(&self) -> IgniteResult<()> {
+        let mut keys = Vec::new();
+        for entry in self.iter() {
+            keys.push(self.key(&entry?.0));
+        }
+        if keys.is_empty() {
+            return Ok(());
+        }
+        self.cache.remove_keys(&keys)
+    }
+}
+
+/// Iterator over the entries of the namespace, see `PrefixedCache::iter`
+pub struct PrefixedEntries<V: WritableType + ReadableType> {
+    entries: CacheEntries<String, V>,
+    namespace: String,
+}
+
+impl<V: WritableType + ReadableType> Iterator for PrefixedEntries<V> {
+    type Item = IgniteResult<(String, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.entries.next()? {
+                Ok((key, value)) => {
+                    if let Some(key) = key.strip_prefix(&self.namespace) {
+                        return Some(Ok((key.to_owned(), value)));
+                    }
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::{read_req, write_handshake_resp, write_success};
+    use crate::connection::Connection;
+    use crate::protocol::{read_i32, read_i64, write_i32, write_i64};
+    use crate::ClientConfig;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_prefixed_cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let len = read_i32(&mut stream).unwrap();
+            let mut put = vec![0u8; len as usize];
+            stream.read_exact(&mut put).unwrap();
+            write_success(&mut stream, read_i64(&mut &put[2..]).unwrap(), &[]);
+            // the scan reads the entries of all the tenants, t10 is not in the namespace of t1
+            let (_, request_id) = read_req(&mut stream);
+            let mut page = Vec::new();
+            write_i64(&mut page, 7).unwrap(); // cursor id
+            write_i32(&mut page, 3).unwrap();
+            for (key, value) in &[("t1:a", 1), ("t2:b", 2), ("t10:c", 3)] {
+                key.to_string().write(&mut page).unwrap();
+                value.write(&mut page).unwrap();
+            }
+            page.push(0); // no more pages
+            write_success(&mut stream, request_id, &page);
+            put
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<String, i32>::new(1, "test".to_owned(), conn);
+        let tenant = cache.with_key_prefix("t1").unwrap();
+        assert_eq!(tenant.prefix(), "t1");
+        tenant.put("a", &1).unwrap();
+        let entries: Vec<_> = tenant.iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![("a".to_owned(), 1)]);
+        let put = server.join().unwrap();
+        // op code, request id, cache id, flags, then the key
+        let mut key = &put[15..];
+        assert_eq!(String::read(&mut key).unwrap().unwrap(), "t1:a");
+
+        // the keys of t1 with `0:c` would be the ones of t10 with `c` otherwise
+        let err = cache.with_key_prefix("t1:0").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidConfig);
+        let tenant10 = cache.with_key_prefix("t10").unwrap();
+        assert_eq!(tenant.strip("t10:c"), None);
+        assert_eq!(tenant10.strip("t10:c").as_deref(), Some("c"));
+        assert_eq!(tenant10.strip("t1:a"), None);
+    }
+}
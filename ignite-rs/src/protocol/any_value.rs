@@ -0,0 +1,327 @@
+use std::io;
+use std::io::{Read, Write};
+
+use crate::binary::DynamicBinaryObject;
+use crate::error::IgniteResult;
+use crate::protocol::data_types::{decimal_size, read_decimal, write_decimal};
+use crate::protocol::*;
+use crate::{AnyValue, OpaqueObject, ReadableType, WritableType};
+
+fn read_items(reader: &mut impl Read, len: i32) -> IgniteResult<Vec<AnyValue>> {
+    let mut items = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        items.push(AnyValue::read(reader)?.unwrap_or(AnyValue::Null));
+    }
+    Ok(items)
+}
+
+fn write_items(writer: &mut dyn Write, items: &[AnyValue]) -> io::Result<()> {
+    write_i32(writer, items.len() as i32)?;
+    for item in items {
+        item.write(writer)?;
+    }
+    Ok(())
+}
+
+fn items_size(items: &[AnyValue]) -> usize {
+    items.iter().map(AnyValue::size).sum::<usize>() + 4 // items, len
+}
+
+/// Writes type code and the array of values
+fn write_arr(writer: &mut dyn Write, type_code: TypeCode, items: &[AnyValue]) -> io::Result<()> {
+    write_u8(writer, type_code as u8)?;
+    write_items(writer, items)
+}
+
+impl WritableType for AnyValue {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        match self {
+            AnyValue::Null => write_null(writer),
+            AnyValue::Byte(v) => v.write(writer),
+            AnyValue::Short(v) => v.write(writer),
+            AnyValue::Int(v) => v.write(writer),
+            AnyValue::Long(v) => v.write(writer),
+            AnyValue::Float(v) => v.write(writer),
+            AnyValue::Double(v) => v.write(writer),
+            AnyValue::Char(v) => v.write(writer),
+            AnyValue::Bool(v) => v.write(writer),
+            AnyValue::String(v) => v.write(writer),
+            AnyValue::Uuid(v) => {
+                write_u8(writer, TypeCode::Uuid as u8)?;
+                write_u64(writer, (*v >> 64) as u64)?;
+                write_u64(writer, *v as u64)
+            }
+            AnyValue::Date(millis) => {
+                write_u8(writer, TypeCode::Date as u8)?;
+                write_i64(writer, *millis)
+            }
+            AnyValue::Timestamp(millis, nanos) => {
+                write_u8(writer, TypeCode::Timestamp as u8)?;
+                write_i64(writer, *millis)?;
+                write_i32(writer, *nanos)
+            }
+            AnyValue::Time(millis) => {
+                write_u8(writer, TypeCode::Time as u8)?;
+                write_i64(writer, *millis)
+            }
+            AnyValue::Decimal {
+                scale,
+                negative,
+                magnitude,
+            } => write_decimal(writer, *scale, *negative, magnitude),
+            AnyValue::Enum(v) => v.write(writer),
+            AnyValue::ArrByte(v) => v.write(writer),
+            AnyValue::ArrShort(v) => v.write(writer),
+            AnyValue::ArrInt(v) => v.write(writer),
+            AnyValue::ArrLong(v) => v.write(writer),
+            AnyValue::ArrFloat(v) => v.write(writer),
+            AnyValue::ArrDouble(v) => v.write(writer),
+            AnyValue::ArrChar(v) => v.write(writer),
+            AnyValue::ArrBool(v) => v.write(writer),
+            AnyValue::ArrString(items) => write_arr(writer, TypeCode::ArrString, items),
+            AnyValue::ArrUuid(items) => write_arr(writer, TypeCode::ArrUuid, items),
+            AnyValue::ArrDate(items) => write_arr(writer, TypeCode::ArrDate, items),
+            AnyValue::ArrTimestamp(items) => write_arr(writer, TypeCode::TimestampArray, items),
+            AnyValue::ArrTime(items) => write_arr(writer, TypeCode::ArrTime, items),
+            AnyValue::ArrDecimal(items) => write_arr(writer, TypeCode::ArrDecimal, items),
+            AnyValue::ArrEnum { type_id, items } | AnyValue::ArrObj { type_id, items } => {
+                let type_code = match self {
+                    AnyValue::ArrEnum { .. } => TypeCode::ArrEnum,
+                    _ => TypeCode::ArrObj,
+                };
+                write_u8(writer, type_code as u8)?;
+                write_i32(writer, *type_id)?;
+                write_items(writer, items)
+            }
+            AnyValue::Collection { kind, items } => {
+                write_u8(writer, TypeCode::Collection as u8)?;
+                write_i32(writer, items.len() as i32)?;
+                write_i8(writer, *kind)?;
+                for item in items {
+                    item.write(writer)?;
+                }
+                Ok(())
+            }
+            AnyValue::Map { kind, entries } => {
+                write_u8(writer, TypeCode::Map as u8)?;
+                write_i32(writer, entries.len() as i32)?;
+                write_i8(writer, *kind)?;
+                for (key, value) in entries {
+                    key.write(writer)?;
+                    value.write(writer)?;
+                }
+                Ok(())
+            }
+            AnyValue::Object(v) => v.write(writer),
+            AnyValue::Opaque(v) => v.write(writer),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            AnyValue::Null => 1,
+            AnyValue::Byte(v) => v.size(),
+            AnyValue::Short(v) => v.size(),
+            AnyValue::Int(v) => v.size(),
+            AnyValue::Long(v) => v.size(),
+            AnyValue::Float(v) => v.size(),
+            AnyValue::Double(v) => v.size(),
+            AnyValue::Char(v) => v.size(),
+            AnyValue::Bool(v) => v.size(),
+            AnyValue::String(v) => v.size(),
+            AnyValue::Uuid(_) => 16 + 1,
+            AnyValue::Date(_) | AnyValue::Time(_) => 8 + 1,
+            AnyValue::Timestamp(_, _) => 8 + 4 + 1,
+            AnyValue::Decimal { magnitude, .. } => decimal_size(magnitude),
+            AnyValue::Enum(v) => v.size(),
+            AnyValue::ArrByte(v) => v.size(),
+            AnyValue::ArrShort(v) => v.size(),
+            AnyValue::ArrInt(v) => v.size(),
+            AnyValue::ArrLong(v) => v.size(),
+            AnyValue::ArrFloat(v) => v.size(),
+            AnyValue::ArrDouble(v) => v.size(),
+            AnyValue::ArrChar(v) => v.size(),
+            AnyValue::ArrBool(v) => v.size(),
+            AnyValue::ArrString(items)
+            | AnyValue::ArrUuid(items)
+            | AnyValue::ArrDate(items)
+            | AnyValue::ArrTimestamp(items)
+            | AnyValue::ArrTime(items)
+            | AnyValue::ArrDecimal(items) => items_size(items) + 1, // items, type code
+            AnyValue::ArrEnum { items, .. } | AnyValue::ArrObj { items, .. } => {
+                items_size(items) + 4 + 1 // items, type id, type code
+            }
+            AnyValue::Collection { items, .. } => items_size(items) + 1 + 1, // items, kind, type code
+            AnyValue::Map { entries, .. } => {
+                entries
+                    .iter()
+                    .map(|(key, value)| key.size() + value.size())
+                    .sum::<usize>()
+                    + 4
+                    + 1
+                    + 1 // entries, len, kind, type code
+            }
+            AnyValue::Object(v) => v.size(),
+            AnyValue::Opaque(v) => v.size(),
+        }
+    }
+}
+
+impl ReadableType for AnyValue {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        let value = match type_code {
+            TypeCode::Null => return Ok(None),
+            TypeCode::Byte => AnyValue::Byte(read_u8(reader)?),
+            TypeCode::Short => AnyValue::Short(read_i16(reader)?),
+            TypeCode::Int => AnyValue::Int(read_i32(reader)?),
+            TypeCode::Long => AnyValue::Long(read_i64(reader)?),
+            TypeCode::Float => AnyValue::Float(read_f32(reader)?),
+            TypeCode::Double => AnyValue::Double(read_f64(reader)?),
+            TypeCode::Char => AnyValue::Char(read_u16(reader)?),
+            TypeCode::Bool => AnyValue::Bool(read_bool(reader)?),
+            TypeCode::String => AnyValue::String(read_string(reader)?),
+            TypeCode::Uuid => AnyValue::Uuid(read_uuid(reader)?),
+            TypeCode::Date => AnyValue::Date(read_i64(reader)?),
+            TypeCode::Timestamp => AnyValue::Timestamp(read_i64(reader)?, read_i32(reader)?),
+            TypeCode::Time => AnyValue::Time(read_i64(reader)?),
+            TypeCode::Decimal => {
+                let (scale, negative, magnitude) = read_decimal(reader)?;
+                AnyValue::Decimal {
+                    scale,
+                    negative,
+                    magnitude,
+                }
+            }
+            TypeCode::Enum | TypeCode::BinaryEnum => AnyValue::Enum(read_enum(reader)?),
+            TypeCode::ArrByte => AnyValue::ArrByte(read_primitive_arr(reader, read_u8)?),
+            TypeCode::ArrShort => AnyValue::ArrShort(read_primitive_arr(reader, read_i16)?),
+            TypeCode::ArrInt => AnyValue::ArrInt(read_primitive_arr(reader, read_i32)?),
+            TypeCode::ArrLong => AnyValue::ArrLong(read_primitive_arr(reader, read_i64)?),
+            TypeCode::ArrFloat => AnyValue::ArrFloat(read_primitive_arr(reader, read_f32)?),
+            TypeCode::ArrDouble => AnyValue::ArrDouble(read_primitive_arr(reader, read_f64)?),
+            TypeCode::ArrChar => AnyValue::ArrChar(read_primitive_arr(reader, read_u16)?),
+            TypeCode::ArrBool => AnyValue::ArrBool(read_primitive_arr(reader, read_bool)?),
+            TypeCode::ArrString
+            | TypeCode::ArrUuid
+            | TypeCode::ArrDate
+            | TypeCode::TimestampArray
+            | TypeCode::ArrTime
+            | TypeCode::ArrDecimal => {
+                let len = read_i32(reader)?;
+                let items = read_items(reader, len)?;
+                match type_code {
+                    TypeCode::ArrString => AnyValue::ArrString(items),
+                    TypeCode::ArrUuid => AnyValue::ArrUuid(items),
+                    TypeCode::ArrDate => AnyValue::ArrDate(items),
+                    TypeCode::TimestampArray => AnyValue::ArrTimestamp(items),
+                    TypeCode::ArrTime => AnyValue::ArrTime(items),
+                    _ => AnyValue::ArrDecimal(items),
+                }
+            }
+            TypeCode::ArrEnum | TypeCode::ArrObj => {
+                let type_id = read_i32(reader)?;
+                let len = read_i32(reader)?;
+                let items = read_items(reader, len)?;
+                match type_code {
+                    TypeCode::ArrEnum => AnyValue::ArrEnum { type_id, items },
+                    _ => AnyValue::ArrObj { type_id, items },
+                }
+            }
+            TypeCode::Collection => {
+                let len = read_i32(reader)?;
+                let kind = read_i8(reader)?;
+                let items = read_items(reader, len)?;
+                AnyValue::Collection { kind, items }
+            }
+            TypeCode::Map => {
+                let len = read_i32(reader)?;
+                let kind = read_i8(reader)?;
+                let mut entries = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    let key = AnyValue::read(reader)?.unwrap_or(AnyValue::Null);
+                    let value = AnyValue::read(reader)?.unwrap_or(AnyValue::Null);
+                    entries.push((key, value));
+                }
+                AnyValue::Map { kind, entries }
+            }
+            TypeCode::ComplexObj => match DynamicBinaryObject::read_unwrapped(type_code, reader)? {
+                Some(obj) => AnyValue::Object(obj),
+                None => return Ok(None),
+            },
+            TypeCode::Class
+            | TypeCode::Proxy
+            | TypeCode::OptimizedMarshaller
+            | TypeCode::Handle
+            | TypeCode::WrappedData => match OpaqueObject::read_unwrapped(type_code, reader)? {
+                Some(obj) => AnyValue::Opaque(obj),
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Enum;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_any_value() {
+        let value = AnyValue::Map {
+            kind: 1,
+            entries: vec![
+                (
+                    AnyValue::String("foo".into()),
+                    AnyValue::ArrObj {
+                        type_id: -1,
+                        items: vec![
+                            AnyValue::Uuid(0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10),
+                            AnyValue::Null,
+                            AnyValue::Timestamp(1_600_000_000_000, 999),
+                        ],
+                    },
+                ),
+                (
+                    AnyValue::Int(42),
+                    AnyValue::Collection {
+                        kind: 3,
+                        items: vec![
+                            AnyValue::Enum(Enum {
+                                type_id: 7,
+                                ordinal: 1,
+                            }),
+                            AnyValue::Decimal {
+                                scale: 2,
+                                negative: true,
+                                magnitude: vec![0x30, 0x39],
+                            },
+                            AnyValue::ArrString(vec![AnyValue::String("bar".into())]),
+                        ],
+                    },
+                ),
+            ],
+        };
+        let mut bytes = vec![];
+        value.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), value.size());
+
+        let actual = AnyValue::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(value));
+    }
+
+    #[test]
+    fn test_any_value_typed() {
+        let mut bytes = vec![];
+        (42i32, "foo".to_string()).write(&mut bytes).unwrap();
+        let actual = AnyValue::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(
+            actual,
+            Some(AnyValue::ArrObj {
+                type_id: -1,
+                items: vec![AnyValue::Int(42), AnyValue::String("foo".into())],
+            })
+        );
+    }
+}
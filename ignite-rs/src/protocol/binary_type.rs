@@ -0,0 +1,134 @@
+use std::io;
+use std::io::Read;
+
+use crate::binary::{BinaryField, BinarySchema, BinaryType};
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{
+    read_bool, read_i32, write_bool, write_i32, write_null, write_string_type_code,
+};
+use crate::ReadableType;
+
+/// https://ignite.apache.org/docs/latest/binary-client-protocol/binary-type-metadata#op_get_binary_type
+pub(crate) fn read_binary_type(reader: &mut impl Read) -> IgniteResult<BinaryType> {
+    let type_id = read_i32(reader)?;
+    let type_name =
+        String::read(reader)?.ok_or_else(|| IgniteError::from("type name is required"))?;
+    let affinity_key_field_name = String::read(reader)?;
+
+    let fields_count = read_i32(reader)?;
+    let mut fields = Vec::<BinaryField>::new();
+    for _ in 0..fields_count {
+        let name =
+            String::read(reader)?.ok_or_else(|| IgniteError::from("field name is required"))?;
+        let type_code = read_i32(reader)?;
+        let field_id = read_i32(reader)?;
+        fields.push(BinaryField {
+            name,
+            type_code,
+            field_id,
+        })
+    }
+
+    let is_enum = read_bool(reader)?;
+    let mut enum_values = Vec::<(String, i32)>::new();
+    if is_enum {
+        let count = read_i32(reader)?;
+        for _ in 0..count {
+            let name =
+                String::read(reader)?.ok_or_else(|| IgniteError::from("enum name is required"))?;
+            let ordinal = read_i32(reader)?;
+            enum_values.push((name, ordinal))
+        }
+    }
+
+    let schemas_count = read_i32(reader)?;
+    let mut schemas = Vec::<BinarySchema>::new();
+    for _ in 0..schemas_count {
+        let schema_id = read_i32(reader)?;
+        let count = read_i32(reader)?;
+        let mut field_ids = Vec::<i32>::new();
+        for _ in 0..count {
+            field_ids.push(read_i32(reader)?);
+        }
+        schemas.push(BinarySchema {
+            schema_id,
+            field_ids,
+        })
+    }
+
+    Ok(BinaryType {
+        type_id,
+        type_name,
+        affinity_key_field_name,
+        fields,
+        is_enum,
+        enum_values,
+        schemas,
+    })
+}
+
+/// https://ignite.apache.org/docs/latest/binary-client-protocol/binary-type-metadata#op_put_binary_type
+pub(crate) fn get_binary_type_bytes(binary_type: &BinaryType) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::<u8>::new();
+    write_i32(&mut bytes, binary_type.type_id)?;
+    write_string_type_code(&mut bytes, binary_type.type_name.as_str())?;
+    match &binary_type.affinity_key_field_name {
+        Some(name) => write_string_type_code(&mut bytes, name.as_str())?,
+        None => write_null(&mut bytes)?,
+    }
+
+    write_i32(&mut bytes, binary_type.fields.len() as i32)?;
+    for field in &binary_type.fields {
+        write_string_type_code(&mut bytes, field.name.as_str())?;
+        write_i32(&mut bytes, field.type_code)?;
+        write_i32(&mut bytes, field.field_id)?;
+    }
+
+    write_bool(&mut bytes, binary_type.is_enum)?;
+    if binary_type.is_enum {
+        write_i32(&mut bytes, binary_type.enum_values.len() as i32)?;
+        for (name, ordinal) in &binary_type.enum_values {
+            write_string_type_code(&mut bytes, name.as_str())?;
+            write_i32(&mut bytes, *ordinal)?;
+        }
+    }
+
+    write_i32(&mut bytes, binary_type.schemas.len() as i32)?;
+    for schema in &binary_type.schemas {
+        write_i32(&mut bytes, schema.schema_id)?;
+        write_i32(&mut bytes, schema.field_ids.len() as i32)?;
+        for id in &schema.field_ids {
+            write_i32(&mut bytes, *id)?;
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_binary_type_round_trip() {
+        let binary_type = BinaryType {
+            type_id: 1,
+            type_name: "Color".to_string(),
+            affinity_key_field_name: None,
+            fields: vec![BinaryField {
+                name: "name".to_string(),
+                type_code: 9,
+                field_id: 2,
+            }],
+            is_enum: true,
+            enum_values: vec![("RED".to_string(), 0), ("GREEN".to_string(), 1)],
+            schemas: vec![BinarySchema {
+                schema_id: 3,
+                field_ids: vec![2],
+            }],
+        };
+        let bytes = get_binary_type_bytes(&binary_type).unwrap();
+        let actual = read_binary_type(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, binary_type);
+    }
+}
@@ -6,19 +6,20 @@ use crate::cache::{
     WriteSynchronizationMode,
 };
 use crate::cache::{
-    CacheConfiguration, CacheKeyConfiguration, QueryEntity, QueryField, QueryIndex,
+    CacheConfiguration, CacheKeyConfiguration, ExpiryDuration, ExpiryPolicy, QueryEntity,
+    QueryField, QueryIndex,
 };
 use crate::error::IgniteError;
 use crate::error::IgniteResult;
 use crate::protocol::cache_config::ConfigPropertyCode::*;
 use crate::protocol::{
-    read_bool, read_i32, read_i64, read_object, read_u8, write_bool, write_i16, write_i32,
-    write_i64, write_string_type_code, write_u8,
+    read_bool, read_i32, read_i64, read_u8, write_bool, write_i16, write_i32, write_i64,
+    write_null, write_string_type_code, write_u8,
 };
-use crate::ReadableType;
+use crate::{AnyValue, ReadableType, WritableType};
 use std::io;
 
-const MIN_CONFIG_PARAMS: i16 = 26;
+const MIN_CONFIG_PARAMS: i16 = 25;
 
 /// Cache Configuration Properties Codes
 #[derive(PartialOrd, PartialEq)]
@@ -53,6 +54,7 @@ pub(crate) enum ConfigPropertyCode {
     SqlSchema = 203,
     CacheKeyConfigurations = 401,
     QueryEntities = 200,
+    ExpiryPolicy = 407,
 }
 
 impl Into<i16> for ConfigPropertyCode {
@@ -100,9 +102,6 @@ pub(crate) fn get_cache_configuration_bytes(config: &CacheConfiguration) -> io::
     write_i16(&mut config_opts, IsOnheapCacheEnabled as i16)?;
     write_bool(&mut config_opts, config.onheap_cache_enabled)?;
 
-    write_i16(&mut config_opts, MaxQueryIterators as i16)?;
-    write_i32(&mut config_opts, config.max_query_iterators)?;
-
     write_i16(&mut config_opts, PartitionLossPolicy as i16)?;
     write_i32(
         &mut config_opts,
@@ -177,16 +176,24 @@ pub(crate) fn get_cache_configuration_bytes(config: &CacheConfiguration) -> io::
         write_query_entities(&mut config_opts, v)?;
         config_param_len += 1;
     }
+    if let Some(ref v) = config.expiry_policy {
+        write_i16(&mut config_opts, ExpiryPolicy as i16)?;
+        write_bool(&mut config_opts, true)?;
+        write_expiry_policy(&mut config_opts, v)?;
+        config_param_len += 1;
+    }
 
     let mut bytes = Vec::<u8>::new();
-    write_i32(&mut bytes, config_opts.len() as i32)?;
+    // the length counts the number of the properties too
+    write_i32(&mut bytes, config_opts.len() as i32 + 2)?;
     write_i16(&mut bytes, config_param_len)?;
     bytes.append(&mut config_opts);
 
     Ok(bytes)
 }
 
-pub(crate) fn read_cache_configuration(reader: &mut impl Read) -> IgniteResult<CacheConfiguration> {
+/// Reads the configuration which the node sends, the rest of `reader` is the configuration
+pub(crate) fn read_cache_configuration(reader: &mut &[u8]) -> IgniteResult<CacheConfiguration> {
     let config = CacheConfiguration {
         atomicity_mode: AtomicityMode::try_from(read_i32(reader)?)?,
         num_backup: read_i32(reader)?,
@@ -218,10 +225,30 @@ pub(crate) fn read_cache_configuration(reader: &mut impl Read) -> IgniteResult<C
         write_synchronization_mode: WriteSynchronizationMode::try_from(read_i32(reader)?)?,
         cache_key_configurations: Some(read_cache_key_configs(reader)?),
         query_entities: Some(read_query_entities(reader)?),
+        expiry_policy: read_expiry_policy(reader)?,
     };
     Ok(config)
 }
 
+/// Time to live after the creation, the update and the access, in milliseconds
+pub(crate) fn write_expiry_policy(writer: &mut dyn Write, policy: &ExpiryPolicy) -> io::Result<()> {
+    write_i64(writer, policy.create.millis())?;
+    write_i64(writer, policy.update.millis())?;
+    write_i64(writer, policy.access.millis())
+}
+
+/// The nodes of protocol v1.6.0 and newer send the expiry policy last, the older ones do not
+fn read_expiry_policy(reader: &mut &[u8]) -> IgniteResult<Option<ExpiryPolicy>> {
+    if reader.is_empty() || !read_bool(reader)? {
+        return Ok(None);
+    }
+    Ok(Some(ExpiryPolicy {
+        create: ExpiryDuration::from_millis(read_i64(reader)?),
+        update: ExpiryDuration::from_millis(read_i64(reader)?),
+        access: ExpiryDuration::from_millis(read_i64(reader)?),
+    }))
+}
+
 fn read_cache_key_configs(reader: &mut impl Read) -> IgniteResult<Vec<CacheKeyConfiguration>> {
     let count = read_i32(reader)?;
     let mut result = Vec::<CacheKeyConfiguration>::new();
@@ -249,15 +276,15 @@ fn write_cache_key_configs(
     Ok(())
 }
 
-fn read_query_entities(reader: &mut impl Read) -> IgniteResult<Vec<QueryEntity>> {
+pub(crate) fn read_query_entities(reader: &mut impl Read) -> IgniteResult<Vec<QueryEntity>> {
     let count = read_i32(reader)?;
     let mut result = Vec::<QueryEntity>::new();
     for _ in 0..count {
         let key_type = String::read(reader)?.unwrap();
         let value_type = String::read(reader)?.unwrap();
-        let table = String::read(reader)?.unwrap();
-        let key_field = String::read(reader)?.unwrap_or("".to_string());
-        let value_field = String::read(reader)?.unwrap_or("".to_string());
+        let table = String::read(reader)?.unwrap_or_default();
+        let key_field = String::read(reader)?.unwrap_or_default();
+        let value_field = String::read(reader)?.unwrap_or_default();
         let query_fields = read_query_fields(reader)?;
         let field_aliases = read_query_field_aliases(reader)?;
         let query_indexes = read_query_indexes(reader)?;
@@ -270,20 +297,23 @@ fn read_query_entities(reader: &mut impl Read) -> IgniteResult<Vec<QueryEntity>>
             query_fields,
             field_aliases,
             query_indexes,
-            default_value: None, //TODO
         })
     }
     Ok(result)
 }
 
-fn write_query_entities(writer: &mut dyn Write, entities: &[QueryEntity]) -> io::Result<()> {
+pub(crate) fn write_query_entities(
+    writer: &mut dyn Write,
+    entities: &[QueryEntity],
+) -> io::Result<()> {
     write_i32(writer, entities.len() as i32)?;
     for entity in entities.iter() {
         write_string_type_code(writer, entity.key_type.as_str())?;
         write_string_type_code(writer, entity.value_type.as_str())?;
-        write_string_type_code(writer, entity.table.as_str())?;
-        write_string_type_code(writer, entity.key_field.as_str())?;
-        write_string_type_code(writer, entity.value_field.as_str())?;
+        // the empty names are not set
+        write_optional_string(writer, &entity.table)?;
+        write_optional_string(writer, &entity.key_field)?;
+        write_optional_string(writer, &entity.value_field)?;
         write_query_fields(writer, &entity.query_fields)?;
         write_field_aliases(writer, &entity.field_aliases)?;
         write_query_indexes(writer, &entity.query_indexes)?;
@@ -300,7 +330,7 @@ fn read_query_fields(reader: &mut impl Read) -> IgniteResult<Vec<QueryField>> {
         let type_name = String::read(reader)?.unwrap();
         let key_field = read_bool(reader)?;
         let not_null_constraint = read_bool(reader)?;
-        let _default_val = read_object(reader)?;
+        let default_value = AnyValue::read(reader)?;
         let precision = read_i32(reader)?;
         let scale = read_i32(reader)?;
         result.push(QueryField {
@@ -308,6 +338,7 @@ fn read_query_fields(reader: &mut impl Read) -> IgniteResult<Vec<QueryField>> {
             type_name,
             key_field,
             not_null_constraint,
+            default_value,
             precision,
             scale,
         })
@@ -322,10 +353,23 @@ fn write_query_fields(writer: &mut dyn Write, fields: &[QueryField]) -> io::Resu
         write_string_type_code(writer, field.type_name.as_str())?;
         write_bool(writer, field.key_field)?;
         write_bool(writer, field.not_null_constraint)?;
+        match &field.default_value {
+            Some(value) => value.write(writer)?,
+            None => write_null(writer)?,
+        }
+        write_i32(writer, field.precision)?;
+        write_i32(writer, field.scale)?;
     }
     Ok(())
 }
 
+fn write_optional_string(writer: &mut dyn Write, value: &str) -> io::Result<()> {
+    match value {
+        "" => write_null(writer),
+        value => write_string_type_code(writer, value),
+    }
+}
+
 fn read_query_field_aliases(reader: &mut impl Read) -> IgniteResult<Vec<(String, String)>> {
     let count = read_i32(reader)?;
     let mut result = Vec::<(String, String)>::new();
@@ -0,0 +1,107 @@
+//! chrono crate support. Naive values have no timezone, so they are treated as UTC.
+//! This way dates are not shifted when the client and the cluster nodes are in different timezones:
+//! * NaiveDateTime is written as Timestamp (type code 33). Nanosecond precision is kept
+//! * NaiveDate is written as Date (type code 11), the midnight of the date
+//!
+//! Both could be read from Timestamp and Date values.
+
+use std::io;
+use std::io::{Read, Write};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{read_i32, read_i64, write_i32, write_i64, write_u8, TypeCode};
+use crate::{ReadableType, WritableType};
+
+const NANOS_IN_MILLI: i64 = 1_000_000;
+
+fn from_ignite_timestamp(millis: i64, nanos: i32) -> IgniteResult<NaiveDateTime> {
+    let secs = millis.div_euclid(1000);
+    let nanos = millis.rem_euclid(1000) * NANOS_IN_MILLI + nanos as i64;
+    DateTime::from_timestamp(secs, nanos as u32)
+        .map(|value| value.naive_utc())
+        .ok_or_else(|| IgniteError::from("Timestamp is out of range"))
+}
+
+fn read_timestamp(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<NaiveDateTime> {
+    match type_code {
+        TypeCode::Timestamp => {
+            let millis = read_i64(reader)?;
+            let nanos = read_i32(reader)?;
+            from_ignite_timestamp(millis, nanos)
+        }
+        TypeCode::Date => from_ignite_timestamp(read_i64(reader)?, 0),
+        _ => Err(IgniteError::from("Expected Timestamp or Date!")),
+    }
+}
+
+impl WritableType for NaiveDateTime {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let utc = self.and_utc();
+        let nanos = utc.timestamp_subsec_nanos() as i64;
+        write_u8(writer, TypeCode::Timestamp as u8)?;
+        write_i64(writer, utc.timestamp() * 1000 + nanos / NANOS_IN_MILLI)?;
+        write_i32(writer, (nanos % NANOS_IN_MILLI) as i32)
+    }
+
+    fn size(&self) -> usize {
+        8 + 4 + 1 // millis, nanos, type code
+    }
+}
+
+impl ReadableType for NaiveDateTime {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            _ => read_timestamp(type_code, reader).map(Some),
+        }
+    }
+}
+
+impl WritableType for NaiveDate {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let millis = self.and_time(Default::default()).and_utc().timestamp() * 1000;
+        write_u8(writer, TypeCode::Date as u8)?;
+        write_i64(writer, millis)
+    }
+
+    fn size(&self) -> usize {
+        8 + 1 // millis, type code
+    }
+}
+
+impl ReadableType for NaiveDate {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            _ => read_timestamp(type_code, reader).map(|value| Some(value.date())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_naive_round_trip() {
+        let date = NaiveDate::from_ymd_opt(1969, 7, 20).unwrap();
+        let mut bytes = vec![];
+        date.write(&mut bytes).unwrap();
+        assert_eq!(bytes[1..9], (-14_256_000_000i64).to_le_bytes()); // midnight UTC
+        assert_eq!(
+            NaiveDate::read(&mut Cursor::new(bytes)).unwrap(),
+            Some(date)
+        );
+
+        let date_time = date.and_hms_nano_opt(20, 17, 40, 123_456_789).unwrap();
+        let mut bytes = vec![];
+        date_time.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), date_time.size());
+        assert_eq!(bytes[9..13], 456_789i32.to_le_bytes());
+        let actual = NaiveDateTime::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(date_time));
+    }
+}
@@ -1,5 +1,7 @@
+use crate::binary::get_binary_type;
 use crate::cache::{QueryEntity, QueryField};
-use crate::error::{IgniteError, IgniteResult};
+use crate::error::{ErrorKind as IgniteErrorKind, IgniteError, IgniteResult};
+use crate::protocol::handles;
 use crate::protocol::{
     read_bool, read_i16, read_i32, read_i64, read_string, read_u16, read_u8, write_i16, write_i32,
     write_i64, write_null, write_string, write_u16, write_u8, TypeCode, COMPLEX_OBJ_HEADER_LEN,
@@ -123,6 +125,207 @@ impl ComplexObject {
     }
 }
 
+/// Header and fields layout of a complex object that is being read.
+/// Fields are located by the object's schema, so they could be read in any order
+/// https://apacheignite.readme.io/docs/binary-client-protocol-data-format#complex-object
+pub struct ComplexObjectLayout {
+    pub flags: u16,
+    pub type_id: i32,
+    pub hash_code: i32,
+    pub schema_id: i32,
+    /// position of the object within the current read
+    start: usize,
+    /// field ids and offsets from the object's start, in schema order
+    fields: Vec<(i32, usize)>,
+    /// the whole object, including header
+    data: Vec<u8>,
+}
+
+impl ComplexObjectLayout {
+    /// Returns ids of the object's fields in schema order
+    pub fn field_ids(&self) -> Vec<i32> {
+        self.fields.iter().map(|(id, _)| *id).collect()
+    }
+
+    /// Returns the bytes of the whole object, including header
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the raw (not described by the schema) data of the object, if any
+    pub fn raw_data(&self) -> Option<&[u8]> {
+        if self.flags & HAS_RAW_DATA == 0 {
+            return None;
+        }
+        let offset_pos = match self.flags & FLAG_HAS_SCHEMA {
+            0 => 20, // schema offset holds raw data offset if there is no schema
+            _ => self.data.len() - 4,
+        };
+        let mut offset = [0u8; 4];
+        offset.copy_from_slice(&self.data[offset_pos..offset_pos + 4]);
+        let offset = i32::from_le_bytes(offset) as usize;
+        let end = match self.flags & FLAG_HAS_SCHEMA {
+            0 => self.data.len(),
+            _ => {
+                let mut schema_offset = [0u8; 4];
+                schema_offset.copy_from_slice(&self.data[20..24]);
+                i32::from_le_bytes(schema_offset) as usize
+            }
+        };
+        self.data.get(offset..end)
+    }
+
+    /// Reads the value of the field. Returns None if the value is NULL or there is no such field
+    pub fn read_field<T: ReadableType>(&self, field_id: i32) -> IgniteResult<Option<T>> {
+        self.read_field_with(field_id, |reader| T::read(reader))
+            .map(Option::flatten)
+    }
+
+    /// Reads the value of the field using the provided function. Returns None if there is no such field
+    pub(crate) fn read_field_with<T, F>(&self, field_id: i32, read_fn: F) -> IgniteResult<Option<T>>
+    where
+        F: FnOnce(&mut handles::ObjectReader) -> IgniteResult<T>,
+    {
+        match self.fields.iter().find(|(id, _)| *id == field_id) {
+            None => Ok(None),
+            Some((_, offset)) => handles::read_at(self.start + offset, read_fn).map(Some),
+        }
+    }
+}
+
+/// Reads a complex object (type code is already consumed) and passes its layout to read_fn.
+/// Compact footers are resolved using binary type metadata. If there is no metadata for the object,
+/// an error of ErrorKind::UnknownBinaryType kind is returned
+pub fn read_complex_obj<T, R, F>(reader: &mut R, read_fn: F) -> IgniteResult<T>
+where
+    R: Read,
+    F: FnOnce(&ComplexObjectLayout) -> IgniteResult<T>,
+{
+    let start = match handles::position() {
+        Some(pos) => pos - 1, // type code is already read
+        None => {
+            return handles::read_root(TypeCode::ComplexObj, reader, |_, root| {
+                read_complex_obj(root, read_fn)
+            })
+        }
+    };
+
+    // read header minus type code
+    let mut data = vec![0u8; COMPLEX_OBJ_HEADER_LEN as usize];
+    data[0] = TypeCode::ComplexObj as u8;
+    reader.read_exact(&mut data[1..])?;
+
+    let mut header = Cursor::new(&data[1..]);
+    let version = read_u8(&mut header)?; // offset 1
+    if version != 1 {
+        return Err(IgniteError::from(
+            format!("Unsupported object version {}", version).as_str(),
+        ));
+    }
+    let flags = read_u16(&mut header)?; // offset 2
+    let type_id = read_i32(&mut header)?; // offset 4
+    let hash_code = read_i32(&mut header)?; // offset 8
+    let object_len = read_i32(&mut header)?; // offset 12
+    let schema_id = read_i32(&mut header)?; // offset 16
+    let schema_offset = read_i32(&mut header)?; // offset 20
+    if object_len < COMPLEX_OBJ_HEADER_LEN {
+        return Err(IgniteError::from(
+            format!("Invalid object length {}", object_len).as_str(),
+        ));
+    }
+    if schema_offset < 0 {
+        return Err(IgniteError::from(
+            format!("Invalid schema offset {}", schema_offset).as_str(),
+        ));
+    }
+    let schema_offset = schema_offset as usize;
+
+    // append body. The buffer grows as the bytes come, so a bogus length does not allocate it all
+    let body_len = (object_len - COMPLEX_OBJ_HEADER_LEN) as u64;
+    if reader.by_ref().take(body_len).read_to_end(&mut data)? as u64 != body_len {
+        return Err(IgniteError::from("Object is shorter than its length"));
+    }
+
+    let fields = read_schema(&data, flags, type_id, schema_id, schema_offset)?;
+    for (_, offset) in fields.iter() {
+        handles::register(start + offset);
+    }
+
+    read_fn(&ComplexObjectLayout {
+        flags,
+        type_id,
+        hash_code,
+        schema_id,
+        start,
+        fields,
+        data,
+    })
+}
+
+/// Reads field ids and offsets from the object's footer
+fn read_schema(
+    data: &[u8],
+    flags: u16,
+    type_id: i32,
+    schema_id: i32,
+    schema_offset: usize,
+) -> IgniteResult<Vec<(i32, usize)>> {
+    if flags & FLAG_HAS_SCHEMA == 0 {
+        return Ok(vec![]);
+    }
+    let footer_end = match flags & HAS_RAW_DATA {
+        0 => data.len(),
+        _ => data.len() - 4, // raw data offset
+    };
+    if schema_offset < COMPLEX_OBJ_HEADER_LEN as usize || schema_offset > footer_end {
+        return Err(IgniteError::from("Invalid schema offset"));
+    }
+    let offset_size = match (
+        flags & FLAG_OFFSET_ONE_BYTE != 0,
+        flags & FLAG_OFFSET_TWO_BYTES != 0,
+    ) {
+        (true, false) => 1,
+        (false, true) => 2,
+        (false, false) => 4,
+        (true, true) => return Err(IgniteError::from("Invalid offset flags")),
+    };
+
+    let mut footer = Cursor::new(&data[schema_offset..footer_end]);
+    let mut fields = Vec::new();
+    if flags & FLAG_COMPACT_FOOTER != 0 {
+        // footer has offsets only. Field ids are known from the metadata
+        let binary_type = get_binary_type(type_id);
+        let field_ids = binary_type
+            .as_ref()
+            .and_then(|t| t.schema(schema_id))
+            .ok_or_else(|| {
+                IgniteError::new(
+                    IgniteErrorKind::UnknownBinaryType { type_id, schema_id },
+                    format!("Unknown schema {} of binary type {}", schema_id, type_id),
+                )
+            })?;
+        for field_id in field_ids {
+            fields.push((*field_id, read_field_offset(&mut footer, offset_size)?));
+        }
+    } else {
+        let count = (footer_end - schema_offset) / (4 + offset_size);
+        for _ in 0..count {
+            let field_id = read_i32(&mut footer)?;
+            fields.push((field_id, read_field_offset(&mut footer, offset_size)?));
+        }
+    }
+    Ok(fields)
+}
+
+fn read_field_offset(reader: &mut impl Read, offset_size: usize) -> IgniteResult<usize> {
+    let offset = match offset_size {
+        1 => read_u8(reader)? as usize,
+        2 => read_u16(reader)? as usize,
+        _ => read_i32(reader)? as usize,
+    };
+    Ok(offset)
+}
+
 impl ReadableType for ComplexObject {
     fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
         let mut me = ComplexObject {
@@ -410,6 +613,172 @@ mod tests {
         let actual_hex = format!("{:02X?}", actual_bytes);
         assert_eq!(actual_hex, expected_hex);
     }
+
+    fn compact_footer_obj(type_id: i32, schema_id: i32) -> Vec<u8> {
+        let mut fields = vec![];
+        write_u8(&mut fields, TypeCode::Int as u8).unwrap();
+        write_i32(&mut fields, 42).unwrap();
+        write_u8(&mut fields, TypeCode::String as u8).unwrap();
+        write_string(&mut fields, "foo").unwrap();
+        let footer = [
+            COMPLEX_OBJ_HEADER_LEN as u8,
+            COMPLEX_OBJ_HEADER_LEN as u8 + 5,
+        ]; // offsets only
+
+        let mut bytes = vec![];
+        write_u8(&mut bytes, TypeCode::ComplexObj as u8).unwrap();
+        write_u8(&mut bytes, 1).unwrap();
+        write_u16(
+            &mut bytes,
+            FLAG_USER_TYPE | FLAG_HAS_SCHEMA | FLAG_COMPACT_FOOTER | FLAG_OFFSET_ONE_BYTE,
+        )
+        .unwrap();
+        write_i32(&mut bytes, type_id).unwrap();
+        write_i32(&mut bytes, 0).unwrap(); // hash code
+        write_i32(
+            &mut bytes,
+            COMPLEX_OBJ_HEADER_LEN + (fields.len() + footer.len()) as i32,
+        )
+        .unwrap();
+        write_i32(&mut bytes, schema_id).unwrap();
+        write_i32(&mut bytes, COMPLEX_OBJ_HEADER_LEN + fields.len() as i32).unwrap();
+        bytes.extend(fields);
+        bytes.extend(&footer);
+        bytes
+    }
+
+    fn read_compact_footer_obj(bytes: Vec<u8>) -> IgniteResult<(Option<String>, Option<i32>)> {
+        let mut reader = Cursor::new(bytes);
+        read_u8(&mut reader).unwrap(); // type code
+        read_complex_obj(&mut reader, |obj| {
+            Ok((obj.read_field::<String>(22)?, obj.read_field::<i32>(11)?))
+        })
+    }
+
+    #[test]
+    fn test_read_compact_footer() {
+        crate::binary::register_binary_type(crate::binary::BinaryType {
+            type_id: 777,
+            type_name: "Foo".to_string(),
+            affinity_key_field_name: None,
+            fields: vec![],
+            is_enum: false,
+            enum_values: vec![],
+            schemas: vec![crate::binary::BinarySchema {
+                schema_id: 555,
+                field_ids: vec![11, 22],
+            }],
+        });
+        let actual = read_compact_footer_obj(compact_footer_obj(777, 555)).unwrap();
+        assert_eq!(actual, (Some("foo".to_string()), Some(42)));
+    }
+
+    #[derive(ignite_rs_derive::IgniteObj, Debug, PartialEq)]
+    struct Nullable {
+        id: i32,
+        name: Option<String>,
+        tag: Option<String>, // absent from the schema
+    }
+
+    #[test]
+    fn test_derive_read_null_field() {
+        let mut fields = vec![];
+        write_u8(&mut fields, TypeCode::Int as u8).unwrap();
+        write_i32(&mut fields, 42).unwrap();
+        write_u8(&mut fields, TypeCode::Null as u8).unwrap();
+        let footer = [
+            COMPLEX_OBJ_HEADER_LEN as u8,
+            COMPLEX_OBJ_HEADER_LEN as u8 + 5,
+        ]; // offsets only
+
+        let mut bytes = vec![];
+        write_u8(&mut bytes, TypeCode::ComplexObj as u8).unwrap();
+        write_u8(&mut bytes, 1).unwrap();
+        write_u16(
+            &mut bytes,
+            FLAG_USER_TYPE | FLAG_HAS_SCHEMA | FLAG_COMPACT_FOOTER | FLAG_OFFSET_ONE_BYTE,
+        )
+        .unwrap();
+        write_i32(&mut bytes, Nullable::type_id()).unwrap();
+        write_i32(&mut bytes, 0).unwrap(); // hash code
+        write_i32(
+            &mut bytes,
+            COMPLEX_OBJ_HEADER_LEN + (fields.len() + footer.len()) as i32,
+        )
+        .unwrap();
+        write_i32(&mut bytes, 556).unwrap(); // schema id
+        write_i32(&mut bytes, COMPLEX_OBJ_HEADER_LEN + fields.len() as i32).unwrap();
+        bytes.extend(fields);
+        bytes.extend(&footer);
+
+        crate::binary::register_binary_type(crate::binary::BinaryType {
+            type_id: Nullable::type_id(),
+            type_name: "Nullable".to_string(),
+            affinity_key_field_name: None,
+            fields: vec![],
+            is_enum: false,
+            enum_values: vec![],
+            schemas: vec![crate::binary::BinarySchema {
+                schema_id: 556,
+                field_ids: vec![
+                    string_to_java_hashcode("id"),
+                    string_to_java_hashcode("name"),
+                ],
+            }],
+        });
+        let actual = Nullable::read(&mut Cursor::new(bytes)).unwrap();
+        let expected = Nullable {
+            id: 42,
+            name: None,
+            tag: None,
+        };
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn test_read_merged_schemas() {
+        let binary_type = |field_ids: Vec<i32>| crate::binary::BinaryType {
+            type_id: 779,
+            type_name: "Foo".to_string(),
+            affinity_key_field_name: None,
+            fields: vec![],
+            is_enum: false,
+            enum_values: vec![],
+            schemas: vec![crate::binary::BinarySchema {
+                schema_id: 550 + field_ids[0],
+                field_ids,
+            }],
+        };
+        crate::binary::register_binary_type(binary_type(vec![11, 22]));
+        crate::binary::register_binary_type(binary_type(vec![33, 22]));
+        // the objects of both schemas are read
+        let actual = read_compact_footer_obj(compact_footer_obj(779, 561)).unwrap();
+        assert_eq!(actual, (Some("foo".to_string()), Some(42)));
+        let actual = read_compact_footer_obj(compact_footer_obj(779, 583)).unwrap();
+        assert_eq!(actual, (Some("foo".to_string()), None));
+    }
+
+    #[test]
+    fn test_read_invalid_length() {
+        let mut bytes = compact_footer_obj(777, 555);
+        bytes[12..16].copy_from_slice(&(-1i32).to_le_bytes());
+        assert!(read_compact_footer_obj(bytes.clone()).is_err());
+        // longer than the bytes which come
+        bytes[12..16].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(read_compact_footer_obj(bytes).is_err());
+    }
+
+    #[test]
+    fn test_read_compact_footer_unknown_type() {
+        let err = read_compact_footer_obj(compact_footer_obj(778, 555)).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            IgniteErrorKind::UnknownBinaryType {
+                type_id: 778,
+                schema_id: 555
+            }
+        );
+    }
 }
 
 impl ComplexObjectSchema {
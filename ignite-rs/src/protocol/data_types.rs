@@ -4,8 +4,14 @@ use crate::error::{IgniteError, IgniteResult};
 use crate::protocol::*;
 use crate::protocol::{read_u8, TypeCode};
 
-use crate::{Enum, ReadableType, WritableType};
+use crate::{
+    AnyValue, Dedup, Enum, EnumType, IgniteMillis, ObjArray, OpaqueObject, ReadableType, TypeInfo,
+    UserType, Versioned, WrappingU64, WritableType,
+};
+use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::io;
+use std::time::Duration;
 
 /// Ignite's 'char' is a UTF-16 code UNIT, which means its size is 2 bytes.
 /// As Rust's 'char' is a Unicode scalar value (a.k.a UTF-32 code unit) and has 4 bytes,
@@ -37,6 +43,53 @@ write_type!(f64, TypeCode::Double, write_f64, 8);
 write_type!(bool, TypeCode::Bool, write_bool, 1);
 write_type!(Enum, TypeCode::Enum, write_enum, 8);
 
+macro_rules! type_info {
+    ($t:ty, $java_type:expr) => {
+        impl TypeInfo for $t {
+            fn cluster_type_name() -> Option<String> {
+                Some($java_type.to_owned())
+            }
+        }
+    };
+}
+
+type_info!(u8, "java.lang.Byte");
+type_info!(u16, "java.lang.Character");
+type_info!(i16, "java.lang.Short");
+type_info!(i32, "java.lang.Integer");
+type_info!(i64, "java.lang.Long");
+type_info!(f32, "java.lang.Float");
+type_info!(f64, "java.lang.Double");
+type_info!(bool, "java.lang.Boolean");
+type_info!(String, "java.lang.String");
+
+/// The values of any type are not checked
+impl TypeInfo for AnyValue {
+    fn cluster_type_name() -> Option<String> {
+        None
+    }
+}
+
+impl<V> TypeInfo for Versioned<V> {
+    fn cluster_type_name() -> Option<String> {
+        Some("java.lang.Object[]".to_owned())
+    }
+}
+
+impl<T: TypeInfo> TypeInfo for Option<T> {
+    fn cluster_type_name() -> Option<String> {
+        T::cluster_type_name()
+    }
+
+    fn cluster_type_id() -> Option<i32> {
+        T::cluster_type_id()
+    }
+
+    fn cluster_field_names() -> Vec<&'static str> {
+        T::cluster_field_names()
+    }
+}
+
 impl WritableType for String {
     fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
         write_u8(writer, TypeCode::String as u8)?;
@@ -49,6 +102,97 @@ impl WritableType for String {
     }
 }
 
+// borrowed values are written the same way as owned ones
+impl WritableType for str {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::String as u8)?;
+        write_string(writer, self)?;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.len() + 1 + 4 // string itself, type code, len
+    }
+}
+
+impl WritableType for [u8] {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::ArrByte as u8)?;
+        write_i32(writer, self.len() as i32)?; // length of array
+        writer.write_all(self)
+    }
+
+    fn size(&self) -> usize {
+        self.len() + 4 + 1 // bytes, len, type code
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl WritableType for bytes::Bytes {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self[..].write(writer)
+    }
+
+    fn size(&self) -> usize {
+        self[..].size()
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl WritableType for bytes::BytesMut {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self[..].write(writer)
+    }
+
+    fn size(&self) -> usize {
+        self[..].size()
+    }
+}
+
+/// Reads ArrByte payload straight into a single buffer
+#[cfg(feature = "bytes")]
+impl ReadableType for bytes::BytesMut {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::ArrByte => {
+                let len = read_i32(reader)?;
+                let mut buf = bytes::BytesMut::zeroed(len as usize);
+                reader.read_exact(&mut buf)?;
+                Ok(Some(buf))
+            }
+            _ => Err(IgniteError::from("Expected ArrByte!")),
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl ReadableType for bytes::Bytes {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        Ok(bytes::BytesMut::read_unwrapped(type_code, reader)?.map(bytes::BytesMut::freeze))
+    }
+}
+
+impl<T: WritableType + ?Sized> WritableType for &T {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        (**self).write(writer)
+    }
+
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+}
+
+impl<T: WritableType + ToOwned + ?Sized> WritableType for Cow<'_, T> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        (**self).write(writer)
+    }
+
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+}
+
 macro_rules! read_type {
     ($t:ty, $read_fn:ident) => {
         impl ReadableType for $t {
@@ -66,17 +210,376 @@ macro_rules! read_type {
     };
 }
 
-read_type!(u8, read_u8);
-read_type!(u16, read_u16);
-read_type!(i16, read_i16);
-read_type!(i32, read_i32);
-read_type!(i64, read_i64);
-read_type!(f32, read_f32);
-read_type!(f64, read_f64);
-read_type!(bool, read_bool);
 read_type!(String, read_string);
 read_type!(Enum, read_enum);
 
+/// Reads a number of the narrower type. The type code is already consumed
+fn read_narrower(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<f64> {
+    let value = match type_code {
+        TypeCode::Byte => read_i8(reader)? as f64, // Java's byte is signed
+        TypeCode::Short => read_i16(reader)? as f64,
+        TypeCode::Char => read_u16(reader)? as f64,
+        TypeCode::Int => read_i32(reader)? as f64,
+        TypeCode::Long => read_i64(reader)? as f64,
+        TypeCode::Float => read_f32(reader)? as f64,
+        _ => unreachable!("not a narrower number"),
+    };
+    Ok(value)
+}
+
+fn read_narrower_int(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<i64> {
+    let value = match type_code {
+        TypeCode::Byte => read_i8(reader)? as i64,
+        TypeCode::Short => read_i16(reader)? as i64,
+        TypeCode::Char => read_u16(reader)? as i64,
+        TypeCode::Int => read_i32(reader)? as i64,
+        _ => unreachable!("not a narrower integer"),
+    };
+    Ok(value)
+}
+
+/// Primitives are read from the values of their own type only.
+/// Within with_numeric_widening(), numbers of narrower types are accepted as well
+macro_rules! read_primitive {
+    ($t:ty, $code:path, $read_fn:ident) => {
+        impl ReadableType for $t {
+            fn read_unwrapped(
+                type_code: TypeCode,
+                reader: &mut impl Read,
+            ) -> IgniteResult<Option<Self>> {
+                match type_code {
+                    TypeCode::Null => Ok(None),
+                    $code => Ok(Some($read_fn(reader)?)),
+                    _ => Err(IgniteError::from(
+                        format!("Expected {:?}, got {:?}", $code, type_code).as_str(),
+                    )),
+                }
+            }
+        }
+    };
+    ($t:ty, $code:path, $read_fn:ident; $read_narrower:ident, $($narrower:path),+) => {
+        impl ReadableType for $t {
+            fn read_unwrapped(
+                type_code: TypeCode,
+                reader: &mut impl Read,
+            ) -> IgniteResult<Option<Self>> {
+                match type_code {
+                    TypeCode::Null => Ok(None),
+                    $code => Ok(Some($read_fn(reader)?)),
+                    $($narrower)|+ if numeric_widening() => {
+                        Ok(Some($read_narrower(type_code, reader)? as $t))
+                    }
+                    _ => Err(IgniteError::from(
+                        format!("Expected {:?}, got {:?}", $code, type_code).as_str(),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+read_primitive!(u8, TypeCode::Byte, read_u8);
+read_primitive!(u16, TypeCode::Char, read_u16);
+read_primitive!(bool, TypeCode::Bool, read_bool);
+read_primitive!(i16, TypeCode::Short, read_i16; read_narrower_int, TypeCode::Byte);
+read_primitive!(i32, TypeCode::Int, read_i32;
+    read_narrower_int, TypeCode::Byte, TypeCode::Short, TypeCode::Char);
+read_primitive!(i64, TypeCode::Long, read_i64;
+    read_narrower_int, TypeCode::Byte, TypeCode::Short, TypeCode::Char, TypeCode::Int);
+read_primitive!(f32, TypeCode::Float, read_f32;
+    read_narrower, TypeCode::Byte, TypeCode::Short, TypeCode::Char, TypeCode::Int, TypeCode::Long);
+read_primitive!(f64, TypeCode::Double, read_f64;
+    read_narrower, TypeCode::Byte, TypeCode::Short, TypeCode::Char, TypeCode::Int, TypeCode::Long, TypeCode::Float);
+
+/// Java has no unsigned integers, so unsigned types are mapped as follows:
+/// * u8 is written as Byte. Values above 127 are read as negative bytes by Java
+/// * u16 is written as Char which is an unsigned 16-bit value in Java
+/// * u32 is written as Long. No precision is lost
+/// * u64 is written as Long. Values above i64::MAX could not be written.
+///   Use WrappingU64 to store all 64 bits as is
+///
+/// The mapping is fixed, there is no option to store u64 as Decimal.
+/// Reads of u32 and u64 fail if the stored value is negative or does not fit.
+/// Like i64, they accept narrower integers only within with_numeric_widening()
+macro_rules! unsigned_type {
+    ($t:ty) => {
+        impl WritableType for $t {
+            fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+                let value = i64::try_from(*self).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} does not fit into Long", self),
+                    )
+                })?;
+                write_u8(writer, TypeCode::Long as u8)?;
+                write_i64(writer, value)
+            }
+
+            fn size(&self) -> usize {
+                8 + 1 // size, type code
+            }
+        }
+
+        impl ReadableType for $t {
+            fn read_unwrapped(
+                type_code: TypeCode,
+                reader: &mut impl Read,
+            ) -> IgniteResult<Option<Self>> {
+                let value = match type_code {
+                    TypeCode::Null => return Ok(None),
+                    TypeCode::Long => read_i64(reader)?,
+                    TypeCode::Byte | TypeCode::Short | TypeCode::Char | TypeCode::Int
+                        if numeric_widening() =>
+                    {
+                        read_narrower_int(type_code, reader)?
+                    }
+                    _ => {
+                        return Err(IgniteError::from(
+                            format!("Expected {:?}, got {:?}", TypeCode::Long, type_code).as_str(),
+                        ))
+                    }
+                };
+                match <$t>::try_from(value) {
+                    Ok(value) => Ok(Some(value)),
+                    Err(_) => Err(IgniteError::from(
+                        format!("{} does not fit into {}", value, stringify!($t)).as_str(),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+unsigned_type!(u32);
+unsigned_type!(u64);
+
+impl WritableType for WrappingU64 {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::Long as u8)?;
+        write_u64(writer, self.0)
+    }
+
+    fn size(&self) -> usize {
+        8 + 1 // size, type code
+    }
+}
+
+impl ReadableType for WrappingU64 {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::Long => Ok(Some(WrappingU64(read_u64(reader)?))),
+            _ => Err(IgniteError::from("Expected Long!")),
+        }
+    }
+}
+
+impl WritableType for OpaqueObject {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.0)
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl ReadableType for OpaqueObject {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            _ => {
+                let mut bytes = Vec::new();
+                read_value_bytes(type_code, reader, &mut bytes)?;
+                Ok(Some(OpaqueObject(bytes)))
+            }
+        }
+    }
+}
+
+// durations are stored as Long of milliseconds
+impl WritableType for Duration {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let millis = i64::try_from(self.as_millis()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} does not fit into Long of milliseconds", self),
+            )
+        })?;
+        write_u8(writer, TypeCode::Long as u8)?;
+        write_i64(writer, millis)
+    }
+
+    fn size(&self) -> usize {
+        8 + 1 // size, type code
+    }
+}
+
+impl ReadableType for Duration {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match u64::read_unwrapped(type_code, reader)? {
+            None => Ok(None),
+            Some(millis) => Ok(Some(Duration::from_millis(millis))),
+        }
+    }
+}
+
+impl WritableType for IgniteMillis {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.0.write(writer)
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
+
+impl ReadableType for IgniteMillis {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        Ok(Duration::read_unwrapped(type_code, reader)?.map(IgniteMillis))
+    }
+}
+
+/// Decimal is a scale and a big-endian magnitude with the sign in the most significant bit,
+/// like Java's BigDecimal is serialized. Integers are written with the scale of 0
+pub(crate) fn write_decimal(
+    writer: &mut dyn Write,
+    scale: i32,
+    negative: bool,
+    magnitude: &[u8],
+) -> io::Result<()> {
+    let bytes = decimal_bytes(negative, magnitude);
+    write_u8(writer, TypeCode::Decimal as u8)?;
+    write_i32(writer, scale)?;
+    write_i32(writer, bytes.len() as i32)?;
+    writer.write_all(&bytes)
+}
+
+pub(crate) fn decimal_size(magnitude: &[u8]) -> usize {
+    decimal_bytes(false, magnitude).len() + 4 + 4 + 1 // bytes, len, scale, type code
+}
+
+/// Strips leading zeros and leaves room for the sign bit
+fn decimal_bytes(negative: bool, magnitude: &[u8]) -> Vec<u8> {
+    let magnitude: Vec<u8> = magnitude.iter().copied().skip_while(|b| *b == 0).collect();
+    let mut bytes = match magnitude.first() {
+        Some(b) if b & 0x80 == 0 => magnitude,
+        _ => [&[0u8][..], &magnitude].concat(),
+    };
+    if negative {
+        bytes[0] |= 0x80;
+    }
+    bytes
+}
+
+/// Returns scale, sign and big-endian magnitude. The type code is already consumed
+pub(crate) fn read_decimal(reader: &mut impl Read) -> IgniteResult<(i32, bool, Vec<u8>)> {
+    let scale = read_i32(reader)?;
+    let len = read_i32(reader)?;
+    let mut bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut bytes)?;
+    let negative = bytes.first().is_some_and(|b| b & 0x80 != 0);
+    if let Some(b) = bytes.first_mut() {
+        *b &= 0x7F;
+    }
+    Ok((scale, negative, bytes))
+}
+
+impl WritableType for i128 {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_decimal(writer, 0, *self < 0, &self.unsigned_abs().to_be_bytes())
+    }
+
+    fn size(&self) -> usize {
+        decimal_size(&self.unsigned_abs().to_be_bytes())
+    }
+}
+
+impl ReadableType for i128 {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::Long => Ok(Some(read_i64(reader)? as i128)),
+            TypeCode::Int => Ok(Some(read_i32(reader)? as i128)),
+            TypeCode::Decimal => {
+                let (scale, negative, bytes) = read_decimal(reader)?;
+                let overflow = || IgniteError::from("Decimal does not fit into i128");
+                let magnitude: Vec<u8> = bytes.into_iter().skip_while(|b| *b == 0).collect();
+                if magnitude.len() > 16 {
+                    return Err(overflow());
+                }
+                let mut buf = [0u8; 16];
+                buf[16 - magnitude.len()..].copy_from_slice(&magnitude);
+                let mut value = u128::from_be_bytes(buf);
+                if scale < 0 {
+                    for _ in scale..0 {
+                        value = value.checked_mul(10).ok_or_else(overflow)?;
+                    }
+                }
+                for _ in 0..scale {
+                    if value % 10 != 0 {
+                        return Err(IgniteError::from(
+                            format!("Decimal with scale {} is not an integer", scale).as_str(),
+                        ));
+                    }
+                    value /= 10;
+                }
+                match negative {
+                    true => 0i128.checked_sub_unsigned(value).ok_or_else(overflow),
+                    false => i128::try_from(value).map_err(|_| overflow()),
+                }
+                .map(Some)
+            }
+            _ => Err(IgniteError::from("Expected Decimal, Long or Int!")),
+        }
+    }
+}
+
+#[cfg(feature = "num-bigint")]
+impl WritableType for num_bigint::BigInt {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let (sign, magnitude) = self.to_bytes_be();
+        write_decimal(writer, 0, sign == num_bigint::Sign::Minus, &magnitude)
+    }
+
+    fn size(&self) -> usize {
+        decimal_size(&self.magnitude().to_bytes_be())
+    }
+}
+
+#[cfg(feature = "num-bigint")]
+impl ReadableType for num_bigint::BigInt {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::Long => Ok(Some(read_i64(reader)?.into())),
+            TypeCode::Int => Ok(Some(read_i32(reader)?.into())),
+            TypeCode::Decimal => {
+                let (scale, negative, bytes) = read_decimal(reader)?;
+                let sign = match negative {
+                    true => num_bigint::Sign::Minus,
+                    false => num_bigint::Sign::Plus,
+                };
+                let mut value = num_bigint::BigInt::from_bytes_be(sign, &bytes);
+                let ten = num_bigint::BigInt::from(10);
+                if scale < 0 {
+                    value *= ten.pow((-scale) as u32);
+                } else if scale > 0 {
+                    let divisor = ten.pow(scale as u32);
+                    if (&value % &divisor) != num_bigint::BigInt::from(0) {
+                        return Err(IgniteError::from(
+                            format!("Decimal with scale {} is not an integer", scale).as_str(),
+                        ));
+                    }
+                    value /= divisor;
+                }
+                Ok(Some(value))
+            }
+            _ => Err(IgniteError::from("Expected Decimal, Long or Int!")),
+        }
+    }
+}
+
 macro_rules! write_primitive_arr {
     ($t:ty, $code:path, $write_fn:ident, $size:expr) => {
         impl WritableType for Vec<$t> {
@@ -131,19 +634,65 @@ read_primitive_arr!(f32, read_f32);
 read_primitive_arr!(f64, read_f64);
 read_primitive_arr!(bool, read_bool);
 
-// pack all vectors as object array
-impl<T: WritableType + ReadableType> WritableType for Vec<Option<T>> {
+impl<T: WritableType> WritableType for Dedup<T> {
     fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
-        write_u8(writer, TypeCode::ArrObj as u8)?;
-        write_i32(writer, -1)?; // typeid. always -1
-        write_i32(writer, self.len() as i32)?; // length of array
-        for item in self {
+        handles::with_dedup(|| self.0.write(writer))
+    }
+
+    fn size(&self) -> usize {
+        let mut bytes = Vec::new();
+        match handles::with_dedup(|| self.0.write(&mut bytes)) {
+            Ok(_) => bytes.len(),
+            Err(_) => self.0.size(), // the write is going to fail anyway
+        }
+    }
+}
+
+impl<T: ReadableType> ReadableType for Dedup<T> {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        Ok(T::read_unwrapped(type_code, reader)?.map(Dedup))
+    }
+}
+
+/// Writes items of an Object Array. Items are buffered only if repeated objects are replaced with handles
+fn write_obj_items<'a, I>(writer: &mut dyn Write, items: I) -> io::Result<()>
+where
+    I: Iterator<Item = Option<&'a dyn WritableType>>,
+{
+    let mut dedup = ObjectDedup::new();
+    if !dedup.is_enabled() {
+        for item in items {
             match item {
                 None => write_u8(writer, TypeCode::Null as u8)?,
                 Some(value) => value.write(writer)?,
             }
         }
-        Ok(())
+        return Ok(());
+    }
+    let mut buf = Vec::new();
+    for item in items {
+        let start = buf.len();
+        match item {
+            None => write_u8(&mut buf, TypeCode::Null as u8)?,
+            Some(value) => {
+                value.write(&mut buf)?;
+                dedup.push(&mut buf, start);
+            }
+        }
+    }
+    writer.write_all(&buf)
+}
+
+// pack all vectors as object array
+impl<T: WritableType + ReadableType> WritableType for Vec<Option<T>> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::ArrObj as u8)?;
+        write_i32(writer, -1)?; // typeid. always -1
+        write_i32(writer, self.len() as i32)?; // length of array
+        let items = self
+            .iter()
+            .map(|item| item.as_ref().map(|value| value as &dyn WritableType));
+        write_obj_items(writer, items)
     }
 
     fn size(&self) -> usize {
@@ -162,7 +711,7 @@ impl<T: WritableType + ReadableType> ReadableType for Vec<Option<T>> {
     fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
         match type_code {
             TypeCode::Null => Ok(None),
-            TypeCode::ArrObj => {
+            TypeCode::ArrObj | TypeCode::ArrEnum => {
                 read_i32(reader)?; // ignore type id
                 let len = read_i32(reader)?;
                 let mut data: Vec<Option<T>> = Vec::with_capacity(len as usize);
@@ -187,6 +736,249 @@ impl<T: WritableType + ReadableType> ReadableType for Vec<Option<T>> {
     }
 }
 
+impl<T: UserType> WritableType for ObjArray<T> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::ArrObj as u8)?;
+        write_i32(writer, T::user_type_id())?; // component type id
+        write_i32(writer, self.len() as i32)?; // length of array
+        let items = self
+            .iter()
+            .map(|item| item.as_ref().map(|value| value as &dyn WritableType));
+        write_obj_items(writer, items)
+    }
+
+    fn size(&self) -> usize {
+        let items: usize = self
+            .iter()
+            .map(|item| item.as_ref().map_or(1, |value| value.size()))
+            .sum();
+        items + 4 + 4 + 1 // items, len, type id, type code
+    }
+}
+
+impl<T: UserType> ReadableType for ObjArray<T> {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::ArrObj => {
+                let type_id = read_i32(reader)?;
+                if type_id == 0 {
+                    // unregistered type, class name follows
+                    String::read(reader)?;
+                } else if type_id != T::user_type_id() {
+                    return Err(IgniteError::from(
+                        format!(
+                            "Array of type {} expected, got {}",
+                            T::user_type_id(),
+                            type_id
+                        )
+                        .as_str(),
+                    ));
+                }
+                let len = read_i32(reader)?;
+                let mut items: Vec<Option<T>> = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    items.push(T::read(reader)?);
+                }
+                Ok(Some(ObjArray(items)))
+            }
+            _ => Err(IgniteError::from("Expected Object Array!")),
+        }
+    }
+}
+
+// pack versioned values as Object[] of the version and the value
+impl<V: WritableType> WritableType for Versioned<V> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::ArrObj as u8)?;
+        write_i32(writer, -1)?; // typeid. always -1
+        write_i32(writer, 2)?; // length of array
+        self.version.write(writer)?;
+        self.value.write(writer)
+    }
+
+    fn size(&self) -> usize {
+        self.version.size() + self.value.size() + 1 + 4 + 4 // items, type code, typeId, len
+    }
+}
+
+impl<V: ReadableType> ReadableType for Versioned<V> {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::ArrObj => {
+                read_i32(reader)?; // ignore type id
+                if read_i32(reader)? != 2 {
+                    return Err(IgniteError::from("Expected version and value!"));
+                }
+                match (i64::read(reader)?, V::read(reader)?) {
+                    (Some(version), Some(value)) => Ok(Some(Versioned { version, value })),
+                    _ => Err(IgniteError::from("Version or value is null!")),
+                }
+            }
+            _ => Err(IgniteError::from("Expected Object Array!")),
+        }
+    }
+}
+
+// pack vectors of enums as enum array
+impl<T: EnumType> WritableType for Vec<T> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::ArrEnum as u8)?;
+        write_i32(writer, self.first().map_or(-1, |e| e.enum_type_id()))?; // component type id
+        write_i32(writer, self.len() as i32)?; // length of array
+        for item in self {
+            item.write(writer)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        let items_size: usize = self.iter().map(|item| item.size()).sum();
+        items_size + 1 + 4 + 4 // items, type code, typeId, len
+    }
+}
+
+impl<T: EnumType> ReadableType for Vec<T> {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::ArrEnum | TypeCode::ArrObj => {
+                read_i32(reader)?; // ignore component type id
+                let len = read_i32(reader)?;
+                let mut data: Vec<T> = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    match T::read(reader)? {
+                        Some(item) => data.push(item),
+                        None => {
+                            return Err(IgniteError::from("NULL is not expected in Enum Array!"))
+                        }
+                    }
+                }
+                Ok(Some(data))
+            }
+            _ => Err(IgniteError::from("Expected Enum Array!")),
+        }
+    }
+}
+
+/// Java's LinkedHashMap, which keeps the insertion order
+#[cfg(feature = "indexmap")]
+const LINKED_HASH_MAP: i8 = 2;
+
+#[cfg(feature = "indexmap")]
+impl<K: WritableType, V: WritableType, S> WritableType for indexmap::IndexMap<K, V, S> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u8(writer, TypeCode::Map as u8)?;
+        write_i32(writer, self.len() as i32)?; // number of entries
+        write_i8(writer, LINKED_HASH_MAP)?;
+        for (key, value) in self {
+            key.write(writer)?;
+            value.write(writer)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        let entries: usize = self.iter().map(|(k, v)| k.size() + v.size()).sum();
+        entries + 1 + 4 + 1 // entries, type code, len, map type
+    }
+}
+
+/// Entries are read in the order they are written, any map type is accepted
+#[cfg(feature = "indexmap")]
+impl<K, V, S> ReadableType for indexmap::IndexMap<K, V, S>
+where
+    K: ReadableType + std::hash::Hash + Eq,
+    V: ReadableType,
+    S: std::hash::BuildHasher + Default,
+{
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::Map => {
+                let len = read_i32(reader)?;
+                read_i8(reader)?; // ignore map type
+                let mut map =
+                    indexmap::IndexMap::with_capacity_and_hasher(len as usize, S::default());
+                for _ in 0..len {
+                    let key = K::read(reader)?
+                        .ok_or_else(|| IgniteError::from("NULL map keys are not supported!"))?;
+                    let value = V::read(reader)?
+                        .ok_or_else(|| IgniteError::from("NULL map values are not supported!"))?;
+                    map.insert(key, value);
+                }
+                Ok(Some(map))
+            }
+            _ => Err(IgniteError::from("Expected Map!")),
+        }
+    }
+}
+
+// pack tuples as object arrays
+macro_rules! tuple_type {
+    ($len:expr; $($t:ident $idx:tt),+) => {
+        impl<$($t: WritableType),+> WritableType for ($($t,)+) {
+            fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+                write_u8(writer, TypeCode::ArrObj as u8)?;
+                write_i32(writer, -1)?; // typeid. always -1
+                write_i32(writer, $len)?; // length of array
+                let items = [$(Some(&self.$idx as &dyn WritableType)),+];
+                write_obj_items(writer, IntoIterator::into_iter(items))
+            }
+
+            fn size(&self) -> usize {
+                let items_size: usize = 0 $(+ self.$idx.size())+;
+                items_size + 1 + 4 + 4 // items, type code, typeId, len
+            }
+        }
+
+        impl<$($t: ReadableType),+> ReadableType for ($($t,)+) {
+            fn read_unwrapped(
+                type_code: TypeCode,
+                reader: &mut impl Read,
+            ) -> IgniteResult<Option<Self>> {
+                let len = match type_code {
+                    TypeCode::Null => return Ok(None),
+                    TypeCode::ArrObj => {
+                        read_i32(reader)?; // ignore type id
+                        read_i32(reader)?
+                    }
+                    TypeCode::Collection => {
+                        let len = read_i32(reader)?;
+                        read_i8(reader)?; // ignore collection type
+                        len
+                    }
+                    _ => return Err(IgniteError::from("Expected Array or Collection!")),
+                };
+                if len != $len {
+                    return Err(IgniteError::from(
+                        format!("Expected {} elements, got {}", $len, len).as_str(),
+                    ));
+                }
+                Ok(Some(($(
+                    $t::read(reader)?.ok_or_else(|| {
+                        IgniteError::from(format!("Tuple element {} is NULL!", $idx).as_str())
+                    })?,
+                )+)))
+            }
+        }
+    };
+}
+
+tuple_type!(1; A 0);
+tuple_type!(2; A 0, B 1);
+tuple_type!(3; A 0, B 1, C 2);
+tuple_type!(4; A 0, B 1, C 2, D 3);
+tuple_type!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_type!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_type!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_type!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+tuple_type!(9; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+tuple_type!(10; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+tuple_type!(11; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+tuple_type!(12; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
 impl<T: WritableType> WritableType for Option<T> {
     fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
         match self {
@@ -211,4 +1003,271 @@ impl<T: ReadableType> ReadableType for Option<T> {
             Some(inner) => Ok(Some(Some(inner))),
         }
     }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_enum_arr_round_trip() {
+        let expected = vec![
+            Enum {
+                type_id: 42,
+                ordinal: 0,
+            },
+            Enum {
+                type_id: 42,
+                ordinal: 3,
+            },
+        ];
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        assert_eq!(bytes[0], TypeCode::ArrEnum as u8);
+
+        let actual = Vec::<Enum>::read(&mut Cursor::new(bytes)).unwrap().unwrap();
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[1].type_id, 42);
+        assert_eq!(actual[1].ordinal, 3);
+    }
+
+    #[test]
+    fn test_versioned_round_trip() {
+        let expected = Versioned::new("a".to_owned()).next("b".to_owned());
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        assert_eq!(bytes[0], TypeCode::ArrObj as u8);
+        let actual = Versioned::<String>::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn test_read_wrapped_data() {
+        let mut bytes = vec![TypeCode::WrappedData as u8];
+        write_i32(&mut bytes, 7).unwrap(); // len
+        bytes.extend(&[0xFF, 0xFF, TypeCode::Int as u8, 42, 0, 0, 0]); // garbage, then i32
+        write_i32(&mut bytes, 2).unwrap(); // offset
+
+        let mut reader = Cursor::new(bytes);
+        let actual = i32::read(&mut reader).unwrap();
+        assert_eq!(actual, Some(42));
+        assert_eq!(reader.position() as usize, reader.get_ref().len());
+
+        let mut bytes = vec![TypeCode::WrappedData as u8];
+        write_i32(&mut bytes, -1).unwrap(); // len
+        assert!(i32::read(&mut Cursor::new(bytes)).is_err());
+
+        let mut bytes = vec![TypeCode::WrappedData as u8];
+        write_i32(&mut bytes, 1).unwrap(); // len
+        bytes.push(0);
+        write_i32(&mut bytes, 2).unwrap(); // offset
+        assert!(i32::read(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_numeric_widening() {
+        let mut bytes = vec![];
+        (-5i16).write(&mut bytes).unwrap();
+        assert!(i64::read(&mut Cursor::new(bytes.clone())).is_err());
+        let actual = with_numeric_widening(true, || i64::read(&mut Cursor::new(bytes.clone())));
+        assert_eq!(actual.unwrap(), Some(-5));
+        let actual = with_numeric_widening(true, || f64::read(&mut Cursor::new(bytes.clone())));
+        assert_eq!(actual.unwrap(), Some(-5.0));
+
+        let mut bytes = vec![];
+        7i64.write(&mut bytes).unwrap();
+        let actual = with_numeric_widening(true, || i32::read(&mut Cursor::new(bytes.clone())));
+        assert!(actual.is_err()); // narrowing is never done
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let mut bytes = vec![];
+        u32::MAX.write(&mut bytes).unwrap();
+        assert_eq!(bytes[0], TypeCode::Long as u8);
+        assert_eq!(u32::read(&mut Cursor::new(bytes)).unwrap(), Some(u32::MAX));
+
+        assert!(u64::MAX.write(&mut vec![]).is_err());
+        let mut bytes = vec![];
+        WrappingU64(u64::MAX).write(&mut bytes).unwrap();
+        assert!(u64::read(&mut Cursor::new(bytes.clone())).is_err());
+        assert_eq!(
+            i64::read(&mut Cursor::new(bytes.clone())).unwrap(),
+            Some(-1)
+        );
+        let actual = WrappingU64::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(WrappingU64(u64::MAX)));
+
+        let mut bytes = vec![];
+        7i32.write(&mut bytes).unwrap();
+        assert!(u32::read(&mut Cursor::new(bytes.clone())).is_err());
+        let actual = with_numeric_widening(true, || u64::read(&mut Cursor::new(bytes)));
+        assert_eq!(actual.unwrap(), Some(7));
+        let mut bytes = vec![];
+        (-7i32).write(&mut bytes).unwrap();
+        let actual = with_numeric_widening(true, || u32::read(&mut Cursor::new(bytes)));
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn test_i128_decimal() {
+        for value in [
+            0i128,
+            127,
+            128,
+            -128,
+            i64::MAX as i128 + 1,
+            i128::MAX,
+            i128::MIN,
+        ] {
+            let mut bytes = vec![];
+            value.write(&mut bytes).unwrap();
+            assert_eq!(bytes.len(), value.size());
+            assert_eq!(i128::read(&mut Cursor::new(bytes)).unwrap(), Some(value));
+        }
+
+        // -128 is written by Java as scale 0, len 2, [0x80, 0x80]
+        let mut bytes = vec![];
+        (-128i128).write(&mut bytes).unwrap();
+        assert_eq!(bytes, [30, 0, 0, 0, 0, 2, 0, 0, 0, 0x80, 0x80]);
+
+        // 1200 with scale 2 is 12
+        let mut bytes = vec![TypeCode::Decimal as u8];
+        write_i32(&mut bytes, 2).unwrap(); // scale
+        write_i32(&mut bytes, 2).unwrap(); // len
+        bytes.extend(&[0x04, 0xB0]);
+        assert_eq!(i128::read(&mut Cursor::new(bytes)).unwrap(), Some(12));
+    }
+
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn test_big_int_decimal() {
+        let value = -num_bigint::BigInt::from(i128::MAX) * num_bigint::BigInt::from(1000);
+        let mut bytes = vec![];
+        value.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), value.size());
+        let actual = num_bigint::BigInt::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(value));
+    }
+
+    #[test]
+    fn test_tuple_round_trip() {
+        let expected = (42i32, "foo".to_string(), vec![1i64, 2], true);
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        assert_eq!(bytes[0], TypeCode::ArrObj as u8);
+
+        let actual = <(i32, String, Vec<i64>, bool)>::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(expected));
+        let wrong_len = <(i32, String)>::read(&mut Cursor::new(vec![]));
+        assert!(wrong_len.is_err());
+    }
+
+    #[test]
+    fn test_string_decoding() {
+        // U+1F600 as a CESU-8 surrogate pair, then NUL as Java writes it
+        let mut bytes = vec![TypeCode::String as u8];
+        write_i32(&mut bytes, 9).unwrap();
+        bytes.extend(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80, 0xC0, 0x80, b'a']);
+
+        assert!(String::read(&mut Cursor::new(bytes.clone())).is_err());
+        let actual = with_string_decoding(StringDecoding::ModifiedUtf8, || {
+            String::read(&mut Cursor::new(bytes.clone()))
+        });
+        assert_eq!(actual.unwrap(), Some("\u{1F600}\0a".to_string()));
+        let actual = with_string_decoding(StringDecoding::Lossy, || {
+            String::read(&mut Cursor::new(bytes.clone()))
+        });
+        assert!(actual.unwrap().unwrap().ends_with("\u{FFFD}a"));
+    }
+
+    #[test]
+    fn test_borrowed_write() {
+        let mut owned = vec![];
+        "foo".to_string().write(&mut owned).unwrap();
+        let mut borrowed = vec![];
+        "foo".write(&mut borrowed).unwrap();
+        assert_eq!(owned, borrowed);
+        let mut cow = vec![];
+        Cow::Borrowed("foo").write(&mut cow).unwrap();
+        assert_eq!(owned, cow);
+
+        let mut owned = vec![];
+        vec![1u8, 2, 3].write(&mut owned).unwrap();
+        let mut borrowed = vec![];
+        [1u8, 2, 3][..].write(&mut borrowed).unwrap();
+        assert_eq!(owned, borrowed);
+        assert_eq!(borrowed.len(), [1u8, 2, 3][..].size());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_bytes() {
+        let value = bytes::Bytes::from_static(&[1, 2, 3]);
+        let mut written = vec![];
+        value.write(&mut written).unwrap();
+        let mut expected = vec![];
+        vec![1u8, 2, 3].write(&mut expected).unwrap();
+        assert_eq!(written, expected);
+        assert_eq!(written.len(), value.size());
+
+        let actual = bytes::Bytes::read(&mut Cursor::new(written)).unwrap();
+        assert_eq!(actual, Some(value));
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn test_index_map() {
+        let mut expected: indexmap::IndexMap<String, i32> = indexmap::IndexMap::new();
+        expected.insert("b".into(), 1);
+        expected.insert("a".into(), 2);
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        assert_eq!(bytes[5], 2); // LinkedHashMap
+
+        let actual = indexmap::IndexMap::<String, i32>::read(&mut Cursor::new(bytes))
+            .unwrap()
+            .unwrap();
+        assert!(actual.keys().eq(expected.keys()));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_duration_millis() {
+        let mut bytes = vec![];
+        IgniteMillis(Duration::from_micros(1_500_999))
+            .write(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, [TypeCode::Long as u8, 0xDC, 0x05, 0, 0, 0, 0, 0, 0]); // 1500
+        let actual = Duration::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn test_opaque_object() {
+        let mut bytes = vec![TypeCode::OptimizedMarshaller as u8];
+        write_i32(&mut bytes, 3).unwrap();
+        bytes.extend(&[0xAC, 0xED, 0x00]);
+        let value = (OpaqueObject(bytes.clone()), "foo".to_string());
+        let mut written = vec![];
+        value.write(&mut written).unwrap();
+
+        let mut reader = Cursor::new(written);
+        let actual = <(OpaqueObject, String)>::read(&mut reader)
+            .unwrap()
+            .unwrap();
+        assert_eq!(actual.0, OpaqueObject(bytes));
+        assert_eq!(actual.0.type_code(), Some(TypeCode::OptimizedMarshaller));
+        assert_eq!(actual.1, "foo");
+        assert_eq!(reader.position() as usize, reader.get_ref().len());
+    }
 }
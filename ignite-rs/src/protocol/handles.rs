@@ -0,0 +1,349 @@
+//! Back-references (Handle, type code 102) support for reads.
+//! Java writers do not serialize the same object twice within a single top-level value.
+//! Instead they write a handle: an offset from the handle itself back to the object's first byte.
+//! https://apacheignite.readme.io/docs/binary-client-protocol-data-format#handle
+//!
+//! Values that may contain handles are read through a tracked reader which remembers
+//! all the consumed bytes and the positions where values start (a positional registry).
+//! When a handle is met, the referenced object is read once again from the remembered bytes.
+//!
+//! Writes could produce handles as well, see Dedup. Complex objects which are equal to the objects
+//! written before them into the same object or array are replaced with handles.
+
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::Read;
+use std::rc::Rc;
+
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{read_i32, TypeCode};
+use crate::ReadableType;
+
+/// State of a single tracked read. Shared by all the frames of the same read
+#[derive(Clone)]
+struct Registry {
+    /// All bytes of the value being read. Positions are indexes in this buffer
+    data: Rc<RefCell<Vec<u8>>>,
+    /// Positions of values that were read so far
+    values: Rc<RefCell<BTreeSet<usize>>>,
+}
+
+struct Frame {
+    registry: Registry,
+    /// Current position of the frame's reader
+    pos: Rc<Cell<usize>>,
+    /// Position of the object this frame re-reads because of a handle
+    handle_target: Option<usize>,
+}
+
+thread_local! {
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Pops the frame even if the read has failed or panicked
+struct FrameGuard;
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+fn with_frame<T, F>(frame: Frame, read_fn: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    FRAMES.with(|frames| frames.borrow_mut().push(frame));
+    let _guard = FrameGuard;
+    read_fn()
+}
+
+/// Reads and remembers the bytes of the top-level value
+pub(crate) struct RootReader<'a> {
+    inner: &'a mut dyn Read,
+    data: Rc<RefCell<Vec<u8>>>,
+    pos: Rc<Cell<usize>>,
+}
+
+impl Read for RootReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.data.borrow_mut().extend_from_slice(&buf[..n]);
+        self.pos.set(self.pos.get() + n);
+        Ok(n)
+    }
+}
+
+/// Reads already remembered bytes starting from some position
+pub(crate) struct ObjectReader {
+    data: Rc<RefCell<Vec<u8>>>,
+    pos: Rc<Cell<usize>>,
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data.borrow();
+        let pos = self.pos.get().min(data.len());
+        let n = buf.len().min(data.len() - pos);
+        buf[..n].copy_from_slice(&data[pos..pos + n]);
+        self.pos.set(pos + n);
+        Ok(n)
+    }
+}
+
+/// Returns true if a value of this type could contain handles
+pub(crate) fn may_contain_handles(type_code: &TypeCode) -> bool {
+    matches!(
+        type_code,
+        TypeCode::ComplexObj | TypeCode::ArrObj | TypeCode::Collection | TypeCode::Map
+    )
+}
+
+/// Returns the current position of the tracked read or None if there is no tracked read
+pub(crate) fn position() -> Option<usize> {
+    FRAMES.with(|frames| frames.borrow().last().map(|frame| frame.pos.get()))
+}
+
+/// Remembers that a value starts at the provided position
+pub(crate) fn register(pos: usize) {
+    FRAMES.with(|frames| {
+        if let Some(frame) = frames.borrow().last() {
+            frame.registry.values.borrow_mut().insert(pos);
+        }
+    })
+}
+
+/// Starts a tracked read of a top-level value. The type code is already consumed
+pub(crate) fn read_root<T, R, F>(type_code: TypeCode, reader: &mut R, read_fn: F) -> IgniteResult<T>
+where
+    R: Read,
+    F: FnOnce(TypeCode, &mut RootReader) -> IgniteResult<T>,
+{
+    let registry = Registry {
+        data: Rc::new(RefCell::new(vec![type_code as u8])),
+        values: Rc::new(RefCell::new(BTreeSet::new())),
+    };
+    registry.values.borrow_mut().insert(0);
+    let pos = Rc::new(Cell::new(1));
+    let mut root_reader = RootReader {
+        inner: reader,
+        data: registry.data.clone(),
+        pos: pos.clone(),
+    };
+    let frame = Frame {
+        registry,
+        pos,
+        handle_target: None,
+    };
+    with_frame(frame, || read_fn(type_code, &mut root_reader))
+}
+
+/// Starts a tracked read of a value which is stored in a byte array at some offset
+pub(crate) fn read_bytes<T, F>(data: Vec<u8>, offset: usize, read_fn: F) -> IgniteResult<T>
+where
+    F: FnOnce(&mut ObjectReader) -> IgniteResult<T>,
+{
+    let registry = Registry {
+        data: Rc::new(RefCell::new(data)),
+        values: Rc::new(RefCell::new(BTreeSet::new())),
+    };
+    let pos = Rc::new(Cell::new(offset));
+    let mut reader = ObjectReader {
+        data: registry.data.clone(),
+        pos: pos.clone(),
+    };
+    let frame = Frame {
+        registry,
+        pos,
+        handle_target: None,
+    };
+    with_frame(frame, || read_fn(&mut reader))
+}
+
+/// Reads a value which starts at the provided position of the current tracked read
+pub(crate) fn read_at<T, F>(pos: usize, read_fn: F) -> IgniteResult<T>
+where
+    F: FnOnce(&mut ObjectReader) -> IgniteResult<T>,
+{
+    let registry = FRAMES
+        .with(|frames| frames.borrow().last().map(|frame| frame.registry.clone()))
+        .ok_or_else(|| IgniteError::from("No tracked read is in progress!"))?;
+    let pos = Rc::new(Cell::new(pos));
+    let mut reader = ObjectReader {
+        data: registry.data.clone(),
+        pos: pos.clone(),
+    };
+    let frame = Frame {
+        registry,
+        pos,
+        handle_target: None,
+    };
+    with_frame(frame, || read_fn(&mut reader))
+}
+
+/// Reads a value referenced by a handle. The type code is already consumed
+pub(crate) fn read_handle<T: ReadableType>(reader: &mut impl Read) -> IgniteResult<Option<T>> {
+    read_handle_with(reader, |object_reader| T::read(object_reader))
+}
+
+/// Reads a value referenced by a handle using the provided function. The type code is already consumed
+pub(crate) fn read_handle_with<T, R, F>(reader: &mut R, read_fn: F) -> IgniteResult<T>
+where
+    R: Read,
+    F: FnOnce(&mut ObjectReader) -> IgniteResult<T>,
+{
+    let handle_pos = position()
+        .ok_or_else(|| IgniteError::from("Handle is not expected outside of an object!"))?
+        - 1; // type code is already read
+    let offset = read_i32(reader)?;
+    if offset <= 0 || offset as usize > handle_pos {
+        return Err(IgniteError::from(
+            format!("Invalid handle offset {}", offset).as_str(),
+        ));
+    }
+    let target = handle_pos - offset as usize;
+
+    let registry = FRAMES.with(|frames| {
+        let frames = frames.borrow();
+        let top = frames.last().expect("tracked read is in progress");
+        if !top.registry.values.borrow().contains(&target) {
+            return Err(IgniteError::from(
+                format!("Handle points to unknown object at {}", target).as_str(),
+            ));
+        }
+        let cyclic = frames.iter().any(|frame| {
+            Rc::ptr_eq(&frame.registry.data, &top.registry.data)
+                && frame.handle_target == Some(target)
+        });
+        if cyclic {
+            return Err(IgniteError::from(
+                "Cyclic object references are not supported!",
+            ));
+        }
+        Ok(top.registry.clone())
+    })?;
+
+    let pos = Rc::new(Cell::new(target));
+    let mut object_reader = ObjectReader {
+        data: registry.data.clone(),
+        pos: pos.clone(),
+    };
+    let frame = Frame {
+        registry,
+        pos,
+        handle_target: Some(target),
+    };
+    with_frame(frame, || read_fn(&mut object_reader))
+}
+
+thread_local! {
+    static DEDUP: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Writes the value replacing repeated complex objects with handles. Returns the closure's result
+pub(crate) fn with_dedup<T, F: FnOnce() -> T>(write_fn: F) -> T {
+    let prev = DEDUP.with(|cell| cell.replace(true));
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEDUP.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(prev);
+    write_fn()
+}
+
+/// Tracks complex objects written into a single buffer, like object's fields or array's items.
+/// Does nothing unless the write is done within Dedup
+pub struct ObjectDedup {
+    enabled: bool,
+    /// Hash, start and length of the objects written so far
+    written: Vec<(u64, usize, usize)>,
+}
+
+impl ObjectDedup {
+    pub fn new() -> ObjectDedup {
+        ObjectDedup {
+            enabled: DEDUP.with(Cell::get),
+            written: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Checks the value written into buf at start. If an equal object was written before,
+    /// the value is replaced with a handle to that object
+    pub fn push(&mut self, buf: &mut Vec<u8>, start: usize) {
+        if !self.enabled || buf.get(start) != Some(&(TypeCode::ComplexObj as u8)) {
+            return;
+        }
+        let value = &buf[start..];
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let prev = self.written.iter().find(|(h, pos, len)| {
+            *h == hash && *len == value.len() && buf[*pos..*pos + *len] == *value
+        });
+        match prev {
+            Some((_, pos, _)) => {
+                let offset = (start - pos) as i32;
+                buf.truncate(start);
+                buf.push(TypeCode::Handle as u8);
+                buf.extend_from_slice(&offset.to_le_bytes());
+            }
+            None => self.written.push((hash, start, value.len())),
+        }
+    }
+}
+
+impl Default for ObjectDedup {
+    fn default() -> Self {
+        ObjectDedup::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{write_i32, write_u8};
+    use crate::WritableType;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_handle_to_array() {
+        let shared: Vec<Option<i32>> = vec![Some(1), Some(2)];
+        let mut bytes = vec![];
+        write_u8(&mut bytes, TypeCode::ArrObj as u8).unwrap();
+        write_i32(&mut bytes, -1).unwrap(); // type id
+        write_i32(&mut bytes, 2).unwrap(); // len
+        let first_pos = bytes.len();
+        shared.write(&mut bytes).unwrap();
+        let handle_pos = bytes.len();
+        write_u8(&mut bytes, TypeCode::Handle as u8).unwrap();
+        write_i32(&mut bytes, (handle_pos - first_pos) as i32).unwrap();
+
+        let actual = Vec::<Option<Vec<Option<i32>>>>::read(&mut Cursor::new(bytes))
+            .unwrap()
+            .unwrap();
+        assert_eq!(actual, vec![Some(shared.clone()), Some(shared)]);
+    }
+
+    #[test]
+    fn test_read_cyclic_handle() {
+        let mut bytes = vec![];
+        write_u8(&mut bytes, TypeCode::ArrObj as u8).unwrap();
+        write_i32(&mut bytes, -1).unwrap(); // type id
+        write_i32(&mut bytes, 1).unwrap(); // len
+        let handle_pos = bytes.len();
+        write_u8(&mut bytes, TypeCode::Handle as u8).unwrap();
+        write_i32(&mut bytes, handle_pos as i32).unwrap(); // points to the array itself
+
+        let actual = Vec::<Option<Vec<Option<i32>>>>::read(&mut Cursor::new(bytes));
+        assert!(actual.is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::io;
 use std::io::{ErrorKind, Read, Write};
 
@@ -6,9 +7,18 @@ use crate::error::{IgniteError, IgniteResult};
 use crate::{Enum, ReadableType};
 use std::convert::TryFrom;
 
+pub(crate) mod any_value;
+pub(crate) mod binary_type;
 pub(crate) mod cache_config;
+#[cfg(feature = "chrono")]
+pub(crate) mod chrono_types;
 pub mod complex_obj;
 pub(crate) mod data_types;
+pub(crate) mod handles;
+#[cfg(feature = "time")]
+pub(crate) mod time_types;
+
+pub use handles::ObjectDedup;
 
 pub const FLAG_USER_TYPE: u16 = 0x0001;
 pub const FLAG_HAS_SCHEMA: u16 = 0x0002;
@@ -21,7 +31,7 @@ pub const COMPLEX_OBJ_HEADER_LEN: i32 = 24;
 
 /// All Data types described in Binary Protocol
 /// https://apacheignite.readme.io/docs/binary-client-protocol-data-format
-#[derive(PartialOrd, PartialEq, Debug)]
+#[derive(PartialOrd, PartialEq, Debug, Clone, Copy)]
 pub enum TypeCode {
     Byte = 1,
     Short = 2,
@@ -32,6 +42,8 @@ pub enum TypeCode {
     Char = 7,
     Bool = 8,
     String = 9,
+    Uuid = 10,
+    Date = 11,
     ArrByte = 12,
     ArrShort = 13,
     ArrInt = 14,
@@ -40,15 +52,29 @@ pub enum TypeCode {
     ArrDouble = 17,
     ArrChar = 18,
     ArrBool = 19,
+    ArrString = 20,
+    ArrUuid = 21,
+    ArrDate = 22,
     ArrObj = 23,
     Collection = 24,
+    Map = 25,
     Decimal = 30,
+    ArrDecimal = 31,
+    Class = 32,
     Timestamp = 33,
+    Proxy = 35,
+    Time = 36,
+    ArrTime = 37,
     WrappedData = 27,
     Enum = 28,
+    ArrEnum = 29,
     TimestampArray = 34,
+    BinaryEnum = 38,
     Null = 101,
+    Handle = 102,
     ComplexObj = 103,
+    /// object serialized by Java's OptimizedMarshaller
+    OptimizedMarshaller = 254,
 }
 
 impl TryFrom<u8> for TypeCode {
@@ -66,6 +92,8 @@ impl TryFrom<u8> for TypeCode {
             8 => Ok(TypeCode::Bool),
             9 => Ok(TypeCode::String),
             28 => Ok(TypeCode::Enum),
+            29 => Ok(TypeCode::ArrEnum),
+            38 => Ok(TypeCode::BinaryEnum),
             12 => Ok(TypeCode::ArrByte),
             13 => Ok(TypeCode::ArrShort),
             14 => Ok(TypeCode::ArrInt),
@@ -77,10 +105,23 @@ impl TryFrom<u8> for TypeCode {
             23 => Ok(TypeCode::ArrObj),
             30 => Ok(TypeCode::Decimal),
             33 => Ok(TypeCode::Timestamp),
+            10 => Ok(TypeCode::Uuid),
+            11 => Ok(TypeCode::Date),
+            20 => Ok(TypeCode::ArrString),
+            21 => Ok(TypeCode::ArrUuid),
+            22 => Ok(TypeCode::ArrDate),
+            31 => Ok(TypeCode::ArrDecimal),
+            32 => Ok(TypeCode::Class),
+            35 => Ok(TypeCode::Proxy),
+            37 => Ok(TypeCode::ArrTime),
+            254 => Ok(TypeCode::OptimizedMarshaller),
+            36 => Ok(TypeCode::Time),
             24 => Ok(TypeCode::Collection),
+            25 => Ok(TypeCode::Map),
             27 => Ok(TypeCode::WrappedData),
             103 => Ok(TypeCode::ComplexObj),
             101 => Ok(TypeCode::Null),
+            102 => Ok(TypeCode::Handle),
             _ => Err(IgniteError::from(
                 format!("Cannot read TypeCode {}", value).as_str(),
             )),
@@ -91,32 +132,54 @@ impl TryFrom<u8> for TypeCode {
 /// Flag of general Response header
 pub(crate) enum Flag {
     Success,
-    Failure { err_msg: String },
-}
-
-fn read_object(reader: &mut impl Read) -> IgniteResult<Option<()>> {
-    let flag = read_u8(reader)?;
-    let code = TypeCode::try_from(flag);
-    let code = code?;
-    match code {
-        TypeCode::Null => Ok(Some(())),
-        _ => Err(IgniteError::from(
-            format!("Cannot read TypeCode {}", flag).as_str(),
-        )),
+    Failure { status: i32, err_msg: String },
+}
+
+/// Reads the byte array and the offset of WrappedData. The type code is already consumed
+pub(crate) fn read_wrapped_bytes(reader: &mut impl Read) -> IgniteResult<(Vec<u8>, usize)> {
+    let len = read_i32(reader)?;
+    if len < 0 {
+        return Err(IgniteError::from(
+            format!("Invalid wrapped data length {}", len).as_str(),
+        ));
+    }
+    let mut data = vec![0u8; len as usize];
+    reader.read_exact(&mut data)?;
+    let offset = read_i32(reader)?;
+    if offset < 0 || offset >= len {
+        return Err(IgniteError::from(
+            format!("Invalid wrapped data offset {} (len {})", offset, len).as_str(),
+        ));
     }
+    Ok((data, offset as usize))
 }
 
-/// Reads data objects that are wrapped in the WrappedData(type code = 27)
+/// Reads data objects that are wrapped in the WrappedData(type code = 27).
+/// Wrapped data is a byte array with an offset of the actual object inside it.
+/// Objects that are not wrapped are read as is. Handles (type code = 102) are resolved
+/// to the objects they reference
 pub fn read_wrapped_data<T: ReadableType>(reader: &mut impl Read) -> IgniteResult<Option<T>> {
+    let pos = handles::position(); // position of the type code within a tracked read
     let type_code = TypeCode::try_from(read_u8(reader)?)?;
     match type_code {
         TypeCode::WrappedData => {
-            read_i32(reader)?; // skip len
-            let value = T::read(reader);
-            read_i32(reader)?; // skip offset
-            value
+            let (data, offset) = read_wrapped_bytes(reader)?;
+            // handles inside of wrapped data are relative to the byte array
+            handles::read_bytes(data, offset, |wrapped| T::read(wrapped))
         }
-        _ => T::read_unwrapped(type_code, reader),
+        TypeCode::Handle => handles::read_handle(reader),
+        _ => match pos {
+            Some(pos) => {
+                handles::register(pos);
+                T::read_unwrapped(type_code, reader)
+            }
+            None if handles::may_contain_handles(&type_code) => {
+                handles::read_root(type_code, reader, |code, root| {
+                    T::read_unwrapped(code, root)
+                })
+            }
+            None => T::read_unwrapped(type_code, reader),
+        },
     }
 }
 
@@ -147,12 +210,106 @@ pub fn read_string(reader: &mut impl Read) -> io::Result<String> {
     match reader.read_exact(new_alloc.as_mut_slice()) {
         Ok(_) => match String::from_utf8(new_alloc) {
             Ok(s) => Ok(s),
-            Err(err) => Err(io::Error::new(ErrorKind::InvalidData, err)),
+            Err(err) => match STRING_DECODING.with(Cell::get) {
+                StringDecoding::Strict => Err(io::Error::new(ErrorKind::InvalidData, err)),
+                StringDecoding::Lossy => Ok(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+                StringDecoding::ModifiedUtf8 => Ok(decode_modified_utf8(err.as_bytes())),
+            },
         },
         Err(err) => Err(err),
     }
 }
 
+/// Defines how strings which are not valid UTF-8 are read
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum StringDecoding {
+    /// Read fails
+    #[default]
+    Strict,
+    /// Invalid sequences are replaced with U+FFFD
+    Lossy,
+    /// Strings are decoded as Java's modified UTF-8 (CESU-8), so surrogate pairs encoded
+    /// separately and the two-byte NUL are read properly. Invalid sequences are replaced with U+FFFD
+    ModifiedUtf8,
+}
+
+thread_local! {
+    static STRING_DECODING: Cell<StringDecoding> = const { Cell::new(StringDecoding::Strict) };
+    static NUMERIC_WIDENING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Reads strings within the closure using provided decoding.
+/// Client's reads use the decoding from ClientConfig, this is required only for the values
+/// which are read lazily, like the fields of DynamicBinaryObject
+pub fn with_string_decoding<T, F: FnOnce() -> T>(decoding: StringDecoding, read_fn: F) -> T {
+    let prev = STRING_DECODING.with(|cell| cell.replace(decoding));
+    // restore the decoding even if the read has panicked
+    struct Restore(StringDecoding);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STRING_DECODING.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(prev);
+    read_fn()
+}
+
+/// Reads numbers within the closure allowing widening conversions, like Java does:
+/// Byte, Short and Char could be read as i32, Int as i64, Float as f64 and so on.
+/// Client's reads use the setting from ClientConfig
+pub fn with_numeric_widening<T, F: FnOnce() -> T>(enabled: bool, read_fn: F) -> T {
+    let prev = NUMERIC_WIDENING.with(|cell| cell.replace(enabled));
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            NUMERIC_WIDENING.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(prev);
+    read_fn()
+}
+
+pub(crate) fn numeric_widening() -> bool {
+    NUMERIC_WIDENING.with(Cell::get)
+}
+
+fn decode_modified_utf8(bytes: &[u8]) -> String {
+    let mut units: Vec<u16> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let (len, init) = match bytes[i] {
+            b @ 0x00..=0x7F => (1, b as u32),
+            b @ 0xC0..=0xDF => (2, (b & 0x1F) as u32),
+            b @ 0xE0..=0xEF => (3, (b & 0x0F) as u32),
+            b @ 0xF0..=0xF7 => (4, (b & 0x07) as u32),
+            _ => (0, 0),
+        };
+        let tail = match bytes.get(i + 1..i + len.max(1)) {
+            Some(tail) if len > 0 && tail.iter().all(|b| b & 0xC0 == 0x80) => tail,
+            _ => {
+                units.push(0xFFFD);
+                i += 1;
+                continue;
+            }
+        };
+        let code_point = tail
+            .iter()
+            .fold(init, |acc, b| (acc << 6) | (b & 0x3F) as u32);
+        match code_point {
+            0..=0xFFFF => units.push(code_point as u16),
+            0x10000..=0x10FFFF => {
+                let c = code_point - 0x10000;
+                units.push(0xD800 | (c >> 10) as u16);
+                units.push(0xDC00 | (c & 0x3FF) as u16);
+            }
+            _ => units.push(0xFFFD),
+        }
+        i += len;
+    }
+    // unpaired surrogates are replaced
+    String::from_utf16_lossy(&units)
+}
+
 pub fn read_bool(reader: &mut impl Read) -> io::Result<bool> {
     let mut new_alloc = [0u8; 1];
     match reader.read_exact(&mut new_alloc[..]) {
@@ -268,6 +425,13 @@ pub fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
     }
 }
 
+/// Reads UUID value, the most significant bits go first
+pub(crate) fn read_uuid(reader: &mut impl Read) -> io::Result<u128> {
+    let msb = read_u64(reader)?;
+    let lsb = read_u64(reader)?;
+    Ok(((msb as u128) << 64) | lsb as u128)
+}
+
 pub fn write_i64(writer: &mut dyn Write, v: i64) -> io::Result<()> {
     writer.write_all(&i64::to_le_bytes(v))?;
     Ok(())
@@ -328,3 +492,128 @@ pub fn write_null(writer: &mut dyn Write) -> io::Result<()> {
     write_u8(writer, TypeCode::Null as u8)?;
     Ok(())
 }
+
+/// Reads the bytes of the whole value without decoding it and appends them to out.
+/// The type code is already consumed, it is appended as well
+pub(crate) fn read_value_bytes(
+    type_code: TypeCode,
+    reader: &mut impl Read,
+    out: &mut Vec<u8>,
+) -> IgniteResult<()> {
+    out.push(type_code as u8);
+    match type_code {
+        TypeCode::Null => {}
+        TypeCode::Byte | TypeCode::Bool => copy_bytes(reader, out, 1)?,
+        TypeCode::Short | TypeCode::Char => copy_bytes(reader, out, 2)?,
+        TypeCode::Int | TypeCode::Float | TypeCode::Handle => copy_bytes(reader, out, 4)?,
+        TypeCode::Long | TypeCode::Double | TypeCode::Date | TypeCode::Time => {
+            copy_bytes(reader, out, 8)?
+        }
+        TypeCode::Timestamp => copy_bytes(reader, out, 12)?,
+        TypeCode::Uuid => copy_bytes(reader, out, 16)?,
+        TypeCode::String
+        | TypeCode::ArrByte
+        | TypeCode::ArrBool
+        | TypeCode::OptimizedMarshaller => {
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, len as usize)?
+        }
+        TypeCode::ArrShort | TypeCode::ArrChar => {
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, len as usize * 2)?
+        }
+        TypeCode::ArrInt | TypeCode::ArrFloat => {
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, len as usize * 4)?
+        }
+        TypeCode::ArrLong | TypeCode::ArrDouble => {
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, len as usize * 8)?
+        }
+        TypeCode::Decimal => {
+            copy_bytes(reader, out, 4)?; // scale
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, len as usize)?
+        }
+        TypeCode::Enum | TypeCode::BinaryEnum => {
+            if copy_i32(reader, out)? == 0 {
+                // unregistered type, class name follows
+                copy_value(reader, out)?;
+            }
+            copy_bytes(reader, out, 4)? // ordinal
+        }
+        TypeCode::Class => {
+            if copy_i32(reader, out)? == 0 {
+                copy_value(reader, out)?;
+            }
+        }
+        TypeCode::ArrString
+        | TypeCode::ArrUuid
+        | TypeCode::ArrDate
+        | TypeCode::ArrDecimal
+        | TypeCode::TimestampArray
+        | TypeCode::ArrTime => {
+            let len = copy_i32(reader, out)?;
+            for _ in 0..len {
+                copy_value(reader, out)?;
+            }
+        }
+        TypeCode::ArrObj | TypeCode::ArrEnum => {
+            if copy_i32(reader, out)? == 0 {
+                copy_value(reader, out)?; // component class name
+            }
+            let len = copy_i32(reader, out)?;
+            for _ in 0..len {
+                copy_value(reader, out)?;
+            }
+        }
+        TypeCode::Collection | TypeCode::Map => {
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, 1)?; // collection type
+            let items = match type_code {
+                TypeCode::Map => len * 2,
+                _ => len,
+            };
+            for _ in 0..items {
+                copy_value(reader, out)?;
+            }
+        }
+        TypeCode::WrappedData => {
+            let len = copy_i32(reader, out)?;
+            copy_bytes(reader, out, len as usize + 4)? // bytes, offset
+        }
+        TypeCode::ComplexObj => {
+            let start = out.len() - 1;
+            copy_bytes(reader, out, 11)?; // version, flags, type id, hash code
+            let len = copy_i32(reader, out)? as usize;
+            let header_read = out.len() - start;
+            if len < header_read {
+                return Err(IgniteError::from("Invalid object length"));
+            }
+            copy_bytes(reader, out, len - header_read)?
+        }
+        TypeCode::Proxy => {
+            return Err(IgniteError::from("Proxy objects could not be read!"));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the type code and the whole value as is
+fn copy_value(reader: &mut impl Read, out: &mut Vec<u8>) -> IgniteResult<()> {
+    let type_code = TypeCode::try_from(read_u8(reader)?)?;
+    read_value_bytes(type_code, reader, out)
+}
+
+fn copy_i32(reader: &mut impl Read, out: &mut Vec<u8>) -> IgniteResult<i32> {
+    let value = read_i32(reader)?;
+    out.extend_from_slice(&value.to_le_bytes());
+    Ok(value)
+}
+
+fn copy_bytes(reader: &mut impl Read, out: &mut Vec<u8>, len: usize) -> IgniteResult<()> {
+    let start = out.len();
+    out.resize(start + len, 0);
+    reader.read_exact(&mut out[start..])?;
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! time crate support. All values are treated as UTC:
+//! * OffsetDateTime is written as Timestamp (type code 33). Nanosecond precision is kept
+//! * Date is written as Date (type code 11), the midnight of the date
+//! * Time is written as Time (type code 36), milliseconds since midnight. Precision is milliseconds
+//!
+//! Dates and times could be read from Timestamp, Date and Time values as well.
+
+use std::io;
+use std::io::{Read, Write};
+
+use time::{Date, OffsetDateTime, Time};
+
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::{read_i32, read_i64, write_i32, write_i64, write_u8, TypeCode};
+use crate::{ReadableType, WritableType};
+
+const NANOS_IN_MILLI: i128 = 1_000_000;
+const MILLIS_IN_DAY: i64 = 86_400_000;
+
+/// Splits the timestamp into milliseconds since epoch and nanoseconds fraction of a millisecond
+fn to_ignite_timestamp(value: OffsetDateTime) -> (i64, i32) {
+    let nanos = value.unix_timestamp_nanos();
+    (
+        nanos.div_euclid(NANOS_IN_MILLI) as i64,
+        nanos.rem_euclid(NANOS_IN_MILLI) as i32,
+    )
+}
+
+fn from_ignite_timestamp(millis: i64, nanos: i32) -> IgniteResult<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * NANOS_IN_MILLI + nanos as i128)
+        .map_err(|err| IgniteError::from(err.to_string().as_str()))
+}
+
+/// Reads any of the date/time values as a timestamp. Time is read as a time on 1 Jan 1970
+fn read_timestamp(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<OffsetDateTime> {
+    match type_code {
+        TypeCode::Timestamp => {
+            let millis = read_i64(reader)?;
+            let nanos = read_i32(reader)?;
+            from_ignite_timestamp(millis, nanos)
+        }
+        TypeCode::Date | TypeCode::Time => from_ignite_timestamp(read_i64(reader)?, 0),
+        _ => Err(IgniteError::from("Expected Timestamp, Date or Time!")),
+    }
+}
+
+impl WritableType for OffsetDateTime {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let (millis, nanos) = to_ignite_timestamp(*self);
+        write_u8(writer, TypeCode::Timestamp as u8)?;
+        write_i64(writer, millis)?;
+        write_i32(writer, nanos)
+    }
+
+    fn size(&self) -> usize {
+        8 + 4 + 1 // millis, nanos, type code
+    }
+}
+
+impl ReadableType for OffsetDateTime {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            _ => read_timestamp(type_code, reader).map(Some),
+        }
+    }
+}
+
+impl WritableType for Date {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let millis = self.midnight().assume_utc().unix_timestamp() * 1000;
+        write_u8(writer, TypeCode::Date as u8)?;
+        write_i64(writer, millis)
+    }
+
+    fn size(&self) -> usize {
+        8 + 1 // millis, type code
+    }
+}
+
+impl ReadableType for Date {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            _ => read_timestamp(type_code, reader).map(|value| Some(value.date())),
+        }
+    }
+}
+
+impl WritableType for Time {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let seconds = self.hour() as i64 * 3600 + self.minute() as i64 * 60 + self.second() as i64;
+        write_u8(writer, TypeCode::Time as u8)?;
+        write_i64(writer, seconds * 1000 + self.millisecond() as i64)
+    }
+
+    fn size(&self) -> usize {
+        8 + 1 // millis, type code
+    }
+}
+
+impl ReadableType for Time {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            TypeCode::Time => {
+                // Java writes the time as an instant on some day, only the time of the day matters
+                let millis = read_i64(reader)?.rem_euclid(MILLIS_IN_DAY);
+                Time::from_hms_milli(
+                    (millis / 3_600_000) as u8,
+                    (millis / 60_000 % 60) as u8,
+                    (millis / 1000 % 60) as u8,
+                    (millis % 1000) as u16,
+                )
+                .map(Some)
+                .map_err(|err| IgniteError::from(err.to_string().as_str()))
+            }
+            _ => read_timestamp(type_code, reader).map(|value| Some(value.time())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let expected = OffsetDateTime::from_unix_timestamp_nanos(-1_500_000_001).unwrap();
+        let mut bytes = vec![];
+        expected.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), expected.size());
+        assert_eq!(bytes[1..9], (-1501i64).to_le_bytes()); // millis are floored
+        assert_eq!(bytes[9..13], 999_999i32.to_le_bytes());
+        let actual = OffsetDateTime::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn test_date_and_time_round_trip() {
+        let date = Date::from_calendar_date(1969, time::Month::July, 20).unwrap();
+        let mut bytes = vec![];
+        date.write(&mut bytes).unwrap();
+        assert_eq!(Date::read(&mut Cursor::new(bytes)).unwrap(), Some(date));
+
+        let time = Time::from_hms_milli(20, 17, 40, 123).unwrap();
+        let mut bytes = vec![];
+        time.write(&mut bytes).unwrap();
+        assert_eq!(Time::read(&mut Cursor::new(bytes)).unwrap(), Some(time));
+    }
+}
@@ -0,0 +1,300 @@
+//! Proxies which the connections with the cluster could be tunneled through
+use std::convert::TryFrom;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_PASSWORD_AUTH: u8 = 2;
+const SOCKS_NO_ACCEPTABLE_AUTH: u8 = 0xff;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+/// Longest header of the HTTP proxy response, which is read
+const MAX_HTTP_HEADER: usize = 8192;
+
+/// Proxy which the TCP connections are opened through. The node addresses are resolved by the proxy
+#[derive(Clone, Debug)]
+pub enum ProxyConfig {
+    /// SOCKS5 proxy, with username and password authentication if the credentials are set
+    Socks5 {
+        addr: String,
+        credentials: Option<(String, String)>,
+    },
+    /// HTTP proxy which supports CONNECT method, with basic authentication if the credentials are set
+    HttpConnect {
+        addr: String,
+        credentials: Option<(String, String)>,
+    },
+}
+
+impl ProxyConfig {
+    /// Address of the proxy as `host:port`
+    pub fn addr(&self) -> &str {
+        match self {
+            ProxyConfig::Socks5 { addr, .. } | ProxyConfig::HttpConnect { addr, .. } => addr,
+        }
+    }
+
+    /// Asks the proxy, which the stream is connected to, to open the tunnel to the target address
+    pub(crate) fn tunnel(&self, stream: &mut (impl Read + Write), target: &str) -> io::Result<()> {
+        match self {
+            ProxyConfig::Socks5 { credentials, .. } => {
+                socks5_tunnel(stream, target, credentials.as_ref())
+            }
+            ProxyConfig::HttpConnect { credentials, .. } => {
+                http_tunnel(stream, target, credentials.as_ref())
+            }
+        }
+    }
+}
+
+fn proxy_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, msg)
+}
+
+/// Splits `host:port` or `[IPv6]:port` address
+fn split_addr(addr: &str) -> io::Result<(&str, u16)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid address {}!", addr),
+        )
+    };
+    let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host, port.parse().map_err(|_| invalid())?))
+}
+
+/// Returns the length of the SOCKS5 field, which may not be longer than 255 bytes
+fn socks5_len(field: &str, value: &str) -> io::Result<u8> {
+    u8::try_from(value.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("SOCKS5 {} is longer than 255 bytes!", field),
+        )
+    })
+}
+
+fn socks5_tunnel(
+    stream: &mut (impl Read + Write),
+    target: &str,
+    credentials: Option<&(String, String)>,
+) -> io::Result<()> {
+    let method = match credentials {
+        Some(_) => SOCKS_PASSWORD_AUTH,
+        None => SOCKS_NO_AUTH,
+    };
+    stream.write_all(&[SOCKS_VERSION, 1, method])?;
+    stream.flush()?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    match reply[1] {
+        SOCKS_NO_AUTH => {}
+        SOCKS_PASSWORD_AUTH if credentials.is_some() => {
+            let (username, password) = credentials.unwrap();
+            let mut auth = vec![1, socks5_len("username", username)?];
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(socks5_len("password", password)?);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth)?;
+            stream.flush()?;
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0 {
+                return Err(proxy_err(
+                    "SOCKS5 proxy has rejected the credentials!".to_owned(),
+                ));
+            }
+        }
+        SOCKS_NO_ACCEPTABLE_AUTH => {
+            return Err(proxy_err(
+                "SOCKS5 proxy requires another authentication method!".to_owned(),
+            ))
+        }
+        other => {
+            return Err(proxy_err(format!(
+                "SOCKS5 proxy has chosen unexpected authentication method {}!",
+                other
+            )))
+        }
+    }
+
+    let (host, port) = split_addr(target)?;
+    let mut req = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        req.push(SOCKS_IPV4);
+        req.extend_from_slice(&ip.octets());
+    } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        req.push(SOCKS_IPV6);
+        req.extend_from_slice(&ip.octets());
+    } else {
+        req.push(SOCKS_DOMAIN);
+        req.push(socks5_len("host", host)?);
+        req.extend_from_slice(host.as_bytes());
+    }
+    req.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&req)?;
+    stream.flush()?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(proxy_err(format!(
+            "SOCKS5 proxy could not connect to {}, reply {}!",
+            target, reply[1]
+        )));
+    }
+    // skip the bound address and port
+    let addr_len = match reply[3] {
+        SOCKS_IPV4 => 4,
+        SOCKS_IPV6 => 16,
+        _ => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+    };
+    stream.read_exact(&mut vec![0u8; addr_len + 2])
+}
+
+fn http_tunnel(
+    stream: &mut (impl Read + Write),
+    target: &str,
+    credentials: Option<&(String, String)>,
+) -> io::Result<()> {
+    let mut req = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if let Some((username, password)) = credentials {
+        let token = base64(format!("{}:{}", username, password).as_bytes());
+        req.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes())?;
+    stream.flush()?;
+
+    // the header is read byte by byte, as the tunneled data follows it
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_HTTP_HEADER {
+            return Err(proxy_err("HTTP proxy response is too long!".to_owned()));
+        }
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte)?;
+        header.push(byte[0]);
+    }
+    let header = String::from_utf8_lossy(&header);
+    let status_line = header.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(proxy_err(format!(
+            "HTTP proxy could not connect to {}: {}",
+            target, status_line
+        ))),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Reads the prepared responses and keeps the requests
+    struct Scripted {
+        responses: Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Write::write(&mut self.requests, buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn scripted(responses: &[u8]) -> Scripted {
+        Scripted {
+            responses: Cursor::new(responses.to_vec()),
+            requests: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_socks5() {
+        let proxy = ProxyConfig::Socks5 {
+            addr: "bastion:1080".to_owned(),
+            credentials: Some(("user".to_owned(), "pw".to_owned())),
+        };
+        let mut stream = scripted(&[5, 2, 1, 0, 5, 0, 0, 1, 10, 0, 0, 1, 0, 80, 42]);
+        proxy.tunnel(&mut stream, "node-1:10800").unwrap();
+        assert_eq!(stream.requests[..3], [5, 1, 2]);
+        assert_eq!(stream.requests[3..12], *b"\x01\x04user\x02pw");
+        assert_eq!(stream.requests[12..17], [5, 1, 0, 3, 6]);
+        assert_eq!(stream.requests[17..], *b"node-1\x2a\x30");
+        assert_eq!(stream.responses.position(), 14); // the tunneled data is not consumed
+
+        let mut refused = scripted(&[5, 0, 5, 5, 0, 1]);
+        assert!(proxy.tunnel(&mut refused, "[::1]:10800").is_err());
+        assert_eq!(refused.requests[6], SOCKS_IPV6);
+
+        let long_host = format!("{}:10800", "a".repeat(256));
+        let err = proxy
+            .tunnel(&mut scripted(&[5, 0]), &long_host)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let long_user = ProxyConfig::Socks5 {
+            addr: "bastion:1080".to_owned(),
+            credentials: Some(("u".repeat(256), "pw".to_owned())),
+        };
+        let mut stream = scripted(&[5, 2]);
+        let err = long_user.tunnel(&mut stream, "node-1:10800").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(stream.requests.len(), 3); // the truncated credentials are not sent
+    }
+
+    #[test]
+    fn test_http_connect() {
+        let proxy = ProxyConfig::HttpConnect {
+            addr: "bastion:3128".to_owned(),
+            credentials: Some(("user".to_owned(), "pass".to_owned())),
+        };
+        let mut stream = scripted(b"HTTP/1.1 200 Connection established\r\n\r\n*");
+        proxy.tunnel(&mut stream, "node-1:10800").unwrap();
+        let req = String::from_utf8(stream.requests).unwrap();
+        assert!(req.starts_with("CONNECT node-1:10800 HTTP/1.1\r\n"));
+        assert!(req.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+        assert_eq!(stream.responses.position(), 39);
+
+        let mut denied = scripted(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n");
+        assert!(proxy.tunnel(&mut denied, "node-1:10800").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Policies which decide if a request is sent again when its connection breaks
+use crate::error::IgniteError;
+
+/// Request which has hit a broken connection
+pub struct RetryContext<'a> {
+    /// Code of the operation in the binary protocol, like 1000 for `get`
+    pub op_code: i16,
+    /// Whether repeating the operation leaves the same data and returns the same result,
+    /// like `put` but not `replace` or `remove_key`
+    pub idempotent: bool,
+    /// Whether the operation changes no data, like `get` or `query_scan`
+    pub read_only: bool,
+    /// Whether the request could have reached the node. If it could not,
+    /// the connection has failed before the request was sent
+    pub sent: bool,
+    /// How many times the request has been sent again already
+    pub retry: u32,
+    pub error: &'a IgniteError,
+}
+
+/// Decides if the request is sent again over a new connection. Consulted at most
+/// `ReconnectPolicy::max_retries` times per request, timed out requests are never sent again
+pub trait RetryPolicy: Send + Sync {
+    fn should_retry(&self, ctx: &RetryContext) -> bool;
+}
+
+/// Sends again the requests which could be repeated safely. The default policy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryIdempotent;
+
+impl RetryPolicy for RetryIdempotent {
+    fn should_retry(&self, ctx: &RetryContext) -> bool {
+        !ctx.sent || ctx.idempotent
+    }
+}
+
+/// Sends again only the requests which change no data
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryReadOnly;
+
+impl RetryPolicy for RetryReadOnly {
+    fn should_retry(&self, ctx: &RetryContext) -> bool {
+        !ctx.sent || ctx.read_only
+    }
+}
+
+/// Sends again all the requests, even if they could have been applied already
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryAll;
+
+impl RetryPolicy for RetryAll {
+    fn should_retry(&self, _: &RetryContext) -> bool {
+        true
+    }
+}
+
+/// Never sends a request again
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryNone;
+
+impl RetryPolicy for RetryNone {
+    fn should_retry(&self, _: &RetryContext) -> bool {
+        false
+    }
+}
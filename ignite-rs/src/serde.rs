@@ -0,0 +1,1057 @@
+//! Serde adapter. Serializes any Serialize type into Ignite binary format and back,
+//! so the types annotated with #[derive(Serialize, Deserialize)] could be stored in caches
+//! without #[derive(IgniteObj)].
+//!
+//! ```ignore
+//! use ignite_rs::serde::Serde;
+//! let cache = client.get_or_create_cache::<String, Serde<MyType>>("my_cache")?;
+//! cache.put(&"key".to_string(), &Serde::new(my_value))?;
+//! ```
+//!
+//! Mapping:
+//! * structs are written as complex objects (type code 103) exactly like #[derive(IgniteObj)] does.
+//!   Type id and field ids are the hash codes of the struct and field names
+//! * unit enum variants are written as Enum (type code 28) with the variant index as an ordinal
+//! * sequences and tuples are written as object arrays (type code 23)
+//! * maps are written as Map (type code 25)
+//! * None and unit are written as Null
+//! * primitives, including unsigned integers, are mapped the same way as WritableType implementations do
+//!
+//! Enum variants with data are not supported as Ignite has no counterpart for them.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+
+use ::serde::de::value::{StringDeserializer, U32Deserializer};
+use ::serde::de::{
+    DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use ::serde::ser::{
+    Impossible, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple, SerializeTupleStruct,
+};
+use ::serde::{de, ser, Serialize};
+
+use crate::binary::get_binary_type;
+use crate::error::{IgniteError, IgniteResult};
+use crate::protocol::complex_obj::{read_complex_obj, ComplexObjectLayout};
+use crate::protocol::*;
+use crate::utils::{bytes_to_java_hashcode, get_schema_id_from_field_ids, string_to_java_hashcode};
+use crate::{ReadableType, WritableType};
+
+/// Map type of the Map (type code 25). Always HashMap
+const HASH_MAP: u8 = 1;
+
+/// Wrapper which allows any Serialize/Deserialize type to be used as cache key or value
+pub struct Serde<T> {
+    value: T,
+    /// Bytes which are written, serialized once by `size` or `write`, whichever is first
+    encoded: OnceLock<Vec<u8>>,
+}
+
+impl<T> Serde<T> {
+    pub fn new(value: T) -> Serde<T> {
+        Serde {
+            value,
+            encoded: OnceLock::new(),
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: Clone> Clone for Serde<T> {
+    fn clone(&self) -> Self {
+        Serde {
+            value: self.value.clone(),
+            encoded: self.encoded.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Serde<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Serde").field(&self.value).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Serde<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Serde<T> {}
+
+impl<T: Hash> Hash for Serde<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
+    }
+}
+
+impl<T: Default> Default for Serde<T> {
+    fn default() -> Self {
+        Serde::new(T::default())
+    }
+}
+
+impl<T: Serialize> Serde<T> {
+    /// Returns the bytes which are written, serializes the value the first time
+    fn encoded(&self) -> io::Result<&[u8]> {
+        if let Some(bytes) = self.encoded.get() {
+            return Ok(bytes);
+        }
+        let bytes = to_bytes(&self.value).map_err(|err| io::Error::other(err.to_string()))?;
+        Ok(self.encoded.get_or_init(|| bytes))
+    }
+}
+
+impl<T: Serialize> WritableType for Serde<T> {
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(self.encoded()?)
+    }
+
+    fn size(&self) -> usize {
+        // the size is known only when the value is serialized, the bytes are kept for the write
+        self.encoded().map_or(0, |bytes| bytes.len())
+    }
+}
+
+impl<T: DeserializeOwned> ReadableType for Serde<T> {
+    fn read_unwrapped(type_code: TypeCode, reader: &mut impl Read) -> IgniteResult<Option<Self>> {
+        match type_code {
+            TypeCode::Null => Ok(None),
+            _ => {
+                let mut deserializer = Deserializer {
+                    reader,
+                    peeked: Some(type_code),
+                };
+                T::deserialize(&mut deserializer).map(|value| Some(Serde::new(value)))
+            }
+        }
+    }
+}
+
+/// Serializes the value into Ignite binary format
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> IgniteResult<Vec<u8>> {
+    let mut serializer = Serializer { output: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Deserializes the value from Ignite binary format
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> IgniteResult<T> {
+    handles::read_bytes(bytes.to_vec(), 0, |reader| {
+        T::deserialize(&mut Deserializer::new(reader))
+    })
+}
+
+/// Writes values into Ignite binary format
+pub struct Serializer {
+    output: Vec<u8>,
+}
+
+impl Serializer {
+    fn write_code(&mut self, type_code: TypeCode) {
+        self.output.push(type_code as u8);
+    }
+
+    /// Writes i32 placeholder and returns its position
+    fn placeholder(&mut self) -> usize {
+        let pos = self.output.len();
+        self.output.extend_from_slice(&0i32.to_le_bytes());
+        pos
+    }
+
+    fn patch_i32(&mut self, pos: usize, value: i32) {
+        self.output[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn unsupported(what: &str) -> IgniteError {
+        IgniteError::from(
+            format!("{} could not be serialized into Ignite binary format", what).as_str(),
+        )
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = IgniteError;
+    type SerializeSeq = ArraySerializer<'a>;
+    type SerializeTuple = ArraySerializer<'a>;
+    type SerializeTupleStruct = ArraySerializer<'a>;
+    type SerializeTupleVariant = Impossible<(), IgniteError>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = StructSerializer<'a>;
+    type SerializeStructVariant = Impossible<(), IgniteError>;
+
+    fn serialize_bool(self, v: bool) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_i8(self, v: i8) -> IgniteResult<()> {
+        self.write_code(TypeCode::Byte);
+        write_i8(&mut self.output, v).map_err(IgniteError::from)
+    }
+
+    fn serialize_i16(self, v: i16) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_i32(self, v: i32) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_i64(self, v: i64) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_i128(self, v: i128) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_u8(self, v: u8) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_u16(self, v: u16) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_u32(self, v: u32) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_u64(self, v: u64) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_f32(self, v: f32) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_f64(self, v: f64) -> IgniteResult<()> {
+        v.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_char(self, v: char) -> IgniteResult<()> {
+        // Ignite's char is a single UTF-16 code unit
+        let code_unit = u16::try_from(v as u32)
+            .map_err(|_| Serializer::unsupported("char outside of the Basic Multilingual Plane"))?;
+        code_unit.write(&mut self.output).map_err(IgniteError::from)
+    }
+
+    fn serialize_str(self, v: &str) -> IgniteResult<()> {
+        write_string_type_code(&mut self.output, v).map_err(IgniteError::from)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> IgniteResult<()> {
+        self.write_code(TypeCode::ArrByte);
+        write_i32(&mut self.output, v.len() as i32)?;
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> IgniteResult<()> {
+        self.write_code(TypeCode::Null);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> IgniteResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> IgniteResult<()> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> IgniteResult<()> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        _: &'static str,
+    ) -> IgniteResult<()> {
+        self.write_code(TypeCode::Enum);
+        write_i32(&mut self.output, string_to_java_hashcode(name))?; // type id
+        write_i32(&mut self.output, variant_index as i32)?; // ordinal
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> IgniteResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: &T,
+    ) -> IgniteResult<()> {
+        Err(Serializer::unsupported(variant))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> IgniteResult<ArraySerializer<'a>> {
+        self.write_code(TypeCode::ArrObj);
+        write_i32(&mut self.output, -1)?; // type id. always -1
+        let len_pos = self.placeholder();
+        Ok(ArraySerializer {
+            ser: self,
+            len_pos,
+            len: 0,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> IgniteResult<ArraySerializer<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        len: usize,
+    ) -> IgniteResult<ArraySerializer<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> IgniteResult<Self::SerializeTupleVariant> {
+        Err(Serializer::unsupported(variant))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> IgniteResult<MapSerializer<'a>> {
+        self.write_code(TypeCode::Map);
+        let len_pos = self.placeholder();
+        self.output.push(HASH_MAP);
+        Ok(MapSerializer {
+            ser: self,
+            len_pos,
+            len: 0,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, _: usize) -> IgniteResult<StructSerializer<'a>> {
+        let start = self.output.len();
+        self.write_code(TypeCode::ComplexObj);
+        self.output.push(1); // version. always 1
+        write_u16(&mut self.output, FLAG_USER_TYPE | FLAG_HAS_SCHEMA)?; // flags
+        write_i32(&mut self.output, string_to_java_hashcode(name))?; // type id
+        self.placeholder(); // hash code
+        self.placeholder(); // length. including header
+        self.placeholder(); // schema id
+        self.placeholder(); // schema offset
+        Ok(StructSerializer {
+            ser: self,
+            start,
+            fields: Vec::new(),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> IgniteResult<Self::SerializeStructVariant> {
+        Err(Serializer::unsupported(variant))
+    }
+}
+
+/// Writes sequences and tuples as object arrays
+pub struct ArraySerializer<'a> {
+    ser: &'a mut Serializer,
+    len_pos: usize,
+    len: i32,
+}
+
+impl ArraySerializer<'_> {
+    fn add<T: Serialize + ?Sized>(&mut self, value: &T) -> IgniteResult<()> {
+        value.serialize(&mut *self.ser)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> IgniteResult<()> {
+        self.ser.patch_i32(self.len_pos, self.len);
+        Ok(())
+    }
+}
+
+impl SerializeSeq for ArraySerializer<'_> {
+    type Ok = ();
+    type Error = IgniteError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> IgniteResult<()> {
+        self.add(value)
+    }
+
+    fn end(self) -> IgniteResult<()> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for ArraySerializer<'_> {
+    type Ok = ();
+    type Error = IgniteError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> IgniteResult<()> {
+        self.add(value)
+    }
+
+    fn end(self) -> IgniteResult<()> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for ArraySerializer<'_> {
+    type Ok = ();
+    type Error = IgniteError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> IgniteResult<()> {
+        self.add(value)
+    }
+
+    fn end(self) -> IgniteResult<()> {
+        self.finish()
+    }
+}
+
+/// Writes maps as Map (type code 25)
+pub struct MapSerializer<'a> {
+    ser: &'a mut Serializer,
+    len_pos: usize,
+    len: i32,
+}
+
+impl SerializeMap for MapSerializer<'_> {
+    type Ok = ();
+    type Error = IgniteError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> IgniteResult<()> {
+        key.serialize(&mut *self.ser)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> IgniteResult<()> {
+        value.serialize(&mut *self.ser)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn end(self) -> IgniteResult<()> {
+        self.ser.patch_i32(self.len_pos, self.len);
+        Ok(())
+    }
+}
+
+/// Writes structs as complex objects
+pub struct StructSerializer<'a> {
+    ser: &'a mut Serializer,
+    /// position of the object's type code
+    start: usize,
+    /// field ids and offsets from the object's start
+    fields: Vec<(i32, i32)>,
+}
+
+impl SerializeStruct for StructSerializer<'_> {
+    type Ok = ();
+    type Error = IgniteError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> IgniteResult<()> {
+        let offset = (self.ser.output.len() - self.start) as i32;
+        value.serialize(&mut *self.ser)?;
+        self.fields.push((string_to_java_hashcode(key), offset));
+        Ok(())
+    }
+
+    fn end(self) -> IgniteResult<()> {
+        let fields_start = self.start + COMPLEX_OBJ_HEADER_LEN as usize;
+        let schema_offset = (self.ser.output.len() - self.start) as i32;
+        let hash_code = bytes_to_java_hashcode(&self.ser.output[fields_start..]);
+        for (field_id, offset) in self.fields.iter() {
+            write_i32(&mut self.ser.output, *field_id)?;
+            write_i32(&mut self.ser.output, *offset)?;
+        }
+        let field_ids: Vec<i32> = self.fields.iter().map(|(id, _)| *id).collect();
+        let len = (self.ser.output.len() - self.start) as i32;
+
+        self.ser.patch_i32(self.start + 8, hash_code); // offset 8
+        self.ser.patch_i32(self.start + 12, len); // offset 12
+        self.ser
+            .patch_i32(self.start + 16, get_schema_id_from_field_ids(&field_ids)); // offset 16
+        self.ser.patch_i32(self.start + 20, schema_offset); // offset 20
+        Ok(())
+    }
+}
+
+/// Reads values from Ignite binary format
+pub struct Deserializer<'r, R: Read> {
+    reader: &'r mut R,
+    /// type code that is already consumed
+    peeked: Option<TypeCode>,
+}
+
+impl<'r, R: Read> Deserializer<'r, R> {
+    pub fn new(reader: &'r mut R) -> Deserializer<'r, R> {
+        Deserializer {
+            reader,
+            peeked: None,
+        }
+    }
+
+    /// Consumes the type code of the next value
+    fn next_code(&mut self) -> IgniteResult<TypeCode> {
+        if let Some(type_code) = self.peeked.take() {
+            return Ok(type_code);
+        }
+        let pos = handles::position(); // position of the type code within a tracked read
+        let type_code = TypeCode::try_from(read_u8(self.reader)?)?;
+        match (pos, type_code) {
+            (_, TypeCode::Handle) | (_, TypeCode::WrappedData) => {}
+            (Some(pos), _) => handles::register(pos),
+            (None, _) => {}
+        }
+        Ok(type_code)
+    }
+
+    fn peek_code(&mut self) -> IgniteResult<TypeCode> {
+        let type_code = self.next_code()?;
+        self.peeked = Some(type_code);
+        Ok(type_code)
+    }
+
+    /// Reads the value referenced by a handle or wrapped into WrappedData
+    fn indirect<T, F>(&mut self, type_code: TypeCode, read_fn: F) -> IgniteResult<T>
+    where
+        F: FnOnce(&mut Deserializer<handles::ObjectReader>) -> IgniteResult<T>,
+    {
+        match type_code {
+            TypeCode::Handle => handles::read_handle_with(self.reader, |reader| {
+                read_fn(&mut Deserializer::new(reader))
+            }),
+            TypeCode::WrappedData => {
+                let (data, offset) = read_wrapped_bytes(self.reader)?;
+                handles::read_bytes(data, offset, |reader| {
+                    read_fn(&mut Deserializer::new(reader))
+                })
+            }
+            _ => Err(IgniteError::from(
+                format!("Unexpected type code {:?}", type_code).as_str(),
+            )),
+        }
+    }
+
+    fn read_struct<'de, V: Visitor<'de>>(
+        &mut self,
+        fields: Option<&'static [&'static str]>,
+        visitor: V,
+    ) -> IgniteResult<V::Value> {
+        read_complex_obj(self.reader, |layout| {
+            let fields: Vec<(String, i32)> = match fields {
+                Some(names) => names
+                    .iter()
+                    .map(|name| (name.to_string(), string_to_java_hashcode(name)))
+                    .collect(),
+                None => match get_binary_type(layout.type_id) {
+                    Some(binary_type) => binary_type
+                        .fields
+                        .iter()
+                        .map(|field| (field.name.clone(), field.field_id))
+                        .collect(),
+                    None => {
+                        return Err(IgniteError::from(
+                            format!("Field names of type {} are unknown", layout.type_id).as_str(),
+                        ))
+                    }
+                },
+            };
+            let present = layout.field_ids();
+            visitor.visit_map(StructAccess {
+                layout,
+                fields: fields
+                    .into_iter()
+                    .filter(|(_, id)| present.contains(id))
+                    .collect(),
+                next: 0,
+            })
+        })
+    }
+}
+
+impl<'de, R: Read> de::Deserializer<'de> for &mut Deserializer<'_, R> {
+    type Error = IgniteError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> IgniteResult<V::Value> {
+        let type_code = self.next_code()?;
+        match type_code {
+            TypeCode::Null => visitor.visit_unit(),
+            TypeCode::Byte => visitor.visit_i8(read_i8(self.reader)?),
+            TypeCode::Short => visitor.visit_i16(read_i16(self.reader)?),
+            TypeCode::Int => visitor.visit_i32(read_i32(self.reader)?),
+            TypeCode::Long => visitor.visit_i64(read_i64(self.reader)?),
+            TypeCode::Float => visitor.visit_f32(read_f32(self.reader)?),
+            TypeCode::Double => visitor.visit_f64(read_f64(self.reader)?),
+            TypeCode::Bool => visitor.visit_bool(read_bool(self.reader)?),
+            TypeCode::Char => {
+                let code_unit = read_u16(self.reader)?;
+                match char::from_u32(code_unit as u32) {
+                    Some(ch) => visitor.visit_char(ch),
+                    None => visitor.visit_u16(code_unit),
+                }
+            }
+            TypeCode::String => visitor.visit_string(read_string(self.reader)?),
+            TypeCode::Decimal => match i128::read_unwrapped(type_code, self.reader)? {
+                Some(value) => visitor.visit_i128(value),
+                None => visitor.visit_unit(),
+            },
+            TypeCode::ArrByte
+            | TypeCode::ArrShort
+            | TypeCode::ArrInt
+            | TypeCode::ArrLong
+            | TypeCode::ArrFloat
+            | TypeCode::ArrDouble
+            | TypeCode::ArrChar
+            | TypeCode::ArrBool => {
+                let item_code = match type_code {
+                    TypeCode::ArrByte => TypeCode::Byte,
+                    TypeCode::ArrShort => TypeCode::Short,
+                    TypeCode::ArrInt => TypeCode::Int,
+                    TypeCode::ArrLong => TypeCode::Long,
+                    TypeCode::ArrFloat => TypeCode::Float,
+                    TypeCode::ArrDouble => TypeCode::Double,
+                    TypeCode::ArrChar => TypeCode::Char,
+                    _ => TypeCode::Bool,
+                };
+                let len = read_i32(self.reader)?;
+                visitor.visit_seq(ArrayAccess {
+                    de: self,
+                    item_code: Some(item_code),
+                    remaining: len,
+                })
+            }
+            TypeCode::ArrObj | TypeCode::ArrEnum => {
+                read_i32(self.reader)?; // ignore type id
+                let len = read_i32(self.reader)?;
+                visitor.visit_seq(ArrayAccess {
+                    de: self,
+                    item_code: None,
+                    remaining: len,
+                })
+            }
+            TypeCode::Collection => {
+                let len = read_i32(self.reader)?;
+                read_i8(self.reader)?; // ignore collection type
+                visitor.visit_seq(ArrayAccess {
+                    de: self,
+                    item_code: None,
+                    remaining: len,
+                })
+            }
+            TypeCode::Map => {
+                let len = read_i32(self.reader)?;
+                read_i8(self.reader)?; // ignore map type
+                visitor.visit_map(EntriesAccess {
+                    de: self,
+                    remaining: len,
+                })
+            }
+            TypeCode::Enum | TypeCode::BinaryEnum => {
+                read_i32(self.reader)?; // ignore type id
+                visitor.visit_i32(read_i32(self.reader)?)
+            }
+            TypeCode::ComplexObj => self.read_struct(None, visitor),
+            TypeCode::Handle | TypeCode::WrappedData => {
+                self.indirect(type_code, |de| de.deserialize_any(visitor))
+            }
+            _ => Err(IgniteError::from(
+                format!(
+                    "Type code {:?} is not supported by serde adapter",
+                    type_code
+                )
+                .as_str(),
+            )),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> IgniteResult<V::Value> {
+        match self.peek_code()? {
+            TypeCode::Byte => {
+                self.next_code()?;
+                visitor.visit_u8(read_u8(self.reader)?)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> IgniteResult<V::Value> {
+        match self.peek_code()? {
+            TypeCode::Char => {
+                self.next_code()?;
+                visitor.visit_u16(read_u16(self.reader)?)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> IgniteResult<V::Value> {
+        match self.peek_code()? {
+            TypeCode::Null => {
+                self.next_code()?;
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> IgniteResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> IgniteResult<V::Value> {
+        match self.peek_code()? {
+            TypeCode::ArrByte => {
+                self.next_code()?;
+                let len = read_i32(self.reader)?;
+                let mut bytes = vec![0u8; len as usize];
+                self.reader.read_exact(&mut bytes)?;
+                visitor.visit_byte_buf(bytes)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> IgniteResult<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> IgniteResult<V::Value> {
+        let type_code = self.next_code()?;
+        match type_code {
+            TypeCode::ComplexObj => self.read_struct(Some(fields), visitor),
+            TypeCode::Handle | TypeCode::WrappedData => self.indirect(type_code, |de| {
+                de::Deserializer::deserialize_struct(de, name, fields, visitor)
+            }),
+            _ => Err(IgniteError::from(
+                format!("Expected Complex Object, got {:?}", type_code).as_str(),
+            )),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> IgniteResult<V::Value> {
+        let type_code = self.next_code()?;
+        match type_code {
+            TypeCode::Enum | TypeCode::BinaryEnum => {
+                let type_id = read_i32(self.reader)?;
+                if type_id != string_to_java_hashcode(name) {
+                    return Err(IgniteError::from(
+                        format!(
+                            "Type ID mismatch: expected {}, got {}",
+                            string_to_java_hashcode(name),
+                            type_id
+                        )
+                        .as_str(),
+                    ));
+                }
+                let ordinal = read_i32(self.reader)?;
+                visitor.visit_enum(UnitVariantAccess { ordinal })
+            }
+            TypeCode::Handle | TypeCode::WrappedData => self.indirect(type_code, |de| {
+                de::Deserializer::deserialize_enum(de, name, variants, visitor)
+            }),
+            _ => Err(IgniteError::from(
+                format!("Expected Enum, got {:?}", type_code).as_str(),
+            )),
+        }
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u32 u64 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+/// Reads items of arrays and collections
+struct ArrayAccess<'a, 'r, R: Read> {
+    de: &'a mut Deserializer<'r, R>,
+    /// type code of items of primitive arrays. Such items have no own type codes
+    item_code: Option<TypeCode>,
+    remaining: i32,
+}
+
+impl<'de, R: Read> SeqAccess<'de> for ArrayAccess<'_, '_, R> {
+    type Error = IgniteError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> IgniteResult<Option<T::Value>> {
+        if self.remaining <= 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.de.peeked = self.item_code;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.max(0) as usize)
+    }
+}
+
+/// Reads entries of maps
+struct EntriesAccess<'a, 'r, R: Read> {
+    de: &'a mut Deserializer<'r, R>,
+    remaining: i32,
+}
+
+impl<'de, R: Read> MapAccess<'de> for EntriesAccess<'_, '_, R> {
+    type Error = IgniteError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> IgniteResult<Option<K::Value>> {
+        if self.remaining <= 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> IgniteResult<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.max(0) as usize)
+    }
+}
+
+/// Reads fields of complex objects. Fields that are absent in the object are skipped
+struct StructAccess<'a> {
+    layout: &'a ComplexObjectLayout,
+    /// names and ids of the fields to read
+    fields: Vec<(String, i32)>,
+    next: usize,
+}
+
+impl<'de> MapAccess<'de> for StructAccess<'_> {
+    type Error = IgniteError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> IgniteResult<Option<K::Value>> {
+        match self.fields.get(self.next) {
+            None => Ok(None),
+            Some((name, _)) => {
+                let name: StringDeserializer<IgniteError> = name.clone().into_deserializer();
+                seed.deserialize(name).map(Some)
+            }
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> IgniteResult<V::Value> {
+        let (name, field_id) = &self.fields[self.next];
+        self.next += 1;
+        self.layout
+            .read_field_with(*field_id, |reader| {
+                seed.deserialize(&mut Deserializer::new(reader))
+            })?
+            .ok_or_else(|| IgniteError::from(format!("Field {} is missing!", name).as_str()))
+    }
+}
+
+/// Unit enum variant identified by its ordinal
+struct UnitVariantAccess {
+    ordinal: i32,
+}
+
+impl<'de> EnumAccess<'de> for UnitVariantAccess {
+    type Error = IgniteError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> IgniteResult<(V::Value, Self)> {
+        let ordinal =
+            u32::try_from(self.ordinal).map_err(|_| IgniteError::from("Negative enum ordinal"))?;
+        let ordinal: U32Deserializer<IgniteError> = ordinal.into_deserializer();
+        let variant = seed.deserialize(ordinal)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for UnitVariantAccess {
+    type Error = IgniteError;
+
+    fn unit_variant(self) -> IgniteResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, _: T) -> IgniteResult<T::Value> {
+        Err(IgniteError::from("Only unit enum variants are supported"))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, _: V) -> IgniteResult<V::Value> {
+        Err(IgniteError::from("Only unit enum variants are supported"))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        _: V,
+    ) -> IgniteResult<V::Value> {
+        Err(IgniteError::from("Only unit enum variants are supported"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::serde::Deserialize;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+    enum Status {
+        Active,
+        Blocked,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Inner {
+        id: i32,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Outer {
+        name: String,
+        age: Option<i64>,
+        score: f64,
+        flag: bool,
+        status: Status,
+        tags: Vec<String>,
+        bytes: Vec<u8>,
+        inner: Inner,
+        pair: (i16, char),
+        attrs: HashMap<String, i32>,
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        let mut attrs = HashMap::new();
+        attrs.insert("a".to_string(), 1);
+        let value = Outer {
+            name: "bob".to_string(),
+            age: None,
+            score: 1.5,
+            flag: true,
+            status: Status::Blocked,
+            tags: vec!["x".to_string(), "y".to_string()],
+            bytes: vec![1, 200],
+            inner: Inner { id: 7 },
+            pair: (-3, 'z'),
+            attrs,
+        };
+        let bytes = to_bytes(&value).unwrap();
+        assert_eq!(bytes[0], TypeCode::ComplexObj as u8);
+        let actual: Outer = from_bytes(&bytes).unwrap();
+        assert_eq!(actual, value);
+    }
+
+    #[test]
+    fn test_read_handle() {
+        // array of two equal objects, the second one is a handle to the first one
+        let inner = to_bytes(&Inner { id: 42 }).unwrap();
+        let mut bytes = vec![TypeCode::ArrObj as u8];
+        write_i32(&mut bytes, -1).unwrap(); // type id
+        write_i32(&mut bytes, 2).unwrap(); // len
+        let first_pos = bytes.len();
+        bytes.extend_from_slice(&inner);
+        let handle_pos = bytes.len();
+        bytes.push(TypeCode::Handle as u8);
+        write_i32(&mut bytes, (handle_pos - first_pos) as i32).unwrap();
+
+        let actual: Vec<Inner> = from_bytes(&bytes).unwrap();
+        assert_eq!(actual, vec![Inner { id: 42 }, Inner { id: 42 }]);
+    }
+
+    #[test]
+    fn test_wrapper_reads_derived_layout() {
+        let value = Serde::new(Inner { id: 3 });
+        let mut bytes = vec![];
+        value.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), value.size());
+        // the same layout as #[derive(IgniteObj)] produces
+        assert_eq!(
+            bytes[2..4],
+            (FLAG_USER_TYPE | FLAG_HAS_SCHEMA).to_le_bytes()
+        );
+        assert_eq!(bytes[4..8], string_to_java_hashcode("Inner").to_le_bytes());
+        let actual = Serde::<Inner>::read(&mut io::Cursor::new(bytes)).unwrap();
+        assert_eq!(actual, Some(value));
+    }
+
+    #[test]
+    fn test_wrapper_serializes_once() {
+        static SERIALIZATIONS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted;
+
+        impl Serialize for Counted {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                SERIALIZATIONS.fetch_add(1, Ordering::SeqCst);
+                serializer.serialize_i32(7)
+            }
+        }
+
+        let value = Serde::new(Counted);
+        let size = value.size();
+        let mut bytes = vec![];
+        value.write(&mut bytes).unwrap();
+        // the size and the write share the serialized bytes
+        assert_eq!(SERIALIZATIONS.load(Ordering::SeqCst), 1);
+        assert_eq!(bytes.len(), size);
+        assert_eq!(i32::read(&mut io::Cursor::new(bytes)).unwrap(), Some(7));
+    }
+}
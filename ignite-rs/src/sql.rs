@@ -0,0 +1,293 @@
+//! SQL fields queries, which return the rows of the selected columns.
+//! The rows are fetched by pages over the connection which has opened the cursor,
+//! and the cursor is closed on the node when the iterator is done or dropped
+//!
+//! ```ignore
+//! let query = SqlFieldsQuery::new("SELECT name, age FROM Person WHERE age > ?").arg(30);
+//! for row in people.query_sql_fields(&query)? {
+//!     let row = row?;
+//!     let name: Option<String> = row.get(0)?;
+//! }
+//! ```
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::sql::{SqlFieldsPageResp, SqlFieldsReq, SqlFieldsResp, StatementType};
+use crate::api::OpCode;
+use crate::cache::DFLT_PAGE_SIZE;
+use crate::connection::{with_op_timeout, with_pinned, Connection, Pinned};
+use crate::error::{IgniteError, IgniteResult};
+use crate::{AnyValue, ReadableType, WritableType};
+
+/// SQL statement with its arguments and options
+///
+/// ```ignore
+/// let query = SqlFieldsQuery::new("SELECT * FROM City WHERE country = ?")
+///     .arg("France".to_owned())
+///     .schema("PUBLIC")
+///     .page_size(100);
+/// ```
+pub struct SqlFieldsQuery {
+    sql: String,
+    args: Vec<Box<dyn WritableType + Send + Sync>>,
+    schema: Option<String>,
+    page_size: i32,
+    max_rows: Option<i32>,
+    timeout: Option<Duration>,
+    distributed_joins: bool,
+    enforce_join_order: bool,
+    collocated: bool,
+    lazy: bool,
+}
+
+impl fmt::Debug for SqlFieldsQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqlFieldsQuery")
+            .field("sql", &self.sql)
+            .field("args", &self.args.len())
+            .field("schema", &self.schema)
+            .field("page_size", &self.page_size)
+            .finish()
+    }
+}
+
+impl SqlFieldsQuery {
+    pub fn new(sql: &str) -> SqlFieldsQuery {
+        SqlFieldsQuery {
+            sql: sql.to_owned(),
+            args: Vec::new(),
+            schema: None,
+            page_size: DFLT_PAGE_SIZE,
+            max_rows: None,
+            timeout: None,
+            distributed_joins: false,
+            enforce_join_order: false,
+            collocated: false,
+            lazy: false,
+        }
+    }
+
+    /// Adds the value of the next `?` of the statement
+    pub fn arg(mut self, value: impl WritableType + Send + Sync + 'static) -> SqlFieldsQuery {
+        self.args.push(Box::new(value));
+        self
+    }
+
+    /// Sets the schema of the tables, like "PUBLIC". The schema of the cache
+    /// which runs the query is used by default
+    pub fn schema(mut self, schema: &str) -> SqlFieldsQuery {
+        self.schema = Some(schema.to_owned());
+        self
+    }
+
+    /// Sets the number of the rows which are fetched at once, 1024 by default
+    pub fn page_size(mut self, page_size: i32) -> SqlFieldsQuery {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Limits the number of the rows, no limit by default
+    pub fn max_rows(mut self, max_rows: i32) -> SqlFieldsQuery {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Sets the time limit of the query on the nodes, none by default
+    pub fn timeout(mut self, timeout: Duration) -> SqlFieldsQuery {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Allows the joins of the data which is not collocated
+    pub fn distributed_joins(mut self, distributed_joins: bool) -> SqlFieldsQuery {
+        self.distributed_joins = distributed_joins;
+        self
+    }
+
+    /// Joins the tables in the order of the statement
+    pub fn enforce_join_order(mut self, enforce_join_order: bool) -> SqlFieldsQuery {
+        self.enforce_join_order = enforce_join_order;
+        self
+    }
+
+    /// Tells the nodes that the data of the GROUP BY is collocated
+    pub fn collocated(mut self, collocated: bool) -> SqlFieldsQuery {
+        self.collocated = collocated;
+        self
+    }
+
+    /// Fetches the rows on the nodes by pages too, so large results do not take their memory
+    pub fn lazy(mut self, lazy: bool) -> SqlFieldsQuery {
+        self.lazy = lazy;
+        self
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// Returns the request of the query, sent with the id of the cache or 0
+    pub(crate) fn req(&self, cache_id: i32) -> SqlFieldsReq<'_> {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| &**arg as &dyn WritableType)
+            .collect();
+        SqlFieldsReq {
+            schema: self.schema.as_deref(),
+            page_size: self.page_size,
+            max_rows: self.max_rows.unwrap_or(-1),
+            distributed_joins: self.distributed_joins,
+            enforce_join_order: self.enforce_join_order,
+            collocated: self.collocated,
+            lazy: self.lazy,
+            timeout_ms: self.timeout.map_or(0, |timeout| timeout.as_millis() as i64),
+            ..SqlFieldsReq::new(cache_id, &self.sql, args, StatementType::Any)
+        }
+    }
+}
+
+/// Row of the query, with the values of the columns in the order of the statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlRow {
+    columns: Arc<Vec<String>>,
+    values: Vec<AnyValue>,
+}
+
+impl SqlRow {
+    pub(crate) fn new(columns: Arc<Vec<String>>, values: Vec<AnyValue>) -> SqlRow {
+        SqlRow { columns, values }
+    }
+
+    /// Names of the columns, as the nodes return them, like "NAME"
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[AnyValue] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<AnyValue> {
+        self.values
+    }
+
+    /// Returns the value of the column, None if the index is out of range
+    pub fn value(&self, index: usize) -> Option<&AnyValue> {
+        self.values.get(index)
+    }
+
+    /// Returns the value of the column as the type, None if it is NULL.
+    /// Fails if the index is out of range or the value could not be read as the type
+    ///
+    /// ```ignore
+    /// let age: Option<i32> = row.get(1)?;
+    /// ```
+    pub fn get<T: ReadableType>(&self, index: usize) -> IgniteResult<Option<T>> {
+        let value = self.values.get(index).ok_or_else(|| {
+            IgniteError::from(
+                format!("Row has {} columns, not {}", self.values.len(), index + 1).as_str(),
+            )
+        })?;
+        let mut bytes = Vec::with_capacity(value.size());
+        value.write(&mut bytes)?;
+        T::read(&mut bytes.as_slice())
+    }
+
+    /// Returns the value of the column with the name, which is not case-sensitive, see `get`
+    pub fn get_by_name<T: ReadableType>(&self, name: &str) -> IgniteResult<Option<T>> {
+        let index = self
+            .columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+            .ok_or_else(|| IgniteError::from(format!("Row has no column {:?}", name).as_str()))?;
+        self.get(index)
+    }
+}
+
+/// Iterator over the rows of the query, see `Cache::query_sql_fields`. Yields the error
+/// if a page could not be fetched, and ends after it
+pub struct SqlFieldsCursor {
+    conn: Arc<Connection>,
+    pinned: Option<Arc<Pinned>>,
+    timeout: Option<Duration>,
+    columns: Arc<Vec<String>>,
+    page: std::vec::IntoIter<Vec<AnyValue>>,
+    /// Set while the node keeps more pages
+    cursor_id: Option<i64>,
+    done: bool,
+}
+
+impl SqlFieldsCursor {
+    /// Runs the query and returns the cursor of its first page
+    pub(crate) fn open(
+        conn: Arc<Connection>,
+        pinned: Option<Arc<Pinned>>,
+        timeout: Option<Duration>,
+        cache_id: i32,
+        query: &SqlFieldsQuery,
+    ) -> IgniteResult<SqlFieldsCursor> {
+        let resp: SqlFieldsResp = with_pinned(pinned.as_ref(), || {
+            with_op_timeout(timeout, || {
+                conn.send_and_read(OpCode::QuerySqlFields, query.req(cache_id))
+            })
+        })?;
+        Ok(SqlFieldsCursor {
+            conn,
+            pinned,
+            timeout,
+            columns: Arc::new(resp.columns),
+            page: resp.rows.into_iter(),
+            cursor_id: resp.cursor_id,
+            done: false,
+        })
+    }
+
+    /// Names of the columns, as the nodes return them, like "NAME"
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn fetch(&mut self, cursor_id: i64) -> IgniteResult<()> {
+        let conn = &self.conn;
+        let page: SqlFieldsPageResp = with_pinned(self.pinned.as_ref(), || {
+            let columns = self.columns.len();
+            with_op_timeout(self.timeout, || {
+                conn.cursor_page_with(cursor_id, |reader| SqlFieldsPageResp::read(reader, columns))
+            })
+        })?;
+        self.cursor_id = Some(cursor_id).filter(|_| page.more);
+        self.page = page.rows.into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for SqlFieldsCursor {
+    type Item = IgniteResult<SqlRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(values) = self.page.next() {
+                return Some(Ok(SqlRow::new(self.columns.clone(), values)));
+            }
+            let cursor_id = match self.cursor_id {
+                Some(cursor_id) if !self.done => cursor_id,
+                _ => return None,
+            };
+            if let Err(err) = self.fetch(cursor_id) {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl Drop for SqlFieldsCursor {
+    fn drop(&mut self) {
+        if let Some(cursor_id) = self.cursor_id.take() {
+            let conn = &self.conn;
+            let _ = with_pinned(self.pinned.as_ref(), || conn.close_cursor(cursor_id));
+        }
+    }
+}
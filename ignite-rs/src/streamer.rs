@@ -0,0 +1,149 @@
+//! Buffered loading of the entries, like the data streamer of Ignite
+use std::time::{Duration, Instant};
+
+use crate::cache::Cache;
+use crate::error::IgniteResult;
+use crate::{ReadableType, WritableType};
+
+/// Number of the entries which `CacheWriter` puts at once by default
+const DFLT_BUFFER_SIZE: usize = 512;
+
+/// Buffers the entries and puts them into the cache in batches, see `Cache::writer`.
+/// The batch is put when the buffer is full or the flush interval has passed since the last one,
+/// and the rest of the entries are put by `flush`, `close` or when the writer is dropped
+pub struct CacheWriter<K: WritableType + ReadableType, V: WritableType + ReadableType> {
+    cache: Cache<K, V>,
+    buffer: Vec<(K, V)>,
+    buffer_size: usize,
+    flush_interval: Option<Duration>,
+    allow_overwrite: bool,
+    last_flush: Instant,
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> CacheWriter<K, V> {
+    pub(crate) fn new(cache: Cache<K, V>) -> CacheWriter<K, V> {
+        CacheWriter {
+            cache,
+            buffer: Vec::new(),
+            buffer_size: DFLT_BUFFER_SIZE,
+            flush_interval: None,
+            allow_overwrite: true,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Sets the number of the entries which are put in a single request. 512 by default
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Puts the buffered entries on `add` once the interval has passed since the last batch,
+    /// so the entries of a slow source do not wait for the buffer to fill up. Not set by default
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = Some(flush_interval);
+        self
+    }
+
+    /// Whether the entries replace the values of the keys which are in the cache. True by default.
+    /// Otherwise the entries are put one by one with `put_if_absent`, which is much slower
+    pub fn allow_overwrite(mut self, allow_overwrite: bool) -> Self {
+        self.allow_overwrite = allow_overwrite;
+        self
+    }
+
+    /// Adds the entry to the buffer and puts the batch if the buffer is full
+    /// or the flush interval has passed
+    pub fn add(&mut self, key: K, value: V) -> IgniteResult<()> {
+        self.buffer.push((key, value));
+        let interval_passed = self
+            .flush_interval
+            .is_some_and(|interval| self.last_flush.elapsed() >= interval);
+        if self.buffer.len() >= self.buffer_size || interval_passed {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Puts the buffered entries. The entries stay in the buffer if the request fails,
+    /// so the next `flush` sends them again
+    pub fn flush(&mut self) -> IgniteResult<()> {
+        if !self.buffer.is_empty() {
+            if self.allow_overwrite {
+                self.cache
+                    .put_all(self.buffer.iter().map(|(key, value)| (key, value)))?;
+            } else {
+                for (key, value) in &self.buffer {
+                    self.cache.put_if_absent(key, value)?;
+                }
+            }
+            self.buffer.clear();
+        }
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Puts the buffered entries and returns the error, unlike dropping the writer
+    pub fn close(mut self) -> IgniteResult<()> {
+        self.flush()
+    }
+}
+
+impl<K: WritableType + ReadableType, V: WritableType + ReadableType> Drop for CacheWriter<K, V> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::OpCode;
+    use crate::connection::tests::{read_req, write_handshake_resp, write_success};
+    use crate::connection::Connection;
+    use crate::ClientConfig;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_cache_writer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = ClientConfig::new(&listener.local_addr().unwrap().to_string());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_req(&mut stream);
+            write_handshake_resp(&mut stream);
+            let mut requests = Vec::new();
+            for _ in 0..4 {
+                let (op_code, request_id) = read_req(&mut stream);
+                requests.push(op_code);
+                // put_if_absent returns whether the entry is put
+                match op_code == OpCode::CachePutIfAbsent as i16 {
+                    true => write_success(&mut stream, request_id, &[1]),
+                    false => write_success(&mut stream, request_id, &[]),
+                }
+            }
+            requests
+        });
+
+        let conn = Arc::new(Connection::new(&conf).unwrap());
+        let cache = Cache::<i32, i32>::new(1, "test".to_owned(), conn);
+        let mut writer = cache.writer().buffer_size(2);
+        for i in 0..3 {
+            writer.add(i, i).unwrap();
+        }
+        writer.close().unwrap();
+        let mut writer = cache.writer().allow_overwrite(false);
+        writer.add(3, 3).unwrap();
+        writer.add(4, 4).unwrap();
+        // the rest of the entries are put when the writer is dropped
+        drop(writer);
+        let put_all = OpCode::CachePutAll as i16;
+        let put_if_absent = OpCode::CachePutIfAbsent as i16;
+        assert_eq!(
+            server.join().unwrap(),
+            vec![put_all, put_all, put_if_absent, put_if_absent]
+        );
+    }
+}
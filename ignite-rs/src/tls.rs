@@ -0,0 +1,232 @@
+//! TLS settings of the connections with the cluster,
+//! if "tls" (rustls) or "native-tls" feature is enabled
+#[cfg(feature = "tls")]
+use std::io::BufRead;
+use std::io::{Read, Write};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+use crate::error::{IgniteError, IgniteResult};
+
+/// TLS settings of the connections. Ignite thin client SSL port expects
+/// a plain TLS session, no ALPN protocols are negotiated
+#[derive(Clone)]
+pub enum TlsConfig {
+    #[cfg(feature = "tls")]
+    Rustls {
+        /// rustls settings, like the trusted root certificates
+        client_conf: Arc<rustls::ClientConfig>,
+        /// Name which the certificates of the nodes are verified against.
+        /// It is also sent as SNI, unless `client_conf.enable_sni` is disabled
+        hostname: String,
+    },
+    /// Session of the platform TLS library, like OpenSSL. See `NativeTlsBuilder`
+    #[cfg(feature = "native-tls")]
+    Native {
+        connector: native_tls::TlsConnector,
+        /// Name which the certificates of the nodes are verified against, also sent as SNI
+        hostname: String,
+    },
+}
+
+#[cfg(feature = "tls")]
+impl TlsConfig {
+    pub fn new(client_conf: rustls::ClientConfig, hostname: &str) -> TlsConfig {
+        TlsConfig::Rustls {
+            client_conf: Arc::new(client_conf),
+            hostname: hostname.to_owned(),
+        }
+    }
+
+    /// Trusts only the root certificates which are read in PEM format
+    pub fn with_root_certificates(
+        pem: &mut dyn BufRead,
+        hostname: &str,
+    ) -> IgniteResult<TlsConfig> {
+        let mut client_conf = rustls::ClientConfig::new();
+        match client_conf.root_store.add_pem_file(pem) {
+            Ok((added, _)) if added > 0 => Ok(TlsConfig::new(client_conf, hostname)),
+            _ => Err(IgniteError::from("No valid root certificates are found!")),
+        }
+    }
+}
+
+/// Stream encrypted with the configured TLS library
+pub(crate) enum TlsStream<S: Read + Write> {
+    #[cfg(feature = "tls")]
+    Rustls(Box<rustls::StreamOwned<rustls::ClientSession, S>>),
+    #[cfg(feature = "native-tls")]
+    Native(Box<native_tls::TlsStream<S>>),
+}
+
+impl<S: Read + Write> TlsStream<S> {
+    /// Starts the TLS session over the stream
+    pub(crate) fn wrap(conf: &TlsConfig, stream: S) -> IgniteResult<TlsStream<S>> {
+        match conf {
+            #[cfg(feature = "tls")]
+            TlsConfig::Rustls {
+                client_conf,
+                hostname,
+            } => {
+                let hostname = webpki::DNSNameRef::try_from_ascii_str(hostname)?;
+                let tls_session = rustls::ClientSession::new(client_conf, hostname);
+                let tls_stream = rustls::StreamOwned::new(tls_session, stream);
+                Ok(TlsStream::Rustls(Box::new(tls_stream)))
+            }
+            #[cfg(feature = "native-tls")]
+            TlsConfig::Native {
+                connector,
+                hostname,
+            } => match connector.connect(hostname, stream) {
+                Ok(tls_stream) => Ok(TlsStream::Native(Box::new(tls_stream))),
+                Err(native_tls::HandshakeError::Failure(err)) => Err(IgniteError::from(
+                    format!("TLS handshake failed: {}", err).as_str(),
+                )),
+                Err(native_tls::HandshakeError::WouldBlock(_)) => Err(IgniteError::from(
+                    "TLS handshake could not complete on a nonblocking stream!",
+                )),
+            },
+        }
+    }
+}
+
+impl<S: Read + Write> TlsStream<S> {
+    /// Returns the underlying stream
+    pub(crate) fn get_ref(&self) -> &S {
+        match self {
+            #[cfg(feature = "tls")]
+            TlsStream::Rustls(stream) => stream.get_ref(),
+            #[cfg(feature = "native-tls")]
+            TlsStream::Native(stream) => stream.get_ref(),
+        }
+    }
+}
+
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            #[cfg(feature = "tls")]
+            TlsStream::Rustls(stream) => stream.read(buf),
+            #[cfg(feature = "native-tls")]
+            TlsStream::Native(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            #[cfg(feature = "tls")]
+            TlsStream::Rustls(stream) => stream.write(buf),
+            #[cfg(feature = "native-tls")]
+            TlsStream::Native(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            #[cfg(feature = "tls")]
+            TlsStream::Rustls(stream) => stream.flush(),
+            #[cfg(feature = "native-tls")]
+            TlsStream::Native(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Builds native TLS settings, including the client certificate for the clusters
+/// which require mutual TLS
+#[cfg(feature = "native-tls")]
+pub struct NativeTlsBuilder {
+    builder: native_tls::TlsConnectorBuilder,
+    hostname: String,
+}
+
+#[cfg(feature = "native-tls")]
+impl NativeTlsBuilder {
+    pub fn new(hostname: &str) -> NativeTlsBuilder {
+        NativeTlsBuilder {
+            builder: native_tls::TlsConnector::builder(),
+            hostname: hostname.to_owned(),
+        }
+    }
+
+    /// Trusts the root certificate in PEM format, in addition to the system ones
+    pub fn root_certificate_pem(mut self, pem: &[u8]) -> IgniteResult<NativeTlsBuilder> {
+        self.builder
+            .add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+        Ok(self)
+    }
+
+    /// Presents the client certificate and key from a PKCS #12 archive
+    pub fn identity_pkcs12(mut self, der: &[u8], password: &str) -> IgniteResult<NativeTlsBuilder> {
+        self.builder
+            .identity(native_tls::Identity::from_pkcs12(der, password)?);
+        Ok(self)
+    }
+
+    /// Presents the client certificate chain and PKCS #8 key in PEM format
+    pub fn identity_pem(mut self, cert: &[u8], key: &[u8]) -> IgniteResult<NativeTlsBuilder> {
+        self.builder
+            .identity(native_tls::Identity::from_pkcs8(cert, key)?);
+        Ok(self)
+    }
+
+    /// Checks that the certificates of the nodes match the hostname. Enabled by default,
+    /// could be disabled for internal PKI which certificates lack the proper names
+    pub fn verify_hostname(mut self, verify: bool) -> NativeTlsBuilder {
+        self.builder.danger_accept_invalid_hostnames(!verify);
+        self
+    }
+
+    /// Checks that the certificates of the nodes are valid and trusted. Enabled by default.
+    /// Disabling it makes the connections open to man-in-the-middle attacks
+    pub fn verify_certificates(mut self, verify: bool) -> NativeTlsBuilder {
+        self.builder.danger_accept_invalid_certs(!verify);
+        self
+    }
+
+    pub fn build(self) -> IgniteResult<TlsConfig> {
+        Ok(TlsConfig::Native {
+            connector: self.builder.build()?,
+            hostname: self.hostname,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_root_certificates() {
+        let not_pem = b"-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydA==\n-----END CERTIFICATE-----\n";
+        assert!(TlsConfig::with_root_certificates(&mut &not_pem[..], "node").is_err());
+        assert!(TlsConfig::with_root_certificates(&mut &b""[..], "node").is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_invalid_hostname() {
+        let conf = TlsConfig::new(rustls::ClientConfig::new(), "not a hostname");
+        assert!(TlsStream::wrap(&conf, Cursor::new(vec![])).is_err());
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn test_native_identity() {
+        assert!(NativeTlsBuilder::new("node")
+            .identity_pkcs12(b"not an archive", "secret")
+            .is_err());
+        assert!(NativeTlsBuilder::new("node")
+            .identity_pem(b"not a cert", b"not a key")
+            .is_err());
+        let conf = NativeTlsBuilder::new("node")
+            .verify_hostname(false)
+            .build()
+            .unwrap();
+        // the node closes the connection instead of answering the client hello
+        assert!(TlsStream::wrap(&conf, Cursor::new(vec![])).is_err());
+    }
+}
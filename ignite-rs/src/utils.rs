@@ -1,4 +1,6 @@
 use crate::protocol::complex_obj::IgniteField;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 
 /// Converts string into Java-like hash code
 // Note: we do not call lowercase() in here like the docs say
@@ -27,21 +29,42 @@ pub const FNV1_OFFSET_BASIS: i32 = 0x811C_9DC5_u32 as i32;
 pub const FNV1_PRIME: i32 = 0x0100_0193;
 
 pub fn get_schema_id(fields: &[IgniteField]) -> i32 {
-    fields
+    let field_ids: Vec<i32> = fields
         .iter()
         .map(|f| string_to_java_hashcode(&f.name.to_lowercase()))
-        .fold(FNV1_OFFSET_BASIS, |acc, field_id| {
-            let mut res = acc;
-            res ^= field_id & 0xFF;
-            res = res.overflowing_mul(FNV1_PRIME).0;
-            res ^= (field_id >> 8) & 0xFF;
-            res = res.overflowing_mul(FNV1_PRIME).0;
-            res ^= (field_id >> 16) & 0xFF;
-            res = res.overflowing_mul(FNV1_PRIME).0;
-            res ^= (field_id >> 24) & 0xFF;
-            res = res.overflowing_mul(FNV1_PRIME).0;
-            res
-        })
+        .collect();
+    get_schema_id_from_field_ids(&field_ids)
+}
+
+/// Schema ID based on field ids, in schema order
+pub fn get_schema_id_from_field_ids(field_ids: &[i32]) -> i32 {
+    field_ids.iter().fold(FNV1_OFFSET_BASIS, |acc, field_id| {
+        let mut res = acc;
+        res ^= field_id & 0xFF;
+        res = res.overflowing_mul(FNV1_PRIME).0;
+        res ^= (field_id >> 8) & 0xFF;
+        res = res.overflowing_mul(FNV1_PRIME).0;
+        res ^= (field_id >> 16) & 0xFF;
+        res = res.overflowing_mul(FNV1_PRIME).0;
+        res ^= (field_id >> 24) & 0xFF;
+        res = res.overflowing_mul(FNV1_PRIME).0;
+        res
+    })
+}
+
+/// Returns the items in random order
+pub(crate) fn shuffled<T: Clone>(items: &[T]) -> Vec<T> {
+    let mut items = items.to_vec();
+    for i in (1..items.len()).rev() {
+        items.swap(i, (random() % (i as u64 + 1)) as usize);
+    }
+    items
+}
+
+/// Returns a random number, not suitable for cryptography
+pub(crate) fn random() -> u64 {
+    // randomly seeded hasher is a good enough source of random numbers here
+    RandomState::new().build_hasher().finish()
 }
 
 #[cfg(test)]
@@ -74,4 +97,12 @@ mod tests {
         let actual = string_to_java_hashcode(type_name.as_str());
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_shuffled() {
+        let items: Vec<i32> = (0..10).collect();
+        let mut actual = shuffled(&items);
+        actual.sort();
+        assert_eq!(actual, items);
+    }
 }
@@ -14,7 +14,7 @@ mod int_test {
     #[test]
     fn should_list_caches() {
         let config = ClientConfig::new("127.0.0.1:10800");
-        let mut ignite = new_client(config).unwrap();
+        let ignite = new_client(config).unwrap();
         let actual = ignite.get_cache_names().unwrap();
         let expected = vec!["SQL_PUBLIC_RAINBOW"];
         assert_eq!(actual, expected);
@@ -23,7 +23,7 @@ mod int_test {
     #[test]
     fn should_read_schema() {
         let config = ClientConfig::new("127.0.0.1:10800");
-        let mut ignite = new_client(config).unwrap();
+        let ignite = new_client(config).unwrap();
 
         let table_name = "SQL_PUBLIC_RAINBOW";
         let cfg = ignite.get_cache_config(table_name).unwrap();
@@ -91,7 +91,7 @@ mod int_test {
     #[test]
     fn should_read_data() {
         let config = ClientConfig::new("localhost:10800");
-        let mut ignite = new_client(config).unwrap();
+        let ignite = new_client(config).unwrap();
         let table_name = "SQL_PUBLIC_RAINBOW";
 
         // read a row
@@ -1,7 +1,7 @@
 use proc_macro2::{Ident, TokenStream};
 use quote::*;
 use syn::spanned::Spanned;
-use syn::{Data, DeriveInput, Fields, FieldsNamed};
+use syn::{Data, DataEnum, DeriveInput, Fields, FieldsNamed};
 
 /// FNV1 hash offset basis
 const FNV1_OFFSET_BASIS: i32 = 0x811C_9DC5_u32 as i32;
@@ -21,6 +21,7 @@ pub fn derive_ignite_obj(item: proc_macro::TokenStream) -> proc_macro::TokenStre
             Fields::Named(ref fields) => {
                 let write_tokens = impl_write_type(type_name, fields, type_id);
                 let read_tokens = impl_read_type(type_name, fields, type_id);
+                let field_names = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
 
                 quote! {
                     #write_tokens
@@ -31,11 +32,62 @@ pub fn derive_ignite_obj(item: proc_macro::TokenStream) -> proc_macro::TokenStre
                             #type_id
                         }
                     }
+
+                    impl ignite_rs::UserType for #type_name {
+                        fn user_type_id() -> i32 {
+                            #type_id
+                        }
+                    }
+
+                    impl ignite_rs::TypeInfo for #type_name {
+                        fn cluster_type_name() -> Option<String> {
+                            Some(stringify!(#type_name).to_owned())
+                        }
+
+                        fn cluster_type_id() -> Option<i32> {
+                            Some(#type_id)
+                        }
+
+                        fn cluster_field_names() -> Vec<&'static str> {
+                            vec![#(stringify!(#field_names)),*]
+                        }
+                    }
                 }
             }
             _ => quote_spanned! { st.fields.span() => compile_error!("Named struct expected!");},
         },
-        _ => quote_spanned! { input.span() => compile_error!("Named struct expected!");},
+        Data::Enum(ref en) => {
+            if en
+                .variants
+                .iter()
+                .any(|v| !matches!(v.fields, Fields::Unit))
+            {
+                quote_spanned! { en.variants.span() => compile_error!("Fieldless enum expected!");}
+            } else {
+                let enum_tokens = impl_enum_type(type_name, en, type_id);
+
+                quote! {
+                    #enum_tokens
+
+                    impl #type_name {
+                        pub const fn type_id() -> i32 {
+                            #type_id
+                        }
+                    }
+
+                    impl ignite_rs::TypeInfo for #type_name {
+                        fn cluster_type_name() -> Option<String> {
+                            Some(stringify!(#type_name).to_owned())
+                        }
+
+                        fn cluster_type_id() -> Option<i32> {
+                            Some(#type_id)
+                        }
+                    }
+                }
+            }
+        }
+        _ => quote_spanned! { input.span() => compile_error!("Named struct or enum expected!");},
     };
 
     proc_macro::TokenStream::from(output)
@@ -71,7 +123,9 @@ fn impl_write_type(type_name: &Ident, fields: &FieldsNamed, type_id: i32) -> Tok
         quote_spanned! { field_name.span() =>
             ignite_rs::protocol::write_i32(&mut schema, ignite_rs::utils::string_to_java_hashcode(stringify!(#field_name)))?; // field id
             ignite_rs::protocol::write_i32(&mut schema, ignite_rs::protocol::COMPLEX_OBJ_HEADER_LEN + fields.len() as i32)?; // field offset
+            let start = fields.len();
             self.#field_name.write(&mut fields)?;
+            dedup.push(&mut fields, start); // replace repeated object with a handle
         }
     });
 
@@ -93,6 +147,8 @@ fn impl_write_type(type_name: &Ident, fields: &FieldsNamed, type_id: i32) -> Tok
                 //prepare buffers
                 let mut fields: Vec<u8> = Vec::new();
                 let mut schema: Vec<u8> = Vec::new();
+                #[allow(unused_mut, unused_variables)] // no fields
+                let mut dedup = ignite_rs::protocol::ObjectDedup::new();
 
                 //write fields
                 #( #fields_schema)*
@@ -118,14 +174,23 @@ fn impl_write_type(type_name: &Ident, fields: &FieldsNamed, type_id: i32) -> Tok
 
 /// Implements ReadableType trait
 fn impl_read_type(type_name: &Ident, fields: &FieldsNamed, type_id: i32) -> TokenStream {
-    let fields_count = fields.named.len();
-
     let fields_read = fields.named.iter().map(|f| {
         let field_name = &f.ident;
         let ty = &f.ty;
+        let field_id = string_to_java_hashcode(&field_name.as_ref().unwrap().to_string());
         let formatted_name = format_ident!("_{}", field_name.as_ref().unwrap().to_string());
         quote_spanned! { field_name.span() =>
-            let #formatted_name = <#ty>::read(reader)?.unwrap(); // get option value
+            let #formatted_name = match obj.read_field::<#ty>(#field_id)? { // get option value
+                Some(value) => value,
+                None => match <#ty as ignite_rs::ReadableType>::from_missing() { // None for Option fields
+                    Some(value) => value,
+                    None => {
+                        return Err(ignite_rs::error::IgniteError::from(
+                            concat!("Field ", stringify!(#field_name), " is NULL or missing!"),
+                        ))
+                    }
+                },
+            };
         }
     });
 
@@ -138,49 +203,90 @@ fn impl_read_type(type_name: &Ident, fields: &FieldsNamed, type_id: i32) -> Toke
     quote! {
         impl ignite_rs::ReadableType for #type_name {
             fn read_unwrapped(type_code: ignite_rs::protocol::TypeCode, reader: &mut impl std::io::Read) -> ignite_rs::error::IgniteResult<Option<Self>> {
-                let value: Option<Self> = match type_code {
-                    ignite_rs::protocol::TypeCode::Null => None,
-                    _ => {
-                        ignite_rs::protocol::read_u8(reader)?; // read version. skip
-
-                        let flags = ignite_rs::protocol::read_u16(reader)?; // read and parse flags
-                        if (flags & ignite_rs::protocol::FLAG_HAS_SCHEMA) == 0 {
-                            return Err(ignite_rs::error::IgniteError::from("Serialized object schema expected!"));
-                        }
-                        if (flags & ignite_rs::protocol::FLAG_COMPACT_FOOTER) != 0 {
-                            return Err(ignite_rs::error::IgniteError::from("Compact footer is not supported!"));
-                        }
-                        if (flags & ignite_rs::protocol::FLAG_OFFSET_ONE_BYTE) != 0 || (flags & ignite_rs::protocol::FLAG_OFFSET_TWO_BYTES) != 0 {
-                            return Err(ignite_rs::error::IgniteError::from("Schema offset=4 is expected!"));
-                        }
-
-                        let received_type_id = ignite_rs::protocol::read_i32(reader)?; // read and check type_id
-                        if received_type_id != #type_id {
+                match type_code {
+                    ignite_rs::protocol::TypeCode::Null => Ok(None),
+                    _ => ignite_rs::protocol::complex_obj::read_complex_obj(reader, |obj| {
+                        if obj.type_id != #type_id { // check type_id
                             return Err(ignite_rs::error::IgniteError::from(
-                                format!("Type ID mismatch: expected {}, got {}", #type_id, received_type_id).as_str(),
+                                format!("Type ID mismatch: expected {}, got {}", #type_id, obj.type_id).as_str(),
                             ));
                         }
 
-                        ignite_rs::protocol::read_i32(reader)?; // read hashcode
-                        ignite_rs::protocol::read_i32(reader)?; // read length (with header)
-                        ignite_rs::protocol::read_i32(reader)?; // read schema id
-                        ignite_rs::protocol::read_i32(reader)?; // read schema offset
-
                         #( #fields_read)*
 
-                        // read schema
-                        for _ in 0..#fields_count {
-                            ignite_rs::protocol::read_i64(reader)?; // read one field (id and offset)
-                        }
-
-                        Some(
+                        Ok(Some(
                             #type_name{
                                 #(#field_pairs)*
                             }
-                        )
-                    }
+                        ))
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// Implements WritableType, ReadableType and EnumType traits for a fieldless enum.
+/// Variants are mapped to ordinals in declaration order, like Java does
+fn impl_enum_type(type_name: &Ident, data: &DataEnum, type_id: i32) -> TokenStream {
+    let to_ordinal = data.variants.iter().enumerate().map(|(ordinal, v)| {
+        let variant = &v.ident;
+        let ordinal = ordinal as i32;
+        quote! { #type_name::#variant => #ordinal, }
+    });
+
+    let from_ordinal = data.variants.iter().enumerate().map(|(ordinal, v)| {
+        let variant = &v.ident;
+        let ordinal = ordinal as i32;
+        quote! { #ordinal => #type_name::#variant, }
+    });
+
+    quote! {
+        impl ignite_rs::WritableType for #type_name {
+            fn write(&self, writer: &mut dyn std::io::Write) -> std::io::Result<()> {
+                let ordinal: i32 = match self {
+                    #( #to_ordinal)*
                 };
-                Ok(value)
+                ignite_rs::protocol::write_u8(writer, ignite_rs::protocol::TypeCode::Enum as u8)?;
+                ignite_rs::protocol::write_i32(writer, #type_id)?; //type_id
+                ignite_rs::protocol::write_i32(writer, ordinal)?; //ordinal
+                Ok(())
+            }
+
+            fn size(&self) -> usize {
+                4 + 4 + 1 // type_id, ordinal, type code
+            }
+        }
+
+        impl ignite_rs::ReadableType for #type_name {
+            fn read_unwrapped(type_code: ignite_rs::protocol::TypeCode, reader: &mut impl std::io::Read) -> ignite_rs::error::IgniteResult<Option<Self>> {
+                match type_code {
+                    ignite_rs::protocol::TypeCode::Null => Ok(None),
+                    ignite_rs::protocol::TypeCode::Enum | ignite_rs::protocol::TypeCode::BinaryEnum => {
+                        let received_type_id = ignite_rs::protocol::read_i32(reader)?; // read and check type_id
+                        if received_type_id != #type_id {
+                            return Err(ignite_rs::error::IgniteError::from(
+                                format!("Type ID mismatch: expected {}, got {}", #type_id, received_type_id).as_str(),
+                            ));
+                        }
+                        let value = match ignite_rs::protocol::read_i32(reader)? {
+                            #( #from_ordinal)*
+                            ordinal => {
+                                return Err(ignite_rs::error::IgniteError::from(
+                                    format!("Unknown ordinal {} of enum {}", ordinal, stringify!(#type_name)).as_str(),
+                                ))
+                            }
+                        };
+                        Ok(Some(value))
+                    }
+                    _ => Err(ignite_rs::error::IgniteError::from("Enum expected!")),
+                }
+            }
+        }
+
+        impl ignite_rs::EnumType for #type_name {
+            fn enum_type_id(&self) -> i32 {
+                #type_id
             }
         }
     }
@@ -215,4 +321,4 @@ fn string_to_java_hashcode(value: &str) -> i32 {
         hash = 31i32.overflowing_mul(hash).0 + char as i32;
     }
     hash
-}
\ No newline at end of file
+}